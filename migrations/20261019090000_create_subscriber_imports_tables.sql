@@ -0,0 +1,31 @@
+-- Add migration script here
+CREATE TABLE subscriber_imports(
+  import_id uuid NOT NULL,
+  user_id uuid NOT NULL REFERENCES users(user_id),
+  filename TEXT NOT NULL,
+  mode TEXT NOT NULL,
+  total_rows INT NOT NULL,
+  imported_rows INT NOT NULL DEFAULT 0,
+  duplicate_rows INT NOT NULL DEFAULT 0,
+  rejected_rows INT NOT NULL DEFAULT 0,
+  created_at timestamptz NOT NULL,
+  PRIMARY KEY(import_id)
+);
+
+-- rows waiting to be picked up by the import worker
+CREATE TABLE subscriber_import_queue(
+  import_id uuid NOT NULL REFERENCES subscriber_imports(import_id),
+  row_number INT NOT NULL,
+  email TEXT NOT NULL,
+  name TEXT NOT NULL,
+  PRIMARY KEY(import_id, row_number)
+);
+
+CREATE TABLE subscriber_import_rejections(
+  import_id uuid NOT NULL REFERENCES subscriber_imports(import_id),
+  row_number INT NOT NULL,
+  email TEXT NOT NULL,
+  name TEXT NOT NULL,
+  reason TEXT NOT NULL,
+  PRIMARY KEY(import_id, row_number)
+);
@@ -0,0 +1,28 @@
+-- Add migration script here
+-- only a sha-256 of each token is kept, same as api tokens
+CREATE TABLE subscriber_data_tokens(
+  data_token_hash TEXT NOT NULL,
+  subscriber_id uuid NOT NULL REFERENCES subscriptions(id),
+  created_at timestamptz NOT NULL,
+  PRIMARY KEY (data_token_hash)
+);
+
+-- requests are answered by a worker so the form responds the same way whether
+-- or not the address is subscribed
+CREATE TABLE subscriber_data_request_queue(
+  request_id uuid NOT NULL,
+  email TEXT NOT NULL,
+  requested_at timestamptz NOT NULL,
+  PRIMARY KEY (request_id)
+);
+
+-- erasures keep no personal data, only what was removed and on whose behalf
+CREATE TABLE subscriber_erasures(
+  erasure_id uuid NOT NULL,
+  subscriber_id uuid NOT NULL,
+  requested_by TEXT NOT NULL,
+  user_id uuid NULL REFERENCES users(user_id),
+  deleted_rows JSONB NOT NULL,
+  erased_at timestamptz NOT NULL,
+  PRIMARY KEY (erasure_id)
+);
@@ -0,0 +1,24 @@
+-- Add migration script here
+CREATE TABLE consent_events(
+  event_id uuid NOT NULL,
+  subscriber_id uuid NOT NULL REFERENCES subscriptions(id),
+  kind TEXT NOT NULL,
+  ip_address TEXT NULL,
+  user_agent TEXT NULL,
+  source TEXT NOT NULL,
+  consent_text_version TEXT NOT NULL,
+  occurred_at timestamptz NOT NULL,
+  PRIMARY KEY (event_id)
+);
+
+-- consent records are evidence: rows can be erased along with the subscriber
+-- but never rewritten
+CREATE FUNCTION reject_consent_event_update() RETURNS trigger AS $$
+BEGIN
+  RAISE EXCEPTION 'consent_events is append-only';
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE TRIGGER consent_events_append_only
+  BEFORE UPDATE ON consent_events
+  FOR EACH ROW EXECUTE FUNCTION reject_consent_event_update();
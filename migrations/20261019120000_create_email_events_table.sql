@@ -0,0 +1,12 @@
+-- Add migration script here
+-- bounces and spam complaints reported by the email provider
+CREATE TABLE email_events(
+  event_id uuid NOT NULL,
+  email TEXT NOT NULL,
+  record_type TEXT NOT NULL,
+  bounce_type TEXT NULL,
+  payload JSONB NOT NULL,
+  received_at timestamptz NOT NULL,
+  PRIMARY KEY (event_id)
+);
+CREATE INDEX email_events_email_idx ON email_events(email);
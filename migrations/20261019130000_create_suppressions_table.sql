@@ -0,0 +1,10 @@
+-- Add migration script here
+-- addresses and domains that are never emailed, whatever their subscription status;
+-- entries outlive erasure on purpose, that's how we keep honouring them
+CREATE TABLE suppressions(
+  entry TEXT NOT NULL,
+  reason TEXT NOT NULL,
+  user_id uuid NULL REFERENCES users(user_id),
+  created_at timestamptz NOT NULL,
+  PRIMARY KEY (entry)
+);
@@ -0,0 +1,11 @@
+-- Add migration script here
+CREATE TABLE failed_login_attempts(
+  attempt_id uuid NOT NULL,
+  username TEXT NOT NULL,
+  ip_address TEXT NULL,
+  user_agent TEXT NULL,
+  locked_out BOOLEAN NOT NULL,
+  attempted_at timestamptz NOT NULL,
+  PRIMARY KEY (attempt_id)
+);
+CREATE INDEX failed_login_attempts_attempted_at_idx ON failed_login_attempts(attempted_at);
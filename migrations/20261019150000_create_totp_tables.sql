@@ -0,0 +1,21 @@
+-- Add migration script here
+CREATE TABLE user_totp(
+  user_id uuid NOT NULL REFERENCES users(user_id),
+  -- base32, as shown to the user when enrolling
+  secret TEXT NOT NULL,
+  -- codes are only required once the user proved their authenticator works
+  confirmed_at timestamptz NULL,
+  -- a code can't be used twice, so we remember the last accepted time step
+  last_used_step BIGINT NULL,
+  created_at timestamptz NOT NULL,
+  PRIMARY KEY (user_id)
+);
+
+CREATE TABLE totp_recovery_codes(
+  recovery_code_id uuid NOT NULL,
+  user_id uuid NOT NULL REFERENCES users(user_id),
+  code_hash TEXT NOT NULL,
+  used_at timestamptz NULL,
+  PRIMARY KEY (recovery_code_id)
+);
+CREATE INDEX totp_recovery_codes_user_id_idx ON totp_recovery_codes(user_id);
@@ -0,0 +1,21 @@
+-- where reset links are sent, NULL for users that haven't set one
+ALTER TABLE users ADD COLUMN email TEXT NULL UNIQUE;
+
+-- only a sha-256 of each token is kept, same as api tokens
+CREATE TABLE password_reset_tokens(
+  reset_token_hash TEXT NOT NULL,
+  user_id uuid NOT NULL REFERENCES users (user_id),
+  created_at timestamptz NOT NULL,
+  used_at timestamptz NULL,
+  PRIMARY KEY (reset_token_hash)
+);
+CREATE INDEX password_reset_tokens_user_id_idx ON password_reset_tokens (user_id);
+
+-- requests are answered by a worker so the form responds the same way whether
+-- or not the account exists
+CREATE TABLE password_reset_request_queue(
+  request_id uuid NOT NULL,
+  identifier TEXT NOT NULL,
+  requested_at timestamptz NOT NULL,
+  PRIMARY KEY (request_id)
+);
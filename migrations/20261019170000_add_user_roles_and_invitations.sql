@@ -0,0 +1,17 @@
+-- everyone who could log in so far could do everything
+ALTER TABLE users ADD COLUMN role TEXT NOT NULL DEFAULT 'owner';
+ALTER TABLE users ALTER COLUMN role DROP DEFAULT;
+-- usernames are picked by invitees now, they have to tell users apart
+ALTER TABLE users ADD CONSTRAINT users_name_key UNIQUE (name);
+
+-- only a sha-256 of each token is kept, same as reset links
+CREATE TABLE user_invitations(
+  invitation_id uuid NOT NULL,
+  invitation_token_hash TEXT NOT NULL UNIQUE,
+  email TEXT NOT NULL,
+  role TEXT NOT NULL,
+  invited_by uuid NOT NULL REFERENCES users (user_id),
+  created_at timestamptz NOT NULL,
+  accepted_at timestamptz NULL,
+  PRIMARY KEY (invitation_id)
+);
@@ -0,0 +1,13 @@
+-- one row per login, the redis session only holds the session_id
+CREATE TABLE user_sessions(
+  session_id uuid NOT NULL,
+  user_id uuid NOT NULL REFERENCES users (user_id),
+  created_at timestamptz NOT NULL,
+  last_seen_at timestamptz NOT NULL,
+  ip_address TEXT NOT NULL,
+  user_agent TEXT NULL,
+  -- set on logout and when the session is ended from elsewhere
+  revoked_at timestamptz NULL,
+  PRIMARY KEY (session_id)
+);
+CREATE INDEX user_sessions_user_id_idx ON user_sessions (user_id);
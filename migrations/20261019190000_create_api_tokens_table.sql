@@ -0,0 +1,15 @@
+-- personal tokens for the JSON api, only a hash of the token is kept
+CREATE TABLE api_tokens(
+  token_id uuid NOT NULL,
+  user_id uuid NOT NULL REFERENCES users (user_id),
+  name TEXT NOT NULL,
+  -- sha-256 in hex, the tokens are random so a slow hash buys nothing
+  token_hash TEXT NOT NULL UNIQUE,
+  -- `Permission::as_str` values, further limited by the owner's current role
+  scopes TEXT[] NOT NULL,
+  created_at timestamptz NOT NULL,
+  last_used_at timestamptz NULL,
+  revoked_at timestamptz NULL,
+  PRIMARY KEY (token_id)
+);
+CREATE INDEX api_tokens_user_id_idx ON api_tokens (user_id);
@@ -0,0 +1,12 @@
+-- accounts at an OpenID Connect provider, linked to the user they sign in as
+CREATE TABLE user_identities(
+  issuer TEXT NOT NULL,
+  subject TEXT NOT NULL,
+  user_id uuid NOT NULL REFERENCES users (user_id) ON DELETE CASCADE,
+  created_at timestamptz NOT NULL,
+  last_login_at timestamptz NULL,
+  PRIMARY KEY (issuer, subject),
+  -- a user is one account at each provider, a second one signing in with the
+  -- same email is more likely a reassigned address than the same person
+  UNIQUE (issuer, user_id)
+);
@@ -0,0 +1,26 @@
+-- who did what to what, from where; only ever appended to
+CREATE TABLE audit_log(
+  audit_event_id uuid NOT NULL,
+  occurred_at timestamptz NOT NULL,
+  -- no foreign key, entries outlive the users they're about
+  user_id uuid NULL,
+  ip_address TEXT NULL,
+  action TEXT NOT NULL,
+  target TEXT NULL,
+  changes JSONB NOT NULL,
+  PRIMARY KEY (audit_event_id)
+);
+CREATE INDEX audit_log_occurred_at_idx ON audit_log (occurred_at DESC);
+
+CREATE FUNCTION reject_audit_log_changes() RETURNS trigger AS $$
+BEGIN
+  RAISE EXCEPTION 'audit_log is append-only';
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE TRIGGER audit_log_is_append_only
+  BEFORE UPDATE OR DELETE ON audit_log
+  FOR EACH ROW EXECUTE FUNCTION reject_audit_log_changes();
+CREATE TRIGGER audit_log_is_not_truncated
+  BEFORE TRUNCATE ON audit_log
+  FOR EACH STATEMENT EXECUTE FUNCTION reject_audit_log_changes();
@@ -0,0 +1,8 @@
+-- keys on anonymous routes belong to the client's ip address instead of a user
+ALTER TABLE idempotency DROP CONSTRAINT idempotency_pkey;
+ALTER TABLE idempotency ALTER COLUMN user_id DROP NOT NULL;
+ALTER TABLE idempotency ADD COLUMN client_ip TEXT NULL;
+ALTER TABLE idempotency ADD CONSTRAINT idempotency_has_one_owner
+  CHECK ((user_id IS NULL) <> (client_ip IS NULL));
+ALTER TABLE idempotency ADD CONSTRAINT idempotency_user_key UNIQUE (idempotency_key, user_id);
+ALTER TABLE idempotency ADD CONSTRAINT idempotency_client_key UNIQUE (idempotency_key, client_ip);
@@ -0,0 +1,2 @@
+-- a hash of the request a key was first used with, to catch reuse with another payload
+ALTER TABLE idempotency ADD COLUMN request_fingerprint TEXT NULL;
@@ -0,0 +1,5 @@
+-- background workers check in here, readiness checks they still do
+CREATE TABLE worker_heartbeats(
+  worker TEXT NOT NULL PRIMARY KEY,
+  last_seen_at timestamptz NOT NULL
+);
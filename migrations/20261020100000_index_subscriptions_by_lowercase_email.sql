@@ -0,0 +1,2 @@
+-- addresses are looked up regardless of case by imports, webhooks and suppressions
+CREATE INDEX subscriptions_lower_email_idx ON subscriptions (lower(email));
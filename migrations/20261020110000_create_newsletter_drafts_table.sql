@@ -0,0 +1,11 @@
+-- issues being written, they become a `newsletter_issues` row once published
+CREATE TABLE newsletter_drafts(
+  draft_id uuid NOT NULL,
+  title TEXT NOT NULL,
+  text_content TEXT NOT NULL,
+  html_content TEXT NOT NULL,
+  created_by uuid NOT NULL REFERENCES users (user_id),
+  created_at timestamptz NOT NULL,
+  updated_at timestamptz NOT NULL,
+  PRIMARY KEY (draft_id)
+);
@@ -0,0 +1,146 @@
+use std::future::{ready, Ready};
+
+use actix_web::{dev::Payload, FromRequest, HttpMessage, HttpRequest};
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use sqlx::{PgExecutor, PgPool};
+use uuid::Uuid;
+
+use crate::{authentication::middleware::UserId, domain::AuditAction, utils::client_ip};
+
+pub const PAGE_SIZE: i64 = 50;
+
+/// who is acting and from where; an extractor, the user is whoever the
+/// session or api token belongs to
+#[derive(Debug, Clone)]
+pub struct AuditContext {
+    user_id: Option<Uuid>,
+    ip_address: Option<String>,
+}
+
+impl AuditContext {
+    pub fn for_request(request: &HttpRequest) -> Self {
+        Self {
+            user_id: request.extensions().get::<UserId>().map(|user_id| **user_id),
+            ip_address: client_ip(request).map(|ip| ip.chars().take(64).collect()),
+        }
+    }
+
+    /// for requests made before anyone is logged in, e.g. the login itself
+    pub fn as_user(self, user_id: Uuid) -> Self {
+        Self {
+            user_id: Some(user_id),
+            ..self
+        }
+    }
+}
+
+impl FromRequest for AuditContext {
+    type Error = actix_web::Error;
+    type Future = Ready<Result<Self, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, _payload: &mut Payload) -> Self::Future {
+        ready(Ok(Self::for_request(req)))
+    }
+}
+
+/// `changes` holds whatever tells the story, e.g. `{"role": {"from": .., "to": ..}}`;
+/// pass the transaction the change is made in, so neither is kept without the other
+#[tracing::instrument(name = "record audit event", skip(executor, changes))]
+pub async fn record_audit_event<'e>(
+    executor: impl PgExecutor<'e>,
+    context: &AuditContext,
+    action: AuditAction,
+    target: Option<&str>,
+    changes: serde_json::Value,
+) -> Result<(), anyhow::Error> {
+    sqlx::query!(
+        r#"
+        INSERT INTO audit_log(audit_event_id, occurred_at, user_id, ip_address, action, target, changes)
+        VALUES ($1, now(), $2, $3, $4, $5, $6)
+        "#,
+        Uuid::new_v4(),
+        context.user_id,
+        context.ip_address,
+        action.as_str(),
+        target,
+        changes
+    )
+    .execute(executor)
+    .await
+    .context("failed to record audit event")?;
+    Ok(())
+}
+
+pub struct AuditEvent {
+    pub occurred_at: DateTime<Utc>,
+    pub user_id: Option<Uuid>,
+    /// `None` for events without a user, or whose user is gone
+    pub username: Option<String>,
+    pub ip_address: Option<String>,
+    pub action: String,
+    pub target: Option<String>,
+    pub changes: serde_json::Value,
+}
+
+#[derive(Debug, Default)]
+pub struct AuditFilter {
+    pub action: Option<AuditAction>,
+    pub username: Option<String>,
+    /// matched as a substring
+    pub target: Option<String>,
+}
+
+/// newest first
+#[tracing::instrument(name = "search audit log", skip(pool))]
+pub async fn search_audit_log(
+    pool: &PgPool,
+    filter: &AuditFilter,
+    page: i64,
+) -> Result<(Vec<AuditEvent>, i64), anyhow::Error> {
+    let action = filter.action.map(|a| a.as_str());
+    let target = filter.target.as_ref().map(|t| format!("%{}%", t.trim()));
+
+    let events = sqlx::query_as!(
+        AuditEvent,
+        r#"
+        SELECT a.occurred_at, a.user_id, u.name as "username?", a.ip_address, a.action, a.target, a.changes
+        FROM audit_log a
+        LEFT JOIN users u ON u.user_id = a.user_id
+        WHERE
+            ($1::text IS NULL OR a.action = $1) AND
+            ($2::text IS NULL OR u.name = $2) AND
+            ($3::text IS NULL OR a.target ILIKE $3)
+        ORDER BY a.occurred_at DESC
+        LIMIT $4 OFFSET $5
+        "#,
+        action,
+        filter.username,
+        target,
+        PAGE_SIZE,
+        (page - 1) * PAGE_SIZE,
+    )
+    .fetch_all(pool)
+    .await
+    .context("failed to fetch audit log")?;
+
+    let total = sqlx::query_scalar!(
+        r#"
+        SELECT count(*) as "count!"
+        FROM audit_log a
+        LEFT JOIN users u ON u.user_id = a.user_id
+        WHERE
+            ($1::text IS NULL OR a.action = $1) AND
+            ($2::text IS NULL OR u.name = $2) AND
+            ($3::text IS NULL OR a.target ILIKE $3)
+        "#,
+        action,
+        filter.username,
+        target,
+    )
+    .fetch_one(pool)
+    .await
+    .context("failed to count audit events")?;
+
+    Ok((events, total))
+}
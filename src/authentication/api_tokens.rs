@@ -0,0 +1,163 @@
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use rand::{distributions::Alphanumeric, Rng};
+use secrecy::{ExposeSecret, Secret};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::{domain::Permission, utils::hash_token};
+
+/// makes leaked tokens easy to spot, e.g. by secret scanners
+const TOKEN_PREFIX: &str = "z2p_";
+
+pub struct ApiToken {
+    pub token_id: Uuid,
+    pub name: String,
+    pub scopes: Vec<Permission>,
+    pub created_at: DateTime<Utc>,
+    pub last_used_at: Option<DateTime<Utc>>,
+}
+
+/// who a valid token belongs to and what it was issued for
+pub struct ApiTokenGrant {
+    pub user_id: Uuid,
+    pub scopes: Vec<Permission>,
+}
+
+fn generate_api_token() -> Secret<String> {
+    let random: String = rand::thread_rng()
+        .sample_iter(Alphanumeric)
+        .map(char::from)
+        .take(40)
+        .collect();
+    Secret::new(format!("{TOKEN_PREFIX}{random}"))
+}
+
+fn parse_scopes(scopes: Vec<String>) -> Result<Vec<Permission>, anyhow::Error> {
+    scopes
+        .into_iter()
+        .map(|s| Permission::try_from(s).map_err(|e| anyhow::anyhow!(e)))
+        .collect()
+}
+
+/// returns the token itself, which isn't stored and so can only be shown this once
+#[tracing::instrument(name = "create api token", skip(pool))]
+pub async fn create_api_token(
+    pool: &PgPool,
+    user_id: Uuid,
+    name: &str,
+    scopes: &[Permission],
+) -> Result<Secret<String>, anyhow::Error> {
+    let token = generate_api_token();
+    let scopes: Vec<&str> = scopes.iter().map(|s| s.as_str()).collect();
+    sqlx::query!(
+        r#"
+        INSERT INTO api_tokens(token_id, user_id, name, token_hash, scopes, created_at)
+        VALUES ($1, $2, $3, $4, $5, now())
+        "#,
+        Uuid::new_v4(),
+        user_id,
+        name,
+        hash_token(token.expose_secret()),
+        &scopes as &[&str],
+    )
+    .execute(pool)
+    .await
+    .context("failed to store api token")?;
+    Ok(token)
+}
+
+/// the user's tokens that haven't been revoked, newest first
+pub async fn list_api_tokens(pool: &PgPool, user_id: Uuid) -> Result<Vec<ApiToken>, anyhow::Error> {
+    let rows = sqlx::query!(
+        r#"
+        SELECT token_id, name, scopes, created_at, last_used_at
+        FROM api_tokens
+        WHERE user_id = $1 AND revoked_at IS NULL
+        ORDER BY created_at DESC
+        "#,
+        user_id
+    )
+    .fetch_all(pool)
+    .await
+    .context("failed to fetch api tokens")?;
+
+    rows.into_iter()
+        .map(|r| {
+            Ok(ApiToken {
+                token_id: r.token_id,
+                name: r.name,
+                scopes: parse_scopes(r.scopes)?,
+                created_at: r.created_at,
+                last_used_at: r.last_used_at,
+            })
+        })
+        .collect()
+}
+
+/// false if the token doesn't belong to the user or was revoked already
+#[tracing::instrument(name = "revoke api token", skip(pool))]
+pub async fn revoke_api_token(
+    pool: &PgPool,
+    user_id: Uuid,
+    token_id: Uuid,
+) -> Result<bool, anyhow::Error> {
+    let n_updated_rows = sqlx::query!(
+        r#"
+        UPDATE api_tokens SET revoked_at = now()
+        WHERE token_id = $1 AND user_id = $2 AND revoked_at IS NULL
+        "#,
+        token_id,
+        user_id
+    )
+    .execute(pool)
+    .await
+    .context("failed to revoke api token")?
+    .rows_affected();
+    Ok(n_updated_rows == 1)
+}
+
+/// `None` for unknown and revoked tokens; records the use otherwise
+pub async fn authenticate_api_token(
+    pool: &PgPool,
+    token: &Secret<String>,
+) -> Result<Option<ApiTokenGrant>, anyhow::Error> {
+    if !token.expose_secret().starts_with(TOKEN_PREFIX) {
+        return Ok(None);
+    }
+    // looked up by hash, so there's no comparison of the secret to time
+    let row = sqlx::query!(
+        r#"
+        UPDATE api_tokens SET last_used_at = now()
+        WHERE token_hash = $1 AND revoked_at IS NULL
+        RETURNING user_id, scopes
+        "#,
+        hash_token(token.expose_secret())
+    )
+    .fetch_optional(pool)
+    .await
+    .context("failed to look up api token")?;
+
+    row.map(|r| {
+        Ok(ApiTokenGrant {
+            user_id: r.user_id,
+            scopes: parse_scopes(r.scopes)?,
+        })
+    })
+    .transpose()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{generate_api_token, hash_token, TOKEN_PREFIX};
+    use secrecy::ExposeSecret;
+
+    #[test]
+    fn tokens_are_prefixed_and_unique() {
+        let a = generate_api_token();
+        let b = generate_api_token();
+        assert!(a.expose_secret().starts_with(TOKEN_PREFIX));
+        assert_eq!(a.expose_secret().len(), TOKEN_PREFIX.len() + 40);
+        assert_ne!(hash_token(a.expose_secret()), hash_token(b.expose_secret()));
+    }
+}
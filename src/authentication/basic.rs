@@ -0,0 +1,55 @@
+use actix_web::http::header::HeaderMap;
+use anyhow::Context;
+use base64::Engine;
+use secrecy::{ExposeSecret, Secret};
+
+use super::Credentials;
+use crate::utils::constant_time_eq;
+
+pub fn basic_authentication(headers: &HeaderMap) -> Result<Credentials, anyhow::Error> {
+    // The header value, if present, must be a valid UTF8 string
+    let header_value = headers
+        .get("Authorization")
+        .context("The Authorization' header was missing")?
+        .to_str()
+        .context("The 'Authorization' header was not a valid UTF8 string.")?;
+    let base64encoded_credentials = header_value
+        .strip_prefix("Basic ")
+        .context("The authorization scheme was not 'Basic'.")?;
+    let decoded_credentials = base64::engine::general_purpose::STANDARD
+        .decode(base64encoded_credentials)
+        .context("Failed to base64-decode 'Basic' credentials.")?;
+    let decoded_credentials = String::from_utf8(decoded_credentials)
+        .context("The decoded credential string is valid UTF8.")?;
+
+    // splitn returns at most 2 elements !
+    let mut credentials = decoded_credentials.splitn(2, ':');
+    let username = credentials
+        .next()
+        .ok_or_else(|| anyhow::anyhow!("A username must be provided in 'Basic' auth."))?
+        .to_string();
+    let password = credentials
+        .next()
+        .ok_or_else(|| anyhow::anyhow!("A password must be provided in 'Basic' auth."))?
+        .to_string();
+
+    Ok(Credentials {
+        username,
+        password: Secret::new(password),
+    })
+}
+
+/// for fixed credentials from configuration, rather than users' stored hashes
+pub fn credentials_match(
+    credentials: &Credentials,
+    username: &str,
+    password: &Secret<String>,
+) -> bool {
+    // both are compared, so timing doesn't tell which one was wrong
+    let username_matches = constant_time_eq(credentials.username.as_bytes(), username.as_bytes());
+    let password_matches = constant_time_eq(
+        credentials.password.expose_secret().as_bytes(),
+        password.expose_secret().as_bytes(),
+    );
+    username_matches & password_matches
+}
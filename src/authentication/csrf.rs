@@ -0,0 +1,109 @@
+use std::pin::Pin;
+
+use actix_web::{
+    body::MessageBody,
+    dev::{Payload, ServiceRequest, ServiceResponse},
+    error::{InternalError, PayloadError},
+    http::Method,
+    middleware::Next,
+    web::{self, Bytes},
+    FromRequest, HttpMessage, HttpResponse,
+};
+use futures_util::{stream, Stream};
+use serde::Deserialize;
+
+use crate::{
+    routes::generate_random_token,
+    session_state::TypedSession,
+    utils::{constant_time_eq, e500},
+};
+
+pub const CSRF_FIELD: &str = "csrf_token";
+/// for clients that can't add a form field
+pub const CSRF_HEADER: &str = "X-CSRF-Token";
+
+/// the session's synchronizer token, every admin form has to send it back
+#[derive(Clone, Debug)]
+pub struct CsrfToken(String);
+
+impl CsrfToken {
+    /// goes inside every `<form method="post">`
+    pub fn hidden_input(&self) -> String {
+        format!(
+            r#"<input type="hidden" name="{CSRF_FIELD}" value="{}">"#,
+            htmlescape::encode_attribute(&self.0)
+        )
+    }
+
+    /// multipart bodies aren't buffered, so those forms send the token in their action
+    pub fn query(&self) -> String {
+        format!("{CSRF_FIELD}={}", urlencoding::encode(&self.0))
+    }
+
+    fn matches(&self, candidate: &str) -> bool {
+        // the token is the only thing a forged request is missing
+        constant_time_eq(self.0.as_bytes(), candidate.as_bytes())
+    }
+}
+
+#[derive(Deserialize)]
+struct CsrfField {
+    csrf_token: Option<String>,
+}
+
+/// provides the `CsrfToken` to handlers and rejects state-changing requests that
+/// don't carry it, in the `X-CSRF-Token` header, a `csrf_token` form field, or for
+/// multipart forms the `csrf_token` query parameter
+pub async fn reject_invalid_csrf_tokens(
+    mut req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<impl MessageBody>, actix_web::Error> {
+    let session = {
+        let (request, payload) = req.parts_mut();
+        TypedSession::from_request(request, payload).await
+    }?;
+    let token = match session.get_csrf_token().map_err(e500)? {
+        Some(token) => token,
+        None => {
+            let token = generate_random_token();
+            session.insert_csrf_token(&token).map_err(e500)?;
+            token
+        }
+    };
+    let token = CsrfToken(token);
+
+    if !matches!(*req.method(), Method::GET | Method::HEAD | Method::OPTIONS) {
+        let submitted = submitted_token(&mut req).await?;
+        if !submitted.is_some_and(|t| token.matches(&t)) {
+            tracing::warn!(path = %req.path(), "missing or invalid csrf token");
+            let e = anyhow::anyhow!("missing or invalid csrf token");
+            let response = HttpResponse::Forbidden().body("Missing or invalid CSRF token");
+            return Err(InternalError::from_response(e, response).into());
+        }
+    }
+
+    req.extensions_mut().insert(token);
+    next.call(req).await
+}
+
+async fn submitted_token(req: &mut ServiceRequest) -> Result<Option<String>, actix_web::Error> {
+    if let Some(header) = req.headers().get(CSRF_HEADER) {
+        return Ok(header.to_str().ok().map(str::to_string));
+    }
+    if req.content_type() != "application/x-www-form-urlencoded" {
+        return Ok(web::Query::<CsrfField>::from_query(req.query_string())
+            .ok()
+            .and_then(|q| q.into_inner().csrf_token));
+    }
+
+    // the handler still needs the body, so it's put back after reading the field
+    let body = req.extract::<Bytes>().await?;
+    let token = std::str::from_utf8(&body)
+        .ok()
+        .and_then(|body| web::Query::<CsrfField>::from_query(body).ok())
+        .and_then(|f| f.into_inner().csrf_token);
+    let stream: Pin<Box<dyn Stream<Item = Result<Bytes, PayloadError>>>> =
+        Box::pin(stream::once(async move { Ok(body) }));
+    req.set_payload(Payload::from(stream));
+    Ok(token)
+}
@@ -1,11 +1,21 @@
 use std::{fmt::Display, ops::Deref};
 
 use actix_web::{
-    body::MessageBody, dev::{ServiceRequest, ServiceResponse}, error::InternalError, middleware::Next, FromRequest, HttpMessage
+    body::{BoxBody, MessageBody}, dev::{ServiceRequest, ServiceResponse}, error::InternalError, http::{header, StatusCode}, middleware::Next, web, FromRequest, HttpMessage
 };
+use actix_web_flash_messages::FlashMessage;
+use futures_util::future::LocalBoxFuture;
+use secrecy::Secret;
+use sqlx::PgPool;
 use uuid::Uuid;
 
-use crate::{session_state::TypedSession, utils::{e500, see_other}};
+use crate::{
+    authentication::{api_tokens::authenticate_api_token, sessions::touch_session},
+    domain::{Permission, UserRole},
+    session_state::TypedSession,
+    users::get_user_role,
+    utils::{e500, json_error, see_other},
+};
 
 #[derive(Copy, Clone, Debug)]
 pub struct UserId(Uuid);
@@ -23,17 +33,54 @@ impl Display for UserId{
     }
 }
 
+/// the current login's row in `user_sessions`
+#[derive(Copy, Clone, Debug)]
+pub struct SessionId(Uuid);
+
+impl Deref for SessionId{
+    type Target = Uuid;
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
 pub async fn reject_anonymous_users(
     mut req: ServiceRequest,
     next: Next<impl MessageBody>,
 ) -> Result<ServiceResponse<impl MessageBody>, actix_web::Error> {
     let session = {
-        let (request, mut payload) = req.parts_mut();
-        TypedSession::from_request(&request, &mut payload).await
+        let (request, payload) = req.parts_mut();
+        TypedSession::from_request(request, payload).await
     }?;
-    match session.get_user_id().map_err(e500)? {
-        Some(uid) => {
+    let user = match (
+        session.get_user_id().map_err(e500)?,
+        session.get_session_id().map_err(e500)?,
+    ) {
+        (Some(uid), Some(session_id)) => {
+            let pool = req
+                .app_data::<web::Data<PgPool>>()
+                .ok_or_else(|| e500("no database pool registered"))?;
+            if touch_session(pool.as_ref(), uid, session_id)
+                .await
+                .map_err(e500)?
+            {
+                get_user_role(pool.as_ref(), uid)
+                    .await
+                    .map_err(e500)?
+                    .map(|role| (uid, session_id, role))
+            } else {
+                // ended from elsewhere
+                session.purge();
+                None
+            }
+        }
+        _ => None,
+    };
+    match user {
+        Some((uid, session_id, role)) => {
             req.extensions_mut().insert(UserId(uid));
+            req.extensions_mut().insert(SessionId(session_id));
+            req.extensions_mut().insert(role);
             next.call(req).await
         },
         None => {
@@ -43,3 +90,102 @@ pub async fn reject_anonymous_users(
         }
     }
 }
+
+/// what the API token used for the request was issued for, absent for browser sessions
+#[derive(Clone, Debug)]
+pub struct ApiScopes(Vec<Permission>);
+
+impl ApiScopes {
+    pub fn allows(&self, permission: Permission) -> bool {
+        self.0.contains(&permission)
+    }
+}
+
+/// the `/api` counterpart of `reject_anonymous_users`, authenticating with an
+/// `Authorization: Bearer <token>` header instead of the session cookie
+pub async fn reject_invalid_api_tokens(
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<impl MessageBody>, actix_web::Error> {
+    let token = req
+        .headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|h| h.to_str().ok())
+        .and_then(|h| h.strip_prefix("Bearer "))
+        .map(|token| Secret::new(token.trim().to_string()));
+    let pool = req
+        .app_data::<web::Data<PgPool>>()
+        .ok_or_else(|| e500("no database pool registered"))?;
+
+    let grant = match token {
+        Some(token) => authenticate_api_token(pool, &token).await.map_err(e500)?,
+        None => None,
+    };
+    let user = match grant {
+        // the role is looked up every time, so demoting a user limits their tokens too
+        Some(grant) => get_user_role(pool.as_ref(), grant.user_id)
+            .await
+            .map_err(e500)?
+            .map(|role| (grant, role)),
+        None => None,
+    };
+    match user {
+        Some((grant, role)) => {
+            req.extensions_mut().insert(UserId(grant.user_id));
+            req.extensions_mut().insert(role);
+            req.extensions_mut().insert(ApiScopes(grant.scopes));
+            next.call(req).await
+        }
+        None => {
+            let mut response = json_error(StatusCode::UNAUTHORIZED, "missing or invalid api token");
+            response.headers_mut().insert(
+                header::WWW_AUTHENTICATE,
+                header::HeaderValue::from_static("Bearer"),
+            );
+            let e = anyhow::anyhow!("missing or invalid api token");
+            Err(InternalError::from_response(e, response).into())
+        }
+    }
+}
+
+/// per-route check layered on top of `reject_anonymous_users` or `reject_invalid_api_tokens`,
+/// which provide the role (and for api requests, the token's scopes):
+/// `web::post().to(handler).wrap(from_fn(require_permission(Permission::ManageUsers)))`
+pub fn require_permission(
+    permission: Permission,
+) -> impl Fn(
+    ServiceRequest,
+    Next<BoxBody>,
+) -> LocalBoxFuture<'static, Result<ServiceResponse<BoxBody>, actix_web::Error>>
+       + Clone {
+    move |req, next| {
+        Box::pin(async move {
+            let (is_allowed, is_api_request) = {
+                let extensions = req.extensions();
+                let scopes = extensions.get::<ApiScopes>();
+                (
+                    extensions
+                        .get::<UserRole>()
+                        .is_some_and(|role| role.can(permission))
+                        && scopes.is_none_or(|scopes| scopes.allows(permission)),
+                    scopes.is_some(),
+                )
+            };
+            if is_allowed {
+                return next.call(req).await;
+            }
+
+            tracing::warn!(?permission, "missing permission");
+            if is_api_request {
+                let message = format!(
+                    "this needs the `{}` scope, on a token of a user allowed to use it",
+                    permission.as_str()
+                );
+                return Ok(req.into_response(json_error(StatusCode::FORBIDDEN, &message)));
+            }
+            FlashMessage::error("You don't have permission to do that").send();
+            // an `Err` would skip the flash message middleware on its way out
+            Ok(req.into_response(see_other("/admin/dashboard")))
+        })
+    }
+}
@@ -1,5 +1,14 @@
 pub mod password;
 pub mod middleware;
+pub mod basic;
+pub mod totp;
+pub mod sessions;
+pub mod password_policy;
+pub mod csrf;
+pub mod api_tokens;
+pub mod oidc;
 
 pub use password::*;
+pub use basic::{basic_authentication, credentials_match};
+pub use password_policy::{check_password_policy, PasswordPolicyError};
 // pub use middleware::;
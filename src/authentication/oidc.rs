@@ -0,0 +1,347 @@
+use anyhow::Context;
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use jsonwebtoken::{jwk::JwkSet, DecodingKey, Validation};
+use rand::{distributions::Alphanumeric, Rng};
+use reqwest::Url;
+use secrecy::{ExposeSecret, Secret};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use sqlx::{Executor, PgPool};
+use uuid::Uuid;
+
+use super::PasswordHashing;
+use crate::{configuration::OidcSettings, domain::UserRole, users::insert_user};
+
+/// the parts of the provider's discovery document we use
+#[derive(Deserialize)]
+struct ProviderMetadata {
+    issuer: String,
+    authorization_endpoint: String,
+    token_endpoint: String,
+    jwks_uri: String,
+}
+
+#[derive(Deserialize)]
+struct TokenResponse {
+    id_token: String,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct IdTokenClaims {
+    pub sub: String,
+    nonce: Option<String>,
+    pub email: Option<String>,
+    #[serde(default)]
+    pub email_verified: bool,
+}
+
+impl IdTokenClaims {
+    /// only an address the provider vouches for can be matched against ours
+    pub fn verified_email(&self) -> Option<String> {
+        self.email
+            .as_deref()
+            .filter(|_| self.email_verified)
+            .map(|email| email.trim().to_lowercase())
+    }
+}
+
+/// kept in the session between the redirect to the provider and the callback
+#[derive(Serialize, Deserialize)]
+pub struct PendingOidcLogin {
+    pub state: String,
+    nonce: String,
+    code_verifier: String,
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum OidcError {
+    /// the provider, or whoever called the callback, didn't prove who the user is
+    #[error("Single sign-on failed")]
+    Rejected(#[source] anyhow::Error),
+    #[error(transparent)]
+    UnexpectedError(#[from] anyhow::Error),
+}
+
+/// authorization code flow with PKCE, against the provider in `OidcSettings`
+pub struct OidcClient {
+    settings: OidcSettings,
+    redirect_uri: String,
+    provisioning_role: UserRole,
+    http_client: reqwest::Client,
+}
+
+impl OidcClient {
+    pub fn new(settings: OidcSettings, base_url: &str) -> Result<Self, anyhow::Error> {
+        let provisioning_role = UserRole::try_from(settings.provisioning_role.clone())
+            .map_err(|e| anyhow::anyhow!("invalid oidc provisioning role: {}", e))?;
+        Ok(Self {
+            redirect_uri: format!("{}/login/oidc/callback", base_url.trim_end_matches('/')),
+            provisioning_role,
+            http_client: reqwest::Client::builder()
+                .timeout(std::time::Duration::from_secs(10))
+                .build()?,
+            settings,
+        })
+    }
+
+    pub fn provider_name(&self) -> &str {
+        &self.settings.provider_name
+    }
+
+    fn issuer(&self) -> &str {
+        self.settings.issuer_url.trim_end_matches('/')
+    }
+
+    /// read on every login rather than cached, so key rotations at the provider
+    /// are picked up without a restart
+    async fn metadata(&self) -> Result<ProviderMetadata, anyhow::Error> {
+        let metadata: ProviderMetadata = self
+            .http_client
+            .get(format!("{}/.well-known/openid-configuration", self.issuer()))
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await
+            .context("failed to read the provider's discovery document")?;
+        if metadata.issuer.trim_end_matches('/') != self.issuer() {
+            anyhow::bail!("discovery document is for another issuer: {}", metadata.issuer);
+        }
+        Ok(metadata)
+    }
+
+    /// where to send the browser, and what to remember until it comes back
+    pub async fn authorization_request(&self) -> Result<(Url, PendingOidcLogin), anyhow::Error> {
+        let metadata = self.metadata().await?;
+        let pending = PendingOidcLogin {
+            state: random_string(32),
+            nonce: random_string(32),
+            code_verifier: random_string(64),
+        };
+        let url = Url::parse_with_params(
+            &metadata.authorization_endpoint,
+            &[
+                ("response_type", "code"),
+                ("client_id", &self.settings.client_id),
+                ("redirect_uri", &self.redirect_uri),
+                ("scope", "openid email"),
+                ("state", &pending.state),
+                ("nonce", &pending.nonce),
+                ("code_challenge", &code_challenge(&pending.code_verifier)),
+                ("code_challenge_method", "S256"),
+            ],
+        )
+        .context("invalid authorization endpoint")?;
+        Ok((url, pending))
+    }
+
+    /// trades the code for an id token and checks it was issued by the
+    /// provider, to us, for this login
+    #[tracing::instrument(name = "complete oidc login", skip_all)]
+    pub async fn exchange_code(
+        &self,
+        code: &str,
+        pending: PendingOidcLogin,
+    ) -> Result<IdTokenClaims, OidcError> {
+        let metadata = self.metadata().await?;
+        let response = self
+            .http_client
+            .post(&metadata.token_endpoint)
+            .basic_auth(
+                &self.settings.client_id,
+                Some(self.settings.client_secret.expose_secret()),
+            )
+            .form(&[
+                ("grant_type", "authorization_code"),
+                ("code", code),
+                ("redirect_uri", &self.redirect_uri),
+                ("code_verifier", &pending.code_verifier),
+            ])
+            .send()
+            .await
+            .context("failed to reach the token endpoint")?;
+        if response.status().is_client_error() {
+            let status = response.status();
+            let body = response.text().await.unwrap_or_default();
+            return Err(OidcError::Rejected(anyhow::anyhow!(
+                "token endpoint refused the code ({}): {}",
+                status,
+                body
+            )));
+        }
+        let tokens: TokenResponse = response
+            .error_for_status()
+            .context("token endpoint failed")?
+            .json()
+            .await
+            .context("failed to read the token response")?;
+
+        let jwks: JwkSet = self
+            .http_client
+            .get(&metadata.jwks_uri)
+            .send()
+            .await
+            .and_then(|r| r.error_for_status())
+            .context("failed to fetch the provider's keys")?
+            .json()
+            .await
+            .context("failed to read the provider's keys")?;
+        let claims = self
+            .verify_id_token(&tokens.id_token, &jwks)
+            .map_err(OidcError::Rejected)?;
+        if claims.nonce.as_deref() != Some(pending.nonce.as_str()) {
+            return Err(OidcError::Rejected(anyhow::anyhow!(
+                "id token was issued for another login"
+            )));
+        }
+        Ok(claims)
+    }
+
+    fn verify_id_token(&self, id_token: &str, jwks: &JwkSet) -> Result<IdTokenClaims, anyhow::Error> {
+        let header = jsonwebtoken::decode_header(id_token).context("malformed id token")?;
+        let jwk = match &header.kid {
+            Some(kid) => jwks.find(kid),
+            None if jwks.keys.len() == 1 => jwks.keys.first(),
+            None => None,
+        }
+        .context("id token is signed with an unknown key")?;
+        let key = DecodingKey::from_jwk(jwk).context("unusable provider key")?;
+
+        // the key has to be of the same family as the algorithm, so a token
+        // can't pass an rsa public key off as an hmac secret
+        let mut validation = Validation::new(header.alg);
+        validation.set_issuer(&[self.issuer(), &format!("{}/", self.issuer())]);
+        validation.set_audience(&[&self.settings.client_id]);
+        validation.set_required_spec_claims(&["exp", "iss", "aud", "sub"]);
+        let token = jsonwebtoken::decode::<IdTokenClaims>(id_token, &key, &validation)
+            .context("invalid id token")?;
+        Ok(token.claims)
+    }
+
+    fn may_provision(&self, email: &str) -> bool {
+        email.rsplit_once('@').is_some_and(|(_, domain)| {
+            self.settings
+                .provisioning_domains
+                .iter()
+                .any(|allowed| allowed.eq_ignore_ascii_case(domain))
+        })
+    }
+
+    /// the user the provider's account is linked to. The first time around the
+    /// link is made through a verified email, creating the user if its domain
+    /// allows it; `None` if there's nobody to link to
+    #[tracing::instrument(name = "resolve oidc identity", skip(self, pool, password_hashing))]
+    pub async fn find_or_provision_user(
+        &self,
+        pool: &PgPool,
+        claims: &IdTokenClaims,
+        password_hashing: &PasswordHashing,
+    ) -> Result<Option<Uuid>, anyhow::Error> {
+        let linked = sqlx::query_scalar!(
+            r#"
+            UPDATE user_identities SET last_login_at = now()
+            WHERE issuer = $1 AND subject = $2
+            RETURNING user_id
+            "#,
+            self.issuer(),
+            claims.sub
+        )
+        .fetch_optional(pool)
+        .await
+        .context("failed to look up oidc identity")?;
+        if linked.is_some() {
+            return Ok(linked);
+        }
+
+        let Some(email) = claims.verified_email() else {
+            return Ok(None);
+        };
+        let existing = sqlx::query_scalar!(
+            "SELECT user_id FROM users WHERE lower(email) = $1",
+            email
+        )
+        .fetch_optional(pool)
+        .await
+        .context("failed to look up user by email")?;
+
+        let mut transaction = pool
+            .begin()
+            .await
+            .context("failed to establish connection to postgres")?;
+        let user_id = match existing {
+            Some(user_id) => user_id,
+            None if self.may_provision(&email) => {
+                // nobody knows this password, signing in with it is impossible
+                // until the user sets one through a password reset
+                let password_hash = {
+                    let password_hashing = password_hashing.clone();
+                    tokio::task::spawn_blocking(move || {
+                        password_hashing.hash(Secret::new(random_string(64)))
+                    })
+                    .await
+                    .context("failed to spawn thread")??
+                };
+                insert_user(
+                    &mut *transaction,
+                    &email,
+                    password_hash.expose_secret(),
+                    &email,
+                    self.provisioning_role,
+                )
+                .await
+                .context("failed to provision user")?
+            }
+            None => return Ok(None),
+        };
+        let n_inserted_rows = transaction
+            .execute(sqlx::query!(
+                r#"
+                INSERT INTO user_identities(issuer, subject, user_id, created_at, last_login_at)
+                VALUES ($1, $2, $3, now(), now())
+                ON CONFLICT DO NOTHING
+                "#,
+                self.issuer(),
+                claims.sub,
+                user_id
+            ))
+            .await
+            .context("failed to link oidc identity")?
+            .rows_affected();
+        if n_inserted_rows == 0 {
+            // the user is already linked to another account at the provider
+            return Ok(None);
+        }
+        transaction
+            .commit()
+            .await
+            .context("failed to commit postgres transaction")?;
+        Ok(Some(user_id))
+    }
+}
+
+fn random_string(len: usize) -> String {
+    rand::thread_rng()
+        .sample_iter(Alphanumeric)
+        .map(char::from)
+        .take(len)
+        .collect()
+}
+
+/// S256, see RFC 7636
+fn code_challenge(code_verifier: &str) -> String {
+    URL_SAFE_NO_PAD.encode(Sha256::digest(code_verifier.as_bytes()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::code_challenge;
+
+    #[test]
+    fn code_challenge_matches_the_rfc_example() {
+        // appendix B of RFC 7636
+        assert_eq!(
+            code_challenge("dBjftJeZ4CVP-mB92K27uhbUJU1p1r_wW1gFWFOEjXk"),
+            "E9Melhoa2OwvFrEMTJguCHaoeK1t8URWbuGJSstw-cM"
+        );
+    }
+}
@@ -5,9 +5,11 @@ use argon2::{
 };
 use rand::rngs::OsRng;
 use secrecy::{ExposeSecret, Secret};
-use sqlx::PgPool;
+use sqlx::{PgExecutor, PgPool};
 use uuid::Uuid;
 
+use crate::configuration::PasswordHashingSettings;
+
 #[derive(thiserror::Error, Debug)]
 pub enum AuthError {
     #[error("Authentication Failed")]
@@ -30,18 +32,59 @@ pub struct Credentials {
     pub password: Secret<String>,
 }
 
-/// validation fn simulating same level of work if no user found
+/// argon2id with the configured cost parameters; built once at startup
+#[derive(Clone)]
+pub struct PasswordHashing {
+    params: Params,
+    /// checked against when the username doesn't exist, so that takes as long as a wrong
+    /// password does; made with the current params for the same reason
+    dummy_hash: Secret<String>,
+}
+
+impl PasswordHashing {
+    pub fn new(settings: &PasswordHashingSettings) -> Result<Self, anyhow::Error> {
+        let params = settings
+            .params()
+            .map_err(|e| anyhow::anyhow!("invalid argon2 parameters: {}", e))?;
+        let dummy_hash = compute_password_hash(Secret::new(Uuid::new_v4().to_string()), &params)?;
+        Ok(Self { params, dummy_hash })
+    }
+
+    pub fn hash(&self, password: Secret<String>) -> Result<Secret<String>, anyhow::Error> {
+        compute_password_hash(password, &self.params)
+    }
+
+    /// true for hashes made with another algorithm, an older argon2 version or
+    /// weaker parameters than the configured ones
+    pub fn needs_rehash(&self, hash: &Secret<String>) -> bool {
+        let Ok(hash) = PasswordHash::new(hash.expose_secret()) else {
+            return true;
+        };
+        if hash.algorithm != argon2::Algorithm::Argon2id.ident()
+            || hash.version != Some(argon2::Version::V0x13.into())
+        {
+            return true;
+        }
+        match Params::try_from(&hash) {
+            Ok(params) => {
+                params.m_cost() < self.params.m_cost()
+                    || params.t_cost() < self.params.t_cost()
+                    || params.p_cost() < self.params.p_cost()
+            }
+            Err(_) => true,
+        }
+    }
+}
+
+/// validation fn simulating same level of work if no user found;
+/// hashes made with outdated parameters are upgraded along the way
 pub async fn validate_credentials(
     credentials: Credentials,
     db_pool: &PgPool,
+    hashing: &PasswordHashing,
 ) -> Result<uuid::Uuid, AuthError> {
     // random hash requiring same amount of work to prevent against timing attacks
-    let mut expected_password_hash = Secret::new(
-        "$argon2id$v=19$m=15000,t=2,p=1$\
-        gZiV/M1gPc22ElAH/Jh1Hw$\
-        CWOrkoo7oJBQ/iyh7uJ0LO2aLEfrHwTWllSAxT0zRno"
-            .to_string(),
-    );
+    let mut expected_password_hash = hashing.dummy_hash.clone();
     let mut user_id = None;
 
     if let Some((stored_user_id, stored_password_hash)) =
@@ -52,16 +95,38 @@ pub async fn validate_credentials(
     }
 
     // compute hash in blocking thread
-    let _ = tokio::task::spawn_blocking(move || {
-        verify_password_hash(credentials.password, expected_password_hash)
+    let hashing = hashing.clone();
+    let is_known_user = user_id.is_some();
+    let upgraded_hash = tokio::task::spawn_blocking(move || {
+        verify_password_hash(credentials.password.clone(), expected_password_hash.clone())?;
+        if !(is_known_user && hashing.needs_rehash(&expected_password_hash)) {
+            return Ok(None);
+        }
+        // the password was right, failing to upgrade its hash shouldn't stop the login
+        Ok::<_, AuthError>(
+            hashing
+                .hash(credentials.password)
+                .inspect_err(|e| tracing::warn!(error = ?e, "failed to rehash password"))
+                .ok()
+                .map(|new_hash| (expected_password_hash, new_hash)),
+        )
     })
     .await
     .context("Failed to spawn thread")
     .map_err(AuthError::UnexpectedError)??;
 
-    user_id
+    let user_id = user_id
         .ok_or_else(|| anyhow::anyhow!("Incorrect username"))
-        .map_err(AuthError::InvalidCredentials)
+        .map_err(AuthError::InvalidCredentials)?;
+
+    if let Some((verified_hash, new_hash)) = upgraded_hash {
+        match upgrade_password_hash(user_id, &verified_hash, &new_hash, db_pool).await {
+            Ok(true) => tracing::info!(%user_id, "upgraded password hash parameters"),
+            Ok(false) => tracing::info!(%user_id, "password changed while it was rehashed"),
+            Err(e) => tracing::warn!(error = ?e, "failed to store rehashed password"),
+        }
+    }
+    Ok(user_id)
 }
 
 pub async fn get_stored_credentials(
@@ -85,7 +150,7 @@ pub fn verify_password_hash(
     password: Secret<String>,
     expected_hash: Secret<String>,
 ) -> Result<(), AuthError> {
-    let expected_password_hash = PasswordHash::new(&expected_hash.expose_secret())
+    let expected_password_hash = PasswordHash::new(expected_hash.expose_secret())
         .context("Failed to parse password into PHC format")
         .map_err(AuthError::UnexpectedError)?;
 
@@ -95,11 +160,14 @@ pub fn verify_password_hash(
         .map_err(AuthError::InvalidCredentials)
 }
 
-fn compute_password_hash(password: Secret<String>) -> Result<Secret<String>, anyhow::Error> {
+pub fn compute_password_hash(
+    password: Secret<String>,
+    params: &Params,
+) -> Result<Secret<String>, anyhow::Error> {
     let argon2 = Argon2::new(
         argon2::Algorithm::Argon2id,
         argon2::Version::V0x13,
-        Params::new(15000, 2, 1, None).unwrap(),
+        params.clone(),
     );
     let salt = SaltString::generate(&mut OsRng);
     let hash = argon2
@@ -114,22 +182,99 @@ pub async fn change_password(
     user_id: Uuid,
     password: Secret<String>,
     db_pool: &PgPool,
+    hashing: &PasswordHashing,
 ) -> Result<(), anyhow::Error> {
-    let password_hash = tokio::task::spawn_blocking(move || compute_password_hash(password))
+    let password_hash = hash_new_password(password, hashing).await?;
+    store_password_hash(user_id, &password_hash, db_pool).await
+}
+
+/// hashes off the async runtime, for callers that store the hash themselves
+pub async fn hash_new_password(
+    password: Secret<String>,
+    hashing: &PasswordHashing,
+) -> Result<Secret<String>, anyhow::Error> {
+    let hashing = hashing.clone();
+    let password_hash = tokio::task::spawn_blocking(move || hashing.hash(password))
         .await?
         .context("Failed to spawn thread")
         .map_err(AuthError::UnexpectedError)?;
+    Ok(password_hash)
+}
 
-    // update db
+pub async fn store_password_hash<'e>(
+    user_id: Uuid,
+    password_hash: &Secret<String>,
+    executor: impl PgExecutor<'e>,
+) -> Result<(), anyhow::Error> {
     sqlx::query!(
         "update users set password_hash=$1 where user_id=$2",
         password_hash.expose_secret(),
         user_id
     )
-    .execute(db_pool)
+    .execute(executor)
     .await
     .context("failed to update user's password")?;
 
     Ok(())
 }
 
+/// only replaces the hash that was verified: if the password was changed or
+/// reset meanwhile, writing a hash of the old one would bring it back to life
+async fn upgrade_password_hash(
+    user_id: Uuid,
+    verified_hash: &Secret<String>,
+    new_hash: &Secret<String>,
+    db_pool: &PgPool,
+) -> Result<bool, anyhow::Error> {
+    let result = sqlx::query!(
+        "update users set password_hash=$1 where user_id=$2 and password_hash=$3",
+        new_hash.expose_secret(),
+        user_id,
+        verified_hash.expose_secret()
+    )
+    .execute(db_pool)
+    .await
+    .context("failed to update user's password")?;
+
+    Ok(result.rows_affected() > 0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{compute_password_hash, PasswordHashing};
+    use crate::configuration::PasswordHashingSettings;
+    use argon2::Params;
+    use secrecy::Secret;
+
+    fn hashing(memory_kib: u32, iterations: u32) -> PasswordHashing {
+        PasswordHashing::new(&PasswordHashingSettings {
+            memory_kib,
+            iterations,
+            parallelism: 1,
+        })
+        .unwrap()
+    }
+
+    #[test]
+    fn hashes_with_weaker_params_need_rehashing() {
+        let hashing = hashing(8192, 2);
+        let password = || Secret::new("correct horse".to_string());
+
+        let current = hashing.hash(password()).unwrap();
+        assert!(!hashing.needs_rehash(&current));
+        let weaker = compute_password_hash(password(), &Params::new(4096, 2, 1, None).unwrap())
+            .unwrap();
+        assert!(hashing.needs_rehash(&weaker));
+        let stronger = compute_password_hash(password(), &Params::new(8192, 3, 1, None).unwrap())
+            .unwrap();
+        assert!(!hashing.needs_rehash(&stronger));
+    }
+
+    #[test]
+    fn other_algorithms_need_rehashing() {
+        let hashing = hashing(8192, 2);
+        let argon2i = "$argon2i$v=19$m=8192,t=2,p=1$c29tZXNhbHQ$QGzWqXCXeeBbNjdwvf7wAQ8sa2uzsH1bm9YVj3ALHi8";
+        assert!(hashing.needs_rehash(&Secret::new(argon2i.to_string())));
+        assert!(hashing.needs_rehash(&Secret::new("not a hash".to_string())));
+    }
+}
@@ -0,0 +1,115 @@
+use std::{collections::HashSet, sync::LazyLock};
+
+use secrecy::{ExposeSecret, Secret};
+
+use crate::configuration::PasswordPolicySettings;
+
+static COMMON_PASSWORDS: LazyLock<HashSet<&'static str>> = LazyLock::new(|| {
+    include_str!("common_passwords.txt")
+        .lines()
+        .map(str::trim)
+        .filter(|l| !l.is_empty() && !l.starts_with('#'))
+        .collect()
+});
+
+/// the messages are shown to the user as is
+#[derive(thiserror::Error, Debug, PartialEq)]
+pub enum PasswordPolicyError {
+    #[error("Passwords must be at least {0} characters long")]
+    TooShort(usize),
+    #[error("Passwords must be at most {0} characters long")]
+    TooLong(usize),
+    #[error("Passwords can't contain your username")]
+    ContainsUsername,
+    #[error("That password is on a list of commonly used passwords")]
+    Common,
+    #[error("That password is too easy to guess{}", .0.as_deref().map(|hint| format!(": {}", hint)).unwrap_or_default())]
+    TooWeak(Option<String>),
+}
+
+/// cheapest checks first, the strength estimate is comparatively slow
+pub fn check_password_policy(
+    policy: &PasswordPolicySettings,
+    password: &Secret<String>,
+    username: &str,
+) -> Result<(), PasswordPolicyError> {
+    let password = password.expose_secret();
+
+    let length = password.chars().count();
+    if length < policy.min_length {
+        return Err(PasswordPolicyError::TooShort(policy.min_length));
+    }
+    // hashing is expensive enough without megabyte passwords
+    if length > policy.max_length {
+        return Err(PasswordPolicyError::TooLong(policy.max_length));
+    }
+
+    let lowercase_password = password.to_lowercase();
+    let username = username.trim().to_lowercase();
+    if !username.is_empty() && lowercase_password.contains(&username) {
+        return Err(PasswordPolicyError::ContainsUsername);
+    }
+    if COMMON_PASSWORDS.contains(lowercase_password.as_str()) {
+        return Err(PasswordPolicyError::Common);
+    }
+
+    let estimate = zxcvbn::zxcvbn(password, &[&username, "zero2prod", "newsletter"]);
+    if u8::from(estimate.score()) < policy.min_strength {
+        let hint = estimate
+            .feedback()
+            .and_then(|f| f.warning())
+            .map(|w| w.to_string());
+        return Err(PasswordPolicyError::TooWeak(hint));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{check_password_policy, PasswordPolicyError};
+    use crate::configuration::PasswordPolicySettings;
+    use claims::{assert_err_eq, assert_ok};
+    use secrecy::Secret;
+
+    fn policy() -> PasswordPolicySettings {
+        PasswordPolicySettings {
+            min_length: 12,
+            max_length: 128,
+            min_strength: 3,
+        }
+    }
+
+    fn check(password: &str, username: &str) -> Result<(), PasswordPolicyError> {
+        check_password_policy(&policy(), &Secret::new(password.to_string()), username)
+    }
+
+    #[test]
+    fn length_limits_are_enforced() {
+        assert_err_eq!(check("short", "ursula"), PasswordPolicyError::TooShort(12));
+        assert_err_eq!(
+            check(&"a".repeat(129), "ursula"),
+            PasswordPolicyError::TooLong(128)
+        );
+    }
+
+    #[test]
+    fn username_and_common_passwords_are_rejected() {
+        assert_err_eq!(
+            check("my-name-is-Ursula-LeGuin", "ursula"),
+            PasswordPolicyError::ContainsUsername
+        );
+        assert_err_eq!(
+            check("correcthorsebatterystaple", "ursula"),
+            PasswordPolicyError::Common
+        );
+    }
+
+    #[test]
+    fn guessable_passwords_are_too_weak() {
+        assert!(matches!(
+            check("aaaaaaaaaaaaaaaa", "ursula"),
+            Err(PasswordPolicyError::TooWeak(_))
+        ));
+        assert_ok!(check("lathe of heaven, 1971 edition", "ursula"));
+    }
+}
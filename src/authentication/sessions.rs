@@ -0,0 +1,160 @@
+use actix_web::{http::header, HttpRequest};
+use chrono::{DateTime, Utc};
+use sqlx::{PgExecutor, PgPool};
+use uuid::Uuid;
+
+use crate::utils::client_ip;
+
+/// how long the session state lives in redis after login, sessions older than
+/// this are gone even if their row was never revoked
+pub const SESSION_TTL_HOURS: i64 = 24;
+
+pub struct UserSession {
+    pub session_id: Uuid,
+    pub created_at: DateTime<Utc>,
+    pub last_seen_at: DateTime<Utc>,
+    pub ip_address: String,
+    pub user_agent: Option<String>,
+}
+
+/// where a login came from, shown on the sessions page
+pub struct SessionMetadata {
+    pub ip_address: String,
+    pub user_agent: Option<String>,
+}
+
+impl SessionMetadata {
+    pub fn from_request(request: &HttpRequest) -> Self {
+        Self {
+            ip_address: client_ip(request).unwrap_or_else(|| "unknown".into()),
+            user_agent: request
+                .headers()
+                .get(header::USER_AGENT)
+                .and_then(|h| h.to_str().ok())
+                .map(|ua| ua.chars().take(512).collect()),
+        }
+    }
+}
+
+/// returns the id to keep in the session cookie's state
+#[tracing::instrument(name = "register session", skip(pool, metadata))]
+pub async fn register_session(
+    pool: &PgPool,
+    user_id: Uuid,
+    metadata: &SessionMetadata,
+) -> Result<Uuid, sqlx::Error> {
+    let session_id = Uuid::new_v4();
+    let mut transaction = pool.begin().await?;
+    // housekeeping, the redis side of these expired already
+    sqlx::query!(
+        r#"
+        DELETE FROM user_sessions
+        WHERE user_id = $1 AND created_at < now() - make_interval(hours => $2)
+        "#,
+        user_id,
+        SESSION_TTL_HOURS as i32
+    )
+    .execute(&mut *transaction)
+    .await?;
+    sqlx::query!(
+        r#"
+        INSERT INTO user_sessions(
+            session_id, user_id, created_at, last_seen_at, ip_address, user_agent, revoked_at
+        )
+        VALUES ($1, $2, now(), now(), $3, $4, NULL)
+        "#,
+        session_id,
+        user_id,
+        metadata.ip_address,
+        metadata.user_agent,
+    )
+    .execute(&mut *transaction)
+    .await?;
+    transaction.commit().await?;
+    Ok(session_id)
+}
+
+/// bumps `last_seen_at`; false if the session was revoked or isn't the user's
+pub async fn touch_session<'e>(
+    executor: impl PgExecutor<'e>,
+    user_id: Uuid,
+    session_id: Uuid,
+) -> Result<bool, sqlx::Error> {
+    let n_updated_rows = sqlx::query!(
+        r#"
+        UPDATE user_sessions SET last_seen_at = now()
+        WHERE session_id = $1 AND user_id = $2 AND revoked_at IS NULL
+        "#,
+        session_id,
+        user_id
+    )
+    .execute(executor)
+    .await?
+    .rows_affected();
+    Ok(n_updated_rows == 1)
+}
+
+/// most recently used first
+pub async fn list_active_sessions<'e>(
+    executor: impl PgExecutor<'e>,
+    user_id: Uuid,
+) -> Result<Vec<UserSession>, sqlx::Error> {
+    sqlx::query_as!(
+        UserSession,
+        r#"
+        SELECT session_id, created_at, last_seen_at, ip_address, user_agent
+        FROM user_sessions
+        WHERE user_id = $1
+          AND revoked_at IS NULL
+          AND created_at >= now() - make_interval(hours => $2)
+        ORDER BY last_seen_at DESC
+        "#,
+        user_id,
+        SESSION_TTL_HOURS as i32
+    )
+    .fetch_all(executor)
+    .await
+}
+
+/// false if the session doesn't belong to the user or had already ended
+#[tracing::instrument(name = "revoke session", skip(executor))]
+pub async fn revoke_session<'e>(
+    executor: impl PgExecutor<'e>,
+    user_id: Uuid,
+    session_id: Uuid,
+) -> Result<bool, sqlx::Error> {
+    let n_updated_rows = sqlx::query!(
+        r#"
+        UPDATE user_sessions SET revoked_at = now()
+        WHERE session_id = $1 AND user_id = $2 AND revoked_at IS NULL
+        "#,
+        session_id,
+        user_id
+    )
+    .execute(executor)
+    .await?
+    .rows_affected();
+    Ok(n_updated_rows == 1)
+}
+
+/// logs the user out everywhere, or everywhere but `except`
+#[tracing::instrument(name = "revoke user sessions", skip(executor))]
+pub async fn revoke_user_sessions<'e>(
+    executor: impl PgExecutor<'e>,
+    user_id: Uuid,
+    except: Option<Uuid>,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+        UPDATE user_sessions SET revoked_at = now()
+        WHERE user_id = $1
+          AND revoked_at IS NULL
+          AND ($2::uuid IS NULL OR session_id <> $2)
+        "#,
+        user_id,
+        except
+    )
+    .execute(executor)
+    .await?;
+    Ok(())
+}
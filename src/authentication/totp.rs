@@ -0,0 +1,338 @@
+use anyhow::Context;
+use chrono::Utc;
+use qrcode::{render::svg, QrCode};
+use rand::{distributions::Alphanumeric, Rng};
+use secrecy::{ExposeSecret, Secret};
+use sqlx::{Executor, PgPool, Postgres, Transaction};
+use totp_rs::{Algorithm, TOTP};
+use uuid::Uuid;
+
+use super::{verify_password_hash, PasswordHashing};
+use crate::utils::constant_time_eq;
+
+const ISSUER: &str = "zero2prod";
+const DIGITS: usize = 6;
+const STEP_SECONDS: u64 = 30;
+/// codes from one step either side are accepted, to allow for clock drift
+const SKEW_STEPS: i64 = 1;
+const N_RECOVERY_CODES: usize = 10;
+
+pub struct UserTotp {
+    /// base32
+    pub secret: Secret<String>,
+    pub confirmed: bool,
+    pub last_used_step: Option<i64>,
+}
+
+/// a fresh base32 secret to enrol with
+pub fn generate_totp_secret() -> String {
+    match totp_rs::Secret::generate_secret().to_encoded() {
+        totp_rs::Secret::Encoded(secret) => secret,
+        totp_rs::Secret::Raw(_) => unreachable!("secret was just encoded"),
+    }
+}
+
+fn totp(secret: &Secret<String>, username: &str) -> Result<TOTP, anyhow::Error> {
+    let bytes = totp_rs::Secret::Encoded(secret.expose_secret().clone())
+        .to_bytes()
+        .map_err(|e| anyhow::anyhow!("invalid totp secret: {:?}", e))?;
+    TOTP::new(
+        Algorithm::SHA1,
+        DIGITS,
+        0,
+        STEP_SECONDS,
+        bytes,
+        Some(ISSUER.to_string()),
+        username.replace(':', ""),
+    )
+    .map_err(|e| anyhow::anyhow!("invalid totp parameters: {:?}", e))
+}
+
+/// the `otpauth://` uri authenticator apps enrol from
+pub fn provisioning_uri(secret: &Secret<String>, username: &str) -> Result<String, anyhow::Error> {
+    Ok(totp(secret, username)?.get_url())
+}
+
+pub fn qr_code_svg(uri: &str) -> Result<String, anyhow::Error> {
+    let code = QrCode::new(uri).context("failed to encode provisioning uri")?;
+    Ok(code
+        .render::<svg::Color>()
+        .min_dimensions(200, 200)
+        .build())
+}
+
+/// returns the time step the code belongs to, callers reject steps at or
+/// before the last one used so that a code can't be replayed
+pub fn verify_totp_code(
+    secret: &Secret<String>,
+    code: &str,
+    last_used_step: Option<i64>,
+) -> Result<Option<i64>, anyhow::Error> {
+    let totp = totp(secret, "")?;
+    let current_step = Utc::now().timestamp() / STEP_SECONDS as i64;
+
+    let step = (current_step - SKEW_STEPS..=current_step + SKEW_STEPS)
+        .filter(|step| last_used_step.is_none_or(|last| *step > last))
+        .find(|step| {
+            let expected = totp.generate(*step as u64 * STEP_SECONDS);
+            // the code is a secret until it is used
+            constant_time_eq(expected.as_bytes(), code.as_bytes())
+        });
+    Ok(step)
+}
+
+#[tracing::instrument(name = "get totp settings", skip(pool))]
+pub async fn get_user_totp(pool: &PgPool, user_id: Uuid) -> Result<Option<UserTotp>, anyhow::Error> {
+    let row = sqlx::query!(
+        "SELECT secret, confirmed_at, last_used_step FROM user_totp WHERE user_id = $1",
+        user_id
+    )
+    .fetch_optional(pool)
+    .await
+    .context("failed to fetch totp settings")?;
+
+    Ok(row.map(|r| UserTotp {
+        secret: Secret::new(r.secret),
+        confirmed: r.confirmed_at.is_some(),
+        last_used_step: r.last_used_step,
+    }))
+}
+
+pub async fn has_confirmed_totp(pool: &PgPool, user_id: Uuid) -> Result<bool, anyhow::Error> {
+    Ok(get_user_totp(pool, user_id)
+        .await?
+        .is_some_and(|totp| totp.confirmed))
+}
+
+/// replaces any enrolment that wasn't confirmed yet
+pub async fn start_totp_enrolment(
+    pool: &PgPool,
+    user_id: Uuid,
+    secret: &str,
+) -> Result<(), anyhow::Error> {
+    sqlx::query!(
+        r#"
+        INSERT INTO user_totp(user_id, secret, confirmed_at, last_used_step, created_at)
+        VALUES ($1, $2, NULL, NULL, now())
+        ON CONFLICT (user_id) DO UPDATE
+        SET secret = EXCLUDED.secret, created_at = EXCLUDED.created_at
+        WHERE user_totp.confirmed_at IS NULL
+        "#,
+        user_id,
+        secret
+    )
+    .execute(pool)
+    .await
+    .context("failed to store totp secret")?;
+    Ok(())
+}
+
+/// false if the step, or a later one, was used in the meantime
+pub async fn mark_totp_step_used(
+    transaction: &mut Transaction<'_, Postgres>,
+    user_id: Uuid,
+    step: i64,
+) -> Result<bool, sqlx::Error> {
+    let n_updated_rows = transaction
+        .execute(sqlx::query!(
+            r#"
+            UPDATE user_totp SET last_used_step = $2
+            WHERE user_id = $1 AND (last_used_step IS NULL OR last_used_step < $2)
+            "#,
+            user_id,
+            step
+        ))
+        .await?
+        .rows_affected();
+    Ok(n_updated_rows == 1)
+}
+
+/// confirms the enrolment and returns the recovery codes, which are only
+/// ever shown this once
+pub async fn confirm_totp_enrolment(
+    pool: &PgPool,
+    user_id: Uuid,
+    step: i64,
+    password_hashing: &PasswordHashing,
+) -> Result<Vec<String>, anyhow::Error> {
+    let codes: Vec<String> = (0..N_RECOVERY_CODES).map(|_| generate_recovery_code()).collect();
+    let hashes = {
+        let codes = codes.clone();
+        let password_hashing = password_hashing.clone();
+        tokio::task::spawn_blocking(move || {
+            codes
+                .into_iter()
+                .map(|c| password_hashing.hash(Secret::new(c)))
+                .collect::<Result<Vec<_>, _>>()
+        })
+        .await
+        .context("failed to spawn thread")??
+    };
+
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("failed to establish connection to postgres")?;
+    transaction
+        .execute(sqlx::query!(
+            "UPDATE user_totp SET confirmed_at = now() WHERE user_id = $1",
+            user_id
+        ))
+        .await
+        .context("failed to confirm totp enrolment")?;
+    mark_totp_step_used(&mut transaction, user_id, step).await?;
+    transaction
+        .execute(sqlx::query!(
+            "DELETE FROM totp_recovery_codes WHERE user_id = $1",
+            user_id
+        ))
+        .await?;
+    for hash in hashes {
+        transaction
+            .execute(sqlx::query!(
+                r#"
+                INSERT INTO totp_recovery_codes(recovery_code_id, user_id, code_hash, used_at)
+                VALUES ($1, $2, $3, NULL)
+                "#,
+                Uuid::new_v4(),
+                user_id,
+                hash.expose_secret()
+            ))
+            .await
+            .context("failed to store recovery code")?;
+    }
+    transaction
+        .commit()
+        .await
+        .context("failed to commit postgres transaction")?;
+
+    Ok(codes)
+}
+
+pub async fn disable_totp(pool: &PgPool, user_id: Uuid) -> Result<(), anyhow::Error> {
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("failed to establish connection to postgres")?;
+    transaction
+        .execute(sqlx::query!(
+            "DELETE FROM totp_recovery_codes WHERE user_id = $1",
+            user_id
+        ))
+        .await?;
+    transaction
+        .execute(sqlx::query!("DELETE FROM user_totp WHERE user_id = $1", user_id))
+        .await?;
+    transaction
+        .commit()
+        .await
+        .context("failed to commit postgres transaction")?;
+    Ok(())
+}
+
+/// `xxxxx-xxxxx`, lowercase so they're easy to type
+fn generate_recovery_code() -> String {
+    let chars: String = rand::thread_rng()
+        .sample_iter(Alphanumeric)
+        .map(|c| char::from(c).to_ascii_lowercase())
+        .take(10)
+        .collect();
+    format!("{}-{}", &chars[..5], &chars[5..])
+}
+
+/// marks the matching recovery code used; false if none matched
+#[tracing::instrument(name = "use recovery code", skip(pool, code))]
+pub async fn use_recovery_code(
+    pool: &PgPool,
+    user_id: Uuid,
+    code: &str,
+) -> Result<bool, anyhow::Error> {
+    let candidates = sqlx::query!(
+        "SELECT recovery_code_id, code_hash FROM totp_recovery_codes WHERE user_id = $1 AND used_at IS NULL",
+        user_id
+    )
+    .fetch_all(pool)
+    .await
+    .context("failed to fetch recovery codes")?;
+
+    let code = code.trim().to_lowercase();
+    let matched = tokio::task::spawn_blocking(move || {
+        candidates.into_iter().find_map(|c| {
+            verify_password_hash(Secret::new(code.clone()), Secret::new(c.code_hash))
+                .ok()
+                .map(|_| c.recovery_code_id)
+        })
+    })
+    .await
+    .context("failed to spawn thread")?;
+
+    let Some(recovery_code_id) = matched else {
+        return Ok(false);
+    };
+    // the `used_at` check makes concurrent uses of the same code fail
+    let n_updated_rows = sqlx::query!(
+        "UPDATE totp_recovery_codes SET used_at = now() WHERE recovery_code_id = $1 AND used_at IS NULL",
+        recovery_code_id
+    )
+    .execute(pool)
+    .await
+    .context("failed to mark recovery code used")?
+    .rows_affected();
+
+    Ok(n_updated_rows == 1)
+}
+
+/// six digits are checked as a TOTP code, anything else as a recovery code
+pub async fn verify_second_factor(
+    pool: &PgPool,
+    user_id: Uuid,
+    code: &str,
+) -> Result<bool, anyhow::Error> {
+    if !(code.len() == 6 && code.chars().all(|c| c.is_ascii_digit())) {
+        return use_recovery_code(pool, user_id, code).await;
+    }
+
+    let Some(totp) = get_user_totp(pool, user_id).await? else {
+        return Ok(false);
+    };
+    let Some(step) = verify_totp_code(&totp.secret, code, totp.last_used_step)? else {
+        return Ok(false);
+    };
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("failed to establish connection to postgres")?;
+    let is_unused = mark_totp_step_used(&mut transaction, user_id, step)
+        .await
+        .context("failed to record used totp step")?;
+    transaction
+        .commit()
+        .await
+        .context("failed to commit postgres transaction")?;
+    Ok(is_unused)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{generate_totp_secret, totp, verify_totp_code, STEP_SECONDS};
+    use chrono::Utc;
+    use secrecy::Secret;
+
+    #[test]
+    fn current_code_is_accepted_once() {
+        let secret = Secret::new(generate_totp_secret());
+        let now = Utc::now().timestamp() as u64;
+        let code = totp(&secret, "admin").unwrap().generate(now);
+
+        let step = verify_totp_code(&secret, &code, None).unwrap();
+        assert_eq!(step, Some((now / STEP_SECONDS) as i64));
+        assert_eq!(verify_totp_code(&secret, &code, step).unwrap(), None);
+    }
+
+    #[test]
+    fn wrong_codes_are_rejected() {
+        let secret = Secret::new(generate_totp_secret());
+        assert_eq!(verify_totp_code(&secret, "12345", None).unwrap(), None);
+        assert_eq!(verify_totp_code(&secret, "not a code", None).unwrap(), None);
+    }
+}
@@ -3,7 +3,7 @@ use secrecy::{ExposeSecret, Secret};
 use serde::Deserialize;
 use serde_aux::field_attributes::deserialize_number_from_string;
 use sqlx::postgres::{PgConnectOptions, PgSslMode};
-use std::time::Duration;
+use std::{net::IpAddr, time::Duration};
 
 use crate::{domain::SubscriberEmail, email_client::EmailClient};
 
@@ -13,6 +13,155 @@ pub struct Settings {
     pub app: ApplicationSettings,
     pub email_client: EmailClientSettings,
     pub redis_uri: Secret<String>,
+    pub webhooks: WebhookSettings,
+    pub rate_limit: RateLimitSettings,
+    pub signup: SignupSettings,
+    pub login: LoginSettings,
+    pub password_policy: PasswordPolicySettings,
+    pub password_hashing: PasswordHashingSettings,
+    pub idempotency: IdempotencySettings,
+    /// single sign-on is only offered when this is set
+    pub oidc: Option<OidcSettings>,
+}
+
+#[derive(Clone, Deserialize, Debug)]
+pub struct RateLimitSettings {
+    /// keeps counters apart when several deployments share one redis
+    pub key_prefix: String,
+}
+
+#[derive(Clone, Deserialize, Debug)]
+pub struct SignupSettings {
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub max_attempts_per_ip: u64,
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub max_attempts_per_email: u64,
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub window_seconds: u64,
+    pub block_disposable_domains: bool,
+}
+
+/// failed logins are counted per username and per ip; reaching either limit
+/// locks further attempts out, and every failure is answered a bit more slowly
+#[derive(Clone, Deserialize, Debug)]
+pub struct LoginSettings {
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub max_failures_per_username: u64,
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub max_failures_per_ip: u64,
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub window_seconds: u64,
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub lockout_seconds: u64,
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub base_delay_milliseconds: u64,
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub max_delay_milliseconds: u64,
+}
+
+/// applies wherever a password is chosen: password changes, resets and invitations
+#[derive(Clone, Deserialize, Debug)]
+pub struct PasswordPolicySettings {
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub min_length: usize,
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub max_length: usize,
+    /// zxcvbn score from 0 (guessable in a thousand tries) to 4 (very unguessable)
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub min_strength: u8,
+}
+
+/// argon2id cost parameters for new password hashes; stored hashes made with weaker
+/// ones are upgraded the next time their owner logs in
+#[derive(Clone, Deserialize, Debug)]
+pub struct PasswordHashingSettings {
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub memory_kib: u32,
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub iterations: u32,
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub parallelism: u32,
+}
+
+/// an OpenID Connect provider admins can sign in with instead of a password
+#[derive(Clone, Deserialize, Debug)]
+pub struct OidcSettings {
+    /// shown on the login page, e.g. "Sign in with Acme SSO"
+    pub provider_name: String,
+    /// the discovery document is read from `{issuer_url}/.well-known/openid-configuration`
+    pub issuer_url: String,
+    pub client_id: String,
+    pub client_secret: Secret<String>,
+    /// people with a verified email at one of these domains get an account the
+    /// first time they sign in; everyone else needs an existing account with that email
+    #[serde(default)]
+    pub provisioning_domains: Vec<String>,
+    /// role given to those accounts
+    pub provisioning_role: String,
+}
+
+/// how long idempotency keys are remembered, and how long a request may keep
+/// one busy before it is presumed dead and a retry may take over
+#[derive(Clone, Deserialize, Debug)]
+pub struct IdempotencySettings {
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub ttl_seconds: u64,
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub processing_timeout_seconds: u64,
+    /// how long a duplicate of a request that's still being handled waits for
+    /// its response, before being told to retry later
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub in_progress_wait_milliseconds: u64,
+}
+
+impl IdempotencySettings {
+    pub fn ttl(&self) -> Duration {
+        Duration::from_secs(self.ttl_seconds)
+    }
+
+    pub fn processing_timeout(&self) -> Duration {
+        Duration::from_secs(self.processing_timeout_seconds)
+    }
+
+    pub fn in_progress_wait(&self) -> Duration {
+        Duration::from_millis(self.in_progress_wait_milliseconds)
+    }
+}
+
+impl PasswordHashingSettings {
+    pub fn params(&self) -> Result<argon2::Params, argon2::Error> {
+        argon2::Params::new(self.memory_kib, self.iterations, self.parallelism, None)
+    }
+}
+
+impl LoginSettings {
+    pub fn window(&self) -> Duration {
+        Duration::from_secs(self.window_seconds)
+    }
+
+    pub fn lockout(&self) -> Duration {
+        Duration::from_secs(self.lockout_seconds)
+    }
+
+    /// doubles with every failure, up to the configured maximum
+    pub fn delay_after(&self, n_failures: u64) -> Duration {
+        let factor = 2u64.saturating_pow(n_failures.saturating_sub(1).min(32) as u32);
+        let delay = self.base_delay_milliseconds.saturating_mul(factor);
+        Duration::from_millis(delay.min(self.max_delay_milliseconds))
+    }
+}
+
+impl SignupSettings {
+    pub fn window(&self) -> Duration {
+        Duration::from_secs(self.window_seconds)
+    }
+}
+
+/// the email provider calls us back with these as basic auth credentials
+#[derive(Clone, Deserialize, Debug)]
+pub struct WebhookSettings {
+    pub postmark_username: String,
+    pub postmark_password: Secret<String>,
 }
 
 #[derive(Clone, Deserialize, Debug,)]
@@ -49,6 +198,10 @@ pub struct ApplicationSettings {
     pub host: String,
     pub base_url: String,
     pub hmac_secret: Secret<String>,
+    /// reverse proxies whose forwarded-for headers are believed; requests from
+    /// anywhere else are attributed to the address that connected
+    #[serde(default)]
+    pub trusted_proxies: Vec<IpAddr>,
 }
 
 #[derive(serde::Deserialize, Clone, Debug)]
@@ -85,10 +238,10 @@ pub enum Environment {
 }
 
 impl Environment {
-    pub fn to_string(&self) -> String {
+    pub fn as_str(&self) -> &'static str {
         match self {
-            Environment::Local => "local".to_string(),
-            Environment::Production => "production".to_string(),
+            Environment::Local => "local",
+            Environment::Production => "production",
         }
     }
 }
@@ -115,7 +268,7 @@ pub fn get_configuration() -> Result<Settings, ConfigError> {
         .unwrap_or("local".to_string())
         .try_into()
         .expect("Failed to parse environment");
-    let env_filename = format!("{}.yaml", environ.to_string());
+    let env_filename = format!("{}.yaml", environ.as_str());
 
     let settings = Config::builder()
         .add_source(File::from(configuration_dir.join("base.yaml")))
@@ -129,3 +282,44 @@ pub fn get_configuration() -> Result<Settings, ConfigError> {
 
     settings.try_deserialize()
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{LoginSettings, Settings};
+    use config::{Config, File};
+    use std::time::Duration;
+
+    #[test]
+    fn production_needs_the_webhook_password_from_the_environment() {
+        let settings = Config::builder()
+            .add_source(File::with_name("configuration/base.yaml"))
+            .add_source(File::with_name("configuration/production.yaml"))
+            // set by the deployment, like the password should be
+            .set_override("app.base_url", "https://zero2prod.example")
+            .unwrap()
+            .build()
+            .unwrap()
+            .try_deserialize::<Settings>();
+
+        let error = settings.unwrap_err().to_string();
+        assert!(error.contains("postmark_password"), "{}", error);
+    }
+
+    #[test]
+    fn login_delay_doubles_up_to_the_maximum() {
+        let settings = LoginSettings {
+            max_failures_per_username: 5,
+            max_failures_per_ip: 50,
+            window_seconds: 900,
+            lockout_seconds: 900,
+            base_delay_milliseconds: 250,
+            max_delay_milliseconds: 1500,
+        };
+
+        assert_eq!(settings.delay_after(0), Duration::from_millis(250));
+        assert_eq!(settings.delay_after(1), Duration::from_millis(250));
+        assert_eq!(settings.delay_after(3), Duration::from_millis(1000));
+        assert_eq!(settings.delay_after(4), Duration::from_millis(1500));
+        assert_eq!(settings.delay_after(u64::MAX), Duration::from_millis(1500));
+    }
+}
@@ -0,0 +1,108 @@
+/// what an entry in the audit log records, see `audit::record_audit_event`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AuditAction {
+    Login,
+    Logout,
+    PasswordChange,
+    PasswordReset,
+    AccountEmailChange,
+    TotpEnable,
+    TotpDisable,
+    SessionRevoke,
+    NewsletterPublish,
+    SubscriberConfirm,
+    SubscriberUnsubscribe,
+    SubscriberDelete,
+    SubscriberImport,
+    SubscriberExport,
+    SuppressionAdd,
+    SuppressionRemove,
+    SuppressionImport,
+    UserInvite,
+    InvitationRevoke,
+    InvitationAccept,
+    UserRoleChange,
+    ApiTokenCreate,
+    ApiTokenRevoke,
+}
+
+impl AuditAction {
+    pub const ALL: [AuditAction; 23] = [
+        AuditAction::Login,
+        AuditAction::Logout,
+        AuditAction::PasswordChange,
+        AuditAction::PasswordReset,
+        AuditAction::AccountEmailChange,
+        AuditAction::TotpEnable,
+        AuditAction::TotpDisable,
+        AuditAction::SessionRevoke,
+        AuditAction::NewsletterPublish,
+        AuditAction::SubscriberConfirm,
+        AuditAction::SubscriberUnsubscribe,
+        AuditAction::SubscriberDelete,
+        AuditAction::SubscriberImport,
+        AuditAction::SubscriberExport,
+        AuditAction::SuppressionAdd,
+        AuditAction::SuppressionRemove,
+        AuditAction::SuppressionImport,
+        AuditAction::UserInvite,
+        AuditAction::InvitationRevoke,
+        AuditAction::InvitationAccept,
+        AuditAction::UserRoleChange,
+        AuditAction::ApiTokenCreate,
+        AuditAction::ApiTokenRevoke,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            AuditAction::Login => "login",
+            AuditAction::Logout => "logout",
+            AuditAction::PasswordChange => "password_change",
+            AuditAction::PasswordReset => "password_reset",
+            AuditAction::AccountEmailChange => "account_email_change",
+            AuditAction::TotpEnable => "totp_enable",
+            AuditAction::TotpDisable => "totp_disable",
+            AuditAction::SessionRevoke => "session_revoke",
+            AuditAction::NewsletterPublish => "newsletter_publish",
+            AuditAction::SubscriberConfirm => "subscriber_confirm",
+            AuditAction::SubscriberUnsubscribe => "subscriber_unsubscribe",
+            AuditAction::SubscriberDelete => "subscriber_delete",
+            AuditAction::SubscriberImport => "subscriber_import",
+            AuditAction::SubscriberExport => "subscriber_export",
+            AuditAction::SuppressionAdd => "suppression_add",
+            AuditAction::SuppressionRemove => "suppression_remove",
+            AuditAction::SuppressionImport => "suppression_import",
+            AuditAction::UserInvite => "user_invite",
+            AuditAction::InvitationRevoke => "invitation_revoke",
+            AuditAction::InvitationAccept => "invitation_accept",
+            AuditAction::UserRoleChange => "user_role_change",
+            AuditAction::ApiTokenCreate => "api_token_create",
+            AuditAction::ApiTokenRevoke => "api_token_revoke",
+        }
+    }
+}
+
+impl TryFrom<String> for AuditAction {
+    type Error = String;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        Self::ALL
+            .into_iter()
+            .find(|action| action.as_str() == s)
+            .ok_or_else(|| format!("unknown audit action `{}`", s))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::AuditAction;
+    use claims::{assert_err, assert_ok_eq};
+
+    #[test]
+    fn every_action_round_trips() {
+        for action in AuditAction::ALL {
+            assert_ok_eq!(AuditAction::try_from(action.as_str().to_string()), action);
+        }
+        assert_err!(AuditAction::try_from("anything".to_string()));
+    }
+}
@@ -0,0 +1,29 @@
+/// bump whenever the wording people agree to when subscribing changes,
+/// so every consent event can be matched to the text that was shown
+pub const CONSENT_TEXT_VERSION: &str = "2026-10-19";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConsentEventKind {
+    Subscribe,
+    Confirm,
+    /// brought over from another provider by a subscriber import
+    Import,
+}
+
+impl ConsentEventKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ConsentEventKind::Subscribe => "subscribe",
+            ConsentEventKind::Confirm => "confirm",
+            ConsentEventKind::Import => "import",
+        }
+    }
+}
+
+/// where and how someone gave their consent
+#[derive(Debug, Clone)]
+pub struct ConsentContext {
+    pub ip_address: Option<String>,
+    pub user_agent: Option<String>,
+    pub source: String,
+}
@@ -0,0 +1,37 @@
+use std::{collections::HashSet, sync::LazyLock};
+
+static DISPOSABLE_DOMAINS: LazyLock<HashSet<&'static str>> = LazyLock::new(|| {
+    include_str!("disposable_domains.txt")
+        .lines()
+        .map(str::trim)
+        .filter(|l| !l.is_empty() && !l.starts_with('#'))
+        .collect()
+});
+
+/// subdomains of a listed domain count as disposable too
+pub fn is_disposable_domain(domain: &str) -> bool {
+    let domain = domain.to_lowercase();
+    let mut candidate = domain.as_str();
+    loop {
+        if DISPOSABLE_DOMAINS.contains(candidate) {
+            return true;
+        }
+        match candidate.split_once('.') {
+            Some((_, parent)) if parent.contains('.') => candidate = parent,
+            _ => return false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::is_disposable_domain;
+
+    #[test]
+    fn listed_domains_and_their_subdomains_are_disposable() {
+        assert!(is_disposable_domain("mailinator.com"));
+        assert!(is_disposable_domain("Eu.Mailinator.com"));
+        assert!(!is_disposable_domain("gmail.com"));
+        assert!(!is_disposable_domain("com"));
+    }
+}
@@ -1,8 +1,19 @@
 mod subscriber_name;
 mod subscriber_email;
 mod new_subscriber;
+mod subscription_status;
+mod consent_event;
+mod suppression_entry;
+mod disposable_domains;
+mod user_role;
+mod audit_action;
 
 pub use subscriber_name::SubscriberName;
 pub use subscriber_email::SubscriberEmail;
 pub use new_subscriber::NewSubscriber;
-
+pub use subscription_status::SubscriptionStatus;
+pub use consent_event::{ConsentContext, ConsentEventKind, CONSENT_TEXT_VERSION};
+pub use suppression_entry::SuppressionEntry;
+pub use disposable_domains::is_disposable_domain;
+pub use user_role::{Permission, UserRole};
+pub use audit_action::AuditAction;
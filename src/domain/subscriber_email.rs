@@ -5,7 +5,7 @@ use std::borrow::Cow;
 pub struct SubscriberEmail(String);
 
 impl ValidateEmail for SubscriberEmail{
-    fn as_email_string(&self) -> Option<Cow<str>> {
+    fn as_email_string(&self) -> Option<Cow<'_, str>> {
         Some(Cow::Borrowed(&self.0))
     }
 }
@@ -14,12 +14,17 @@ impl SubscriberEmail{
     pub fn parse(s: String) -> Result<Self, String> {
         let email = SubscriberEmail(s);
         if email.validate_email(){
-            return Ok(email);
+            Ok(email)
         }
         else{
-            return Err("invalid email provided".to_string());
+            Err("invalid email provided".to_string())
         }
     }
+
+    /// everything after the last `@`
+    pub fn domain(&self) -> &str {
+        self.0.rsplit_once('@').map(|(_, d)| d).unwrap_or_default()
+    }
 }
 
 
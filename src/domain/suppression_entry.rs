@@ -0,0 +1,60 @@
+use super::SubscriberEmail;
+
+/// either a full address or a whole domain that must never be emailed,
+/// always stored lowercased
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SuppressionEntry(String);
+
+impl SuppressionEntry {
+    pub fn parse(s: String) -> Result<Self, String> {
+        let entry = s.trim().to_lowercase();
+        if entry.contains('@') {
+            let email = SubscriberEmail::parse(entry)
+                .map_err(|_| format!("`{}` is not a valid email address", s.trim()))?;
+            return Ok(Self(email.as_ref().to_string()));
+        }
+
+        let is_domain = entry.contains('.')
+            && !entry.starts_with(['.', '-'])
+            && !entry.ends_with(['.', '-'])
+            && !entry.contains("..")
+            && entry
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '.' || c == '-');
+        if !is_domain {
+            return Err(format!("`{}` is neither an email address nor a domain", s.trim()));
+        }
+        Ok(Self(entry))
+    }
+}
+
+impl AsRef<str> for SuppressionEntry {
+    fn as_ref(&self) -> &str {
+        &self.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::SuppressionEntry;
+    use claims::{assert_err, assert_ok_eq};
+
+    #[test]
+    fn addresses_and_domains_are_accepted_and_lowercased() {
+        assert_ok_eq!(
+            SuppressionEntry::parse(" Ursula@Example.com ".to_string()).map(|e| e.0),
+            "ursula@example.com".to_string()
+        );
+        assert_ok_eq!(
+            SuppressionEntry::parse("Mailinator.com".to_string()).map(|e| e.0),
+            "mailinator.com".to_string()
+        );
+    }
+
+    #[test]
+    fn garbage_is_rejected() {
+        for entry in ["", "localhost", "not an entry", ".com", "example..com", "@example.com"] {
+            assert_err!(SuppressionEntry::parse(entry.to_string()));
+        }
+    }
+}
@@ -0,0 +1,132 @@
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UserRole {
+    /// everything, including managing other users
+    Owner,
+    /// sends newsletters and manages subscribers
+    Publisher,
+    /// writes newsletters but can't send them
+    Editor,
+    /// read-only
+    Viewer,
+}
+
+/// what a route requires, checked by `authentication::middleware::require_permission`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Permission {
+    ViewSubscribers,
+    ManageSubscribers,
+    DraftNewsletters,
+    PublishNewsletters,
+    ManageUsers,
+}
+
+impl UserRole {
+    pub const ALL: [UserRole; 4] = [
+        UserRole::Owner,
+        UserRole::Publisher,
+        UserRole::Editor,
+        UserRole::Viewer,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            UserRole::Owner => "owner",
+            UserRole::Publisher => "publisher",
+            UserRole::Editor => "editor",
+            UserRole::Viewer => "viewer",
+        }
+    }
+
+    pub fn can(&self, permission: Permission) -> bool {
+        match self {
+            UserRole::Owner => true,
+            UserRole::Publisher => permission != Permission::ManageUsers,
+            UserRole::Editor => matches!(
+                permission,
+                Permission::ViewSubscribers | Permission::DraftNewsletters
+            ),
+            UserRole::Viewer => permission == Permission::ViewSubscribers,
+        }
+    }
+}
+
+impl Permission {
+    pub const ALL: [Permission; 5] = [
+        Permission::ViewSubscribers,
+        Permission::ManageSubscribers,
+        Permission::DraftNewsletters,
+        Permission::PublishNewsletters,
+        Permission::ManageUsers,
+    ];
+
+    /// also the name of the matching API token scope
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Permission::ViewSubscribers => "view_subscribers",
+            Permission::ManageSubscribers => "manage_subscribers",
+            Permission::DraftNewsletters => "draft_newsletters",
+            Permission::PublishNewsletters => "publish_newsletters",
+            Permission::ManageUsers => "manage_users",
+        }
+    }
+}
+
+impl TryFrom<String> for Permission {
+    type Error = String;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        Self::ALL
+            .into_iter()
+            .find(|permission| permission.as_str() == s)
+            .ok_or_else(|| format!("unknown permission `{}`", s))
+    }
+}
+
+impl TryFrom<String> for UserRole {
+    type Error = String;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        Self::ALL
+            .into_iter()
+            .find(|role| role.as_str() == s)
+            .ok_or_else(|| format!("unknown role `{}`", s))
+    }
+}
+
+impl AsRef<str> for UserRole {
+    fn as_ref(&self) -> &str {
+        self.as_str()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Permission, UserRole};
+    use claims::{assert_err, assert_ok_eq};
+
+    #[test]
+    fn every_role_round_trips() {
+        for role in UserRole::ALL {
+            assert_ok_eq!(UserRole::try_from(role.as_str().to_string()), role);
+        }
+        assert_err!(UserRole::try_from("admin".to_string()));
+    }
+
+    #[test]
+    fn every_permission_round_trips() {
+        for permission in Permission::ALL {
+            assert_ok_eq!(Permission::try_from(permission.as_str().to_string()), permission);
+        }
+        assert_err!(Permission::try_from("everything".to_string()));
+    }
+
+    #[test]
+    fn only_publishers_and_owners_can_send() {
+        assert!(UserRole::Owner.can(Permission::PublishNewsletters));
+        assert!(UserRole::Publisher.can(Permission::PublishNewsletters));
+        assert!(!UserRole::Editor.can(Permission::PublishNewsletters));
+        assert!(UserRole::Editor.can(Permission::DraftNewsletters));
+        assert!(!UserRole::Viewer.can(Permission::DraftNewsletters));
+        assert!(!UserRole::Publisher.can(Permission::ManageUsers));
+    }
+}
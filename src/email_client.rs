@@ -114,7 +114,7 @@ mod tests {
                     && body.get("HtmlBody").is_some()
                     && body.get("TextBody").is_some()
             } else {
-                return false;
+                false
             }
         }
     }
@@ -0,0 +1,43 @@
+use std::time::Duration;
+
+use sqlx::PgPool;
+
+use crate::{configuration::Settings, get_connection_pool};
+
+/// expired keys are also ignored when they're looked up, this only keeps the
+/// table from growing
+const CLEANUP_INTERVAL: Duration = Duration::from_secs(600);
+
+pub async fn run_cleanup_until_stopped(config: Settings) {
+    let pool = get_connection_pool(&config.database);
+    cleanup_loop(&pool, config.idempotency.ttl()).await;
+}
+
+async fn cleanup_loop(pool: &PgPool, ttl: Duration) {
+    loop {
+        match delete_expired_idempotency_keys(pool, ttl).await {
+            Ok(n_deleted) => tracing::info!(n_deleted, "deleted expired idempotency keys"),
+            Err(e) => tracing::error!(
+                error.cause_chain = ?e,
+                "failed to delete expired idempotency keys"
+            ),
+        }
+        tokio::time::sleep(CLEANUP_INTERVAL).await;
+    }
+}
+
+/// completed or not, a key older than `ttl` is gone
+#[tracing::instrument(name = "delete expired idempotency keys", skip(pool))]
+pub async fn delete_expired_idempotency_keys(
+    pool: &PgPool,
+    ttl: Duration,
+) -> Result<u64, anyhow::Error> {
+    let n_deleted_rows = sqlx::query!(
+        "delete from idempotency where created_at < now() - $1::float8 * interval '1 second'",
+        ttl.as_secs_f64()
+    )
+    .execute(pool)
+    .await?
+    .rows_affected();
+    Ok(n_deleted_rows)
+}
@@ -0,0 +1,261 @@
+use std::{
+    cell::RefCell,
+    future::{ready, Ready},
+    ops::{Deref, DerefMut},
+    pin::Pin,
+    rc::Rc,
+};
+
+use actix_web::{
+    body::{BoxBody, MessageBody},
+    dev::{Payload, ServiceRequest, ServiceResponse},
+    error::{InternalError, PayloadError},
+    http::{header, StatusCode},
+    middleware::Next,
+    web::{self, Bytes, BytesMut},
+    FromRequest, HttpMessage, HttpRequest, HttpResponse,
+};
+use futures_util::{stream, Stream, StreamExt};
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+use sqlx::{PgPool, Postgres, Transaction};
+
+use super::{
+    abandon_processing, save_response, try_processing, IdempotencyKey, IdempotencyOwner,
+    NextAction,
+};
+use crate::{
+    authentication::middleware::{ApiScopes, UserId},
+    configuration::IdempotencySettings,
+    utils::{client_ip, e500, json_error},
+};
+
+pub const IDEMPOTENCY_KEY_HEADER: &str = "Idempotency-Key";
+
+/// for duplicates of a request that's still being handled
+const RETRY_AFTER_SECONDS: u32 = 1;
+
+/// the api's json limit; bodies are buffered to fingerprint them
+const MAX_BODY_SIZE: usize = 2 * 1024 * 1024;
+
+/// for html forms, which can't set headers
+#[derive(Deserialize)]
+struct IdempotencyKeyField {
+    idempotency_key: Option<String>,
+}
+
+/// the transaction the idempotency record was inserted in, lent to the
+/// handler through `IdempotentTransaction` and committed with the response
+#[derive(Clone)]
+struct TransactionSlot(Rc<RefCell<Option<Transaction<'static, Postgres>>>>);
+
+/// Replays the saved response when a request's key was seen before, and saves
+/// the response otherwise. The key is read from the `Idempotency-Key` header, or
+/// an `idempotency_key` field of url-encoded forms, and belongs to the logged in
+/// user, or to the client's ip address on anonymous routes (see `client_ip`).
+/// Requests without a key go through untouched.
+///
+/// Register it inside the authentication middleware, so the user is known.
+pub async fn idempotent(
+    mut req: ServiceRequest,
+    next: Next<impl MessageBody + 'static>,
+) -> Result<ServiceResponse<BoxBody>, actix_web::Error> {
+    let Some((key, body)) = read_idempotency_key(&mut req).await? else {
+        return Ok(next.call(req).await?.map_into_boxed_body());
+    };
+    let key: IdempotencyKey = match key.try_into() {
+        Ok(key) => key,
+        Err(e) => return Err(client_error(req.request(), StatusCode::BAD_REQUEST, e)),
+    };
+    let user_id = req.extensions().get::<UserId>().copied();
+    let owner = match user_id {
+        Some(user_id) => IdempotencyOwner::User(*user_id),
+        None => IdempotencyOwner::Client(
+            client_ip(req.request()).unwrap_or_else(|| "unknown".into()),
+        ),
+    };
+    let pool = req
+        .app_data::<web::Data<PgPool>>()
+        .ok_or_else(|| e500("no database pool registered"))?
+        .clone();
+    let settings = req
+        .app_data::<web::Data<IdempotencySettings>>()
+        .ok_or_else(|| e500("no idempotency settings registered"))?
+        .clone();
+
+    let fingerprint = request_fingerprint(&req, &body);
+    let transaction = match try_processing(&pool, &key, &owner, &fingerprint, &settings)
+        .await
+        .map_err(e500)?
+    {
+        NextAction::StartProcessing(t) => t,
+        NextAction::ReturnSavedResponse(saved_response) => {
+            return Ok(req.into_response(saved_response));
+        }
+        NextAction::RetryLater => {
+            let e = "a request with this idempotency key is still being processed";
+            let mut response = error_response(req.request(), StatusCode::CONFLICT, e);
+            response.headers_mut().insert(
+                header::RETRY_AFTER,
+                header::HeaderValue::from(RETRY_AFTER_SECONDS),
+            );
+            return Err(InternalError::from_response(e, response).into());
+        }
+        NextAction::RejectReusedKey => {
+            return Err(client_error(
+                req.request(),
+                StatusCode::UNPROCESSABLE_ENTITY,
+                "this idempotency key was already used for a different request",
+            ));
+        }
+    };
+    let slot = TransactionSlot(Rc::new(RefCell::new(Some(transaction))));
+    req.extensions_mut().insert(slot.clone());
+
+    let response = next.call(req).await;
+    let transaction = slot
+        .0
+        .borrow_mut()
+        .take()
+        .ok_or_else(|| e500("the idempotency transaction wasn't handed back"))?;
+    let response = match response {
+        Ok(response) if !response.status().is_server_error() => response,
+        // the handler's work is rolled back with the transaction, the key is
+        // freed so the client can try again
+        failed => {
+            drop(transaction);
+            abandon_processing(&pool, &key, &owner)
+                .await
+                .map_err(e500)?;
+            return Ok(failed?.map_into_boxed_body());
+        }
+    };
+    let (request, response) = response.map_into_boxed_body().into_parts();
+    let response = save_response(transaction, &key, &owner, response)
+        .await
+        .map_err(e500)?;
+    Ok(ServiceResponse::new(request, response))
+}
+
+/// the key and the body it came with. The header wins over the form field;
+/// the body is put back for the handler
+async fn read_idempotency_key(
+    req: &mut ServiceRequest,
+) -> Result<Option<(String, Bytes)>, actix_web::Error> {
+    let header_key = match req.headers().get(IDEMPOTENCY_KEY_HEADER) {
+        Some(key) => Some(
+            key.to_str()
+                .map_err(|e| client_error(req.request(), StatusCode::BAD_REQUEST, e))?
+                .to_string(),
+        ),
+        None => None,
+    };
+    if header_key.is_none() && req.content_type() != "application/x-www-form-urlencoded" {
+        return Ok(None);
+    }
+
+    let body = read_body(req).await?;
+    let key = header_key.or_else(|| {
+        // a malformed body is the handler's to reject
+        std::str::from_utf8(&body)
+            .ok()
+            .and_then(|body| web::Query::<IdempotencyKeyField>::from_query(body).ok())
+            .and_then(|field| field.into_inner().idempotency_key)
+    });
+    Ok(key.map(|key| (key, body)))
+}
+
+async fn read_body(req: &mut ServiceRequest) -> Result<Bytes, actix_web::Error> {
+    let mut payload = req.take_payload();
+    let mut body = BytesMut::new();
+    while let Some(chunk) = payload.next().await {
+        let chunk = chunk?;
+        if body.len() + chunk.len() > MAX_BODY_SIZE {
+            return Err(PayloadError::Overflow.into());
+        }
+        body.extend_from_slice(&chunk);
+    }
+    let body = body.freeze();
+    let replay = body.clone();
+    let stream: Pin<Box<dyn Stream<Item = Result<Bytes, PayloadError>>>> =
+        Box::pin(stream::once(async move { Ok(replay) }));
+    req.set_payload(Payload::from(stream));
+    Ok(body)
+}
+
+/// what a retry has to match: the same method and path, with the same body
+fn request_fingerprint(req: &ServiceRequest, body: &[u8]) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(req.method().as_str());
+    hasher.update(b" ");
+    hasher.update(req.path());
+    hasher.update(b"\n");
+    hasher.update(body);
+    hex::encode(hasher.finalize())
+}
+
+/// json for the api, like the rest of its errors
+fn error_response(request: &HttpRequest, status: StatusCode, message: &str) -> HttpResponse {
+    if request.extensions().get::<ApiScopes>().is_some() {
+        json_error(status, message)
+    } else {
+        HttpResponse::build(status).body(message.to_string())
+    }
+}
+
+fn client_error(
+    request: &HttpRequest,
+    status: StatusCode,
+    e: impl std::fmt::Display,
+) -> actix_web::Error {
+    let response = error_response(request, status, &e.to_string());
+    InternalError::from_response(e.to_string(), response).into()
+}
+
+/// The transaction the request's idempotency key was recorded in. Changes made
+/// through it are committed together with the saved response, so a retry either
+/// finds both or neither. Rejects requests without a key.
+pub struct IdempotentTransaction {
+    transaction: Option<Transaction<'static, Postgres>>,
+    slot: TransactionSlot,
+}
+
+impl FromRequest for IdempotentTransaction {
+    type Error = actix_web::Error;
+    type Future = Ready<Result<Self, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, _payload: &mut Payload) -> Self::Future {
+        let slot = req.extensions().get::<TransactionSlot>().cloned();
+        let transaction = slot.as_ref().and_then(|slot| slot.0.borrow_mut().take());
+        ready(match (slot, transaction) {
+            (Some(slot), Some(transaction)) => Ok(Self {
+                transaction: Some(transaction),
+                slot,
+            }),
+            _ => Err(client_error(
+                req,
+                StatusCode::BAD_REQUEST,
+                "an idempotency key is required",
+            )),
+        })
+    }
+}
+
+impl Deref for IdempotentTransaction {
+    type Target = Transaction<'static, Postgres>;
+    fn deref(&self) -> &Self::Target {
+        self.transaction.as_ref().unwrap()
+    }
+}
+
+impl DerefMut for IdempotentTransaction {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        self.transaction.as_mut().unwrap()
+    }
+}
+
+impl Drop for IdempotentTransaction {
+    fn drop(&mut self) {
+        *self.slot.0.borrow_mut() = self.transaction.take();
+    }
+}
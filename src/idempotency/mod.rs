@@ -1,6 +1,10 @@
+mod expiry;
 mod key;
+mod middleware;
 mod persistence;
 
+pub use expiry::{delete_expired_idempotency_keys, run_cleanup_until_stopped};
 pub use key::IdempotencyKey;
-pub use persistence::{get_saved_response, save_response};
+pub use middleware::{idempotent, IdempotentTransaction, IDEMPOTENCY_KEY_HEADER};
+pub use persistence::{abandon_processing, get_saved_response, save_response, IdempotencyOwner};
 pub use persistence::{try_processing, NextAction};
@@ -1,8 +1,35 @@
 use actix_web::{body::to_bytes, http::StatusCode, HttpResponse};
-use sqlx::{Executor, PgPool, Postgres, Transaction};
+use sqlx::{Executor, PgExecutor, PgPool, Postgres, Transaction};
+use std::time::Duration;
+use tokio::time::Instant;
 use uuid::Uuid;
 
 use super::IdempotencyKey;
+use crate::configuration::IdempotencySettings;
+
+/// whose keys a key is checked against, so clients can't replay each other's responses
+#[derive(Debug, Clone)]
+pub enum IdempotencyOwner {
+    User(Uuid),
+    /// for anonymous routes, the client's ip address
+    Client(String),
+}
+
+impl IdempotencyOwner {
+    fn user_id(&self) -> Option<Uuid> {
+        match self {
+            Self::User(user_id) => Some(*user_id),
+            Self::Client(_) => None,
+        }
+    }
+
+    fn client_ip(&self) -> Option<&str> {
+        match self {
+            Self::User(_) => None,
+            Self::Client(ip) => Some(ip),
+        }
+    }
+}
 
 // for deserializing from db
 #[derive(Debug, sqlx::Type)]
@@ -12,10 +39,10 @@ struct HeaderPairRecord {
     value: Vec<u8>,
 }
 
-pub async fn get_saved_response(
-    pool: &PgPool,
+pub async fn get_saved_response<'e>(
+    executor: impl PgExecutor<'e>,
     key: &IdempotencyKey,
-    user_id: Uuid,
+    owner: &IdempotencyOwner,
 ) -> Result<Option<HttpResponse>, anyhow::Error> {
     let saved_response = sqlx::query!(
         r#"
@@ -25,13 +52,16 @@ pub async fn get_saved_response(
         response_body as "response_body!"
     from idempotency
     where 
-        user_id = $1 and 
-        idempotency_key = $2
+        idempotency_key = $1 and 
+        user_id is not distinct from $2 and
+        client_ip is not distinct from $3 and
+        response_status_code is not null
     "#,
-        user_id,
-        key.as_ref()
+        key.as_ref(),
+        owner.user_id(),
+        owner.client_ip()
     )
-    .fetch_optional(pool)
+    .fetch_optional(executor)
     .await?;
 
     if let Some(r) = saved_response {
@@ -47,10 +77,13 @@ pub async fn get_saved_response(
     }
 }
 
+/// saves the response in the transaction the handler worked in. If another
+/// request took over the key and saved first, the work is rolled back and
+/// that request's response is returned instead
 pub async fn save_response(
     mut transaction: Transaction<'static, Postgres>,
     key: &IdempotencyKey,
-    user_id: Uuid,
+    owner: &IdempotencyOwner,
     response: HttpResponse,
 ) -> Result<HttpResponse, anyhow::Error> {
     let status_code = response.status().as_u16() as i16;
@@ -70,21 +103,31 @@ pub async fn save_response(
         r#"
         update idempotency 
         set
-            response_status_code = $3,
-            response_headers = $4,
-            response_body = $5
+            response_status_code = $4,
+            response_headers = $5,
+            response_body = $6
         where 
-            user_id = $1 and 
-            idempotency_key = $2
+            idempotency_key = $1 and 
+            user_id is not distinct from $2 and
+            client_ip is not distinct from $3 and
+            response_status_code is null
         "#,
-        user_id,
         key.as_ref(),
+        owner.user_id(),
+        owner.client_ip(),
         status_code,
         headers,
         body.as_ref(),
     );
 
-    transaction.execute(query).await?;
+    let n_updated_rows = transaction.execute(query).await?.rows_affected();
+    if n_updated_rows == 0 {
+        let saved_response = get_saved_response(&mut *transaction, key, owner)
+            .await?
+            .ok_or_else(|| anyhow::anyhow!("the idempotency key was removed while in use"))?;
+        transaction.rollback().await?;
+        return Ok(saved_response);
+    }
     transaction.commit().await?;
 
     // apparently need to go from HttpResponse<Bytes> to HttpResponse<BoxBody>
@@ -92,39 +135,174 @@ pub async fn save_response(
     Ok(new_response)
 }
 
+/// frees the key of a request that failed, so it can be retried right away
+pub async fn abandon_processing(
+    pool: &PgPool,
+    key: &IdempotencyKey,
+    owner: &IdempotencyOwner,
+) -> Result<(), anyhow::Error> {
+    sqlx::query!(
+        r#"
+        delete from idempotency
+        where
+            idempotency_key = $1 and
+            user_id is not distinct from $2 and
+            client_ip is not distinct from $3 and
+            response_status_code is null
+        "#,
+        key.as_ref(),
+        owner.user_id(),
+        owner.client_ip()
+    )
+    .execute(pool)
+    .await?;
+    Ok(())
+}
+
 pub enum NextAction {
     StartProcessing(Transaction<'static, Postgres>),
     ReturnSavedResponse(HttpResponse),
+    /// the key was first used for a different request
+    RejectReusedKey,
+    /// another request with the key is still being handled
+    RetryLater,
 }
+
+/// how often a request waiting on another one with the same key checks on it
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// `fingerprint` identifies the request, a retry has to match the original's.
+///
+/// The key is committed as in progress before the handler runs; a request
+/// that died holding it leaves it in progress, and once that is older than
+/// the processing timeout a retry takes it over. Until then duplicates wait a
+/// moment for its response, and are told to retry later if it doesn't come.
+/// Keys older than the ttl are forgotten.
 pub async fn try_processing(
     pool: &PgPool,
     key: &IdempotencyKey,
-    user_id: Uuid,
+    owner: &IdempotencyOwner,
+    fingerprint: &str,
+    settings: &IdempotencySettings,
 ) -> Result<NextAction, anyhow::Error> {
-    let mut transaction = pool.begin().await?;
-
-    let query = sqlx::query!(
+    // the cleanup task gets to them eventually, this makes the ttl exact
+    sqlx::query!(
         r#"
-        INSERT INTO idempotency(
-            user_id, 
-            idempotency_key,
-            created_at
+        delete from idempotency
+        where
+            idempotency_key = $1 and
+            user_id is not distinct from $2 and
+            client_ip is not distinct from $3 and
+            created_at < now() - $4::float8 * interval '1 second'
+        "#,
+        key.as_ref(),
+        owner.user_id(),
+        owner.client_ip(),
+        settings.ttl().as_secs_f64()
+    )
+    .execute(pool)
+    .await?;
+
+    // a concurrent request with the same key is given a moment to finish,
+    // so that the retry can get its response
+    let deadline = Instant::now() + settings.in_progress_wait();
+    loop {
+        let n_inserted_rows = sqlx::query!(
+            r#"
+            INSERT INTO idempotency(
+                user_id, 
+                client_ip,
+                idempotency_key,
+                request_fingerprint,
+                created_at
+            )
+            VALUES ($1, $2, $3, $4, now()) 
+            ON CONFLICT DO NOTHING
+        "#,
+            owner.user_id(),
+            owner.client_ip(),
+            key.as_ref(),
+            fingerprint
         )
-        VALUES ($1, $2, now()) 
-        ON CONFLICT DO NOTHING
-    "#,
-        user_id,
-        key.as_ref()
-    );
+        .execute(pool)
+        .await?
+        .rows_affected();
+        if n_inserted_rows > 0 {
+            return Ok(NextAction::StartProcessing(pool.begin().await?));
+        }
 
-    let n_inserted_rows = transaction.execute(query).await?.rows_affected();
+        let saved = sqlx::query!(
+            r#"
+            select request_fingerprint, response_status_code
+            from idempotency
+            where
+                idempotency_key = $1 and
+                user_id is not distinct from $2 and
+                client_ip is not distinct from $3
+            "#,
+            key.as_ref(),
+            owner.user_id(),
+            owner.client_ip()
+        )
+        .fetch_optional(pool)
+        .await?;
+        let Some(saved) = saved else {
+            // the other request failed and freed the key in the meantime
+            continue;
+        };
+        // keys saved before fingerprints were recorded match anything
+        if saved
+            .request_fingerprint
+            .is_some_and(|saved| saved != fingerprint)
+        {
+            return Ok(NextAction::RejectReusedKey);
+        }
+        if saved.response_status_code.is_some() {
+            let response = get_saved_response(pool, key, owner)
+                .await?
+                .ok_or_else(|| anyhow::anyhow!("we expected a row to exist"))?;
+            return Ok(NextAction::ReturnSavedResponse(response));
+        }
+        if take_over_stale_key(pool, key, owner, settings.processing_timeout()).await? {
+            return Ok(NextAction::StartProcessing(pool.begin().await?));
+        }
 
-    if n_inserted_rows > 0 {
-        return Ok(NextAction::StartProcessing(transaction));
-    } else {
-        let response = get_saved_response(pool, key, user_id)
-            .await?
-            .ok_or_else(|| anyhow::anyhow!("we expected a row to exist"))?;
-        return Ok(NextAction::ReturnSavedResponse(response));
+        if Instant::now() >= deadline {
+            return Ok(NextAction::RetryLater);
+        }
+        tokio::time::sleep(POLL_INTERVAL).await;
+    }
+}
+
+/// restarts the clock on a key whose request has been in progress for longer
+/// than `timeout`; false if it isn't stale, or another retry got it first
+async fn take_over_stale_key(
+    pool: &PgPool,
+    key: &IdempotencyKey,
+    owner: &IdempotencyOwner,
+    timeout: Duration,
+) -> Result<bool, anyhow::Error> {
+    let n_updated_rows = sqlx::query!(
+        r#"
+        update idempotency
+        set created_at = now()
+        where
+            idempotency_key = $1 and
+            user_id is not distinct from $2 and
+            client_ip is not distinct from $3 and
+            response_status_code is null and
+            created_at < now() - $4::float8 * interval '1 second'
+        "#,
+        key.as_ref(),
+        owner.user_id(),
+        owner.client_ip(),
+        timeout.as_secs_f64()
+    )
+    .execute(pool)
+    .await?
+    .rows_affected();
+    if n_updated_rows > 0 {
+        tracing::warn!(key = key.as_ref(), "retrying a request that didn't finish");
     }
+    Ok(n_updated_rows > 0)
 }
@@ -3,7 +3,7 @@ use std::time::Duration;
 use sqlx::{Executor, PgPool, PgTransaction};
 use uuid::Uuid;
 
-use crate::{configuration::Settings, domain::SubscriberEmail, email_client::{EmailClient}, get_connection_pool};
+use crate::{configuration::Settings, domain::SubscriberEmail, email_client::{EmailClient}, get_connection_pool, suppressions::is_suppressed};
 
 pub enum ExecutionOutcome{
     EmptyQueue, 
@@ -18,12 +18,24 @@ pub async fn run_worker_until_stopped(config: Settings){
     let _ = worker_loop(&pool, &email_client).await;
 }
 
+/// how often the worker checks in with `worker_heartbeats`, readiness reports
+/// it down once it's been silent for a few of these
+pub const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(10);
+pub const WORKER_NAME: &str = "issue_delivery";
+
 // should this be yielding stuff for listensers?
 pub async fn worker_loop(
     pool: &PgPool,
     email_client: &EmailClient,
 )->Result<(), anyhow::Error>{
+    let mut last_heartbeat: Option<tokio::time::Instant> = None;
     loop{
+        if last_heartbeat.is_none_or(|at| at.elapsed() >= HEARTBEAT_INTERVAL) {
+            match record_heartbeat(pool).await {
+                Ok(()) => last_heartbeat = Some(tokio::time::Instant::now()),
+                Err(e) => tracing::error!(error.cause_chain = ?e, "failed to record heartbeat"),
+            }
+        }
         match try_execute_task(pool, email_client).await {
             Ok(ExecutionOutcome::EmptyQueue) => {
                 tokio::time::sleep(Duration::from_secs(10)).await;
@@ -36,6 +48,20 @@ pub async fn worker_loop(
     }
 }
 
+pub async fn record_heartbeat(pool: &PgPool) -> Result<(), anyhow::Error> {
+    sqlx::query!(
+        r#"
+        INSERT INTO worker_heartbeats(worker, last_seen_at)
+        VALUES ($1, now())
+        ON CONFLICT (worker) DO UPDATE SET last_seen_at = EXCLUDED.last_seen_at
+        "#,
+        WORKER_NAME
+    )
+    .execute(pool)
+    .await?;
+    Ok(())
+}
+
 //NOTE: we're using updates on table state to drive email send job to completion
 pub async fn try_execute_task(
     pool: &PgPool,
@@ -49,11 +75,15 @@ pub async fn try_execute_task(
 
     if let Some((mut transaction, issue_id, email, retries)) = task {
         match SubscriberEmail::parse(email.clone()) {
+            Ok(email) if is_suppressed(pool, &email).await? => {
+                // dropping the task is enough, the address must never be mailed
+                tracing::info!("skipping delivery to a suppressed address");
+            }
             Ok(email) => {
                 // NOTE: we only perform second query if email valid !
                 let issue = get_issue(pool).await?;
 
-                if let Err(_) = email_client
+                if email_client
                     .send_email(
                         &email,
                         &issue.title,
@@ -61,6 +91,7 @@ pub async fn try_execute_task(
                         &issue.html_content,
                     )
                     .await
+                    .is_err()
                 {
                     // increment retries and bail
                     increment_retries(&mut transaction, issue_id, email.as_ref(), retries).await?;
@@ -82,7 +113,7 @@ pub async fn try_execute_task(
 // - return Result<Option ... since a) query could fail, and b) may be no rows left
 async fn dequeue_task(
     pool: &PgPool,
-) -> Result<Option<(PgTransaction, Uuid, String, i32)>, sqlx::Error> {
+) -> Result<Option<(PgTransaction<'_>, Uuid, String, i32)>, sqlx::Error> {
     let mut transaction = pool.begin().await?;
     let r = sqlx::query!(
         r#"
@@ -1,3 +1,4 @@
+pub mod audit;
 pub mod configuration;
 pub mod routes;
 pub mod startup;
@@ -6,6 +7,12 @@ pub mod domain;
 pub mod email_client;
 pub mod idempotency;
 pub mod issue_delivery_workers;
+pub mod subscriber_import_workers;
+pub mod subscriber_data_workers;
+pub mod password_reset_workers;
+pub mod rate_limit;
+pub mod suppressions;
+pub mod users;
 mod utils;
 
 pub mod authentication;
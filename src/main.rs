@@ -2,7 +2,9 @@ use secrecy::ExposeSecret;
 use zero2prod::{
     self,
     configuration::get_configuration,
+    idempotency,
     issue_delivery_workers::run_worker_until_stopped,
+    password_reset_workers, subscriber_data_workers, subscriber_import_workers,
     telemetry::{get_subscriber, init_subscriber},
     Application,
 };
@@ -22,12 +24,26 @@ async fn main() -> Result<(), std::io::Error> {
     dbg!(&settings);
     dbg!(&settings.email_client.auth_token.expose_secret());
 
-    let worker = tokio::spawn(run_worker_until_stopped(settings));
+    let worker = tokio::spawn(run_worker_until_stopped(settings.clone()));
+    let import_worker = tokio::spawn(subscriber_import_workers::run_worker_until_stopped(
+        settings.clone(),
+    ));
+    let data_request_worker = tokio::spawn(subscriber_data_workers::run_worker_until_stopped(
+        settings.clone(),
+    ));
+    let password_reset_worker = tokio::spawn(password_reset_workers::run_worker_until_stopped(
+        settings.clone(),
+    ));
+    let idempotency_cleanup = tokio::spawn(idempotency::run_cleanup_until_stopped(settings));
 
-    // NOTE: we run until either the app OR the worker finishes !
+    // NOTE: we run until either the app OR one of the workers finishes !
     tokio::select! {
         _ = application => {},
         _ = worker => {},
+        _ = import_worker => {},
+        _ = data_request_worker => {},
+        _ = password_reset_worker => {},
+        _ = idempotency_cleanup => {},
     };
     Ok(())
 }
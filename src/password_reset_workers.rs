@@ -0,0 +1,164 @@
+use std::time::Duration;
+
+use sqlx::{Executor, PgPool, PgTransaction};
+use uuid::Uuid;
+
+use crate::{
+    configuration::Settings,
+    domain::SubscriberEmail,
+    email_client::EmailClient,
+    get_connection_pool,
+    issue_delivery_workers::ExecutionOutcome,
+    routes::{
+        count_recent_reset_tokens, find_user, generate_random_token, store_reset_token,
+        RESET_TOKEN_TTL_MINUTES,
+    },
+};
+
+/// per user, so the form can't be used to flood someone's inbox
+const MAX_RESET_EMAILS_PER_HOUR: i64 = 3;
+
+struct ResetRequestTask {
+    request_id: Uuid,
+    identifier: String,
+}
+
+pub async fn run_worker_until_stopped(config: Settings) {
+    let pool = get_connection_pool(&config.database);
+    let email_client = config.email_client.client().expect("failed to parse email");
+
+    let _ = worker_loop(&pool, &email_client, &config.app.base_url).await;
+}
+
+pub async fn worker_loop(
+    pool: &PgPool,
+    email_client: &EmailClient,
+    base_url: &str,
+) -> Result<(), anyhow::Error> {
+    loop {
+        match try_execute_task(pool, email_client, base_url).await {
+            Ok(ExecutionOutcome::EmptyQueue) => {
+                tokio::time::sleep(Duration::from_secs(10)).await;
+            }
+            Err(_) => {
+                tokio::time::sleep(Duration::from_secs(1)).await;
+            }
+            _ => {}
+        };
+    }
+}
+
+//NOTE: one reset request per transaction, same as the issue delivery queue
+pub async fn try_execute_task(
+    pool: &PgPool,
+    email_client: &EmailClient,
+    base_url: &str,
+) -> Result<ExecutionOutcome, anyhow::Error> {
+    let Some((mut transaction, task)) = dequeue_task(pool).await? else {
+        return Ok(ExecutionOutcome::EmptyQueue);
+    };
+
+    let token_and_email = match find_user(&mut *transaction, &task.identifier).await? {
+        Some(user) => match user.email.map(SubscriberEmail::parse) {
+            Some(Ok(email)) => {
+                let n_recent =
+                    count_recent_reset_tokens(&mut *transaction, user.user_id, 1).await?;
+                if n_recent < MAX_RESET_EMAILS_PER_HOUR {
+                    let token = generate_random_token();
+                    store_reset_token(&mut *transaction, &token, user.user_id).await?;
+                    Some((token, email))
+                } else {
+                    tracing::warn!(user_id = %user.user_id, "too many password reset requests");
+                    None
+                }
+            }
+            _ => {
+                tracing::warn!(user_id = %user.user_id, "password reset requested for user without an email");
+                None
+            }
+        },
+        // unknown accounts are dropped without a trace
+        None => None,
+    };
+    delete_task(transaction, task.request_id).await?;
+
+    // sent once the token is committed, so no locks are held across the http call;
+    // if it fails the user can ask again
+    let Some((token, email)) = token_and_email else {
+        return Ok(ExecutionOutcome::TaskCompleted);
+    };
+    if let Err(e) = send_password_reset_email(email_client, &email, base_url, &token).await {
+        tracing::error!(
+            error.cause_chain = ?e,
+            error.message = %e,
+            "failed to send password reset email"
+        );
+        return Ok(ExecutionOutcome::TaskFailed);
+    }
+    Ok(ExecutionOutcome::TaskCompleted)
+}
+
+async fn dequeue_task(
+    pool: &PgPool,
+) -> Result<Option<(PgTransaction<'static>, ResetRequestTask)>, sqlx::Error> {
+    let mut transaction = pool.begin().await?;
+    let task = sqlx::query_as!(
+        ResetRequestTask,
+        r#"
+        SELECT request_id, identifier
+        FROM password_reset_request_queue
+        ORDER BY requested_at
+        FOR UPDATE
+        SKIP LOCKED
+        LIMIT 1
+        "#,
+    )
+    .fetch_optional(&mut *transaction)
+    .await?;
+
+    Ok(task.map(|task| (transaction, task)))
+}
+
+async fn delete_task(
+    mut transaction: PgTransaction<'_>,
+    request_id: Uuid,
+) -> Result<(), sqlx::Error> {
+    let query = sqlx::query!(
+        "DELETE FROM password_reset_request_queue WHERE request_id = $1",
+        request_id
+    );
+    transaction.execute(query).await?;
+    transaction.commit().await?;
+    Ok(())
+}
+
+#[tracing::instrument(
+    name = "send password reset email",
+    skip(email_client, email, base_url, token)
+)]
+async fn send_password_reset_email(
+    email_client: &EmailClient,
+    email: &SubscriberEmail,
+    base_url: &str,
+    token: &str,
+) -> Result<(), reqwest::Error> {
+    let reset_link = format!("{}/password_reset/confirm?token={}", base_url, token);
+
+    let plain_body = format!(
+        "Someone asked to reset your password.\n\
+        Choose a new one here: {}\n\
+        This link expires in {} minutes. If it wasn't you, you can ignore this email.",
+        reset_link, RESET_TOKEN_TTL_MINUTES
+    );
+    let html_body = format!(
+        "Someone asked to reset your password.<br />\
+        <a href=\"{}\">Choose a new one</a><br />\
+        This link expires in {} minutes. If it wasn't you, you can ignore this email.",
+        reset_link, RESET_TOKEN_TTL_MINUTES
+    );
+
+    email_client
+        .send_email(email, "reset your password", &html_body, &plain_body)
+        .await?;
+    Ok(())
+}
@@ -0,0 +1,113 @@
+use std::time::Duration;
+
+use redis::{aio::ConnectionManager, AsyncCommands};
+
+/// fixed-window counters kept in redis, shared by every instance of the app
+#[derive(Clone)]
+pub struct RateLimiter {
+    connection: ConnectionManager,
+    key_prefix: String,
+}
+
+impl RateLimiter {
+    pub async fn new(redis_uri: &str, key_prefix: String) -> Result<Self, redis::RedisError> {
+        let client = redis::Client::open(redis_uri)?;
+        let connection = ConnectionManager::new(client).await?;
+        Ok(Self {
+            connection,
+            key_prefix,
+        })
+    }
+
+    /// for the readiness check
+    pub async fn ping(&self) -> Result<(), redis::RedisError> {
+        let mut connection = self.connection.clone();
+        redis::cmd("PING").query_async::<_, ()>(&mut connection).await
+    }
+
+    fn key(&self, bucket: &str, key: &str) -> String {
+        format!("{}:rate_limit:{}:{}", self.key_prefix, bucket, key)
+    }
+
+    /// counts one attempt against `key` in `bucket`;
+    /// returns false once more than `limit` attempts were made within `window`
+    #[tracing::instrument(name = "check rate limit", skip(self, key))]
+    pub async fn check(
+        &self,
+        bucket: &str,
+        key: &str,
+        limit: u64,
+        window: Duration,
+    ) -> Result<bool, redis::RedisError> {
+        let attempts = self.record(bucket, key, window).await?;
+        Ok(attempts <= limit)
+    }
+
+    /// counts one attempt and returns how many were made in the current window
+    pub async fn record(
+        &self,
+        bucket: &str,
+        key: &str,
+        window: Duration,
+    ) -> Result<u64, redis::RedisError> {
+        let key = self.key(bucket, key);
+        let mut connection = self.connection.clone();
+
+        // the window starts with the first attempt; creating the key with its expiry
+        // and counting in one transaction means a dropped connection can't leave a
+        // counter behind that never expires
+        let (attempts,): (u64,) = redis::pipe()
+            .atomic()
+            .cmd("SET")
+            .arg(&key)
+            .arg(0)
+            .arg("NX")
+            .arg("EX")
+            .arg(window.as_secs().max(1))
+            .ignore()
+            .incr(&key, 1)
+            .query_async(&mut connection)
+            .await?;
+        Ok(attempts)
+    }
+
+    /// attempts made in the current window, without counting a new one
+    pub async fn attempts(&self, bucket: &str, key: &str) -> Result<u64, redis::RedisError> {
+        let mut connection = self.connection.clone();
+        let attempts: Option<u64> = connection.get(self.key(bucket, key)).await?;
+        Ok(attempts.unwrap_or(0))
+    }
+
+    /// keeps the current count around for `duration` from now
+    pub async fn extend(
+        &self,
+        bucket: &str,
+        key: &str,
+        duration: Duration,
+    ) -> Result<(), redis::RedisError> {
+        let mut connection = self.connection.clone();
+        connection
+            .expire(self.key(bucket, key), duration.as_secs().max(1) as usize)
+            .await
+    }
+
+    /// takes back one attempt counted by `record`, for attempts that turned out fine
+    pub async fn refund(&self, bucket: &str, key: &str) -> Result<(), redis::RedisError> {
+        let key = self.key(bucket, key);
+        let mut connection = self.connection.clone();
+        let attempts: i64 = redis::cmd("DECR")
+            .arg(&key)
+            .query_async(&mut connection)
+            .await?;
+        if attempts < 0 {
+            // the window ran out in the meantime, don't leave a counter without an expiry
+            connection.del::<_, ()>(&key).await?;
+        }
+        Ok(())
+    }
+
+    pub async fn reset(&self, bucket: &str, key: &str) -> Result<(), redis::RedisError> {
+        let mut connection = self.connection.clone();
+        connection.del(self.key(bucket, key)).await
+    }
+}
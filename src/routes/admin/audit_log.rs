@@ -0,0 +1,141 @@
+use actix_web::{http::header::ContentType, web, HttpResponse};
+use serde::Deserialize;
+use sqlx::PgPool;
+use std::fmt::Write;
+
+use crate::{
+    audit::{search_audit_log, AuditFilter, PAGE_SIZE},
+    domain::AuditAction,
+    utils::{e400, e500},
+};
+
+#[derive(Deserialize, Debug)]
+pub struct AuditLogParameters {
+    action: Option<String>,
+    user: Option<String>,
+    target: Option<String>,
+    page: Option<i64>,
+}
+
+pub async fn audit_log(
+    parameters: web::Query<AuditLogParameters>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let AuditLogParameters {
+        action,
+        user,
+        target,
+        page,
+    } = parameters.into_inner();
+
+    // empty form fields are sent as `?action=&user=&target=`
+    let non_empty = |s: Option<String>| s.map(|s| s.trim().to_string()).filter(|s| !s.is_empty());
+    let filter = AuditFilter {
+        action: non_empty(action)
+            .map(AuditAction::try_from)
+            .transpose()
+            .map_err(e400)?,
+        username: non_empty(user),
+        target: non_empty(target),
+    };
+    let page = page.unwrap_or(1).max(1);
+
+    let (events, total) = search_audit_log(&pool, &filter, page)
+        .await
+        .map_err(e500)?;
+    let n_pages = ((total + PAGE_SIZE - 1) / PAGE_SIZE).max(1);
+
+    let mut rows_html = String::new();
+    for e in &events {
+        let escape = |s: Option<&str>| htmlescape::encode_minimal(s.unwrap_or("-"));
+        let user = match (&e.username, e.user_id) {
+            (Some(username), _) => htmlescape::encode_minimal(username),
+            // the user was deleted since
+            (None, Some(user_id)) => user_id.to_string(),
+            (None, None) => "-".to_string(),
+        };
+        writeln!(
+            rows_html,
+            "<tr><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td><code>{}</code></td></tr>",
+            e.occurred_at.format("%Y-%m-%d %H:%M:%S"),
+            user,
+            escape(e.ip_address.as_deref()),
+            e.action,
+            escape(e.target.as_deref()),
+            htmlescape::encode_minimal(&e.changes.to_string()),
+        )
+        .unwrap();
+    }
+
+    let action_value = filter.action.map(|a| a.as_str()).unwrap_or_default();
+    let mut action_options = String::from(r#"<option value="">all</option>"#);
+    for a in AuditAction::ALL {
+        let selected = if filter.action == Some(a) { " selected" } else { "" };
+        write!(
+            action_options,
+            r#"<option value="{0}"{selected}>{0}</option>"#,
+            a.as_str()
+        )
+        .unwrap();
+    }
+    let user_value = filter.username.as_deref().unwrap_or_default();
+    let target_value = filter.target.as_deref().unwrap_or_default();
+
+    let page_link = |page: i64| {
+        format!(
+            "/admin/audit?action={}&user={}&target={}&page={}",
+            action_value,
+            urlencoding::encode(user_value),
+            urlencoding::encode(target_value),
+            page
+        )
+    };
+    let mut pagination_html = format!("<p>page {page} of {n_pages} ({total} events)</p>");
+    if page > 1 {
+        write!(pagination_html, r#"<a href="{}">previous</a> "#, page_link(page - 1)).unwrap();
+    }
+    if page < n_pages {
+        write!(pagination_html, r#"<a href="{}">next</a>"#, page_link(page + 1)).unwrap();
+    }
+
+    let user_value = htmlescape::encode_attribute(user_value);
+    let target_value = htmlescape::encode_attribute(target_value);
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"
+<!DOCTYPE html>
+<html lang="en">
+  <head>
+    <meta charset="UTF-8">
+    <meta name="viewport" content="width=device-width, initial-scale=1">
+    <title>Audit log</title>
+    <link href="css/style.css" rel="stylesheet">
+  </head>
+  <body>
+    <form action="/admin/audit" method="get">
+      <label for="">
+        action
+        <select name="action">{action_options}</select>
+      </label>
+      <label for="">
+        user
+        <input type="text" name="user" placeholder="username" value="{user_value}">
+      </label>
+      <label for="">
+        target
+        <input type="text" name="target" value="{target_value}">
+      </label>
+      <button type="submit">filter</button>
+    </form>
+    <table>
+      <tr><th>at</th><th>user</th><th>ip address</th><th>action</th><th>target</th><th>changes</th></tr>
+      {rows_html}
+    </table>
+    {pagination_html}
+    <p><a href="/admin/dashboard">&lt;- Back</a></p>
+  </body>
+</html>
+        "#
+        )))
+}
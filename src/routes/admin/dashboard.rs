@@ -1,4 +1,8 @@
-use crate::{authentication::middleware::UserId, utils::e500};
+use crate::{
+    authentication::{csrf::CsrfToken, middleware::UserId},
+    domain::{Permission, UserRole},
+    utils::e500,
+};
 use actix_web::{http::header::ContentType, web::{self, ReqData}, HttpResponse};
 use actix_web_flash_messages::IncomingFlashMessages;
 use anyhow::Context;
@@ -11,6 +15,8 @@ pub async fn admin_dashboard(
     db_pool: web::Data<PgPool>,
     flash_messages: IncomingFlashMessages,
     user_id: ReqData<UserId>,
+    role: ReqData<UserRole>,
+    csrf_token: ReqData<CsrfToken>,
 ) -> Result<HttpResponse, actix_web::Error> {
     let mut msg_html = "".to_string();
     for m in flash_messages.iter() {
@@ -20,6 +26,48 @@ pub async fn admin_dashboard(
     let username = get_username(db_pool.as_ref(), *user_id.into_inner())
                 .await
                 .map_err(e500)?;
+    let role = role.into_inner();
+
+    // only link to what the user is allowed to use
+    let mut actions_html = String::new();
+    for (permission, link) in [
+        (
+            Some(Permission::DraftNewsletters),
+            r#"<a href="/admin/newsletters">create newsletter</a>"#,
+        ),
+        (
+            Some(Permission::DraftNewsletters),
+            r#"<a href="/admin/newsletters/drafts">newsletter drafts</a>"#,
+        ),
+        (
+            Some(Permission::ViewSubscribers),
+            r#"<a href="/admin/subscribers">manage subscribers</a>"#,
+        ),
+        (
+            Some(Permission::ViewSubscribers),
+            r#"<a href="/admin/suppressions">suppression list</a>"#,
+        ),
+        (
+            Some(Permission::ManageUsers),
+            r#"<a href="/admin/users">users</a>"#,
+        ),
+        (
+            Some(Permission::ManageUsers),
+            r#"<a href="/admin/login_attempts">failed logins</a>"#,
+        ),
+        (
+            Some(Permission::ManageUsers),
+            r#"<a href="/admin/audit">audit log</a>"#,
+        ),
+        (None, r#"<a href="/admin/password">change password</a>"#),
+        (None, r#"<a href="/admin/totp">two-factor authentication</a>"#),
+        (None, r#"<a href="/admin/sessions">sessions</a>"#),
+        (None, r#"<a href="/admin/api_tokens">API tokens</a>"#),
+    ] {
+        if permission.is_none_or(|p| role.can(p)) {
+            writeln!(actions_html, "      <li>{link}</li>").unwrap();
+        }
+    }
 
     Ok(HttpResponse::Ok()
         .content_type(ContentType::html())
@@ -36,17 +84,19 @@ pub async fn admin_dashboard(
   <body>
     {msg_html}
     <p>Welcome {username} !</p> 
+    <p>Your role: {}</p>
     <p>available actions:</p>
     <ol>
-      <li><a href="/admin/password">change password</a></li>
-      <li><a href="/admin/newsletters">create newsletter</a></li>
-      <li><form name="logoutForm" action="/admin/logout" method="post">
+{actions_html}      <li><form name="logoutForm" action="/admin/logout" method="post">
+       {}
        <input type="submit" value="Logout"> 
       </form></li>
     </ol>
   </body>
 </html>
-        "#
+        "#,
+            role.as_str(),
+            csrf_token.hidden_input(),
         )))
 }
 
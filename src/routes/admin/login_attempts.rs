@@ -0,0 +1,75 @@
+use actix_web::{http::header::ContentType, web, HttpResponse};
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use sqlx::PgPool;
+use std::fmt::Write;
+
+use crate::utils::e500;
+
+const MAX_ATTEMPTS_SHOWN: i64 = 100;
+
+struct FailedLoginAttempt {
+    username: String,
+    ip_address: Option<String>,
+    user_agent: Option<String>,
+    locked_out: bool,
+    attempted_at: DateTime<Utc>,
+}
+
+pub async fn failed_login_attempts(
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let attempts = sqlx::query_as!(
+        FailedLoginAttempt,
+        r#"
+        SELECT username, ip_address, user_agent, locked_out, attempted_at
+        FROM failed_login_attempts
+        ORDER BY attempted_at DESC
+        LIMIT $1
+        "#,
+        MAX_ATTEMPTS_SHOWN
+    )
+    .fetch_all(pool.as_ref())
+    .await
+    .context("failed to fetch failed login attempts")
+    .map_err(e500)?;
+
+    let mut rows_html = String::new();
+    for a in attempts {
+        let escape = |s: Option<String>| htmlescape::encode_minimal(s.as_deref().unwrap_or("-"));
+        writeln!(
+            rows_html,
+            "<tr><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td></tr>",
+            a.attempted_at.format("%Y-%m-%d %H:%M:%S"),
+            htmlescape::encode_minimal(&a.username),
+            escape(a.ip_address),
+            escape(a.user_agent),
+            if a.locked_out { "yes" } else { "no" },
+        )
+        .unwrap();
+    }
+
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"
+<!DOCTYPE html>
+<html lang="en">
+  <head>
+    <meta charset="UTF-8">
+    <meta name="viewport" content="width=device-width, initial-scale=1">
+    <title>Failed logins</title>
+    <link href="css/style.css" rel="stylesheet">
+  </head>
+  <body>
+    <p>The last {MAX_ATTEMPTS_SHOWN} failed login attempts:</p>
+    <table>
+      <tr><th>at</th><th>username</th><th>ip address</th><th>user agent</th><th>locked out</th></tr>
+      {rows_html}
+    </table>
+    <p><a href="/admin/dashboard">&lt;- Back</a></p>
+  </body>
+</html>
+        "#
+        )))
+}
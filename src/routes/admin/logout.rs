@@ -1,9 +1,34 @@
-use crate::{session_state::TypedSession, utils::see_other};
-use actix_web::HttpResponse;
+use crate::{
+    audit::{record_audit_event, AuditContext},
+    authentication::{middleware::{SessionId, UserId}, sessions::revoke_session},
+    domain::AuditAction,
+    session_state::TypedSession,
+    utils::{e500, see_other},
+};
+use actix_web::{web, HttpResponse};
 use actix_web_flash_messages::FlashMessage;
+use sqlx::PgPool;
 
-pub async fn logout(session: TypedSession) -> Result<HttpResponse, actix_web::Error> {
+pub async fn logout(
+    session: TypedSession,
+    pool: web::Data<PgPool>,
+    user_id: web::ReqData<UserId>,
+    session_id: web::ReqData<SessionId>,
+    audit: AuditContext,
+) -> Result<HttpResponse, actix_web::Error> {
+    revoke_session(pool.as_ref(), **user_id, **session_id)
+        .await
+        .map_err(e500)?;
+    record_audit_event(
+        pool.as_ref(),
+        &audit,
+        AuditAction::Logout,
+        None,
+        serde_json::json!({ "session_id": **session_id }),
+    )
+    .await
+    .map_err(e500)?;
     session.purge();
     FlashMessage::info("You have successfully logged out").send();
-    return Ok(see_other("/login"));
+    Ok(see_other("/login"))
 }
@@ -1,5 +1,9 @@
+mod audit_log;
 mod dashboard;
+mod login_attempts;
 mod logout;
 
+pub use audit_log::*;
 pub use dashboard::{admin_dashboard, get_username};
+pub use login_attempts::*;
 pub use logout::*;
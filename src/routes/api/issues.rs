@@ -0,0 +1,111 @@
+use actix_web::{web, HttpRequest, HttpResponse};
+use anyhow::Context;
+use serde::{Deserialize, Serialize};
+use sqlx::PgPool;
+use utoipa::ToSchema;
+use uuid::Uuid;
+
+use crate::{
+    audit::{record_audit_event, AuditContext},
+    domain::AuditAction,
+    idempotency::IdempotentTransaction,
+    routes::{enqueue_delivery_tasks, insert_newsletter_issue, Content},
+};
+
+use super::{ApiError, ErrorBody};
+
+/// same shape as the admin form's `BodyData`, minus the idempotency key which is a header here
+#[derive(Deserialize, ToSchema)]
+pub struct NewIssue {
+    title: String,
+    #[serde(flatten)]
+    content: Content,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct Issue {
+    issue_id: Uuid,
+    title: String,
+    published_at: String,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct IssueList {
+    issues: Vec<Issue>,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct PublishedIssue {
+    issue_id: Uuid,
+}
+
+#[utoipa::path(
+    get,
+    path = "/issues",
+    tag = "issues",
+    responses(
+        (status = 200, description = "Every issue, newest first", body = IssueList),
+        (status = 401, description = "Missing or invalid token", body = ErrorBody),
+        (status = 403, description = "Missing the `draft_newsletters` scope", body = ErrorBody),
+    ),
+    security(("api_token" = ["draft_newsletters"]))
+)]
+#[tracing::instrument(name = "api: list issues", skip(pool))]
+pub async fn api_list_issues(pool: web::Data<PgPool>) -> Result<web::Json<IssueList>, ApiError> {
+    let issues = sqlx::query_as!(
+        Issue,
+        "SELECT issue_id, title, published_at FROM newsletter_issues ORDER BY published_at DESC"
+    )
+    .fetch_all(pool.as_ref())
+    .await
+    .context("failed to fetch newsletter issues")?;
+    Ok(web::Json(IssueList { issues }))
+}
+
+/// needs an `Idempotency-Key` header, retries with the same key get the first response back
+#[utoipa::path(
+    post,
+    path = "/issues",
+    tag = "issues",
+    request_body = NewIssue,
+    params(
+        ("Idempotency-Key" = String, Header, description = "Up to 50 characters; retries with the same key and body get the first response back"),
+    ),
+    responses(
+        (status = 201, description = "The issue is queued for delivery to every confirmed subscriber", body = PublishedIssue),
+        (status = 400, description = "Invalid body or missing idempotency key", body = ErrorBody),
+        (status = 401, description = "Missing or invalid token", body = ErrorBody),
+        (status = 403, description = "Missing the `publish_newsletters` scope", body = ErrorBody),
+        (status = 409, description = "A request with the same idempotency key is still being processed, retry after the `Retry-After` delay", body = ErrorBody),
+        (status = 422, description = "The idempotency key was already used for a different request", body = ErrorBody),
+    ),
+    security(("api_token" = ["publish_newsletters"]))
+)]
+#[tracing::instrument(name = "api: publish issue", skip(body, transaction, request))]
+pub async fn api_publish_issue(
+    body: web::Json<NewIssue>,
+    mut transaction: IdempotentTransaction,
+    request: HttpRequest,
+) -> Result<HttpResponse, ApiError> {
+    let NewIssue { title, content } = body.into_inner();
+    if title.trim().is_empty() {
+        return Err(ApiError::BadRequest("the title can't be empty".into()));
+    }
+
+    let issue_id = insert_newsletter_issue(&mut transaction, &title, &content.text, &content.html)
+        .await
+        .context("failed to store newsletter issue details")?;
+    enqueue_delivery_tasks(&mut transaction, issue_id)
+        .await
+        .context("failed to enqueue delivery task")?;
+    record_audit_event(
+        &mut **transaction,
+        &AuditContext::for_request(&request),
+        AuditAction::NewsletterPublish,
+        Some(&issue_id.to_string()),
+        serde_json::json!({ "title": title, "via": "api" }),
+    )
+    .await?;
+
+    Ok(HttpResponse::Created().json(PublishedIssue { issue_id }))
+}
@@ -0,0 +1,102 @@
+//! JSON endpoints under `/api/v1`, authenticated with personal api tokens
+mod issues;
+mod subscribers;
+
+pub use issues::*;
+pub use subscribers::*;
+
+use actix_web::{
+    error::{InternalError, JsonPayloadError, QueryPayloadError},
+    http::StatusCode,
+    web, HttpRequest, HttpResponse, ResponseError,
+};
+use serde::Serialize;
+use utoipa::{
+    openapi::security::{HttpAuthScheme, HttpBuilder, SecurityScheme},
+    Modify, OpenApi, ToSchema,
+};
+
+use crate::{routes::Content, utils::json_error};
+
+/// the contract for `/api/v1`, committed as `openapi.json` at the root of the repo
+#[derive(OpenApi)]
+#[openapi(
+    info(
+        title = "zero2prod api",
+        description = "Authenticate with a personal token from /admin/api_tokens: `Authorization: Bearer <token>`."
+    ),
+    servers((url = "/api/v1")),
+    paths(api_list_issues, api_publish_issue, api_list_subscribers, api_get_subscriber),
+    components(schemas(ErrorBody, Content)),
+    modifiers(&ApiTokenAuth),
+    tags(
+        (name = "issues", description = "Newsletter issues"),
+        (name = "subscribers", description = "Subscribers, read only"),
+    )
+)]
+pub struct ApiDoc;
+
+/// the scheme the `security` requirements on each path refer to
+struct ApiTokenAuth;
+
+impl Modify for ApiTokenAuth {
+    fn modify(&self, openapi: &mut utoipa::openapi::OpenApi) {
+        openapi
+            .components
+            .get_or_insert_with(Default::default)
+            .add_security_scheme(
+                "api_token",
+                SecurityScheme::Http(HttpBuilder::new().scheme(HttpAuthScheme::Bearer).build()),
+            );
+    }
+}
+
+/// GET /api/openapi.json
+pub async fn openapi_spec() -> HttpResponse {
+    HttpResponse::Ok().json(ApiDoc::openapi())
+}
+
+/// what `json_error` responds with
+#[derive(Serialize, ToSchema)]
+pub struct ErrorBody {
+    error: String,
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum ApiError {
+    #[error("{0}")]
+    BadRequest(String),
+    #[error("not found")]
+    NotFound,
+    #[error("something went wrong")]
+    UnexpectedError(#[from] anyhow::Error),
+}
+
+impl ResponseError for ApiError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            Self::BadRequest(_) => StatusCode::BAD_REQUEST,
+            Self::NotFound => StatusCode::NOT_FOUND,
+            Self::UnexpectedError(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        json_error(self.status_code(), &self.to_string())
+    }
+}
+
+/// so that malformed bodies and query strings get JSON errors too
+pub fn json_config() -> web::JsonConfig {
+    web::JsonConfig::default().error_handler(|e: JsonPayloadError, _: &HttpRequest| {
+        let response = json_error(StatusCode::BAD_REQUEST, &e.to_string());
+        InternalError::from_response(e, response).into()
+    })
+}
+
+pub fn query_config() -> web::QueryConfig {
+    web::QueryConfig::default().error_handler(|e: QueryPayloadError, _: &HttpRequest| {
+        let response = json_error(StatusCode::BAD_REQUEST, &e.to_string());
+        InternalError::from_response(e, response).into()
+    })
+}
@@ -0,0 +1,117 @@
+use actix_web::web;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::PgPool;
+use utoipa::{IntoParams, ToSchema};
+use uuid::Uuid;
+
+use crate::{
+    domain::SubscriptionStatus,
+    routes::{get_subscriber, search_subscribers, SubscriberRecord, PAGE_SIZE},
+};
+
+use super::{ApiError, ErrorBody};
+
+#[derive(Deserialize, Debug, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct SubscriberQuery {
+    /// matched against email and name
+    search: Option<String>,
+    /// `pending_confirmation`, `confirmed`, `unsubscribed`, `bounced` or `complained`
+    status: Option<String>,
+    /// starts at 1
+    page: Option<i64>,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct Subscriber {
+    id: Uuid,
+    email: String,
+    name: String,
+    #[schema(example = "confirmed")]
+    status: String,
+    subscribed_at: DateTime<Utc>,
+}
+
+impl From<SubscriberRecord> for Subscriber {
+    fn from(r: SubscriberRecord) -> Self {
+        Self {
+            id: r.id,
+            email: r.email,
+            name: r.name,
+            status: r.status,
+            subscribed_at: r.subscribed_at,
+        }
+    }
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct SubscriberPage {
+    subscribers: Vec<Subscriber>,
+    page: i64,
+    page_size: i64,
+    total: i64,
+}
+
+#[utoipa::path(
+    get,
+    path = "/subscribers",
+    tag = "subscribers",
+    params(SubscriberQuery),
+    responses(
+        (status = 200, description = "One page of subscribers, newest first", body = SubscriberPage),
+        (status = 400, description = "Unknown status", body = ErrorBody),
+        (status = 401, description = "Missing or invalid token", body = ErrorBody),
+        (status = 403, description = "Missing the `view_subscribers` scope", body = ErrorBody),
+    ),
+    security(("api_token" = ["view_subscribers"]))
+)]
+#[tracing::instrument(name = "api: list subscribers", skip(pool))]
+pub async fn api_list_subscribers(
+    query: web::Query<SubscriberQuery>,
+    pool: web::Data<PgPool>,
+) -> Result<web::Json<SubscriberPage>, ApiError> {
+    let SubscriberQuery {
+        search,
+        status,
+        page,
+    } = query.into_inner();
+    let status = status
+        .map(SubscriptionStatus::try_from)
+        .transpose()
+        .map_err(ApiError::BadRequest)?;
+    let page = page.unwrap_or(1).max(1);
+
+    let (subscribers, total) =
+        search_subscribers(&pool, search.as_deref(), status, page).await?;
+    Ok(web::Json(SubscriberPage {
+        subscribers: subscribers.into_iter().map(Subscriber::from).collect(),
+        page,
+        page_size: PAGE_SIZE,
+        total,
+    }))
+}
+
+#[utoipa::path(
+    get,
+    path = "/subscribers/{subscriber_id}",
+    tag = "subscribers",
+    params(("subscriber_id" = Uuid, Path, description = "The subscriber's id")),
+    responses(
+        (status = 200, description = "The subscriber", body = Subscriber),
+        (status = 401, description = "Missing or invalid token", body = ErrorBody),
+        (status = 403, description = "Missing the `view_subscribers` scope", body = ErrorBody),
+        (status = 404, description = "No such subscriber", body = ErrorBody),
+    ),
+    security(("api_token" = ["view_subscribers"]))
+)]
+#[tracing::instrument(name = "api: get subscriber", skip(pool))]
+pub async fn api_get_subscriber(
+    subscriber_id: web::Path<Uuid>,
+    pool: web::Data<PgPool>,
+) -> Result<web::Json<Subscriber>, ApiError> {
+    get_subscriber(&pool, subscriber_id.into_inner())
+        .await?
+        .map(|s| web::Json(s.into()))
+        .ok_or(ApiError::NotFound)
+}
@@ -0,0 +1,83 @@
+use actix_web::{http::header::ContentType, web, HttpResponse};
+use actix_web_flash_messages::IncomingFlashMessages;
+use sqlx::PgPool;
+use std::fmt::Write;
+
+use crate::{
+    authentication::{api_tokens::list_api_tokens, csrf::CsrfToken, middleware::UserId},
+    domain::{Permission, UserRole},
+    utils::e500,
+};
+
+pub async fn api_tokens_page(
+    pool: web::Data<PgPool>,
+    flash_messages: IncomingFlashMessages,
+    user_id: web::ReqData<UserId>,
+    role: web::ReqData<UserRole>,
+    csrf_token: web::ReqData<CsrfToken>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let mut msg_html = String::new();
+    for m in flash_messages.iter() {
+        writeln!(msg_html, "<p><i>{}</i></p>", m.content()).unwrap();
+    }
+    let csrf_input = csrf_token.hidden_input();
+
+    let tokens = list_api_tokens(&pool, **user_id).await.map_err(e500)?;
+    let mut rows_html = String::new();
+    for t in tokens {
+        let scopes: Vec<&str> = t.scopes.iter().map(|s| s.as_str()).collect();
+        writeln!(
+            rows_html,
+            r#"      <tr><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td><form action="/admin/api_tokens/{}/revoke" method="post">{csrf_input}<input type="submit" value="Revoke"></form></td></tr>"#,
+            htmlescape::encode_minimal(&t.name),
+            scopes.join(", "),
+            t.created_at.format("%Y-%m-%d %H:%M"),
+            t.last_used_at
+                .map(|at| at.format("%Y-%m-%d %H:%M").to_string())
+                .unwrap_or_else(|| "never".to_string()),
+            t.token_id,
+        )
+        .unwrap();
+    }
+
+    // a token can't be given more than its owner is allowed to do
+    let mut scopes_html = String::new();
+    for permission in Permission::ALL.into_iter().filter(|p| role.can(*p)) {
+        writeln!(
+            scopes_html,
+            r#"      <label><input type="checkbox" name="scope.{0}" value="on"> {0}</label>"#,
+            permission.as_str()
+        )
+        .unwrap();
+    }
+
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"
+<!DOCTYPE html>
+<html lang="en">
+  <head>
+    <meta charset="UTF-8">
+    <meta name="viewport" content="width=device-width, initial-scale=1">
+    <title>API tokens</title>
+  </head>
+  <body>
+    {msg_html}
+    <p>Tokens let scripts use the JSON api under <code>/api/v1</code>, send them as <code>Authorization: Bearer &lt;token&gt;</code>.</p>
+    <table>
+      <tr><th>name</th><th>scopes</th><th>created</th><th>last used</th><th></th></tr>
+{rows_html}    </table>
+    <form action="/admin/api_tokens" method="post">
+      {csrf_input}
+      <label>Name
+        <input type="text" name="name" placeholder="what it's for, e.g. ci">
+      </label>
+{scopes_html}      <button type="submit">create token</button>
+    </form>
+    <p><a href="/admin/dashboard">&lt;- Back</a></p>
+  </body>
+</html>
+"#
+        )))
+}
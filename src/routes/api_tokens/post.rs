@@ -0,0 +1,118 @@
+use std::collections::HashMap;
+
+use actix_web::{http::header::ContentType, web, HttpResponse};
+use actix_web_flash_messages::FlashMessage;
+use secrecy::ExposeSecret;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::{
+    audit::{record_audit_event, AuditContext},
+    authentication::{
+        api_tokens::{create_api_token, revoke_api_token},
+        middleware::UserId,
+    },
+    domain::{AuditAction, Permission, UserRole},
+    utils::{e500, see_other},
+};
+
+const MAX_NAME_LENGTH: usize = 100;
+
+fn api_tokens_page() -> HttpResponse {
+    see_other("/admin/api_tokens")
+}
+
+/// the form has a `name` field and a `scope.<permission>` checkbox per scope
+#[tracing::instrument(name = "create api token", skip(form, pool, audit))]
+pub async fn issue_api_token(
+    form: web::Form<HashMap<String, String>>,
+    pool: web::Data<PgPool>,
+    user_id: web::ReqData<UserId>,
+    role: web::ReqData<UserRole>,
+    audit: AuditContext,
+) -> Result<HttpResponse, actix_web::Error> {
+    let name = form.get("name").map(|n| n.trim()).unwrap_or_default();
+    if name.is_empty() || name.chars().count() > MAX_NAME_LENGTH {
+        FlashMessage::error(format!(
+            "Give the token a name of at most {} characters",
+            MAX_NAME_LENGTH
+        ))
+        .send();
+        return Ok(api_tokens_page());
+    }
+    let scopes: Vec<Permission> = Permission::ALL
+        .into_iter()
+        .filter(|p| form.contains_key(&format!("scope.{}", p.as_str())))
+        .collect();
+    if scopes.is_empty() {
+        FlashMessage::error("Pick at least one scope").send();
+        return Ok(api_tokens_page());
+    }
+    if let Some(p) = scopes.iter().find(|p| !role.can(**p)) {
+        FlashMessage::error(format!("Your role doesn't allow the {} scope", p.as_str())).send();
+        return Ok(api_tokens_page());
+    }
+
+    let token = create_api_token(&pool, **user_id, name, &scopes)
+        .await
+        .map_err(e500)?;
+    let scope_names: Vec<&str> = scopes.iter().map(|s| s.as_str()).collect();
+    record_audit_event(
+        pool.get_ref(),
+        &audit,
+        AuditAction::ApiTokenCreate,
+        Some(name),
+        serde_json::json!({ "scopes": scope_names }),
+    )
+    .await
+    .map_err(e500)?;
+
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"
+<!DOCTYPE html>
+<html lang="en">
+  <head>
+    <meta charset="UTF-8">
+    <meta name="viewport" content="width=device-width, initial-scale=1">
+    <title>New API token</title>
+  </head>
+  <body>
+    <p>Copy your new token now, it won't be shown again:</p>
+    <p><code id="api-token">{}</code></p>
+    <p><a href="/admin/api_tokens">&lt;- Back</a></p>
+  </body>
+</html>
+            "#,
+            token.expose_secret()
+        )))
+}
+
+#[tracing::instrument(name = "revoke api token", skip(pool, audit))]
+pub async fn withdraw_api_token(
+    pool: web::Data<PgPool>,
+    path: web::Path<Uuid>,
+    user_id: web::ReqData<UserId>,
+    audit: AuditContext,
+) -> Result<HttpResponse, actix_web::Error> {
+    let token_id = path.into_inner();
+    if revoke_api_token(&pool, **user_id, token_id)
+        .await
+        .map_err(e500)?
+    {
+        record_audit_event(
+            pool.get_ref(),
+            &audit,
+            AuditAction::ApiTokenRevoke,
+            Some(&token_id.to_string()),
+            serde_json::json!({}),
+        )
+        .await
+        .map_err(e500)?;
+        FlashMessage::info("Token revoked").send();
+    } else {
+        FlashMessage::error("That token had already been revoked").send();
+    }
+    Ok(api_tokens_page())
+}
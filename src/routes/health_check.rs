@@ -1,5 +1,116 @@
-use actix_web::HttpResponse;
+use std::{future::Future, time::Duration};
+
+use actix_web::{web, HttpResponse};
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use serde_json::json;
+use sqlx::PgPool;
+
+use crate::{
+    issue_delivery_workers::{HEARTBEAT_INTERVAL, WORKER_NAME},
+    rate_limit::RateLimiter,
+};
+
+/// a dependency that takes longer than this to answer counts as down
+const CHECK_TIMEOUT: Duration = Duration::from_secs(2);
 
 pub async fn check_health() -> HttpResponse {
     HttpResponse::Ok().finish()
 }
+
+/// the process is up and serving requests, whatever state its dependencies are in
+pub async fn liveness() -> HttpResponse {
+    HttpResponse::Ok().json(json!({ "status": "ok" }))
+}
+
+/// 503 unless every dependency is up, with an up/down breakdown either way; the
+/// endpoint is public, so why a check failed only goes to the logs
+#[tracing::instrument(name = "check readiness", skip(pool, rate_limiter))]
+pub async fn readiness(
+    pool: web::Data<PgPool>,
+    rate_limiter: web::Data<RateLimiter>,
+) -> HttpResponse {
+    let (postgres, redis, migrations, delivery_worker) = tokio::join!(
+        check("postgres", check_postgres(&pool)),
+        check("redis", async {
+            rate_limiter.ping().await.context("redis didn't answer")
+        }),
+        check("migrations", check_migrations(&pool)),
+        check("delivery_worker", check_delivery_worker(&pool)),
+    );
+    let checks = json!({
+        "postgres": postgres,
+        "redis": redis,
+        "migrations": migrations,
+        "delivery_worker": delivery_worker,
+    });
+    let is_ready = [postgres, redis, migrations, delivery_worker]
+        .iter()
+        .all(|status| *status == "up");
+
+    if is_ready {
+        HttpResponse::Ok().json(json!({ "status": "ready", "checks": checks }))
+    } else {
+        tracing::warn!(%checks, "not ready");
+        HttpResponse::ServiceUnavailable()
+            .json(json!({ "status": "unavailable", "checks": checks }))
+    }
+}
+
+/// "up" or "down", logging the reason for the latter
+async fn check(name: &str, probe: impl Future<Output = Result<(), anyhow::Error>>) -> &'static str {
+    let outcome = tokio::time::timeout(CHECK_TIMEOUT, probe)
+        .await
+        .unwrap_or_else(|_| Err(anyhow::anyhow!("timed out")));
+    match outcome {
+        Ok(()) => "up",
+        Err(e) => {
+            tracing::warn!(check = name, error = %format!("{:#}", e), "readiness check failed");
+            "down"
+        }
+    }
+}
+
+async fn check_postgres(pool: &PgPool) -> Result<(), anyhow::Error> {
+    sqlx::query("SELECT 1")
+        .execute(pool)
+        .await
+        .context("postgres didn't answer")?;
+    Ok(())
+}
+
+/// the schema has to be the one this build was compiled against
+async fn check_migrations(pool: &PgPool) -> Result<(), anyhow::Error> {
+    let expected = sqlx::migrate!("./migrations")
+        .iter()
+        .map(|m| m.version)
+        .max()
+        .unwrap_or_default();
+    let applied: Option<i64> =
+        sqlx::query_scalar("SELECT max(version) FROM _sqlx_migrations WHERE success")
+            .fetch_one(pool)
+            .await
+            .context("failed to read the applied migrations")?;
+    let applied = applied.unwrap_or_default();
+    if applied != expected {
+        anyhow::bail!("the database is at migration {applied}, this build expects {expected}");
+    }
+    Ok(())
+}
+
+async fn check_delivery_worker(pool: &PgPool) -> Result<(), anyhow::Error> {
+    let last_seen_at: Option<DateTime<Utc>> = sqlx::query_scalar!(
+        "SELECT last_seen_at FROM worker_heartbeats WHERE worker = $1",
+        WORKER_NAME
+    )
+    .fetch_optional(pool)
+    .await
+    .context("failed to read the worker's heartbeat")?;
+    let last_seen_at = last_seen_at.context("the delivery worker never checked in")?;
+
+    let age = (Utc::now() - last_seen_at).to_std().unwrap_or_default();
+    if age > HEARTBEAT_INTERVAL * 6 {
+        anyhow::bail!("the delivery worker was last seen {}s ago", age.as_secs());
+    }
+    Ok(())
+}
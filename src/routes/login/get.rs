@@ -1,12 +1,28 @@
-use actix_web::{http::header::ContentType, HttpResponse};
+use actix_web::{http::header::ContentType, web, HttpResponse};
 use actix_web_flash_messages::IncomingFlashMessages;
 use std::fmt::Write;
 
-pub async fn login_form(flash_messages: IncomingFlashMessages) -> HttpResponse {
+use crate::{
+    authentication::oidc::OidcClient,
+    session_state::TypedSession,
+    utils::{e500, see_other},
+};
+
+pub async fn login_form(
+    flash_messages: IncomingFlashMessages,
+    oidc: Option<web::Data<OidcClient>>,
+) -> HttpResponse {
     let mut error_html = String::new();
     for m in flash_messages.iter() {
         writeln!(error_html, "<p><i>{}</i></p>", m.content()).unwrap();
     }
+    let sso_html = match oidc {
+        Some(oidc) => format!(
+            r#"<p><a href="/login/oidc">Sign in with {}</a></p>"#,
+            htmlescape::encode_minimal(oidc.provider_name())
+        ),
+        None => String::new(),
+    };
 
     let body = format!(
         r#"<!DOCTYPE html>
@@ -31,6 +47,8 @@ pub async fn login_form(flash_messages: IncomingFlashMessages) -> HttpResponse {
 
       <button type="submit">Login</button>
    </form> 
+   {sso_html}
+   <p><a href="/password_reset">Forgot your password?</a></p>
   </body>
 </html>"#
     );
@@ -64,3 +82,43 @@ pub async fn login_form(flash_messages: IncomingFlashMessages) -> HttpResponse {
 //         Ok(self.error)
 //     }
 // }
+
+pub async fn login_two_factor_form(
+    flash_messages: IncomingFlashMessages,
+    session: TypedSession,
+) -> Result<HttpResponse, actix_web::Error> {
+    if session.get_pending_user_id().map_err(e500)?.is_none() {
+        return Ok(see_other("/login"));
+    }
+
+    let mut error_html = String::new();
+    for m in flash_messages.iter() {
+        writeln!(error_html, "<p><i>{}</i></p>", m.content()).unwrap();
+    }
+
+    let body = format!(
+        r#"<!DOCTYPE html>
+<html lang="en">
+  <head>
+    <meta charset="UTF-8">
+    <meta name="viewport" content="width=device-width, initial-scale=1">
+    <title>Two-factor authentication</title>
+  </head>
+  <body>
+  {error_html}
+  <form action="/login/two_factor" method="post">
+     <label>
+        Code from your authenticator app, or a recovery code
+        <input type="text" name="code" autocomplete="one-time-code" autofocus>
+      </label>
+
+      <button type="submit">Verify</button>
+   </form>
+  </body>
+</html>"#
+    );
+
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(body))
+}
@@ -1,4 +1,6 @@
 mod get; 
 mod post; 
+mod oidc;
 pub use get::*;
 pub use post::*;
+pub use oidc::*;
@@ -0,0 +1,104 @@
+use actix_web::{web, HttpRequest, HttpResponse};
+use serde::Deserialize;
+use sqlx::PgPool;
+
+use super::{login_redirect, start_session, LoginError};
+use crate::{
+    authentication::{
+        oidc::{OidcClient, OidcError},
+        totp::has_confirmed_totp,
+        PasswordHashing,
+    },
+    session_state::TypedSession,
+    utils::{e404, see_other},
+};
+
+/// GET /login/oidc, sends the browser to the identity provider
+#[tracing::instrument(name = "start oidc login", skip(oidc, session))]
+pub async fn login_oidc(
+    oidc: Option<web::Data<OidcClient>>,
+    session: TypedSession,
+) -> Result<HttpResponse, actix_web::Error> {
+    let Some(oidc) = oidc else {
+        return Err(e404("single sign-on is not configured"));
+    };
+    let unexpected = |e: anyhow::Error| login_redirect(LoginError::UnexpectedError(e));
+
+    let (url, pending) = oidc.authorization_request().await.map_err(unexpected)?;
+    session
+        .insert_pending_oidc_login(&pending)
+        .map_err(|e| unexpected(e.into()))?;
+    Ok(see_other(url.as_str()))
+}
+
+#[derive(Deserialize)]
+pub struct CallbackParams {
+    code: Option<String>,
+    state: Option<String>,
+    /// set instead of `code` when the provider didn't sign the user in
+    error: Option<String>,
+}
+
+/// GET /login/oidc/callback, where the identity provider sends the browser back to
+#[tracing::instrument(
+    name = "oidc login callback",
+    skip(request, params, oidc, pool, session, password_hashing),
+    fields(user_id = tracing::field::Empty)
+)]
+pub async fn login_oidc_callback(
+    request: HttpRequest,
+    params: web::Query<CallbackParams>,
+    oidc: Option<web::Data<OidcClient>>,
+    pool: web::Data<PgPool>,
+    session: TypedSession,
+    password_hashing: web::Data<PasswordHashing>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let Some(oidc) = oidc else {
+        return Err(e404("single sign-on is not configured"));
+    };
+    let unexpected = |e: anyhow::Error| login_redirect(LoginError::UnexpectedError(e));
+    let rejected = |e: anyhow::Error| login_redirect(LoginError::AuthError(e));
+
+    let pending = session
+        .take_pending_oidc_login()
+        .map_err(|e| unexpected(e.into()))?;
+    let CallbackParams { code, state, error } = params.into_inner();
+    if let Some(error) = error {
+        return Err(rejected(anyhow::anyhow!("identity provider returned `{}`", error)).into());
+    }
+    // the state ties the callback to a login started from this browser
+    let (Some(pending), Some(code)) = (pending.filter(|p| state == Some(p.state.clone())), code)
+    else {
+        return Err(rejected(anyhow::anyhow!("callback doesn't match a pending login")).into());
+    };
+
+    let claims = oidc
+        .exchange_code(&code, pending)
+        .await
+        .map_err(|e| match e {
+            OidcError::Rejected(e) => rejected(e),
+            OidcError::UnexpectedError(e) => unexpected(e),
+        })?;
+    let Some(user_id) = oidc
+        .find_or_provision_user(&pool, &claims, &password_hashing)
+        .await
+        .map_err(unexpected)?
+    else {
+        return Err(login_redirect(LoginError::UnknownIdentity).into());
+    };
+    tracing::Span::current().record("user_id", tracing::field::display(&user_id));
+
+    session.renew();
+    // a second factor set up here is still asked for, whatever the provider checked
+    if has_confirmed_totp(&pool, user_id).await.map_err(unexpected)? {
+        session
+            .insert_pending_user_id(user_id)
+            .map_err(|e| unexpected(e.into()))?;
+        return Ok(see_other("/login/two_factor"));
+    }
+    start_session(&pool, &session, &request, user_id, "oidc")
+        .await
+        .map_err(unexpected)?;
+
+    Ok(see_other("/admin/dashboard"))
+}
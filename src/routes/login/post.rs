@@ -1,12 +1,33 @@
 use actix_web::{
-    error::InternalError, http::header::LOCATION, web, HttpResponse,
+    error::InternalError,
+    http::header::{self, LOCATION},
+    web, HttpRequest, HttpResponse,
 };
 use actix_web_flash_messages::FlashMessage;
-use secrecy::Secret;
+use anyhow::Context;
+use secrecy::{ExposeSecret, Secret};
 use serde::Deserialize;
 use sqlx::PgPool;
+use uuid::Uuid;
 
-use crate::{authentication::{validate_credentials, AuthError, Credentials}, session_state::TypedSession};
+use crate::{
+    audit::{record_audit_event, AuditContext},
+    authentication::{
+        sessions::{register_session, SessionMetadata},
+        totp::{has_confirmed_totp, verify_second_factor},
+        validate_credentials, AuthError, Credentials, PasswordHashing,
+    },
+    configuration::LoginSettings,
+    domain::AuditAction,
+    rate_limit::RateLimiter,
+    routes::get_username,
+    session_state::TypedSession,
+    utils::{client_ip, see_other},
+};
+
+const USERNAME_BUCKET: &str = "login_username";
+const IP_BUCKET: &str = "login_ip";
+const TWO_FACTOR_BUCKET: &str = "login_two_factor";
 
 #[derive(Deserialize)]
 pub struct LoginData {
@@ -18,6 +39,12 @@ pub struct LoginData {
 pub enum LoginError {
     #[error("Authentication failed")]
     AuthError(#[source] anyhow::Error),
+    // same message whether or not the username exists
+    #[error("Too many failed login attempts, please try again later")]
+    LockedOut,
+    // signed in at the identity provider, but nobody here to sign in as
+    #[error("Your single sign-on account isn't linked to a user, ask an owner for an invitation")]
+    UnknownIdentity,
     #[error(transparent)]
     UnexpectedError(#[from] anyhow::Error),
 }
@@ -31,44 +58,298 @@ pub fn login_redirect(error: LoginError) -> InternalError<LoginError> {
     InternalError::from_response(error, response)
 }
 
+#[tracing::instrument(
+    name = "login",
+    skip(request, form, pool, session, rate_limiter, login_settings, password_hashing),
+    fields(username = %form.username)
+)]
 pub async fn login(
+    request: HttpRequest,
     form: web::Form<LoginData>,
     pool: web::Data<PgPool>,
     session: TypedSession,
+    rate_limiter: web::Data<RateLimiter>,
+    login_settings: web::Data<LoginSettings>,
+    password_hashing: web::Data<PasswordHashing>,
 ) -> Result<HttpResponse, InternalError<LoginError>> {
+    let unexpected = |e: anyhow::Error| login_redirect(LoginError::UnexpectedError(e));
+    let attempt = FailedAttempt::from_request(&request, &form.username);
+
+    // counted before the password is checked, so parallel guesses can't all get in
+    // under the limit while the first ones are still being verified
+    let counts = count_attempt(&rate_limiter, &login_settings, &attempt)
+        .await
+        .context("failed to check login throttling")
+        .map_err(unexpected)?;
+    if counts.is_locked_out(&login_settings) {
+        // the password isn't even checked, so guessing it right doesn't help either
+        attempt.store(&pool, true).await.map_err(unexpected)?;
+        return Err(login_redirect(LoginError::LockedOut));
+    }
+
     //validate login data
     let credentials = Credentials {
         username: form.0.username,
         password: form.0.password,
     };
-    match validate_credentials(credentials, &pool).await {
+    match validate_credentials(credentials, &pool, &password_hashing).await {
         Ok(user_id) => {
+            // only failures count against the ip, people behind one address share it
+            async {
+                rate_limiter
+                    .reset(USERNAME_BUCKET, &attempt.username)
+                    .await?;
+                rate_limiter.refund(IP_BUCKET, &attempt.ip_address).await
+            }
+            .await
+            .context("failed to reset login throttling")
+            .map_err(unexpected)?;
+
             //update session for user
             session.renew(); // NOTE: rotate session keys on login
-            session.insert_user_id(user_id)
-                .map_err(|e| login_redirect(LoginError::UnexpectedError(e.into())))?;
+
+            // the user only gets logged in once the second factor checks out too
+            if has_confirmed_totp(&pool, user_id).await.map_err(unexpected)? {
+                session
+                    .insert_pending_user_id(user_id)
+                    .map_err(|e| login_redirect(LoginError::UnexpectedError(e.into())))?;
+                return Ok(see_other("/login/two_factor"));
+            }
+
+            start_session(&pool, &session, &request, user_id, "password")
+                .await
+                .map_err(unexpected)?;
 
             Ok(HttpResponse::SeeOther()
                 .insert_header((LOCATION, "/admin/dashboard"))
                 .finish())
         }
-        Err(e) => {
-            let e = match e {
-                AuthError::InvalidCredentials(e) => LoginError::AuthError(e),
-                AuthError::UnexpectedError(e) => LoginError::UnexpectedError(e),
-            };
-
-            // old attempt at hmac to verify redirect authenticity
-            // let query_string = format!("error={}", urlencoding::Encoded::new(e.to_string()));
-            //
-            // let hmac_tag = {
-            //     let mut mac =
-            //         Hmac::<Sha256>::new_from_slice(secret.0.expose_secret().as_bytes()).unwrap();
-            //     mac.update(query_string.as_bytes());
-            //     mac.finalize().into_bytes()
-            // };
-
-            Err(login_redirect(e))
+        Err(AuthError::InvalidCredentials(e)) => {
+            start_lockouts(&rate_limiter, &login_settings, &attempt, &counts)
+                .await
+                .context("failed to record failed login")
+                .map_err(unexpected)?;
+            attempt.store(&pool, false).await.map_err(unexpected)?;
+
+            // slows down guessing without tying up anything but this request
+            tokio::time::sleep(login_settings.delay_after(counts.highest())).await;
+            Err(login_redirect(LoginError::AuthError(e)))
+        }
+        Err(AuthError::UnexpectedError(e)) => Err(login_redirect(LoginError::UnexpectedError(e))),
+    }
+}
+
+#[derive(Deserialize)]
+pub struct TwoFactorData {
+    code: Secret<String>,
+}
+
+fn two_factor_redirect(error: LoginError) -> InternalError<LoginError> {
+    FlashMessage::error(error.to_string()).send();
+    InternalError::from_response(error, see_other("/login/two_factor"))
+}
+
+/// second login step for users with TOTP enabled, accepts either a code from
+/// their authenticator app or one of their recovery codes
+#[tracing::instrument(
+    name = "two factor login",
+    skip(request, form, pool, session, rate_limiter, login_settings),
+    fields(user_id = tracing::field::Empty)
+)]
+pub async fn login_two_factor(
+    request: HttpRequest,
+    form: web::Form<TwoFactorData>,
+    pool: web::Data<PgPool>,
+    session: TypedSession,
+    rate_limiter: web::Data<RateLimiter>,
+    login_settings: web::Data<LoginSettings>,
+) -> Result<HttpResponse, InternalError<LoginError>> {
+    let unexpected = |e: anyhow::Error| two_factor_redirect(LoginError::UnexpectedError(e));
+
+    let Some(user_id) = session
+        .get_pending_user_id()
+        .map_err(|e| unexpected(e.into()))?
+    else {
+        return Ok(see_other("/login"));
+    };
+    tracing::Span::current().record("user_id", tracing::field::display(&user_id));
+    let bucket_key = user_id.to_string();
+
+    // counted up front for the same reason as passwords, see `login`
+    let n_attempts = rate_limiter
+        .record(TWO_FACTOR_BUCKET, &bucket_key, login_settings.window())
+        .await
+        .context("failed to check login throttling")
+        .map_err(unexpected)?;
+    if n_attempts > login_settings.max_failures_per_username {
+        // back to square one, the password has to be entered again
+        session.purge();
+        return Err(login_redirect(LoginError::LockedOut));
+    }
+
+    let code = form.0.code.expose_secret().trim().replace(' ', "");
+    let is_valid = verify_second_factor(&pool, user_id, &code)
+        .await
+        .map_err(unexpected)?;
+    if !is_valid {
+        let username = get_username(&pool, user_id).await.map_err(unexpected)?;
+        let attempt = FailedAttempt::from_request(&request, &username);
+        if n_attempts >= login_settings.max_failures_per_username {
+            rate_limiter
+                .extend(TWO_FACTOR_BUCKET, &bucket_key, login_settings.lockout())
+                .await
+                .context("failed to record failed login")
+                .map_err(unexpected)?;
+        }
+        attempt.store(&pool, false).await.map_err(unexpected)?;
+
+        tokio::time::sleep(login_settings.delay_after(n_attempts)).await;
+        return Err(two_factor_redirect(LoginError::AuthError(anyhow::anyhow!(
+            "invalid two factor code"
+        ))));
+    }
+
+    rate_limiter
+        .reset(TWO_FACTOR_BUCKET, &bucket_key)
+        .await
+        .context("failed to reset login throttling")
+        .map_err(unexpected)?;
+
+    session.remove_pending_user_id();
+    session.renew();
+    start_session(&pool, &session, &request, user_id, "two_factor")
+        .await
+        .map_err(unexpected)?;
+
+    Ok(see_other("/admin/dashboard"))
+}
+
+/// registers the login so it shows up on the sessions page and can be ended from
+/// there; `method` is the last step the user passed, for the audit log
+pub(crate) async fn start_session(
+    pool: &PgPool,
+    session: &TypedSession,
+    request: &HttpRequest,
+    user_id: Uuid,
+    method: &str,
+) -> Result<(), anyhow::Error> {
+    let session_id = register_session(pool, user_id, &SessionMetadata::from_request(request))
+        .await
+        .context("failed to register session")?;
+    record_audit_event(
+        pool,
+        &AuditContext::for_request(request).as_user(user_id),
+        AuditAction::Login,
+        None,
+        serde_json::json!({ "method": method, "session_id": session_id }),
+    )
+    .await?;
+    session.insert_user_id(user_id, session_id)?;
+    Ok(())
+}
+
+/// attempts made in the current window, this one included
+struct AttemptCounts {
+    username: u64,
+    ip: u64,
+}
+
+impl AttemptCounts {
+    fn is_locked_out(&self, settings: &LoginSettings) -> bool {
+        self.username > settings.max_failures_per_username || self.ip > settings.max_failures_per_ip
+    }
+
+    fn highest(&self) -> u64 {
+        self.username.max(self.ip)
+    }
+}
+
+async fn count_attempt(
+    rate_limiter: &RateLimiter,
+    settings: &LoginSettings,
+    attempt: &FailedAttempt,
+) -> Result<AttemptCounts, redis::RedisError> {
+    Ok(AttemptCounts {
+        username: rate_limiter
+            .record(USERNAME_BUCKET, &attempt.username, settings.window())
+            .await?,
+        ip: rate_limiter
+            .record(IP_BUCKET, &attempt.ip_address, settings.window())
+            .await?,
+    })
+}
+
+/// called after a failure that used up the last attempt in a bucket
+async fn start_lockouts(
+    rate_limiter: &RateLimiter,
+    settings: &LoginSettings,
+    attempt: &FailedAttempt,
+    counts: &AttemptCounts,
+) -> Result<(), redis::RedisError> {
+    for (bucket, key, n, limit) in [
+        (
+            USERNAME_BUCKET,
+            &attempt.username,
+            counts.username,
+            settings.max_failures_per_username,
+        ),
+        (
+            IP_BUCKET,
+            &attempt.ip_address,
+            counts.ip,
+            settings.max_failures_per_ip,
+        ),
+    ] {
+        if n >= limit {
+            // the lockout lasts from the last failure rather than the first one
+            rate_limiter.extend(bucket, key, settings.lockout()).await?;
         }
     }
+    Ok(())
+}
+
+struct FailedAttempt {
+    username: String,
+    ip_address: String,
+    user_agent: Option<String>,
+}
+
+impl FailedAttempt {
+    fn from_request(request: &HttpRequest, username: &str) -> Self {
+        Self {
+            // `Admin` and `admin` shouldn't get separate allowances
+            username: username.trim().to_lowercase(),
+            ip_address: client_ip(request).unwrap_or_else(|| "unknown".into()),
+            user_agent: request
+                .headers()
+                .get(header::USER_AGENT)
+                .and_then(|h| h.to_str().ok())
+                .map(|ua| ua.chars().take(512).collect()),
+        }
+    }
+
+    async fn store(&self, pool: &PgPool, locked_out: bool) -> Result<(), anyhow::Error> {
+        sqlx::query!(
+            r#"
+            INSERT INTO failed_login_attempts(
+                attempt_id,
+                username,
+                ip_address,
+                user_agent,
+                locked_out,
+                attempted_at
+            )
+            VALUES ($1, $2, $3, $4, $5, now())
+            "#,
+            Uuid::new_v4(),
+            self.username.chars().take(256).collect::<String>(),
+            self.ip_address,
+            self.user_agent,
+            locked_out,
+        )
+        .execute(pool)
+        .await
+        .context("failed to log failed login attempt")?;
+        Ok(())
+    }
 }
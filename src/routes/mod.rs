@@ -5,7 +5,18 @@ mod home;
 mod login;
 mod admin;
 mod password;
+mod password_reset;
+pub mod api;
+mod api_tokens;
 mod newsletter;
+mod newsletter_drafts;
+mod sessions;
+mod subscribers;
+mod subscriber_data;
+mod suppressions;
+mod totp;
+mod users;
+mod webhooks;
 
 pub use health_check::*;
 pub use subscribe::*;
@@ -14,4 +25,14 @@ pub use home::*;
 pub use login::*;
 pub use admin::*;
 pub use password::*;
+pub use password_reset::*;
+pub use api_tokens::*;
 pub use newsletter::*;
+pub use newsletter_drafts::*;
+pub use sessions::*;
+pub use subscribers::*;
+pub use subscriber_data::*;
+pub use suppressions::*;
+pub use totp::*;
+pub use users::*;
+pub use webhooks::*;
@@ -1,8 +1,13 @@
-use actix_web::{http::header::ContentType,HttpResponse};
+use actix_web::{http::header::ContentType, web, HttpResponse};
 use uuid::Uuid;
 
-pub async fn create_newsletter<'a>() -> Result<HttpResponse, actix_web::Error> {
+use crate::authentication::csrf::CsrfToken;
+
+pub async fn create_newsletter(
+    csrf_token: web::ReqData<CsrfToken>,
+) -> Result<HttpResponse, actix_web::Error> {
     let key = Uuid::new_v4().to_string();
+    let csrf_input = csrf_token.hidden_input();
 
     Ok(HttpResponse::Ok()
         .content_type(ContentType::html())
@@ -23,16 +28,18 @@ pub async fn create_newsletter<'a>() -> Result<HttpResponse, actix_web::Error> {
       </label>
      <label for="">
         text content
-        <input name="content.text" type="text" value="">
+        <input name="text" type="text" value="">
       </label>
      <label for="">
         html content
-        <input name="content.html" type="text" value="">
+        <input name="html" type="text" value="">
       </label>
 
       <!-- this input is hidden! -->
       <input hidden type="text" name="idempotency_key" value="{key}">
+      {csrf_input}
 
+      <button type="submit" formaction="/admin/newsletters/drafts">save as draft</button>
       <button type="submit">send!</button>
      </form> 
   </body>
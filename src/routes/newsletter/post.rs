@@ -1,12 +1,12 @@
 use crate::{
-    authentication::{middleware::UserId, Credentials},
-    domain::SubscriberEmail,
-    idempotency::{save_response, try_processing, IdempotencyKey, NextAction},
-    utils::{e400, e500, see_other},
+    audit::{record_audit_event, AuditContext},
+    domain::{AuditAction, SubscriberEmail},
+    idempotency::IdempotentTransaction,
+    utils::{e500, see_other},
 };
 use actix_web::{
     http::{
-        header::{self, HeaderMap, HeaderValue},
+        header::{self, HeaderValue},
         StatusCode,
     },
     web::{self},
@@ -14,8 +14,6 @@ use actix_web::{
 };
 use actix_web_flash_messages::FlashMessage;
 use anyhow::Context;
-use base64::Engine;
-use secrecy::Secret;
 use serde::{Deserialize, Serialize};
 use sqlx::{query, Executor, PgPool, Postgres, Transaction};
 use uuid::Uuid;
@@ -36,7 +34,7 @@ pub struct BodyData {
     pub content: Content,
     idempotency_key: String,
 }
-#[derive(Serialize, Deserialize)]
+#[derive(Serialize, Deserialize, utoipa::ToSchema)]
 pub struct Content {
     pub text: String,
     pub html: String,
@@ -81,37 +79,19 @@ impl ResponseError for PublishError {
     }
 }
 
-#[tracing::instrument(name = "publish newsletter", skip(pool, form))]
-pub async fn publish_newsletter<'a>(
-    pool: web::Data<PgPool>,
+/// the `idempotent` middleware replays the response to double submissions
+#[tracing::instrument(name = "publish newsletter", skip(form, transaction))]
+pub async fn publish_newsletter(
     form: web::Form<BodyData>,
-    // email_client: web::Data<EmailClient>,
+    mut transaction: IdempotentTransaction,
     request: HttpRequest,
-    user_id: web::ReqData<UserId>,
 ) -> Result<HttpResponse, actix_web::Error> {
-    // idempotency check
     let BodyData {
         title,
         content,
-        idempotency_key,
+        idempotency_key: _,
     } = form.0;
 
-    let idempotency_key: IdempotencyKey = idempotency_key.try_into().map_err(e400)?;
-    let user_id = user_id.into_inner();
-
-    let success_message = || FlashMessage::info("Successfully sent out newsletter");
-
-    let mut transaction = match try_processing(&pool, &idempotency_key, *user_id)
-        .await
-        .map_err(e500)?
-    {
-        NextAction::StartProcessing(t) => t,
-        NextAction::ReturnSavedResponse(cached_response) => {
-            success_message().send();
-            return Ok(cached_response);
-        }
-    };
-
     // init send task
     let issue_id = insert_newsletter_issue(&mut transaction, &title, &content.text, &content.html)
         .await
@@ -122,14 +102,18 @@ pub async fn publish_newsletter<'a>(
         .await
         .context("failed to enqueue delivery task")
         .map_err(e500)?;
-
-    let response = see_other("/admin/dashboard");
-    let response = save_response(transaction, &idempotency_key, *user_id, response)
-        .await
-        .map_err(e500)?;
-
-    success_message().send();
-    Ok(response)
+    record_audit_event(
+        &mut **transaction,
+        &AuditContext::for_request(&request),
+        AuditAction::NewsletterPublish,
+        Some(&issue_id.to_string()),
+        serde_json::json!({ "title": title, "via": "admin" }),
+    )
+    .await
+    .map_err(e500)?;
+
+    FlashMessage::info("Successfully sent out newsletter").send();
+    Ok(see_other("/admin/dashboard"))
 }
 
 #[tracing::instrument(name = "get all subscribers with `confirmed` status", skip(pool))]
@@ -151,40 +135,6 @@ async fn get_confirmed_subscribers(
     Ok(rows)
 }
 
-#[allow(dead_code)]
-fn basic_authentication(headers: &HeaderMap) -> Result<Credentials, anyhow::Error> {
-    // The header value, if present, must be a valid UTF8 string
-    let header_value = headers
-        .get("Authorization")
-        .context("The Authorization' header was missing")?
-        .to_str()
-        .context("The 'Authorization' header was not a valid UTF8 string.")?;
-    let base64encoded_credentials = header_value
-        .strip_prefix("Basic ")
-        .context("The authorization scheme was not 'Basic'.")?;
-    let decoded_credentials = base64::engine::general_purpose::STANDARD
-        .decode(base64encoded_credentials)
-        .context("Failed to base64-decode 'Basic' credentials.")?;
-    let decoded_credentials = String::from_utf8(decoded_credentials)
-        .context("The decoded credential string is valid UTF8.")?;
-
-    // splitn returns at most 2 elements !
-    let mut credentials = decoded_credentials.splitn(2, ':');
-    let username = credentials
-        .next()
-        .ok_or_else(|| anyhow::anyhow!("A username must be provided in 'Basic' auth."))?
-        .to_string();
-    let password = credentials
-        .next()
-        .ok_or_else(|| anyhow::anyhow!("A password must be provided in 'Basic' auth."))?
-        .to_string();
-
-    Ok(Credentials {
-        username,
-        password: Secret::new(password),
-    })
-}
-
 pub async fn insert_newsletter_issue(
     transaction: &mut Transaction<'_, Postgres>,
     title: &str,
@@ -0,0 +1,128 @@
+use actix_web::{http::header::ContentType, web, HttpResponse};
+use actix_web_flash_messages::IncomingFlashMessages;
+use sqlx::PgPool;
+use std::fmt::Write;
+use uuid::Uuid;
+
+use crate::{
+    authentication::csrf::CsrfToken,
+    domain::{Permission, UserRole},
+    utils::{e404, e500},
+};
+
+use super::persistence::{get_draft, list_drafts};
+
+fn page(title: &str, content: &str) -> HttpResponse {
+    HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"<!DOCTYPE html>
+<html lang="en">
+  <head>
+    <meta charset="UTF-8">
+    <meta name="viewport" content="width=device-width, initial-scale=1">
+    <title>{title}</title>
+  </head>
+  <body>
+{content}
+  </body>
+</html>"#
+        ))
+}
+
+pub async fn newsletter_drafts_page(
+    pool: web::Data<PgPool>,
+    flash_messages: IncomingFlashMessages,
+) -> Result<HttpResponse, actix_web::Error> {
+    let mut msg_html = String::new();
+    for m in flash_messages.iter() {
+        writeln!(msg_html, "<p><i>{}</i></p>", m.content()).unwrap();
+    }
+
+    let drafts = list_drafts(&pool).await.map_err(e500)?;
+    let mut rows_html = String::new();
+    for d in drafts {
+        writeln!(
+            rows_html,
+            r#"      <tr><td><a href="/admin/newsletters/drafts/{}">{}</a></td><td>{}</td></tr>"#,
+            d.draft_id,
+            htmlescape::encode_minimal(&d.title),
+            d.updated_at.format("%Y-%m-%d %H:%M"),
+        )
+        .unwrap();
+    }
+
+    Ok(page(
+        "Newsletter drafts",
+        &format!(
+            r#"    {msg_html}
+    <table>
+      <tr><th>title</th><th>last saved</th></tr>
+{rows_html}    </table>
+    <p><a href="/admin/newsletters">new newsletter</a></p>
+    <p><a href="/admin/dashboard">&lt;- Back</a></p>"#
+        ),
+    ))
+}
+
+/// editors save changes here, publishers can also send the draft out
+pub async fn edit_newsletter_draft(
+    draft_id: web::Path<Uuid>,
+    pool: web::Data<PgPool>,
+    flash_messages: IncomingFlashMessages,
+    role: web::ReqData<UserRole>,
+    csrf_token: web::ReqData<CsrfToken>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let draft = get_draft(&pool, draft_id.into_inner())
+        .await
+        .map_err(e500)?
+        .ok_or_else(|| e404("draft not found"))?;
+    let mut msg_html = String::new();
+    for m in flash_messages.iter() {
+        writeln!(msg_html, "<p><i>{}</i></p>", m.content()).unwrap();
+    }
+    let csrf_input = csrf_token.hidden_input();
+
+    let publish_html = if role.can(Permission::PublishNewsletters) {
+        format!(
+            r#"    <form action="/admin/newsletters/drafts/{}/publish" method="post">
+      <input hidden type="text" name="idempotency_key" value="{}">
+      {csrf_input}
+      <button type="submit">send!</button>
+    </form>"#,
+            draft.draft_id,
+            Uuid::new_v4(),
+        )
+    } else {
+        String::new()
+    };
+
+    Ok(page(
+        "Edit newsletter draft",
+        &format!(
+            r#"    {msg_html}
+    <form action="/admin/newsletters/drafts/{}" method="post">
+      {csrf_input}
+      <label>
+        title
+        <input name="title" type="text" value="{}">
+      </label>
+      <label>
+        text content
+        <textarea name="text">{}</textarea>
+      </label>
+      <label>
+        html content
+        <textarea name="html">{}</textarea>
+      </label>
+      <button type="submit">save draft</button>
+    </form>
+{publish_html}
+    <p><a href="/admin/newsletters/drafts">&lt;- Back</a></p>"#,
+            draft.draft_id,
+            htmlescape::encode_attribute(&draft.title),
+            htmlescape::encode_minimal(&draft.text_content),
+            htmlescape::encode_minimal(&draft.html_content),
+        ),
+    ))
+}
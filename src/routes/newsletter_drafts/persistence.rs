@@ -0,0 +1,113 @@
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use sqlx::{PgPool, Postgres, Transaction};
+use uuid::Uuid;
+
+use crate::routes::Content;
+
+pub struct NewsletterDraft {
+    pub draft_id: Uuid,
+    pub title: String,
+    pub text_content: String,
+    pub html_content: String,
+    pub updated_at: DateTime<Utc>,
+}
+
+#[tracing::instrument(name = "list newsletter drafts", skip(pool))]
+pub async fn list_drafts(pool: &PgPool) -> Result<Vec<NewsletterDraft>, anyhow::Error> {
+    sqlx::query_as!(
+        NewsletterDraft,
+        r#"
+        SELECT draft_id, title, text_content, html_content, updated_at
+        FROM newsletter_drafts
+        ORDER BY updated_at DESC
+        "#
+    )
+    .fetch_all(pool)
+    .await
+    .context("failed to list newsletter drafts")
+}
+
+pub async fn get_draft(
+    pool: &PgPool,
+    draft_id: Uuid,
+) -> Result<Option<NewsletterDraft>, anyhow::Error> {
+    sqlx::query_as!(
+        NewsletterDraft,
+        r#"
+        SELECT draft_id, title, text_content, html_content, updated_at
+        FROM newsletter_drafts
+        WHERE draft_id = $1
+        "#,
+        draft_id
+    )
+    .fetch_optional(pool)
+    .await
+    .context("failed to fetch newsletter draft")
+}
+
+pub async fn insert_draft(
+    pool: &PgPool,
+    user_id: Uuid,
+    title: &str,
+    content: &Content,
+) -> Result<Uuid, sqlx::Error> {
+    let draft_id = Uuid::new_v4();
+    sqlx::query!(
+        r#"
+        INSERT INTO newsletter_drafts(
+            draft_id, title, text_content, html_content, created_by, created_at, updated_at
+        )
+        VALUES ($1, $2, $3, $4, $5, now(), now())
+        "#,
+        draft_id,
+        title,
+        content.text,
+        content.html,
+        user_id
+    )
+    .execute(pool)
+    .await?;
+    Ok(draft_id)
+}
+
+/// false if there's no such draft, e.g. because it was published meanwhile
+pub async fn update_draft(
+    pool: &PgPool,
+    draft_id: Uuid,
+    title: &str,
+    content: &Content,
+) -> Result<bool, sqlx::Error> {
+    let result = sqlx::query!(
+        r#"
+        UPDATE newsletter_drafts
+        SET title = $2, text_content = $3, html_content = $4, updated_at = now()
+        WHERE draft_id = $1
+        "#,
+        draft_id,
+        title,
+        content.text,
+        content.html
+    )
+    .execute(pool)
+    .await?;
+    Ok(result.rows_affected() > 0)
+}
+
+/// removes the draft as it's published, so it can't go out twice
+pub async fn take_draft(
+    transaction: &mut Transaction<'_, Postgres>,
+    draft_id: Uuid,
+) -> Result<Option<NewsletterDraft>, sqlx::Error> {
+    sqlx::query_as!(
+        NewsletterDraft,
+        r#"
+        DELETE FROM newsletter_drafts
+        WHERE draft_id = $1
+        RETURNING draft_id, title, text_content, html_content, updated_at
+        "#,
+        draft_id
+    )
+    .fetch_optional(&mut **transaction)
+    .await
+}
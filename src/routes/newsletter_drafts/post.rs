@@ -0,0 +1,108 @@
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::FlashMessage;
+use anyhow::Context;
+use serde::Deserialize;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::{
+    audit::{record_audit_event, AuditContext},
+    authentication::middleware::UserId,
+    domain::AuditAction,
+    idempotency::IdempotentTransaction,
+    routes::{enqueue_delivery_tasks, insert_newsletter_issue, Content},
+    utils::{e404, e500, see_other},
+};
+
+use super::persistence::{insert_draft, take_draft, update_draft};
+
+/// same fields as the publish form, so either button of the newsletter form works
+#[derive(Deserialize)]
+pub struct DraftForm {
+    title: String,
+    #[serde(flatten)]
+    content: Content,
+}
+
+#[tracing::instrument(name = "save newsletter draft", skip(form, pool, user_id))]
+pub async fn save_newsletter_draft(
+    form: web::Form<DraftForm>,
+    pool: web::Data<PgPool>,
+    user_id: web::ReqData<UserId>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let DraftForm { title, content } = form.0;
+    let draft_id = insert_draft(&pool, **user_id, &title, &content)
+        .await
+        .context("failed to store newsletter draft")
+        .map_err(e500)?;
+
+    FlashMessage::info("Draft saved").send();
+    Ok(see_other(&format!(
+        "/admin/newsletters/drafts/{}",
+        draft_id
+    )))
+}
+
+#[tracing::instrument(name = "update newsletter draft", skip(form, pool))]
+pub async fn update_newsletter_draft(
+    draft_id: web::Path<Uuid>,
+    form: web::Form<DraftForm>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let draft_id = draft_id.into_inner();
+    let DraftForm { title, content } = form.0;
+    let found = update_draft(&pool, draft_id, &title, &content)
+        .await
+        .context("failed to update newsletter draft")
+        .map_err(e500)?;
+    if !found {
+        return Err(e404("draft not found"));
+    }
+
+    FlashMessage::info("Draft saved").send();
+    Ok(see_other(&format!(
+        "/admin/newsletters/drafts/{}",
+        draft_id
+    )))
+}
+
+/// sends the draft as it was last saved; the `idempotent` middleware replays the
+/// response to double submissions
+#[tracing::instrument(name = "publish newsletter draft", skip(transaction, audit))]
+pub async fn publish_newsletter_draft(
+    draft_id: web::Path<Uuid>,
+    mut transaction: IdempotentTransaction,
+    audit: AuditContext,
+) -> Result<HttpResponse, actix_web::Error> {
+    let draft = take_draft(&mut transaction, draft_id.into_inner())
+        .await
+        .context("failed to fetch newsletter draft")
+        .map_err(e500)?
+        .ok_or_else(|| e404("draft not found"))?;
+
+    let issue_id = insert_newsletter_issue(
+        &mut transaction,
+        &draft.title,
+        &draft.text_content,
+        &draft.html_content,
+    )
+    .await
+    .context("failed to store newsletter issue details")
+    .map_err(e500)?;
+    enqueue_delivery_tasks(&mut transaction, issue_id)
+        .await
+        .context("failed to enqueue delivery task")
+        .map_err(e500)?;
+    record_audit_event(
+        &mut **transaction,
+        &audit,
+        AuditAction::NewsletterPublish,
+        Some(&issue_id.to_string()),
+        serde_json::json!({ "title": draft.title, "via": "draft", "draft_id": draft.draft_id }),
+    )
+    .await
+    .map_err(e500)?;
+
+    FlashMessage::info("Successfully sent out newsletter").send();
+    Ok(see_other("/admin/dashboard"))
+}
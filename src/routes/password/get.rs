@@ -1,15 +1,36 @@
-use actix_web::{http::header::ContentType, HttpResponse};
+use actix_web::{http::header::ContentType, web, HttpResponse};
 use actix_web_flash_messages::IncomingFlashMessages;
+use anyhow::Context;
+use sqlx::PgPool;
 use std::fmt::Write;
 
+use crate::{
+    authentication::{csrf::CsrfToken, middleware::UserId},
+    utils::e500,
+};
+
 pub async fn change_password_form(
     flash_messages: IncomingFlashMessages,
+    db_pool: web::Data<PgPool>,
+    user_id: web::ReqData<UserId>,
+    csrf_token: web::ReqData<CsrfToken>,
 ) -> Result<HttpResponse, actix_web::Error> {
     let mut msg_html = "".to_string();
     for m in flash_messages.iter() {
         writeln!(msg_html, "<p><i>{}</i></p>", m.content()).unwrap();
     }
 
+    let email = sqlx::query_scalar!(
+        "select email from users where user_id=$1",
+        *user_id.into_inner()
+    )
+    .fetch_one(db_pool.as_ref())
+    .await
+    .context("failed to retrieve user's email")
+    .map_err(e500)?;
+    let email = htmlescape::encode_minimal(email.as_deref().unwrap_or_default());
+    let csrf_input = csrf_token.hidden_input();
+
     let page_html = format!(r#"
         <!DOCTYPE html>
         <html lang="en">
@@ -22,6 +43,7 @@ pub async fn change_password_form(
         <body>
         {msg_html}
         <form action="/admin/password" method="post">
+            {csrf_input}
             <label for="">
                 Current password
                 <input type="password" name="old_password" value="enter password">
@@ -36,6 +58,15 @@ pub async fn change_password_form(
             </label>
 
             <button type="submit">confirm</button>
+        </form>
+        <form action="/admin/password/email" method="post">
+            {csrf_input}
+            <label for="">
+                Email for password reset links
+                <input type="email" name="email" value="{email}">
+            </label>
+
+            <button type="submit">save</button>
         </form>
             <p><a href="/admin/dashboard">&lt;- Back</a></p>
         </body>
@@ -3,4 +3,4 @@ mod post;
 
 // TODO: replace this with real function names
 pub use get::change_password_form;
-pub use post::change_password;
+pub use post::{change_account_email, change_password};
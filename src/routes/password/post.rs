@@ -5,7 +5,15 @@ use serde::Deserialize;
 use sqlx::PgPool;
 
 use crate::{
-    authentication::{self, middleware::UserId, validate_credentials, AuthError, Credentials},
+    audit::{record_audit_event, AuditContext},
+    authentication::{
+        self, check_password_policy,
+        middleware::{SessionId, UserId},
+        sessions::revoke_user_sessions,
+        validate_credentials, AuthError, Credentials, PasswordHashing,
+    },
+    configuration::PasswordPolicySettings,
+    domain::{AuditAction, SubscriberEmail},
     routes::get_username,
     utils::{e500, see_other},
 };
@@ -21,6 +29,10 @@ pub async fn change_password(
     form: web::Form<FormData>,
     db_pool: web::Data<PgPool>,
     user_id: web::ReqData<UserId>,
+    password_policy: web::Data<PasswordPolicySettings>,
+    password_hashing: web::Data<PasswordHashing>,
+    session_id: web::ReqData<SessionId>,
+    audit: AuditContext,
 ) -> Result<HttpResponse, actix_web::Error> {
     let user_id = user_id.into_inner();
     if form.new_password.expose_secret() != form.confirm_new_password.expose_secret() {
@@ -29,27 +41,111 @@ pub async fn change_password(
     }
 
     let username = get_username(&db_pool, *user_id).await.map_err(e500)?;
+    if let Err(e) = check_password_policy(&password_policy, &form.new_password, &username) {
+        FlashMessage::error(format!("<p><i>{}</i></p>", e)).send();
+        return Ok(see_other("/admin/password"));
+    }
     let credentials = Credentials {
         username,
         password: form.0.old_password,
     };
 
     // if this is an error do something
-    if let Err(e) = validate_credentials(credentials, &db_pool).await {
+    if let Err(e) = validate_credentials(credentials, &db_pool, &password_hashing).await {
         return match e {
             AuthError::InvalidCredentials(_) => {
                 FlashMessage::error("<p><i>Current password is incorrect</i></p>").send();
                 return Ok(see_other("/admin/password"));
             }
-            AuthError::UnexpectedError(_) => Err(e500(e).into()),
+            AuthError::UnexpectedError(_) => Err(e500(e)),
         };
     }
 
     // update new password hash
-    authentication::change_password(*user_id, form.0.new_password, &db_pool)
+    authentication::change_password(
+        *user_id,
+        form.0.new_password,
+        &db_pool,
+        &password_hashing,
+    )
+    .await
+    .map_err(e500)?;
+    // anyone else who knew the old password gets logged out
+    revoke_user_sessions(db_pool.as_ref(), *user_id, Some(**session_id))
         .await
         .map_err(e500)?;
+    record_audit_event(
+        db_pool.as_ref(),
+        &audit,
+        AuditAction::PasswordChange,
+        Some(&user_id.to_string()),
+        serde_json::json!({}),
+    )
+    .await
+    .map_err(e500)?;
 
     FlashMessage::info("<p><i>Password changed successfully</i></p>").send();
     Ok(see_other("/admin/password"))
 }
+
+#[derive(Deserialize)]
+pub struct EmailFormData {
+    email: String,
+}
+
+/// an empty address turns password reset emails off
+pub async fn change_account_email(
+    form: web::Form<EmailFormData>,
+    db_pool: web::Data<PgPool>,
+    user_id: web::ReqData<UserId>,
+    audit: AuditContext,
+) -> Result<HttpResponse, actix_web::Error> {
+    let user_id = user_id.into_inner();
+    let email = match form.0.email.trim() {
+        "" => None,
+        email => match SubscriberEmail::parse(email.to_lowercase()) {
+            Ok(email) => Some(email),
+            Err(e) => {
+                FlashMessage::error(htmlescape::encode_minimal(&e)).send();
+                return Ok(see_other("/admin/password"));
+            }
+        },
+    };
+
+    let mut transaction = db_pool.begin().await.map_err(e500)?;
+    let previous_email = sqlx::query_scalar!(
+        "SELECT email FROM users WHERE user_id = $1 FOR UPDATE",
+        *user_id
+    )
+    .fetch_one(&mut *transaction)
+    .await
+    .map_err(e500)?;
+    let new_email = email.as_ref().map(|e| e.as_ref());
+    let result = sqlx::query!(
+        "update users set email=$1 where user_id=$2",
+        new_email,
+        *user_id
+    )
+    .execute(&mut *transaction)
+    .await;
+    match result {
+        Ok(_) => {
+            record_audit_event(
+                &mut *transaction,
+                &audit,
+                AuditAction::AccountEmailChange,
+                Some(&user_id.to_string()),
+                serde_json::json!({ "email": { "from": previous_email, "to": new_email } }),
+            )
+            .await
+            .map_err(e500)?;
+            transaction.commit().await.map_err(e500)?;
+            FlashMessage::info("Email updated").send()
+        }
+        Err(sqlx::Error::Database(e)) if e.is_unique_violation() => {
+            FlashMessage::error("Another user already has that email").send()
+        }
+        Err(e) => return Err(e500(anyhow::Error::new(e).context("failed to update user's email"))),
+    }
+    Ok(see_other("/admin/password"))
+}
@@ -0,0 +1,97 @@
+use actix_web::{http::header::ContentType, web, HttpResponse};
+use actix_web_flash_messages::IncomingFlashMessages;
+use serde::Deserialize;
+use sqlx::PgPool;
+use std::fmt::Write;
+
+use crate::utils::e500;
+
+use super::persistence::get_user_id_from_reset_token;
+
+#[derive(Deserialize)]
+pub struct ResetTokenParameters {
+    token: String,
+}
+
+fn page(title: &str, content: &str) -> HttpResponse {
+    HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"<!DOCTYPE html>
+<html lang="en">
+  <head>
+    <meta charset="UTF-8">
+    <meta name="viewport" content="width=device-width, initial-scale=1">
+    <title>{title}</title>
+  </head>
+  <body>
+  {content}
+  </body>
+</html>"#
+        ))
+}
+
+pub async fn password_reset_form(flash_messages: IncomingFlashMessages) -> HttpResponse {
+    let mut msg_html = String::new();
+    for m in flash_messages.iter() {
+        writeln!(msg_html, "<p><i>{}</i></p>", m.content()).unwrap();
+    }
+
+    page(
+        "Forgot your password",
+        &format!(
+            r#"{msg_html}
+  <form action="/password_reset" method="post">
+     <label>
+        Username or email
+        <input type="text" name="identifier">
+      </label>
+      <button type="submit">Send reset link</button>
+   </form>
+   <p><a href="/login">&lt;- Back to login</a></p>"#
+        ),
+    )
+}
+
+#[tracing::instrument(name = "password reset link", skip(parameters, pool, flash_messages))]
+pub async fn set_new_password_form(
+    parameters: web::Query<ResetTokenParameters>,
+    pool: web::Data<PgPool>,
+    flash_messages: IncomingFlashMessages,
+) -> Result<HttpResponse, actix_web::Error> {
+    if get_user_id_from_reset_token(&pool, &parameters.token)
+        .await
+        .map_err(e500)?
+        .is_none()
+    {
+        return Ok(page(
+            "Reset link expired",
+            r#"<p>This reset link has expired or was already used.</p>
+  <p><a href="/password_reset">Request a new one</a></p>"#,
+        ));
+    }
+
+    let mut msg_html = String::new();
+    for m in flash_messages.iter() {
+        writeln!(msg_html, "<p><i>{}</i></p>", m.content()).unwrap();
+    }
+    Ok(page(
+        "Choose a new password",
+        &format!(
+            r#"{msg_html}
+  <form action="/password_reset/confirm" method="post">
+     <input hidden type="text" name="token" value="{}">
+     <label>
+        New password
+        <input type="password" name="new_password">
+      </label>
+     <label>
+        Confirm new password
+        <input type="password" name="confirm_new_password">
+      </label>
+      <button type="submit">Set password</button>
+   </form>"#,
+            htmlescape::encode_attribute(&parameters.token)
+        ),
+    ))
+}
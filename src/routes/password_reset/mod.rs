@@ -0,0 +1,9 @@
+mod get;
+mod persistence;
+mod post;
+
+pub use get::*;
+pub use persistence::{
+    count_recent_reset_tokens, find_user, store_reset_token, RESET_TOKEN_TTL_MINUTES,
+};
+pub use post::*;
@@ -0,0 +1,133 @@
+use anyhow::Context;
+use sqlx::{Executor, PgExecutor, PgPool, Postgres, Transaction};
+use uuid::Uuid;
+
+use crate::utils::hash_token;
+
+/// reset links are only good for a short while, and only once
+pub const RESET_TOKEN_TTL_MINUTES: i32 = 30;
+
+pub struct ResetCandidate {
+    pub user_id: Uuid,
+    pub email: Option<String>,
+}
+
+/// matches either the username or the email address, case insensitively for the latter
+#[tracing::instrument(name = "find user for password reset", skip(executor))]
+pub async fn find_user<'e>(
+    executor: impl PgExecutor<'e>,
+    identifier: &str,
+) -> Result<Option<ResetCandidate>, anyhow::Error> {
+    sqlx::query_as!(
+        ResetCandidate,
+        "SELECT user_id, email FROM users WHERE name = $1 OR lower(email) = lower($1)",
+        identifier
+    )
+    .fetch_optional(executor)
+    .await
+    .context("failed to look up user")
+}
+
+pub async fn enqueue_reset_request(pool: &PgPool, identifier: &str) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+        INSERT INTO password_reset_request_queue(request_id, identifier, requested_at)
+        VALUES ($1, $2, now())
+        "#,
+        Uuid::new_v4(),
+        identifier
+    )
+    .execute(pool)
+    .await?;
+    Ok(())
+}
+
+/// links sent to the user within the last `hours`, used or not
+pub async fn count_recent_reset_tokens<'e>(
+    executor: impl PgExecutor<'e>,
+    user_id: Uuid,
+    hours: i32,
+) -> Result<i64, sqlx::Error> {
+    sqlx::query_scalar!(
+        r#"
+        SELECT count(*) as "count!" FROM password_reset_tokens
+        WHERE user_id = $1 AND created_at > now() - make_interval(hours => $2)
+        "#,
+        user_id,
+        hours
+    )
+    .fetch_one(executor)
+    .await
+}
+
+pub async fn store_reset_token<'e>(
+    executor: impl PgExecutor<'e>,
+    reset_token: &str,
+    user_id: Uuid,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+        INSERT INTO password_reset_tokens(reset_token_hash, user_id, created_at, used_at)
+        VALUES ($1, $2, now(), NULL)
+        "#,
+        hash_token(reset_token),
+        user_id
+    )
+    .execute(executor)
+    .await?;
+    Ok(())
+}
+
+/// `None` if the token doesn't exist, was used or has expired
+#[tracing::instrument(name = "check password reset token", skip(pool, reset_token))]
+pub async fn get_user_id_from_reset_token(
+    pool: &PgPool,
+    reset_token: &str,
+) -> Result<Option<Uuid>, anyhow::Error> {
+    sqlx::query_scalar!(
+        r#"
+        SELECT user_id FROM password_reset_tokens
+        WHERE
+            reset_token_hash = $1 AND
+            used_at IS NULL AND
+            created_at > now() - make_interval(mins => $2)
+        "#,
+        hash_token(reset_token),
+        RESET_TOKEN_TTL_MINUTES,
+    )
+    .fetch_optional(pool)
+    .await
+    .context("failed to look up password reset token")
+}
+
+/// marks the token, and any other outstanding token for the same user, used;
+/// `None` if it wasn't valid anymore
+pub async fn consume_reset_token(
+    transaction: &mut Transaction<'_, Postgres>,
+    reset_token: &str,
+) -> Result<Option<Uuid>, sqlx::Error> {
+    let user_id = sqlx::query_scalar!(
+        r#"
+        UPDATE password_reset_tokens SET used_at = now()
+        WHERE
+            reset_token_hash = $1 AND
+            used_at IS NULL AND
+            created_at > now() - make_interval(mins => $2)
+        RETURNING user_id
+        "#,
+        hash_token(reset_token),
+        RESET_TOKEN_TTL_MINUTES,
+    )
+    .fetch_optional(&mut **transaction)
+    .await?;
+
+    if let Some(user_id) = user_id {
+        transaction
+            .execute(sqlx::query!(
+                "UPDATE password_reset_tokens SET used_at = now() WHERE user_id = $1 AND used_at IS NULL",
+                user_id
+            ))
+            .await?;
+    }
+    Ok(user_id)
+}
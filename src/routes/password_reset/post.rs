@@ -0,0 +1,169 @@
+use std::time::Duration;
+
+use actix_web::{web, HttpRequest, HttpResponse};
+use actix_web_flash_messages::FlashMessage;
+use anyhow::Context;
+use secrecy::{ExposeSecret, Secret};
+use serde::Deserialize;
+use sqlx::PgPool;
+
+use crate::{
+    audit::{record_audit_event, AuditContext},
+    authentication::{
+        check_password_policy, hash_new_password, sessions::revoke_user_sessions,
+        store_password_hash, PasswordHashing,
+    },
+    configuration::PasswordPolicySettings,
+    domain::AuditAction,
+    rate_limit::RateLimiter,
+    routes::get_username,
+    utils::{client_ip, e500, see_other},
+};
+
+use super::persistence::{
+    consume_reset_token, enqueue_reset_request, get_user_id_from_reset_token,
+};
+
+const RESET_IP_BUCKET: &str = "password_reset_ip";
+/// per address, so the form can't be used to probe many accounts; the worker
+/// limits how many links each user gets
+const MAX_RESET_REQUESTS_PER_IP_PER_HOUR: u64 = 10;
+
+#[derive(Deserialize)]
+pub struct PasswordResetRequestForm {
+    identifier: String,
+}
+
+#[derive(Deserialize)]
+pub struct NewPasswordForm {
+    token: String,
+    new_password: Secret<String>,
+    confirm_new_password: Secret<String>,
+}
+
+/// always answers the same way, and about as fast, so the form can't be used to
+/// find out who has an account; the email is sent by `password_reset_workers`
+#[tracing::instrument(
+    name = "request password reset",
+    skip(request, form, pool, rate_limiter)
+)]
+pub async fn request_password_reset(
+    request: HttpRequest,
+    form: web::Form<PasswordResetRequestForm>,
+    pool: web::Data<PgPool>,
+    rate_limiter: web::Data<RateLimiter>,
+) -> Result<HttpResponse, actix_web::Error> {
+    FlashMessage::info(
+        "If that account exists and has an email address, a reset link is on its way.",
+    )
+    .send();
+    let response = see_other("/password_reset");
+
+    let ip_address = client_ip(&request).unwrap_or_else(|| "unknown".into());
+    let is_allowed = rate_limiter
+        .check(
+            RESET_IP_BUCKET,
+            &ip_address,
+            MAX_RESET_REQUESTS_PER_IP_PER_HOUR,
+            Duration::from_secs(60 * 60),
+        )
+        .await
+        .context("failed to check password reset rate limit")
+        .map_err(e500)?;
+    if !is_allowed {
+        tracing::warn!(%ip_address, "too many password reset requests");
+        return Ok(response);
+    }
+
+    let identifier = form.0.identifier.trim().to_string();
+    enqueue_reset_request(&pool, &identifier)
+        .await
+        .context("failed to enqueue password reset request")
+        .map_err(e500)?;
+
+    Ok(response)
+}
+
+#[tracing::instrument(
+    name = "reset password",
+    skip(form, pool, password_policy, password_hashing)
+)]
+pub async fn reset_password(
+    form: web::Form<NewPasswordForm>,
+    pool: web::Data<PgPool>,
+    password_policy: web::Data<PasswordPolicySettings>,
+    password_hashing: web::Data<PasswordHashing>,
+    audit: AuditContext,
+) -> Result<HttpResponse, actix_web::Error> {
+    let NewPasswordForm {
+        token,
+        new_password,
+        confirm_new_password,
+    } = form.0;
+    let retry = |message: &str| {
+        FlashMessage::error(message).send();
+        see_other(&format!(
+            "/password_reset/confirm?token={}",
+            urlencoding::encode(&token)
+        ))
+    };
+    if new_password.expose_secret() != confirm_new_password.expose_secret() {
+        return Ok(retry("You entered two different passwords"));
+    }
+
+    // checked before the token is used up, so the user can try another password
+    if let Some(user_id) = get_user_id_from_reset_token(&pool, &token)
+        .await
+        .map_err(e500)?
+    {
+        let username = get_username(&pool, user_id).await.map_err(e500)?;
+        if let Err(e) = check_password_policy(&password_policy, &new_password, &username) {
+            return Ok(retry(&e.to_string()));
+        }
+    }
+
+    // hashed up front, there's no need to hold the token's row lock while argon2 runs
+    let password_hash = hash_new_password(new_password, &password_hashing)
+        .await
+        .map_err(e500)?;
+
+    // all or nothing: a token is only used up by a reset that went through
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("failed to establish connection to postgres")
+        .map_err(e500)?;
+    let Some(user_id) = consume_reset_token(&mut transaction, &token)
+        .await
+        .context("failed to use password reset token")
+        .map_err(e500)?
+    else {
+        FlashMessage::error("This reset link has expired or was already used").send();
+        return Ok(see_other("/password_reset"));
+    };
+    store_password_hash(user_id, &password_hash, &mut *transaction)
+        .await
+        .map_err(e500)?;
+    // whoever knew the old password shouldn't stay logged in
+    revoke_user_sessions(&mut *transaction, user_id, None)
+        .await
+        .context("failed to revoke sessions")
+        .map_err(e500)?;
+    record_audit_event(
+        &mut *transaction,
+        &audit.as_user(user_id),
+        AuditAction::PasswordReset,
+        Some(&user_id.to_string()),
+        serde_json::json!({}),
+    )
+    .await
+    .map_err(e500)?;
+    transaction
+        .commit()
+        .await
+        .context("failed to commit postgres transaction")
+        .map_err(e500)?;
+
+    FlashMessage::info("Your password has been reset, you can log in with it now").send();
+    Ok(see_other("/login"))
+}
@@ -0,0 +1,86 @@
+use actix_web::{http::header::ContentType, web, HttpResponse};
+use actix_web_flash_messages::IncomingFlashMessages;
+use sqlx::PgPool;
+use std::fmt::Write;
+
+use crate::{
+    authentication::{
+        csrf::CsrfToken,
+        middleware::{SessionId, UserId},
+        sessions::list_active_sessions,
+    },
+    utils::e500,
+};
+
+pub async fn list_sessions(
+    pool: web::Data<PgPool>,
+    flash_messages: IncomingFlashMessages,
+    user_id: web::ReqData<UserId>,
+    session_id: web::ReqData<SessionId>,
+    csrf_token: web::ReqData<CsrfToken>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let mut msg_html = String::new();
+    for m in flash_messages.iter() {
+        writeln!(msg_html, "<p><i>{}</i></p>", m.content()).unwrap();
+    }
+
+    let sessions = list_active_sessions(pool.as_ref(), **user_id)
+        .await
+        .map_err(e500)?;
+    let csrf_input = csrf_token.hidden_input();
+    let mut rows_html = String::new();
+    for s in &sessions {
+        let action_html = if s.session_id == **session_id {
+            "this session".to_string()
+        } else {
+            format!(
+                r#"<form action="/admin/sessions/{}/revoke" method="post">{csrf_input}<button type="submit">Log out</button></form>"#,
+                s.session_id
+            )
+        };
+        writeln!(
+            rows_html,
+            "      <tr><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td></tr>",
+            s.created_at.format("%Y-%m-%d %H:%M UTC"),
+            s.last_seen_at.format("%Y-%m-%d %H:%M UTC"),
+            htmlescape::encode_minimal(&s.ip_address),
+            htmlescape::encode_minimal(s.user_agent.as_deref().unwrap_or("unknown")),
+            action_html,
+        )
+        .unwrap();
+    }
+    let others_html = if sessions.len() > 1 {
+        format!(
+            r#"
+    <form action="/admin/sessions/revoke_others" method="post">
+      {csrf_input}
+      <button type="submit">Log out all other sessions</button>
+    </form>"#
+        )
+    } else {
+        String::new()
+    };
+
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"
+<!DOCTYPE html>
+<html lang="en">
+  <head>
+    <meta charset="UTF-8">
+    <meta name="viewport" content="width=device-width, initial-scale=1">
+    <title>Sessions</title>
+  </head>
+  <body>
+    {msg_html}
+    <p>Where you're logged in:</p>
+    <table>
+      <tr><th>Logged in</th><th>Last seen</th><th>IP address</th><th>Browser</th><th></th></tr>
+{rows_html}    </table>{others_html}
+    <p><a href="/admin/dashboard">&lt;- Back</a></p>
+  </body>
+</html>
+"#
+        )))
+}
@@ -0,0 +1,70 @@
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::FlashMessage;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::{
+    audit::{record_audit_event, AuditContext},
+    authentication::{
+        middleware::{SessionId, UserId},
+        sessions::{revoke_session, revoke_user_sessions},
+    },
+    domain::AuditAction,
+    utils::{e500, see_other},
+};
+
+fn sessions_page() -> HttpResponse {
+    see_other("/admin/sessions")
+}
+
+#[tracing::instrument(name = "end session", skip(pool))]
+pub async fn end_session(
+    pool: web::Data<PgPool>,
+    path: web::Path<Uuid>,
+    user_id: web::ReqData<UserId>,
+    audit: AuditContext,
+) -> Result<HttpResponse, actix_web::Error> {
+    let revoked_session_id = path.into_inner();
+    // scoped to the user, so other people's sessions look the same as unknown ones
+    if revoke_session(pool.as_ref(), **user_id, revoked_session_id)
+        .await
+        .map_err(e500)?
+    {
+        record_audit_event(
+            pool.as_ref(),
+            &audit,
+            AuditAction::SessionRevoke,
+            Some(&revoked_session_id.to_string()),
+            serde_json::json!({}),
+        )
+        .await
+        .map_err(e500)?;
+        FlashMessage::info("Session logged out").send();
+    } else {
+        FlashMessage::error("That session had already ended").send();
+    }
+    Ok(sessions_page())
+}
+
+#[tracing::instrument(name = "end other sessions", skip(pool))]
+pub async fn end_other_sessions(
+    pool: web::Data<PgPool>,
+    user_id: web::ReqData<UserId>,
+    session_id: web::ReqData<SessionId>,
+    audit: AuditContext,
+) -> Result<HttpResponse, actix_web::Error> {
+    revoke_user_sessions(pool.as_ref(), **user_id, Some(**session_id))
+        .await
+        .map_err(e500)?;
+    record_audit_event(
+        pool.as_ref(),
+        &audit,
+        AuditAction::SessionRevoke,
+        None,
+        serde_json::json!({ "kept_session_id": **session_id }),
+    )
+    .await
+    .map_err(e500)?;
+    FlashMessage::info("All other sessions were logged out").send();
+    Ok(sessions_page())
+}
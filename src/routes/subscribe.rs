@@ -1,4 +1,7 @@
-use actix_web::{http::StatusCode, web, HttpResponse, ResponseError};
+use actix_web::{
+    http::{header, StatusCode},
+    web, HttpRequest, HttpResponse, ResponseError,
+};
 use anyhow::Context;
 use chrono::Local as Utc;
 use rand::{distributions::Alphanumeric, Rng};
@@ -10,8 +13,15 @@ use tracing;
 use uuid::Uuid;
 
 use crate::{
-    domain::{NewSubscriber, SubscriberEmail, SubscriberName},
+    configuration::SignupSettings,
+    domain::{
+        is_disposable_domain, ConsentContext, ConsentEventKind, NewSubscriber, SubscriberEmail,
+        SubscriberName, CONSENT_TEXT_VERSION,
+    },
     email_client::EmailClient,
+    rate_limit::RateLimiter,
+    suppressions::is_suppressed,
+    utils::client_ip,
     ApplicationBaseUrl,
 };
 
@@ -19,6 +29,10 @@ use crate::{
 pub struct FormData {
     name: String,
     email: String,
+    /// which form or campaign the sign-up came from
+    source: Option<String>,
+    /// hidden from people by the form's css, only bots fill it in
+    website: Option<String>,
 }
 
 impl TryFrom<FormData> for NewSubscriber {
@@ -35,6 +49,8 @@ impl TryFrom<FormData> for NewSubscriber {
 pub enum SubscribeError {
     #[error("{0}")]
     ValidationError(String),
+    #[error("too many sign-up attempts")]
+    TooManyAttempts,
     #[error(transparent)]
     Other(#[from] anyhow::Error),
 }
@@ -43,6 +59,7 @@ impl ResponseError for SubscribeError {
     fn status_code(&self) -> actix_web::http::StatusCode {
         match self {
             Self::ValidationError(_) => StatusCode::BAD_REQUEST,
+            Self::TooManyAttempts => StatusCode::TOO_MANY_REQUESTS,
             Self::Other(_) => StatusCode::INTERNAL_SERVER_ERROR,
         }
     }
@@ -71,19 +88,69 @@ impl Debug for SubscribeError {
 
 #[tracing::instrument(
     name = "Adding a new subscriber",
-    skip(form, pool, email_client, base_url),
+    skip(request, form, pool, email_client, base_url, rate_limiter, signup),
     fields(
         subscriber_email = %form.email,
         subscriber_name = %form.name,
     )
 )]
 pub async fn subscribe<'a>(
+    request: HttpRequest,
     form: web::Form<FormData>,
     pool: web::Data<PgPool>,
     email_client: web::Data<EmailClient>,
     base_url: web::Data<ApplicationBaseUrl>,
+    rate_limiter: web::Data<RateLimiter>,
+    signup: web::Data<SignupSettings>,
 ) -> Result<HttpResponse, SubscribeError> {
-    let subscriber = form.0.try_into().map_err(SubscribeError::ValidationError)?;
+    let mut form = form.0;
+    // bots get the same answer as everyone else so they don't learn to skip the field,
+    // and they don't get to use up anyone's sign-up allowance either
+    if form.website.as_deref().is_some_and(|w| !w.is_empty()) {
+        tracing::info!("ignoring sign-up with the honeypot field filled in");
+        return Ok(HttpResponse::Ok().finish());
+    }
+
+    let ip_address = client_ip(&request).unwrap_or_else(|| "unknown".into());
+    if !rate_limiter
+        .check(
+            "subscribe_ip",
+            &ip_address,
+            signup.max_attempts_per_ip,
+            signup.window(),
+        )
+        .await
+        .context("failed to check sign-up rate limit")?
+    {
+        return Err(SubscribeError::TooManyAttempts);
+    }
+
+    let source = form
+        .source
+        .take()
+        .filter(|s| !s.trim().is_empty())
+        .unwrap_or_else(|| "subscribe_form".into());
+    let consent = consent_context(&request, source);
+    let subscriber: NewSubscriber = form.try_into().map_err(SubscribeError::ValidationError)?;
+
+    if signup.block_disposable_domains && is_disposable_domain(subscriber.email.domain()) {
+        return Err(SubscribeError::ValidationError(
+            "disposable email addresses are not accepted".into(),
+        ));
+    }
+    // stops anyone from flooding a single inbox with confirmation emails
+    if !rate_limiter
+        .check(
+            "subscribe_email",
+            &subscriber.email.as_ref().to_lowercase(),
+            signup.max_attempts_per_email,
+            signup.window(),
+        )
+        .await
+        .context("failed to check sign-up rate limit")?
+    {
+        return Err(SubscribeError::TooManyAttempts);
+    }
 
     // NOTE:make subscribe and tokens table update atomic
     let mut transaction = pool
@@ -101,20 +168,24 @@ pub async fn subscribe<'a>(
         .await
         .context("failed to persist subscription token")?;
 
+    record_consent_event(&mut transaction, uid, ConsentEventKind::Subscribe, &consent)
+        .await
+        .context("failed to record consent")?;
+
     // make sure to commit transaction !
     transaction
         .commit()
         .await
         .context("failed to commit postgres transaction")?;
 
-   send_confirmation_email(&email_client, subscriber, &base_url.0, &token)
+    send_confirmation_email(&email_client, &pool, subscriber, &base_url.0, &token)
         .await
         .context("failed to send confirmation email")?;
 
     Ok(HttpResponse::Ok().finish())
 }
 
-fn generate_random_token() -> String {
+pub fn generate_random_token() -> String {
     rand::thread_rng()
         .sample_iter(Alphanumeric)
         .map(char::from)
@@ -122,16 +193,26 @@ fn generate_random_token() -> String {
         .collect::<String>()
 }
 
+/// silently does nothing for suppressed addresses, so callers can't leak who is on the list
 #[tracing::instrument(
     name = "send confirmation email to new subscriber",
-    skip(email_client, sub, base_url, token)
+    skip(email_client, pool, sub, base_url, token)
 )]
 pub async fn send_confirmation_email(
     email_client: &EmailClient,
+    pool: &PgPool,
     sub: NewSubscriber,
     base_url: &str,
     token: &str,
-) -> Result<(), reqwest::Error> {
+) -> Result<(), anyhow::Error> {
+    if is_suppressed(pool, &sub.email)
+        .await
+        .context("failed to check suppression list")?
+    {
+        tracing::info!("not sending a confirmation email to a suppressed address");
+        return Ok(());
+    }
+
     let confirmation_link = format!("{}/subscribe/confirm?token={}", base_url, token);
 
     let plain_body = format!(
@@ -144,7 +225,7 @@ pub async fn send_confirmation_email(
         confirmation_link
     );
 
-    let response = email_client
+    email_client
         .send_email(&sub.email, "welcome!", &html_body, &plain_body)
         .await?;
 
@@ -209,3 +290,53 @@ pub async fn store_token(
     transaction.execute(query).await?;
     Ok(())
 }
+
+// longer values are cut rather than rejected, they're only kept as evidence
+const MAX_CONSENT_FIELD_LENGTH: usize = 512;
+
+pub fn consent_context(request: &HttpRequest, source: String) -> ConsentContext {
+    let truncate = |s: &str| s.chars().take(MAX_CONSENT_FIELD_LENGTH).collect::<String>();
+    ConsentContext {
+        ip_address: client_ip(request).as_deref().map(truncate),
+        user_agent: request
+            .headers()
+            .get(header::USER_AGENT)
+            .and_then(|h| h.to_str().ok())
+            .map(truncate),
+        source: truncate(&source),
+    }
+}
+
+#[tracing::instrument(name = "record consent event", skip(transaction, consent))]
+pub async fn record_consent_event(
+    transaction: &mut Transaction<'_, Postgres>,
+    subscriber_id: Uuid,
+    kind: ConsentEventKind,
+    consent: &ConsentContext,
+) -> Result<(), sqlx::Error> {
+    transaction
+        .execute(query!(
+            r#"
+            INSERT INTO consent_events(
+                event_id,
+                subscriber_id,
+                kind,
+                ip_address,
+                user_agent,
+                source,
+                consent_text_version,
+                occurred_at
+            )
+            VALUES ($1, $2, $3, $4, $5, $6, $7, now())
+            "#,
+            Uuid::new_v4(),
+            subscriber_id,
+            kind.as_str(),
+            consent.ip_address,
+            consent.user_agent,
+            consent.source,
+            CONSENT_TEXT_VERSION,
+        ))
+        .await?;
+    Ok(())
+}
@@ -1,18 +1,27 @@
 use actix_web::{
     web::{self, Query},
-    HttpResponse,
+    HttpRequest, HttpResponse,
 };
 use serde::Deserialize;
-use sqlx::{query, PgPool};
+use sqlx::{query, Executor, PgPool, Postgres, Transaction};
 use uuid::Uuid;
 
+use crate::{
+    domain::ConsentEventKind,
+    routes::{consent_context, record_consent_event},
+};
+
 #[derive(Deserialize)]
 pub struct Parameters {
     token: String,
 }
 
-#[tracing::instrument(name = "confirming a new subscriber", skip(pool, parameters))]
-pub async fn confirm(parameters: Query<Parameters>, pool: web::Data<PgPool>) -> HttpResponse {
+#[tracing::instrument(name = "confirming a new subscriber", skip(request, pool, parameters))]
+pub async fn confirm(
+    request: HttpRequest,
+    parameters: Query<Parameters>,
+    pool: web::Data<PgPool>,
+) -> HttpResponse {
     let subscriber_id = match get_subscriber_id_from_token(&parameters.token, &pool).await {
         Ok(uid) => uid,
         Err(_) => return HttpResponse::InternalServerError().finish(),
@@ -20,8 +29,16 @@ pub async fn confirm(parameters: Query<Parameters>, pool: web::Data<PgPool>) ->
 
     match subscriber_id {
         Some(id) => {
-            if confirm_subscriber(id, &pool).await.is_err() {
-                HttpResponse::InternalServerError().finish();
+            let consent = consent_context(&request, "confirmation_link".into());
+            let confirmed = async {
+                let mut transaction = pool.begin().await?;
+                confirm_subscriber(id, &mut transaction).await?;
+                record_consent_event(&mut transaction, id, ConsentEventKind::Confirm, &consent)
+                    .await?;
+                transaction.commit().await
+            };
+            if confirmed.await.is_err() {
+                return HttpResponse::InternalServerError().finish();
             }
             HttpResponse::Ok().finish()
         }
@@ -49,14 +66,17 @@ async fn get_subscriber_id_from_token(
     Ok(record.map(|r| r.subscriber_id))
 }
 
-#[tracing::instrument(name = "update subscriber status to confirmed", skip(pool, uid))]
-async fn confirm_subscriber(uid: Uuid, pool: &PgPool) -> Result<(), sqlx::Error> {
-    query!(
-        r"update subscriptions set status='confirmed' where id = $1",
-        uid,
-    )
-    .execute(pool)
-    .await
+#[tracing::instrument(name = "update subscriber status to confirmed", skip(transaction, uid))]
+async fn confirm_subscriber(
+    uid: Uuid,
+    transaction: &mut Transaction<'_, Postgres>,
+) -> Result<(), sqlx::Error> {
+    transaction
+        .execute(query!(
+            r"update subscriptions set status='confirmed' where id = $1",
+            uid,
+        ))
+        .await
     .map_err(|e| {
         tracing::error!("Failed to execute query: {:?}", e);
         e
@@ -0,0 +1,104 @@
+use actix_web::{
+    http::header::{ContentDisposition, ContentType, DispositionParam, DispositionType},
+    web, HttpResponse,
+};
+use serde::Deserialize;
+use sqlx::PgPool;
+
+use crate::utils::e500;
+
+use super::persistence::{get_subscriber_data, get_subscriber_id_from_data_token};
+
+#[derive(Deserialize)]
+pub struct TokenParameters {
+    token: String,
+}
+
+pub async fn data_request_form() -> HttpResponse {
+    HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(
+            r#"<!DOCTYPE html>
+<html lang="en">
+  <head>
+    <meta charset="UTF-8">
+    <meta name="viewport" content="width=device-width, initial-scale=1">
+    <title>Your data</title>
+    <link href="css/style.css" rel="stylesheet">
+  </head>
+  <body>
+    <p>Enter the address you subscribed with and we'll email you a link to download or erase your data.</p>
+    <form action="/subscriptions/data/request" method="post">
+      <label for="">
+        Email
+        <input type="email" name="email">
+      </label>
+      <button type="submit">send link</button>
+    </form>
+  </body>
+</html>"#,
+        )
+}
+
+#[tracing::instrument(name = "download subscriber data", skip(parameters, pool))]
+pub async fn download_subscriber_data(
+    parameters: web::Query<TokenParameters>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let Some(subscriber_id) = get_subscriber_id_from_data_token(&pool, &parameters.token)
+        .await
+        .map_err(e500)?
+    else {
+        return Ok(HttpResponse::Unauthorized().finish());
+    };
+    let Some(data) = get_subscriber_data(&pool, subscriber_id)
+        .await
+        .map_err(e500)?
+    else {
+        return Ok(HttpResponse::Unauthorized().finish());
+    };
+
+    Ok(HttpResponse::Ok()
+        .insert_header(ContentDisposition {
+            disposition: DispositionType::Attachment,
+            parameters: vec![DispositionParam::Filename("my-data.json".into())],
+        })
+        .json(data))
+}
+
+// erasing is only ever done through the POST so that link previews and
+// prefetchers can't erase anyone by following the emailed link
+pub async fn erasure_form(
+    parameters: web::Query<TokenParameters>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    if get_subscriber_id_from_data_token(&pool, &parameters.token)
+        .await
+        .map_err(e500)?
+        .is_none()
+    {
+        return Ok(HttpResponse::Unauthorized().finish());
+    }
+    let token = htmlescape::encode_attribute(&parameters.token);
+
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"<!DOCTYPE html>
+<html lang="en">
+  <head>
+    <meta charset="UTF-8">
+    <meta name="viewport" content="width=device-width, initial-scale=1">
+    <title>Erase your data</title>
+    <link href="css/style.css" rel="stylesheet">
+  </head>
+  <body>
+    <p>This unsubscribes you and permanently deletes everything we hold about you.</p>
+    <form action="/subscriptions/erase" method="post">
+      <input hidden type="text" name="token" value="{token}">
+      <button type="submit">erase my data</button>
+    </form>
+  </body>
+</html>"#
+        )))
+}
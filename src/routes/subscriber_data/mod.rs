@@ -0,0 +1,9 @@
+mod get;
+mod persistence;
+mod post;
+
+pub use get::*;
+pub use persistence::{
+    erase_subscriber, get_consent_events, store_data_token, ConsentEvent, ErasureRequester,
+};
+pub use post::*;
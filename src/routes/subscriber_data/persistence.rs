@@ -0,0 +1,336 @@
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use sqlx::{Executor, PgPool, Postgres, Transaction};
+use uuid::Uuid;
+
+use crate::{domain::SubscriberEmail, utils::hash_token};
+
+// links emailed to subscribers stop working after this
+const DATA_TOKEN_TTL_HOURS: i32 = 24;
+
+#[derive(Serialize)]
+pub struct SubscriberData {
+    pub subscription: Subscription,
+    pub subscription_tokens: Vec<String>,
+    pub pending_deliveries: Vec<PendingDelivery>,
+    pub consent_events: Vec<ConsentEvent>,
+    pub email_events: Vec<EmailEvent>,
+}
+
+#[derive(Serialize)]
+pub struct Subscription {
+    pub id: Uuid,
+    pub email: String,
+    pub name: String,
+    pub status: String,
+    pub subscribed_at: DateTime<Utc>,
+}
+
+#[derive(Serialize)]
+pub struct PendingDelivery {
+    pub issue_id: Uuid,
+    pub title: String,
+    pub retries: Option<i32>,
+}
+
+#[derive(Serialize)]
+pub struct ConsentEvent {
+    pub kind: String,
+    pub ip_address: Option<String>,
+    pub user_agent: Option<String>,
+    pub source: String,
+    pub consent_text_version: String,
+    pub occurred_at: DateTime<Utc>,
+}
+
+#[derive(Serialize)]
+pub struct EmailEvent {
+    pub record_type: String,
+    pub bounce_type: Option<String>,
+    pub received_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum ErasureRequester {
+    Subscriber,
+    Admin(Uuid),
+}
+
+pub async fn store_data_token(
+    transaction: &mut Transaction<'_, Postgres>,
+    data_token: &str,
+    subscriber_id: Uuid,
+) -> Result<(), sqlx::Error> {
+    transaction
+        .execute(sqlx::query!(
+            r#"
+            INSERT INTO subscriber_data_tokens(data_token_hash, subscriber_id, created_at)
+            VALUES ($1, $2, now())
+            "#,
+            hash_token(data_token),
+            subscriber_id
+        ))
+        .await?;
+    Ok(())
+}
+
+pub async fn enqueue_data_request(
+    pool: &PgPool,
+    email: &SubscriberEmail,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+        INSERT INTO subscriber_data_request_queue(request_id, email, requested_at)
+        VALUES ($1, $2, now())
+        "#,
+        Uuid::new_v4(),
+        email.as_ref()
+    )
+    .execute(pool)
+    .await?;
+    Ok(())
+}
+
+/// `None` if the token doesn't exist or has expired
+#[tracing::instrument(name = "retrieve subscriber_id from data token", skip(pool, data_token))]
+pub async fn get_subscriber_id_from_data_token(
+    pool: &PgPool,
+    data_token: &str,
+) -> Result<Option<Uuid>, anyhow::Error> {
+    let subscriber_id = sqlx::query_scalar!(
+        r#"
+        SELECT subscriber_id
+        FROM subscriber_data_tokens
+        WHERE
+            data_token_hash = $1 AND
+            created_at > now() - make_interval(hours => $2)
+        "#,
+        hash_token(data_token),
+        DATA_TOKEN_TTL_HOURS,
+    )
+    .fetch_optional(pool)
+    .await
+    .context("failed to look up data token")?;
+
+    Ok(subscriber_id)
+}
+
+#[tracing::instrument(name = "collect subscriber data", skip(pool))]
+pub async fn get_subscriber_data(
+    pool: &PgPool,
+    subscriber_id: Uuid,
+) -> Result<Option<SubscriberData>, anyhow::Error> {
+    let Some(subscription) = sqlx::query_as!(
+        Subscription,
+        "SELECT id, email, name, status, subscribed_at FROM subscriptions WHERE id = $1",
+        subscriber_id
+    )
+    .fetch_optional(pool)
+    .await
+    .context("failed to fetch subscription")?
+    else {
+        return Ok(None);
+    };
+
+    let subscription_tokens = sqlx::query_scalar!(
+        "SELECT subscription_token FROM subscription_tokens WHERE subscriber_id = $1",
+        subscriber_id
+    )
+    .fetch_all(pool)
+    .await
+    .context("failed to fetch subscription tokens")?;
+
+    let pending_deliveries = sqlx::query_as!(
+        PendingDelivery,
+        r#"
+        SELECT q.issue_id, i.title, q.retries
+        FROM issue_delivery_queue q
+        JOIN newsletter_issues i USING (issue_id)
+        WHERE lower(q.email) = lower($1)
+        "#,
+        subscription.email
+    )
+    .fetch_all(pool)
+    .await
+    .context("failed to fetch pending deliveries")?;
+
+    let consent_events = get_consent_events(pool, subscriber_id).await?;
+
+    let email_events = sqlx::query_as!(
+        EmailEvent,
+        r#"
+        SELECT record_type, bounce_type, received_at
+        FROM email_events
+        WHERE lower(email) = lower($1)
+        ORDER BY received_at
+        "#,
+        subscription.email
+    )
+    .fetch_all(pool)
+    .await
+    .context("failed to fetch email events")?;
+
+    Ok(Some(SubscriberData {
+        subscription,
+        subscription_tokens,
+        pending_deliveries,
+        consent_events,
+        email_events,
+    }))
+}
+
+/// oldest first
+pub async fn get_consent_events(
+    pool: &PgPool,
+    subscriber_id: Uuid,
+) -> Result<Vec<ConsentEvent>, anyhow::Error> {
+    let events = sqlx::query_as!(
+        ConsentEvent,
+        r#"
+        SELECT kind, ip_address, user_agent, source, consent_text_version, occurred_at
+        FROM consent_events
+        WHERE subscriber_id = $1
+        ORDER BY occurred_at
+        "#,
+        subscriber_id
+    )
+    .fetch_all(pool)
+    .await
+    .context("failed to fetch consent events")?;
+
+    Ok(events)
+}
+
+/// removes every row that holds the subscriber's details and records the erasure;
+/// returns the erased email, or `None` if there was no such subscriber
+#[tracing::instrument(name = "erase subscriber", skip(transaction))]
+pub async fn erase_subscriber(
+    transaction: &mut Transaction<'_, Postgres>,
+    subscriber_id: Uuid,
+    requested_by: ErasureRequester,
+) -> Result<Option<String>, sqlx::Error> {
+    let Some(email) = sqlx::query_scalar!(
+        "SELECT email FROM subscriptions WHERE id = $1 FOR UPDATE",
+        subscriber_id
+    )
+    .fetch_optional(&mut **transaction)
+    .await?
+    else {
+        return Ok(None);
+    };
+
+    let mut deleted_rows = serde_json::Map::new();
+    let mut record = |table: &str, n_rows: u64| {
+        deleted_rows.insert(table.to_string(), n_rows.into());
+    };
+
+    // children first, `subscriptions` last because of the foreign keys
+    let n = transaction
+        .execute(sqlx::query!(
+            "DELETE FROM subscription_tokens WHERE subscriber_id = $1",
+            subscriber_id
+        ))
+        .await?
+        .rows_affected();
+    record("subscription_tokens", n);
+
+    let n = transaction
+        .execute(sqlx::query!(
+            "DELETE FROM subscriber_data_tokens WHERE subscriber_id = $1",
+            subscriber_id
+        ))
+        .await?
+        .rows_affected();
+    record("subscriber_data_tokens", n);
+
+    let n = transaction
+        .execute(sqlx::query!(
+            "DELETE FROM consent_events WHERE subscriber_id = $1",
+            subscriber_id
+        ))
+        .await?
+        .rows_affected();
+    record("consent_events", n);
+
+    let n = transaction
+        .execute(sqlx::query!(
+            "DELETE FROM issue_delivery_queue WHERE lower(email) = lower($1)",
+            email
+        ))
+        .await?
+        .rows_affected();
+    record("issue_delivery_queue", n);
+
+    let n = transaction
+        .execute(sqlx::query!(
+            "DELETE FROM subscriber_import_queue WHERE lower(email) = lower($1)",
+            email
+        ))
+        .await?
+        .rows_affected();
+    record("subscriber_import_queue", n);
+
+    let n = transaction
+        .execute(sqlx::query!(
+            "DELETE FROM subscriber_data_request_queue WHERE lower(email) = lower($1)",
+            email
+        ))
+        .await?
+        .rows_affected();
+    record("subscriber_data_request_queue", n);
+
+    let n = transaction
+        .execute(sqlx::query!(
+            "DELETE FROM subscriber_import_rejections WHERE lower(email) = lower($1)",
+            email
+        ))
+        .await?
+        .rows_affected();
+    record("subscriber_import_rejections", n);
+
+    let n = transaction
+        .execute(sqlx::query!(
+            "DELETE FROM email_events WHERE lower(email) = lower($1)",
+            email
+        ))
+        .await?
+        .rows_affected();
+    record("email_events", n);
+
+    let n = transaction
+        .execute(sqlx::query!(
+            "DELETE FROM subscriptions WHERE id = $1",
+            subscriber_id
+        ))
+        .await?
+        .rows_affected();
+    record("subscriptions", n);
+
+    let (requested_by, user_id) = match requested_by {
+        ErasureRequester::Subscriber => ("subscriber", None),
+        ErasureRequester::Admin(user_id) => ("admin", Some(user_id)),
+    };
+    transaction
+        .execute(sqlx::query!(
+            r#"
+            INSERT INTO subscriber_erasures(
+                erasure_id,
+                subscriber_id,
+                requested_by,
+                user_id,
+                deleted_rows,
+                erased_at
+            )
+            VALUES ($1, $2, $3, $4, $5, now())
+            "#,
+            Uuid::new_v4(),
+            subscriber_id,
+            requested_by,
+            user_id,
+            serde_json::Value::Object(deleted_rows),
+        ))
+        .await?;
+
+    Ok(Some(email))
+}
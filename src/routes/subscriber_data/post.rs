@@ -0,0 +1,142 @@
+use actix_web::{
+    http::{header::ContentType, StatusCode},
+    web, HttpRequest, HttpResponse,
+};
+use anyhow::Context;
+use serde::Deserialize;
+use sqlx::PgPool;
+
+use crate::{
+    configuration::SignupSettings,
+    domain::SubscriberEmail,
+    rate_limit::RateLimiter,
+    utils::{client_ip, e500},
+};
+
+use super::persistence::{
+    enqueue_data_request, erase_subscriber, get_subscriber_id_from_data_token, ErasureRequester,
+};
+
+#[derive(Deserialize)]
+pub struct DataRequestForm {
+    email: String,
+}
+
+#[derive(Deserialize)]
+pub struct ErasureForm {
+    token: String,
+}
+
+fn message_page(message: &str) -> HttpResponse {
+    HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"<!DOCTYPE html>
+<html lang="en">
+  <head>
+    <meta charset="UTF-8">
+    <meta name="viewport" content="width=device-width, initial-scale=1">
+    <title>Your data</title>
+    <link href="css/style.css" rel="stylesheet">
+  </head>
+  <body>
+    <p>{message}</p>
+  </body>
+</html>"#
+        ))
+}
+
+/// always answers the same way so the form can't be used to find out who is subscribed:
+/// the address is only looked up, and the email only sent, by the data request worker
+#[tracing::instrument(
+    name = "request subscriber data link",
+    skip(request, form, pool, rate_limiter, signup)
+)]
+pub async fn request_subscriber_data(
+    request: HttpRequest,
+    form: web::Form<DataRequestForm>,
+    pool: web::Data<PgPool>,
+    rate_limiter: web::Data<RateLimiter>,
+    signup: web::Data<SignupSettings>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let response = message_page(
+        "If this address is subscribed you'll receive an email with a link to your data shortly.",
+    );
+
+    // the same allowances as sign-ups, both forms send email to whatever address is typed in
+    let ip_address = client_ip(&request).unwrap_or_else(|| "unknown".into());
+    if !rate_limiter
+        .check(
+            "data_request_ip",
+            &ip_address,
+            signup.max_attempts_per_ip,
+            signup.window(),
+        )
+        .await
+        .context("failed to check data request rate limit")
+        .map_err(e500)?
+    {
+        return Ok(too_many_requests());
+    }
+    let Ok(email) = SubscriberEmail::parse(form.0.email) else {
+        return Ok(response);
+    };
+    if !rate_limiter
+        .check(
+            "data_request_email",
+            &email.as_ref().to_lowercase(),
+            signup.max_attempts_per_email,
+            signup.window(),
+        )
+        .await
+        .context("failed to check data request rate limit")
+        .map_err(e500)?
+    {
+        return Ok(too_many_requests());
+    }
+
+    enqueue_data_request(&pool, &email)
+        .await
+        .context("failed to enqueue data request")
+        .map_err(e500)?;
+
+    Ok(response)
+}
+
+fn too_many_requests() -> HttpResponse {
+    let mut response = message_page("Too many requests, please try again later.");
+    *response.status_mut() = StatusCode::TOO_MANY_REQUESTS;
+    response
+}
+
+#[tracing::instrument(name = "erase subscriber data", skip(form, pool))]
+pub async fn erase_subscriber_data(
+    form: web::Form<ErasureForm>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let Some(subscriber_id) = get_subscriber_id_from_data_token(&pool, &form.token)
+        .await
+        .map_err(e500)?
+    else {
+        return Ok(HttpResponse::Unauthorized().finish());
+    };
+
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("failed to establish connection to postgres")
+        .map_err(e500)?;
+    erase_subscriber(&mut transaction, subscriber_id, ErasureRequester::Subscriber)
+        .await
+        .context("failed to erase subscriber")
+        .map_err(e500)?;
+    transaction
+        .commit()
+        .await
+        .context("failed to commit postgres transaction")
+        .map_err(e500)?;
+
+    Ok(message_page(
+        "You have been unsubscribed and all of your data has been erased.",
+    ))
+}
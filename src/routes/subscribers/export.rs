@@ -0,0 +1,261 @@
+use actix_web::{
+    http::header::{ContentDisposition, DispositionParam, DispositionType},
+    web::{self, Bytes},
+    HttpResponse,
+};
+use anyhow::Context;
+use chrono::{DateTime, Days, NaiveDate, NaiveTime, Utc};
+use futures_util::TryStreamExt;
+use serde::{Deserialize, Serialize};
+use sqlx::PgPool;
+use tokio::sync::mpsc;
+
+use crate::{
+    audit::{record_audit_event, AuditContext},
+    domain::{AuditAction, SubscriptionStatus},
+    utils::{e400, e500},
+};
+
+#[derive(Deserialize, Debug)]
+pub struct ExportParameters {
+    format: Option<String>,
+    status: Option<String>,
+    /// inclusive, `YYYY-MM-DD`
+    from: Option<String>,
+    /// inclusive, `YYYY-MM-DD`
+    to: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ExportFormat {
+    Csv,
+    Ndjson,
+}
+
+impl ExportFormat {
+    fn as_str(&self) -> &'static str {
+        match self {
+            ExportFormat::Csv => "csv",
+            ExportFormat::Ndjson => "ndjson",
+        }
+    }
+}
+
+impl TryFrom<String> for ExportFormat {
+    type Error = String;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        match s.as_str() {
+            "csv" => Ok(ExportFormat::Csv),
+            "ndjson" => Ok(ExportFormat::Ndjson),
+            _ => Err(format!("unknown export format `{}`; use `csv` or `ndjson`", s)),
+        }
+    }
+}
+
+/// engagement is what we know of past and upcoming deliveries: bounces and spam
+/// complaints reported by the email provider, and issues still queued for them
+#[derive(Serialize)]
+struct ExportedSubscriber {
+    email: String,
+    name: String,
+    status: String,
+    subscribed_at: String,
+    bounces: i64,
+    complaints: i64,
+    last_email_event_at: Option<String>,
+    pending_deliveries: i64,
+}
+
+struct SubscriberRow {
+    email: String,
+    name: String,
+    status: String,
+    subscribed_at: DateTime<Utc>,
+    bounces: i64,
+    complaints: i64,
+    last_email_event_at: Option<DateTime<Utc>>,
+    pending_deliveries: i64,
+}
+
+impl From<SubscriberRow> for ExportedSubscriber {
+    fn from(row: SubscriberRow) -> Self {
+        Self {
+            email: row.email,
+            name: row.name,
+            status: row.status,
+            subscribed_at: row.subscribed_at.to_rfc3339(),
+            bounces: row.bounces,
+            complaints: row.complaints,
+            last_email_event_at: row.last_email_event_at.map(|at| at.to_rfc3339()),
+            pending_deliveries: row.pending_deliveries,
+        }
+    }
+}
+
+// rows are serialized one at a time and handed over through a bounded channel,
+// so a slow client applies backpressure to the query instead of us buffering
+const CHANNEL_CAPACITY: usize = 64;
+
+#[tracing::instrument(name = "export subscribers", skip(pool))]
+pub async fn export_subscribers(
+    parameters: web::Query<ExportParameters>,
+    pool: web::Data<PgPool>,
+    audit: AuditContext,
+) -> Result<HttpResponse, actix_web::Error> {
+    let ExportParameters {
+        format,
+        status,
+        from,
+        to,
+    } = parameters.into_inner();
+
+    let format = format
+        .filter(|f| !f.is_empty())
+        .map(ExportFormat::try_from)
+        .transpose()
+        .map_err(e400)?
+        .unwrap_or(ExportFormat::Csv);
+    let status = status
+        .filter(|s| !s.is_empty())
+        .map(SubscriptionStatus::try_from)
+        .transpose()
+        .map_err(e400)?;
+    let from = parse_date(from).map_err(e400)?;
+    // the whole `to` day is included
+    let to = parse_date(to)
+        .map_err(e400)?
+        .map(|d| d.checked_add_days(Days::new(1)).unwrap_or(d));
+
+    // nothing changes, but it's everyone's personal data leaving the building
+    record_audit_event(
+        pool.as_ref(),
+        &audit,
+        AuditAction::SubscriberExport,
+        None,
+        serde_json::json!({
+            "format": format.as_str(),
+            "status": status.map(|s| s.as_str()),
+            "from": from.map(|d| d.to_rfc3339()),
+            "to": to.map(|d| d.to_rfc3339()),
+        }),
+    )
+    .await
+    .map_err(e500)?;
+
+    let (tx, mut rx) = mpsc::channel::<Result<Bytes, anyhow::Error>>(CHANNEL_CAPACITY);
+    let pool = pool.get_ref().clone();
+
+    tokio::spawn(async move {
+        let mut rows = sqlx::query_as!(
+            SubscriberRow,
+            r#"
+            WITH events AS (
+                SELECT
+                    lower(email) AS email,
+                    count(*) FILTER (WHERE record_type = 'Bounce') AS bounces,
+                    count(*) FILTER (WHERE record_type = 'SpamComplaint') AS complaints,
+                    max(received_at) AS last_event_at
+                FROM email_events
+                GROUP BY lower(email)
+            ),
+            deliveries AS (
+                SELECT email, count(*) AS pending
+                FROM issue_delivery_queue
+                GROUP BY email
+            )
+            SELECT
+                s.email,
+                s.name,
+                s.status,
+                s.subscribed_at,
+                coalesce(e.bounces, 0) AS "bounces!",
+                coalesce(e.complaints, 0) AS "complaints!",
+                e.last_event_at AS last_email_event_at,
+                coalesce(d.pending, 0) AS "pending_deliveries!"
+            FROM subscriptions s
+            LEFT JOIN events e ON e.email = lower(s.email)
+            LEFT JOIN deliveries d ON d.email = s.email
+            WHERE
+                ($1::text IS NULL OR s.status = $1) AND
+                ($2::timestamptz IS NULL OR s.subscribed_at >= $2) AND
+                ($3::timestamptz IS NULL OR s.subscribed_at < $3)
+            ORDER BY s.subscribed_at
+            "#,
+            status.map(|s| s.as_str()),
+            from,
+            to,
+        )
+        .fetch(&pool);
+
+        if format == ExportFormat::Csv {
+            let header = Bytes::from_static(b"email,name,status,subscribed_at,bounces,complaints,last_email_event_at,pending_deliveries\n");
+            if tx.send(Ok(header)).await.is_err() {
+                return;
+            }
+        }
+        loop {
+            let chunk = match rows.try_next().await {
+                Ok(Some(row)) => serialize(format, row.into()),
+                Ok(None) => break,
+                Err(e) => {
+                    tracing::error!(error.cause_chain = ?e, "failed to export subscribers");
+                    Err(anyhow::Error::new(e).context("failed to fetch subscribers"))
+                }
+            };
+            let failed = chunk.is_err();
+            // the client hung up, no point in reading the rest
+            if tx.send(chunk).await.is_err() || failed {
+                break;
+            }
+        }
+    });
+
+    let body = futures_util::stream::poll_fn(move |cx| rx.poll_recv(cx));
+    let (content_type, extension) = match format {
+        ExportFormat::Csv => ("text/csv", "csv"),
+        ExportFormat::Ndjson => ("application/x-ndjson", "ndjson"),
+    };
+
+    Ok(HttpResponse::Ok()
+        .content_type(content_type)
+        .insert_header(ContentDisposition {
+            disposition: DispositionType::Attachment,
+            parameters: vec![DispositionParam::Filename(format!(
+                "subscribers.{}",
+                extension
+            ))],
+        })
+        .streaming(body))
+}
+
+fn parse_date(date: Option<String>) -> Result<Option<DateTime<Utc>>, String> {
+    date.filter(|d| !d.is_empty())
+        .map(|d| {
+            NaiveDate::parse_from_str(&d, "%Y-%m-%d")
+                .map(|d| d.and_time(NaiveTime::MIN).and_utc())
+                .map_err(|_| format!("invalid date `{}`; use YYYY-MM-DD", d))
+        })
+        .transpose()
+}
+
+fn serialize(format: ExportFormat, subscriber: ExportedSubscriber) -> Result<Bytes, anyhow::Error> {
+    let mut buffer = vec![];
+    match format {
+        ExportFormat::Csv => {
+            let mut writer = csv::WriterBuilder::new()
+                .has_headers(false)
+                .from_writer(&mut buffer);
+            writer
+                .serialize(&subscriber)
+                .context("failed to serialize subscriber as csv")?;
+            writer.flush()?;
+        }
+        ExportFormat::Ndjson => {
+            serde_json::to_writer(&mut buffer, &subscriber)
+                .context("failed to serialize subscriber as json")?;
+            buffer.push(b'\n');
+        }
+    }
+    Ok(Bytes::from(buffer))
+}
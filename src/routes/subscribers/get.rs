@@ -0,0 +1,335 @@
+use actix_web::{http::header::ContentType, web, HttpResponse};
+use actix_web_flash_messages::IncomingFlashMessages;
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use serde::Deserialize;
+use sqlx::PgPool;
+use std::fmt::Write;
+use uuid::Uuid;
+
+use crate::{
+    authentication::csrf::CsrfToken,
+    domain::SubscriptionStatus,
+    routes::{get_consent_events, ConsentEvent},
+    utils::{e400, e404, e500},
+};
+
+pub const PAGE_SIZE: i64 = 20;
+
+#[derive(Deserialize, Debug)]
+pub struct ListParameters {
+    search: Option<String>,
+    status: Option<String>,
+    page: Option<i64>,
+}
+
+pub struct SubscriberRecord {
+    pub id: Uuid,
+    pub email: String,
+    pub name: String,
+    pub status: String,
+    pub subscribed_at: DateTime<Utc>,
+}
+
+#[tracing::instrument(name = "list subscribers", skip(pool, flash_messages))]
+pub async fn list_subscribers(
+    parameters: web::Query<ListParameters>,
+    pool: web::Data<PgPool>,
+    flash_messages: IncomingFlashMessages,
+) -> Result<HttpResponse, actix_web::Error> {
+    let ListParameters {
+        search,
+        status,
+        page,
+    } = parameters.into_inner();
+
+    // empty form fields are sent as `?search=&status=`
+    let search = search.filter(|s| !s.trim().is_empty());
+    let status: Option<SubscriptionStatus> = status
+        .filter(|s| !s.is_empty())
+        .map(SubscriptionStatus::try_from)
+        .transpose()
+        .map_err(e400)?;
+    let page = page.unwrap_or(1).max(1);
+
+    let (subscribers, total) = search_subscribers(&pool, search.as_deref(), status, page)
+        .await
+        .map_err(e500)?;
+    let n_pages = ((total + PAGE_SIZE - 1) / PAGE_SIZE).max(1);
+
+    let mut msg_html = String::new();
+    for m in flash_messages.iter() {
+        writeln!(msg_html, "<p><i>{}</i></p>", m.content()).unwrap();
+    }
+
+    let mut rows_html = String::new();
+    for s in &subscribers {
+        writeln!(
+            rows_html,
+            r#"<tr><td><a href="/admin/subscribers/{id}">{email}</a></td><td>{name}</td><td>{status}</td><td>{subscribed_at}</td></tr>"#,
+            id = s.id,
+            email = htmlescape::encode_minimal(&s.email),
+            name = htmlescape::encode_minimal(&s.name),
+            status = s.status,
+            subscribed_at = s.subscribed_at.format("%Y-%m-%d %H:%M"),
+        )
+        .unwrap();
+    }
+
+    let search_value = search.as_deref().unwrap_or_default();
+    let mut status_options = String::from(r#"<option value="">all</option>"#);
+    for s in SubscriptionStatus::ALL {
+        let selected = if status == Some(s) { " selected" } else { "" };
+        write!(
+            status_options,
+            r#"<option value="{0}"{selected}>{0}</option>"#,
+            s.as_str()
+        )
+        .unwrap();
+    }
+
+    let page_link = |page: i64| {
+        format!(
+            "/admin/subscribers?search={}&status={}&page={}",
+            urlencoding::encode(search_value),
+            status.map(|s| s.as_str()).unwrap_or_default(),
+            page
+        )
+    };
+    let mut pagination_html = format!("<p>page {page} of {n_pages} ({total} subscribers)</p>");
+    if page > 1 {
+        write!(pagination_html, r#"<a href="{}">previous</a> "#, page_link(page - 1)).unwrap();
+    }
+    if page < n_pages {
+        write!(pagination_html, r#"<a href="{}">next</a>"#, page_link(page + 1)).unwrap();
+    }
+
+    let search_value = htmlescape::encode_attribute(search_value);
+    let status_value = status.map(|s| s.as_str()).unwrap_or_default();
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"
+<!DOCTYPE html>
+<html lang="en">
+  <head>
+    <meta charset="UTF-8">
+    <meta name="viewport" content="width=device-width, initial-scale=1">
+    <title>Subscribers</title>
+    <link href="css/style.css" rel="stylesheet">
+  </head>
+  <body>
+    {msg_html}
+    <form action="/admin/subscribers" method="get">
+      <label for="">
+        search
+        <input type="text" name="search" placeholder="email or name" value="{search_value}">
+      </label>
+      <label for="">
+        status
+        <select name="status">{status_options}</select>
+      </label>
+      <button type="submit">filter</button>
+    </form>
+    <table>
+      <tr><th>email</th><th>name</th><th>status</th><th>subscribed at</th></tr>
+      {rows_html}
+    </table>
+    {pagination_html}
+    <form action="/admin/subscribers/export" method="get">
+      <input type="hidden" name="status" value="{status_value}">
+      <label for="">
+        subscribed from
+        <input type="date" name="from">
+      </label>
+      <label for="">
+        to
+        <input type="date" name="to">
+      </label>
+      <select name="format">
+        <option value="csv">CSV</option>
+        <option value="ndjson">NDJSON</option>
+      </select>
+      <button type="submit">export</button>
+    </form>
+    <p><a href="/admin/subscribers/imports">import subscribers from CSV</a></p>
+    <p><a href="/admin/dashboard">&lt;- Back</a></p>
+  </body>
+</html>
+        "#
+        )))
+}
+
+#[tracing::instrument(name = "show subscriber details", skip(pool, flash_messages, csrf_token))]
+pub async fn subscriber_details(
+    subscriber_id: web::Path<Uuid>,
+    pool: web::Data<PgPool>,
+    flash_messages: IncomingFlashMessages,
+    csrf_token: web::ReqData<CsrfToken>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let subscriber_id = subscriber_id.into_inner();
+    let subscriber = get_subscriber(&pool, subscriber_id)
+        .await
+        .map_err(e500)?
+        .ok_or_else(|| e404("subscriber not found"))?;
+    let consent_events = get_consent_events(&pool, subscriber_id)
+        .await
+        .map_err(e500)?;
+
+    let mut msg_html = String::new();
+    for m in flash_messages.iter() {
+        writeln!(msg_html, "<p><i>{}</i></p>", m.content()).unwrap();
+    }
+
+    let csrf_input = csrf_token.hidden_input();
+    let action = |path: &str, label: &str| {
+        format!(
+            r#"<li><form action="/admin/subscribers/{subscriber_id}/{path}" method="post">{csrf_input}<input type="submit" value="{label}"></form></li>"#
+        )
+    };
+    let actions = [
+        action("confirm", "Confirm"),
+        action("unsubscribe", "Unsubscribe"),
+        action("resend_confirmation", "Resend confirmation email"),
+        action("delete", "Delete all of their data"),
+    ]
+    .join("\n");
+
+    let SubscriberRecord {
+        id,
+        email,
+        name,
+        status,
+        subscribed_at,
+    } = subscriber;
+    let email = htmlescape::encode_minimal(&email);
+    let name = htmlescape::encode_minimal(&name);
+
+    let mut consent_html = String::new();
+    for event in consent_events {
+        let ConsentEvent {
+            kind,
+            ip_address,
+            user_agent,
+            source,
+            consent_text_version,
+            occurred_at,
+        } = event;
+        let escape = |s: Option<String>| htmlescape::encode_minimal(s.as_deref().unwrap_or("-"));
+        writeln!(
+            consent_html,
+            "<tr><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td></tr>",
+            kind,
+            occurred_at,
+            escape(ip_address),
+            escape(user_agent),
+            htmlescape::encode_minimal(&source),
+            htmlescape::encode_minimal(&consent_text_version),
+        )
+        .unwrap();
+    }
+
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"
+<!DOCTYPE html>
+<html lang="en">
+  <head>
+    <meta charset="UTF-8">
+    <meta name="viewport" content="width=device-width, initial-scale=1">
+    <title>Subscriber</title>
+    <link href="css/style.css" rel="stylesheet">
+  </head>
+  <body>
+    {msg_html}
+    <ul>
+      <li>id: {id}</li>
+      <li>email: {email}</li>
+      <li>name: {name}</li>
+      <li>status: {status}</li>
+      <li>subscribed at: {subscribed_at}</li>
+    </ul>
+    <p>consent history:</p>
+    <table>
+      <tr><th>event</th><th>at</th><th>ip address</th><th>user agent</th><th>source</th><th>consent text version</th></tr>
+      {consent_html}
+    </table>
+    <p>available actions:</p>
+    <ol>
+      {actions}
+    </ol>
+    <p><a href="/admin/subscribers">&lt;- Back</a></p>
+  </body>
+</html>
+        "#
+        )))
+}
+
+#[tracing::instrument(name = "search subscribers", skip(pool))]
+pub async fn search_subscribers(
+    pool: &PgPool,
+    search: Option<&str>,
+    status: Option<SubscriptionStatus>,
+    page: i64,
+) -> Result<(Vec<SubscriberRecord>, i64), anyhow::Error> {
+    let pattern = search.map(|s| format!("%{}%", s.trim()));
+    let status = status.map(|s| s.as_str());
+
+    let subscribers = sqlx::query_as!(
+        SubscriberRecord,
+        r#"
+        SELECT id, email, name, status, subscribed_at
+        FROM subscriptions
+        WHERE
+            ($1::text IS NULL OR email ILIKE $1 OR name ILIKE $1) AND
+            ($2::text IS NULL OR status = $2)
+        ORDER BY subscribed_at DESC
+        LIMIT $3 OFFSET $4
+        "#,
+        pattern,
+        status,
+        PAGE_SIZE,
+        (page - 1) * PAGE_SIZE,
+    )
+    .fetch_all(pool)
+    .await
+    .context("failed to fetch subscribers")?;
+
+    let total = sqlx::query_scalar!(
+        r#"
+        SELECT count(*) as "count!"
+        FROM subscriptions
+        WHERE
+            ($1::text IS NULL OR email ILIKE $1 OR name ILIKE $1) AND
+            ($2::text IS NULL OR status = $2)
+        "#,
+        pattern,
+        status,
+    )
+    .fetch_one(pool)
+    .await
+    .context("failed to count subscribers")?;
+
+    Ok((subscribers, total))
+}
+
+pub async fn get_subscriber(
+    pool: &PgPool,
+    subscriber_id: Uuid,
+) -> Result<Option<SubscriberRecord>, anyhow::Error> {
+    let subscriber = sqlx::query_as!(
+        SubscriberRecord,
+        r#"
+        SELECT id, email, name, status, subscribed_at
+        FROM subscriptions
+        WHERE id = $1
+        "#,
+        subscriber_id
+    )
+    .fetch_optional(pool)
+    .await
+    .context("failed to fetch subscriber")?;
+
+    Ok(subscriber)
+}
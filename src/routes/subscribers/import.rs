@@ -0,0 +1,413 @@
+use actix_multipart::form::{bytes::Bytes, text::Text, MultipartForm};
+use actix_web::{
+    http::header::{ContentDisposition, ContentType, DispositionParam, DispositionType},
+    web, HttpResponse,
+};
+use actix_web_flash_messages::{FlashMessage, IncomingFlashMessages};
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use sqlx::{Executor, PgPool, Postgres, Transaction};
+use std::fmt::Write;
+use uuid::Uuid;
+
+use crate::{
+    audit::{record_audit_event, AuditContext},
+    authentication::{csrf::CsrfToken, middleware::UserId},
+    domain::AuditAction,
+    subscriber_import_workers::ImportMode,
+    utils::{e404, e500, see_other},
+};
+
+#[derive(MultipartForm)]
+pub struct ImportForm {
+    file: Bytes,
+    mode: Text<String>,
+}
+
+struct ImportSummary {
+    import_id: Uuid,
+    filename: String,
+    mode: String,
+    total_rows: i32,
+    imported_rows: i32,
+    duplicate_rows: i32,
+    rejected_rows: i32,
+    created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, PartialEq)]
+struct CsvRow {
+    row_number: i32,
+    email: String,
+    name: String,
+}
+
+/// rows we could read and rows that are rejected before reaching the queue
+#[derive(Debug, Default)]
+struct ParsedCsv {
+    rows: Vec<CsvRow>,
+    unreadable: Vec<(i32, String)>,
+}
+
+pub async fn import_subscribers_form(
+    pool: web::Data<PgPool>,
+    flash_messages: IncomingFlashMessages,
+    csrf_token: web::ReqData<CsrfToken>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let mut msg_html = String::new();
+    for m in flash_messages.iter() {
+        writeln!(msg_html, "<p><i>{}</i></p>", m.content()).unwrap();
+    }
+
+    let imports = get_recent_imports(&pool).await.map_err(e500)?;
+    let mut rows_html = String::new();
+    for i in imports {
+        let pending = i.total_rows - i.imported_rows - i.duplicate_rows - i.rejected_rows;
+        let report = if i.rejected_rows > 0 {
+            format!(
+                r#"<a href="/admin/subscribers/imports/{}/report">download</a>"#,
+                i.import_id
+            )
+        } else {
+            String::new()
+        };
+        writeln!(
+            rows_html,
+            "<tr><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td></tr>",
+            i.created_at.format("%Y-%m-%d %H:%M"),
+            htmlescape::encode_minimal(&i.filename),
+            i.mode,
+            i.total_rows,
+            i.imported_rows,
+            i.duplicate_rows,
+            i.rejected_rows,
+            pending,
+            report,
+        )
+        .unwrap();
+    }
+
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"
+<!DOCTYPE html>
+<html lang="en">
+  <head>
+    <meta charset="UTF-8">
+    <meta name="viewport" content="width=device-width, initial-scale=1">
+    <title>Import subscribers</title>
+    <link href="css/style.css" rel="stylesheet">
+  </head>
+  <body>
+    {msg_html}
+    <p>upload a CSV file with `email` and `name` columns</p>
+    <form action="/admin/subscribers/imports?{csrf_query}" method="post" enctype="multipart/form-data">
+      <input type="file" name="file" accept=".csv,text/csv">
+      <label>
+        <input type="radio" name="mode" value="{double_opt_in}" checked>
+        send double opt-in email
+      </label>
+      <label>
+        <input type="radio" name="mode" value="{confirmed}">
+        import as confirmed
+      </label>
+      <button type="submit">import</button>
+    </form>
+    <table>
+      <tr><th>uploaded at</th><th>file</th><th>mode</th><th>rows</th><th>imported</th><th>duplicates</th><th>rejected</th><th>pending</th><th>report</th></tr>
+      {rows_html}
+    </table>
+    <p><a href="/admin/subscribers">&lt;- Back</a></p>
+  </body>
+</html>
+        "#,
+            double_opt_in = ImportMode::DoubleOptIn.as_str(),
+            confirmed = ImportMode::Confirmed.as_str(),
+            csrf_query = htmlescape::encode_minimal(&csrf_token.query()),
+        )))
+}
+
+#[tracing::instrument(name = "import subscribers", skip(form, pool))]
+pub async fn import_subscribers(
+    form: MultipartForm<ImportForm>,
+    pool: web::Data<PgPool>,
+    user_id: web::ReqData<UserId>,
+    audit: AuditContext,
+) -> Result<HttpResponse, actix_web::Error> {
+    let ImportForm { file, mode } = form.into_inner();
+    let mode = match ImportMode::try_from(mode.into_inner()) {
+        Ok(mode) => mode,
+        Err(e) => {
+            FlashMessage::error(htmlescape::encode_minimal(&e)).send();
+            return Ok(see_other("/admin/subscribers/imports"));
+        }
+    };
+    let parsed = match parse_csv(&file.data) {
+        Ok(parsed) => parsed,
+        Err(e) => {
+            FlashMessage::error(htmlescape::encode_minimal(&e)).send();
+            return Ok(see_other("/admin/subscribers/imports"));
+        }
+    };
+
+    let filename = file.file_name.unwrap_or_else(|| "upload.csv".into());
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("failed to establish connection to postgres")
+        .map_err(e500)?;
+    let import_id = enqueue_import(&mut transaction, *user_id.into_inner(), &filename, mode, parsed)
+        .await
+        .context("failed to enqueue subscriber import")
+        .map_err(e500)?;
+    record_audit_event(
+        &mut *transaction,
+        &audit,
+        AuditAction::SubscriberImport,
+        Some(&import_id.to_string()),
+        serde_json::json!({ "filename": filename, "mode": mode.as_str() }),
+    )
+    .await
+    .map_err(e500)?;
+    transaction
+        .commit()
+        .await
+        .context("failed to commit postgres transaction")
+        .map_err(e500)?;
+
+    tracing::info!(%import_id, "subscriber import queued");
+    FlashMessage::info("Import queued, rows will be processed in the background").send();
+    Ok(see_other("/admin/subscribers/imports"))
+}
+
+#[tracing::instrument(name = "download import report", skip(pool))]
+pub async fn import_report(
+    import_id: web::Path<Uuid>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let import_id = import_id.into_inner();
+    let filename = sqlx::query_scalar!(
+        "SELECT filename FROM subscriber_imports WHERE import_id = $1",
+        import_id
+    )
+    .fetch_optional(pool.as_ref())
+    .await
+    .context("failed to fetch subscriber import")
+    .map_err(e500)?
+    .ok_or_else(|| e404("import not found"))?;
+
+    let rejections = sqlx::query!(
+        r#"
+        SELECT row_number, email, name, reason
+        FROM subscriber_import_rejections
+        WHERE import_id = $1
+        ORDER BY row_number
+        "#,
+        import_id
+    )
+    .fetch_all(pool.as_ref())
+    .await
+    .context("failed to fetch rejected rows")
+    .map_err(e500)?;
+
+    let mut writer = csv::Writer::from_writer(vec![]);
+    writer
+        .write_record(["row", "email", "name", "reason"])
+        .map_err(e500)?;
+    for r in rejections {
+        writer
+            .write_record([&r.row_number.to_string(), &r.email, &r.name, &r.reason])
+            .map_err(e500)?;
+    }
+    let body = writer.into_inner().map_err(e500)?;
+
+    Ok(HttpResponse::Ok()
+        .content_type("text/csv")
+        .insert_header(ContentDisposition {
+            disposition: DispositionType::Attachment,
+            parameters: vec![DispositionParam::Filename(format!(
+                "rejected-{}",
+                filename
+            ))],
+        })
+        .body(body))
+}
+
+/// reads `email`/`name` columns by header so column order and extra columns don't matter
+fn parse_csv(data: &[u8]) -> Result<ParsedCsv, String> {
+    let mut reader = csv::ReaderBuilder::new()
+        .trim(csv::Trim::All)
+        .flexible(true)
+        .from_reader(data);
+
+    let headers = reader
+        .headers()
+        .map_err(|e| format!("failed to read CSV header: {}", e))?
+        .clone();
+    let column = |name: &str| headers.iter().position(|h| h.eq_ignore_ascii_case(name));
+    let (Some(email_column), Some(name_column)) = (column("email"), column("name")) else {
+        return Err("the CSV file must have `email` and `name` columns".into());
+    };
+
+    let mut parsed = ParsedCsv::default();
+    // numbered by record rather than line, so every row gets a distinct number,
+    // readable or not; the header is row 1
+    for (index, record) in reader.records().enumerate() {
+        let row_number = index as i32 + 2;
+        match record {
+            Ok(record) => parsed.rows.push(CsvRow {
+                row_number,
+                email: record.get(email_column).unwrap_or_default().to_string(),
+                name: record.get(name_column).unwrap_or_default().to_string(),
+            }),
+            Err(e) => parsed.unreadable.push((row_number, e.to_string())),
+        }
+    }
+    if parsed.rows.is_empty() && parsed.unreadable.is_empty() {
+        return Err("the CSV file has no rows".into());
+    }
+    Ok(parsed)
+}
+
+async fn enqueue_import(
+    transaction: &mut Transaction<'_, Postgres>,
+    user_id: Uuid,
+    filename: &str,
+    mode: ImportMode,
+    parsed: ParsedCsv,
+) -> Result<Uuid, sqlx::Error> {
+    let import_id = Uuid::new_v4();
+    let n_unreadable = parsed.unreadable.len() as i32;
+    let total_rows = parsed.rows.len() as i32 + n_unreadable;
+
+    transaction
+        .execute(sqlx::query!(
+            r#"
+            INSERT INTO subscriber_imports(
+                import_id,
+                user_id,
+                filename,
+                mode,
+                total_rows,
+                rejected_rows,
+                created_at
+            )
+            VALUES ($1, $2, $3, $4, $5, $6, now())
+            "#,
+            import_id,
+            user_id,
+            filename,
+            mode.as_str(),
+            total_rows,
+            n_unreadable,
+        ))
+        .await?;
+
+    let (row_numbers, (emails, names)): (Vec<i32>, (Vec<String>, Vec<String>)) = parsed
+        .rows
+        .into_iter()
+        .map(|r| (r.row_number, (r.email, r.name)))
+        .unzip();
+    transaction
+        .execute(sqlx::query!(
+            r#"
+            INSERT INTO subscriber_import_queue(import_id, row_number, email, name)
+            SELECT $1, * FROM UNNEST($2::int4[], $3::text[], $4::text[])
+            "#,
+            import_id,
+            &row_numbers,
+            &emails,
+            &names,
+        ))
+        .await?;
+
+    let (row_numbers, reasons): (Vec<i32>, Vec<String>) = parsed.unreadable.into_iter().unzip();
+    transaction
+        .execute(sqlx::query!(
+            r#"
+            INSERT INTO subscriber_import_rejections(import_id, row_number, email, name, reason)
+            SELECT $1, row_number, '', '', reason
+            FROM UNNEST($2::int4[], $3::text[]) AS t(row_number, reason)
+            "#,
+            import_id,
+            &row_numbers,
+            &reasons,
+        ))
+        .await?;
+
+    Ok(import_id)
+}
+
+async fn get_recent_imports(pool: &PgPool) -> Result<Vec<ImportSummary>, anyhow::Error> {
+    let imports = sqlx::query_as!(
+        ImportSummary,
+        r#"
+        SELECT
+            import_id,
+            filename,
+            mode,
+            total_rows,
+            imported_rows,
+            duplicate_rows,
+            rejected_rows,
+            created_at
+        FROM subscriber_imports
+        ORDER BY created_at DESC
+        LIMIT 20
+        "#
+    )
+    .fetch_all(pool)
+    .await
+    .context("failed to fetch subscriber imports")?;
+
+    Ok(imports)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse_csv, CsvRow};
+    use claims::assert_err;
+
+    #[test]
+    fn columns_are_matched_by_header() {
+        let data = b"name,id,Email\nle guin,1, ursula@gmail.com \n";
+        let parsed = parse_csv(data).unwrap();
+        assert_eq!(
+            parsed.rows,
+            vec![CsvRow {
+                row_number: 2,
+                email: "ursula@gmail.com".into(),
+                name: "le guin".into(),
+            }]
+        );
+    }
+
+    #[test]
+    fn missing_columns_are_rejected() {
+        assert_err!(parse_csv(b"email\nursula@gmail.com\n"));
+    }
+
+    #[test]
+    fn empty_files_are_rejected() {
+        assert_err!(parse_csv(b"email,name\n"));
+    }
+
+    #[test]
+    fn unreadable_rows_are_reported() {
+        let data = b"email,name\nursula@gmail.com,le guin\n\xff,bad\n";
+        let parsed = parse_csv(data).unwrap();
+        assert_eq!(parsed.rows.len(), 1);
+        assert_eq!(parsed.unreadable.len(), 1);
+        assert_eq!(parsed.unreadable[0].0, 3);
+    }
+
+    #[test]
+    fn every_row_gets_its_own_number() {
+        let data = b"email,name\n\xff,bad\nursula@gmail.com,le guin\n\xfe,bad\n";
+        let parsed = parse_csv(data).unwrap();
+        assert_eq!(parsed.rows[0].row_number, 3);
+        let unreadable: Vec<_> = parsed.unreadable.iter().map(|(n, _)| *n).collect();
+        assert_eq!(unreadable, vec![2, 4]);
+    }
+}
@@ -0,0 +1,259 @@
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::FlashMessage;
+use anyhow::Context;
+use sqlx::{Executor, PgPool, Postgres, Transaction};
+use uuid::Uuid;
+
+use crate::{
+    audit::{record_audit_event, AuditContext},
+    authentication::middleware::UserId,
+    domain::{AuditAction, NewSubscriber, SubscriberEmail, SubscriberName, SubscriptionStatus},
+    email_client::EmailClient,
+    routes::{
+        erase_subscriber, generate_random_token, send_confirmation_email, store_token,
+        ErasureRequester,
+    },
+    suppressions::is_suppressed,
+    utils::{e404, e500, see_other},
+    ApplicationBaseUrl,
+};
+
+use super::get_subscriber;
+
+fn subscriber_page(subscriber_id: Uuid) -> HttpResponse {
+    see_other(&format!("/admin/subscribers/{}", subscriber_id))
+}
+
+#[tracing::instrument(name = "manually confirm subscriber", skip(pool))]
+pub async fn admin_confirm_subscriber(
+    subscriber_id: web::Path<Uuid>,
+    pool: web::Data<PgPool>,
+    audit: AuditContext,
+) -> Result<HttpResponse, actix_web::Error> {
+    let subscriber_id = subscriber_id.into_inner();
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("failed to establish connection to postgres")
+        .map_err(e500)?;
+    let previous_status =
+        set_subscription_status(&mut transaction, subscriber_id, SubscriptionStatus::Confirmed)
+            .await
+            .map_err(e500)?
+            .ok_or_else(|| e404("subscriber not found"))?;
+    record_audit_event(
+        &mut *transaction,
+        &audit,
+        AuditAction::SubscriberConfirm,
+        Some(&subscriber_id.to_string()),
+        status_change(&previous_status, SubscriptionStatus::Confirmed),
+    )
+    .await
+    .map_err(e500)?;
+    transaction
+        .commit()
+        .await
+        .context("failed to commit postgres transaction")
+        .map_err(e500)?;
+
+    FlashMessage::info("Subscriber confirmed").send();
+    Ok(subscriber_page(subscriber_id))
+}
+
+#[tracing::instrument(name = "unsubscribe subscriber", skip(pool))]
+pub async fn unsubscribe_subscriber(
+    subscriber_id: web::Path<Uuid>,
+    pool: web::Data<PgPool>,
+    audit: AuditContext,
+) -> Result<HttpResponse, actix_web::Error> {
+    let subscriber_id = subscriber_id.into_inner();
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("failed to establish connection to postgres")
+        .map_err(e500)?;
+
+    let email = sqlx::query_scalar!(
+        "SELECT email FROM subscriptions WHERE id = $1",
+        subscriber_id
+    )
+    .fetch_optional(&mut *transaction)
+    .await
+    .context("failed to fetch subscriber")
+    .map_err(e500)?
+    .ok_or_else(|| e404("subscriber not found"))?;
+    let previous_status =
+        set_subscription_status(&mut transaction, subscriber_id, SubscriptionStatus::Unsubscribed)
+            .await
+            .context("failed to unsubscribe subscriber")
+            .map_err(e500)?
+            .ok_or_else(|| e404("subscriber not found"))?;
+
+    // pending deliveries shouldn't go out once someone has unsubscribed
+    transaction
+        .execute(sqlx::query!(
+            "DELETE FROM issue_delivery_queue WHERE email = $1",
+            email
+        ))
+        .await
+        .context("failed to clear pending deliveries")
+        .map_err(e500)?;
+    record_audit_event(
+        &mut *transaction,
+        &audit,
+        AuditAction::SubscriberUnsubscribe,
+        Some(&subscriber_id.to_string()),
+        status_change(&previous_status, SubscriptionStatus::Unsubscribed),
+    )
+    .await
+    .map_err(e500)?;
+
+    transaction
+        .commit()
+        .await
+        .context("failed to commit postgres transaction")
+        .map_err(e500)?;
+
+    FlashMessage::info("Subscriber unsubscribed").send();
+    Ok(subscriber_page(subscriber_id))
+}
+
+/// deleting goes through the same audited erasure subscribers can request themselves
+#[tracing::instrument(name = "delete subscriber", skip(pool))]
+pub async fn delete_subscriber(
+    subscriber_id: web::Path<Uuid>,
+    pool: web::Data<PgPool>,
+    user_id: web::ReqData<UserId>,
+    audit: AuditContext,
+) -> Result<HttpResponse, actix_web::Error> {
+    let subscriber_id = subscriber_id.into_inner();
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("failed to establish connection to postgres")
+        .map_err(e500)?;
+
+    let requested_by = ErasureRequester::Admin(*user_id.into_inner());
+    let email = erase_subscriber(&mut transaction, subscriber_id, requested_by)
+        .await
+        .context("failed to erase subscriber")
+        .map_err(e500)?
+        .ok_or_else(|| e404("subscriber not found"))?;
+    // only the id, the log is kept for good and the point was to forget them
+    record_audit_event(
+        &mut *transaction,
+        &audit,
+        AuditAction::SubscriberDelete,
+        Some(&subscriber_id.to_string()),
+        serde_json::json!({}),
+    )
+    .await
+    .map_err(e500)?;
+
+    transaction
+        .commit()
+        .await
+        .context("failed to commit postgres transaction")
+        .map_err(e500)?;
+
+    FlashMessage::info(format!(
+        "Subscriber {} and all of their data deleted",
+        htmlescape::encode_minimal(&email)
+    ))
+    .send();
+    Ok(see_other("/admin/subscribers"))
+}
+
+#[tracing::instrument(
+    name = "resend confirmation email",
+    skip(pool, email_client, base_url)
+)]
+pub async fn resend_confirmation(
+    subscriber_id: web::Path<Uuid>,
+    pool: web::Data<PgPool>,
+    email_client: web::Data<EmailClient>,
+    base_url: web::Data<ApplicationBaseUrl>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let subscriber_id = subscriber_id.into_inner();
+    let subscriber = get_subscriber(&pool, subscriber_id)
+        .await
+        .map_err(e500)?
+        .ok_or_else(|| e404("subscriber not found"))?;
+
+    if subscriber.status != SubscriptionStatus::PendingConfirmation.as_str() {
+        FlashMessage::error("Only subscribers pending confirmation can be sent a confirmation email")
+            .send();
+        return Ok(subscriber_page(subscriber_id));
+    }
+
+    // rows written before validation was introduced may not parse anymore
+    let new_subscriber = match (
+        SubscriberName::parse(subscriber.name),
+        SubscriberEmail::parse(subscriber.email),
+    ) {
+        (Ok(name), Ok(email)) => NewSubscriber { name, email },
+        _ => {
+            FlashMessage::error("Stored subscriber details are invalid").send();
+            return Ok(subscriber_page(subscriber_id));
+        }
+    };
+    if is_suppressed(pool.as_ref(), &new_subscriber.email)
+        .await
+        .context("failed to check suppression list")
+        .map_err(e500)?
+    {
+        FlashMessage::error("This address is on the suppression list").send();
+        return Ok(subscriber_page(subscriber_id));
+    }
+
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("failed to establish connection to postgres")
+        .map_err(e500)?;
+    let token = generate_random_token();
+    store_token(&token, subscriber_id, &mut transaction)
+        .await
+        .context("failed to persist subscription token")
+        .map_err(e500)?;
+    transaction
+        .commit()
+        .await
+        .context("failed to commit postgres transaction")
+        .map_err(e500)?;
+
+    send_confirmation_email(&email_client, &pool, new_subscriber, &base_url.0, &token)
+        .await
+        .context("failed to send confirmation email")
+        .map_err(e500)?;
+
+    FlashMessage::info("Confirmation email sent").send();
+    Ok(subscriber_page(subscriber_id))
+}
+
+/// returns the status the subscriber had before, `None` if there's no such subscriber
+async fn set_subscription_status(
+    transaction: &mut Transaction<'_, Postgres>,
+    subscriber_id: Uuid,
+    status: SubscriptionStatus,
+) -> Result<Option<String>, anyhow::Error> {
+    let previous_status = sqlx::query_scalar!(
+        r#"
+        UPDATE subscriptions s SET status = $2
+        FROM (SELECT id, status FROM subscriptions WHERE id = $1 FOR UPDATE) previous
+        WHERE s.id = previous.id
+        RETURNING previous.status
+        "#,
+        subscriber_id,
+        status.as_str()
+    )
+    .fetch_optional(&mut **transaction)
+    .await
+    .context("failed to update subscription status")?;
+
+    Ok(previous_status)
+}
+
+fn status_change(from: &str, to: SubscriptionStatus) -> serde_json::Value {
+    serde_json::json!({ "status": { "from": from, "to": to.as_str() } })
+}
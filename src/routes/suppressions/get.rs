@@ -0,0 +1,81 @@
+use actix_web::{http::header::ContentType, web, HttpResponse};
+use actix_web_flash_messages::IncomingFlashMessages;
+use anyhow::Context;
+use sqlx::PgPool;
+use std::fmt::Write;
+
+use crate::{authentication::csrf::CsrfToken, suppressions::list_suppressions, utils::e500};
+
+pub async fn suppressions_page(
+    pool: web::Data<PgPool>,
+    flash_messages: IncomingFlashMessages,
+    csrf_token: web::ReqData<CsrfToken>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let mut msg_html = String::new();
+    for m in flash_messages.iter() {
+        writeln!(msg_html, "<p><i>{}</i></p>", m.content()).unwrap();
+    }
+    let csrf_input = csrf_token.hidden_input();
+
+    let suppressions = list_suppressions(pool.as_ref())
+        .await
+        .context("failed to fetch suppressions")
+        .map_err(e500)?;
+    let mut rows_html = String::new();
+    for s in suppressions {
+        let entry = htmlescape::encode_minimal(&s.entry);
+        writeln!(
+            rows_html,
+            r#"<tr><td>{entry}</td><td>{}</td><td>{}</td><td><form action="/admin/suppressions/remove" method="post">{csrf_input}<input hidden type="text" name="entry" value="{}"><input type="submit" value="Remove"></form></td></tr>"#,
+            htmlescape::encode_minimal(&s.reason),
+            s.created_at.format("%Y-%m-%d %H:%M"),
+            htmlescape::encode_attribute(&s.entry),
+        )
+        .unwrap();
+    }
+
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"
+<!DOCTYPE html>
+<html lang="en">
+  <head>
+    <meta charset="UTF-8">
+    <meta name="viewport" content="width=device-width, initial-scale=1">
+    <title>Suppression list</title>
+    <link href="css/style.css" rel="stylesheet">
+  </head>
+  <body>
+    {msg_html}
+    <p>Nothing is ever emailed to these addresses or domains, whatever their subscription status.</p>
+    <form action="/admin/suppressions" method="post">
+      {csrf_input}
+      <label>Address or domain
+        <input type="text" name="entry" placeholder="someone@example.com or example.com">
+      </label>
+      <label>Reason
+        <input type="text" name="reason">
+      </label>
+      <button type="submit">suppress</button>
+    </form>
+    <form action="/admin/suppressions/import" method="post">
+      {csrf_input}
+      <label>One address or domain per line
+        <textarea name="entries" rows="10" cols="50"></textarea>
+      </label>
+      <label>Reason
+        <input type="text" name="reason">
+      </label>
+      <button type="submit">import</button>
+    </form>
+    <table>
+      <tr><th>entry</th><th>reason</th><th>added</th><th></th></tr>
+      {rows_html}
+    </table>
+    <p><a href="/admin/dashboard">&lt;- Back</a></p>
+  </body>
+</html>
+        "#
+        )))
+}
@@ -0,0 +1,206 @@
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::FlashMessage;
+use anyhow::Context;
+use serde::Deserialize;
+use sqlx::PgPool;
+
+use crate::{
+    audit::{record_audit_event, AuditContext},
+    authentication::middleware::UserId,
+    domain::{AuditAction, SuppressionEntry},
+    suppressions::{add_suppression, remove_suppression},
+    utils::{e500, see_other},
+};
+
+#[derive(Deserialize)]
+pub struct SuppressionForm {
+    entry: String,
+    reason: String,
+}
+
+#[derive(Deserialize)]
+pub struct SuppressionImportForm {
+    entries: String,
+    reason: String,
+}
+
+#[derive(Deserialize)]
+pub struct RemoveSuppressionForm {
+    entry: String,
+}
+
+fn suppressions_page() -> HttpResponse {
+    see_other("/admin/suppressions")
+}
+
+fn reason_or_default(reason: &str) -> &str {
+    match reason.trim() {
+        "" => "added by an admin",
+        reason => reason,
+    }
+}
+
+#[tracing::instrument(name = "add suppression", skip(form, pool))]
+pub async fn add_suppression_entry(
+    form: web::Form<SuppressionForm>,
+    pool: web::Data<PgPool>,
+    user_id: web::ReqData<UserId>,
+    audit: AuditContext,
+) -> Result<HttpResponse, actix_web::Error> {
+    let SuppressionForm { entry, reason } = form.0;
+    let entry = match SuppressionEntry::parse(entry) {
+        Ok(entry) => entry,
+        Err(e) => {
+            FlashMessage::error(htmlescape::encode_minimal(&e)).send();
+            return Ok(suppressions_page());
+        }
+    };
+
+    let reason = reason_or_default(&reason);
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("failed to establish connection to postgres")
+        .map_err(e500)?;
+    let added = add_suppression(&mut *transaction, &entry, reason, Some(*user_id.into_inner()))
+        .await
+        .context("failed to add suppression")
+        .map_err(e500)?;
+    if added {
+        record_audit_event(
+            &mut *transaction,
+            &audit,
+            AuditAction::SuppressionAdd,
+            Some(entry.as_ref()),
+            serde_json::json!({ "reason": reason }),
+        )
+        .await
+        .map_err(e500)?;
+    }
+    transaction
+        .commit()
+        .await
+        .context("failed to commit postgres transaction")
+        .map_err(e500)?;
+
+    let entry = htmlescape::encode_minimal(entry.as_ref());
+    if added {
+        FlashMessage::info(format!("{} is now suppressed", entry)).send();
+    } else {
+        FlashMessage::info(format!("{} was already suppressed", entry)).send();
+    }
+    Ok(suppressions_page())
+}
+
+/// every line is handled on its own, invalid ones are reported back and skipped
+#[tracing::instrument(name = "import suppressions", skip(form, pool))]
+pub async fn import_suppressions(
+    form: web::Form<SuppressionImportForm>,
+    pool: web::Data<PgPool>,
+    user_id: web::ReqData<UserId>,
+    audit: AuditContext,
+) -> Result<HttpResponse, actix_web::Error> {
+    let user_id = *user_id.into_inner();
+    let reason = reason_or_default(&form.reason);
+
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("failed to establish connection to postgres")
+        .map_err(e500)?;
+    let (mut n_added, mut n_existing, mut invalid) = (0, 0, vec![]);
+    for line in form.entries.lines().filter(|l| !l.trim().is_empty()) {
+        let Ok(entry) = SuppressionEntry::parse(line.to_string()) else {
+            invalid.push(line.trim().to_string());
+            continue;
+        };
+        if add_suppression(&mut *transaction, &entry, reason, Some(user_id))
+            .await
+            .context("failed to add suppression")
+            .map_err(e500)?
+        {
+            n_added += 1;
+        } else {
+            n_existing += 1;
+        }
+    }
+    record_audit_event(
+        &mut *transaction,
+        &audit,
+        AuditAction::SuppressionImport,
+        None,
+        serde_json::json!({
+            "reason": reason,
+            "added": n_added,
+            "already_present": n_existing,
+            "invalid": invalid.len(),
+        }),
+    )
+    .await
+    .map_err(e500)?;
+    transaction
+        .commit()
+        .await
+        .context("failed to commit postgres transaction")
+        .map_err(e500)?;
+
+    FlashMessage::info(format!(
+        "{} suppressions added, {} already present",
+        n_added, n_existing
+    ))
+    .send();
+    if !invalid.is_empty() {
+        FlashMessage::error(format!(
+            "Skipped invalid lines: {}",
+            htmlescape::encode_minimal(&invalid.join(", "))
+        ))
+        .send();
+    }
+    Ok(suppressions_page())
+}
+
+#[tracing::instrument(name = "remove suppression", skip(form, pool))]
+pub async fn remove_suppression_entry(
+    form: web::Form<RemoveSuppressionForm>,
+    pool: web::Data<PgPool>,
+    audit: AuditContext,
+) -> Result<HttpResponse, actix_web::Error> {
+    let removed = match SuppressionEntry::parse(form.0.entry) {
+        Ok(entry) => {
+            let mut transaction = pool
+                .begin()
+                .await
+                .context("failed to establish connection to postgres")
+                .map_err(e500)?;
+            let removed = remove_suppression(&mut *transaction, &entry)
+                .await
+                .context("failed to remove suppression")
+                .map_err(e500)?;
+            if removed {
+                record_audit_event(
+                    &mut *transaction,
+                    &audit,
+                    AuditAction::SuppressionRemove,
+                    Some(entry.as_ref()),
+                    serde_json::json!({}),
+                )
+                .await
+                .map_err(e500)?;
+            }
+            transaction
+                .commit()
+                .await
+                .context("failed to commit postgres transaction")
+                .map_err(e500)?;
+            removed
+        }
+        Err(_) => false,
+    };
+
+    if removed {
+        FlashMessage::info("Suppression removed").send();
+    } else {
+        FlashMessage::error("No such suppression").send();
+    }
+    Ok(suppressions_page())
+}
@@ -0,0 +1,92 @@
+use actix_web::{http::header::ContentType, web, HttpResponse};
+use actix_web_flash_messages::IncomingFlashMessages;
+use secrecy::ExposeSecret;
+use sqlx::PgPool;
+use std::fmt::Write;
+
+use crate::{
+    authentication::{
+        csrf::CsrfToken,
+        middleware::UserId,
+        totp::{get_user_totp, provisioning_uri, qr_code_svg},
+    },
+    routes::get_username,
+    utils::e500,
+};
+
+pub async fn totp_settings(
+    pool: web::Data<PgPool>,
+    flash_messages: IncomingFlashMessages,
+    user_id: web::ReqData<UserId>,
+    csrf_token: web::ReqData<CsrfToken>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let mut msg_html = String::new();
+    for m in flash_messages.iter() {
+        writeln!(msg_html, "<p><i>{}</i></p>", m.content()).unwrap();
+    }
+    let user_id = *user_id.into_inner();
+    let csrf_input = csrf_token.hidden_input();
+
+    let content_html = match get_user_totp(&pool, user_id).await.map_err(e500)? {
+        None => format!(
+            r#"
+    <p>Two-factor authentication is off.</p>
+    <form action="/admin/totp/enroll" method="post">
+      {csrf_input}
+      <button type="submit">Set up an authenticator app</button>
+    </form>"#
+        ),
+        Some(totp) if totp.confirmed => format!(
+            r#"
+    <p>Two-factor authentication is on.</p>
+    <form action="/admin/totp/disable" method="post">
+      {csrf_input}
+      <label>Code from your authenticator app, or a recovery code
+        <input type="text" name="code" autocomplete="one-time-code">
+      </label>
+      <button type="submit">Turn off</button>
+    </form>"#
+        ),
+        Some(totp) => {
+            let username = get_username(&pool, user_id).await.map_err(e500)?;
+            let uri = provisioning_uri(&totp.secret, &username).map_err(e500)?;
+            let qr_code = qr_code_svg(&uri).map_err(e500)?;
+            format!(
+                r#"
+    <p>Scan this with your authenticator app, then enter the code it shows to finish.</p>
+    {qr_code}
+    <p>Or enter the key by hand: <code id="totp-secret">{}</code></p>
+    <p><a href="{}">open in an authenticator app</a></p>
+    <form action="/admin/totp/confirm" method="post">
+      {csrf_input}
+      <label>Code
+        <input type="text" name="code" autocomplete="one-time-code">
+      </label>
+      <button type="submit">Confirm</button>
+    </form>"#,
+                htmlescape::encode_minimal(totp.secret.expose_secret()),
+                htmlescape::encode_minimal(&uri),
+            )
+        }
+    };
+
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"
+<!DOCTYPE html>
+<html lang="en">
+  <head>
+    <meta charset="UTF-8">
+    <meta name="viewport" content="width=device-width, initial-scale=1">
+    <title>Two-factor authentication</title>
+  </head>
+  <body>
+    {msg_html}
+    {content_html}
+    <p><a href="/admin/dashboard">&lt;- Back</a></p>
+  </body>
+</html>
+            "#
+        )))
+}
@@ -0,0 +1,139 @@
+use actix_web::{http::header::ContentType, web, HttpResponse};
+use actix_web_flash_messages::FlashMessage;
+use secrecy::{ExposeSecret, Secret};
+use serde::Deserialize;
+use sqlx::PgPool;
+use std::fmt::Write;
+
+use crate::{
+    audit::{record_audit_event, AuditContext},
+    authentication::{
+        middleware::UserId,
+        PasswordHashing,
+        totp::{
+            confirm_totp_enrolment, disable_totp, generate_totp_secret, get_user_totp,
+            start_totp_enrolment, verify_second_factor, verify_totp_code,
+        },
+    },
+    domain::AuditAction,
+    utils::{e500, see_other},
+};
+
+#[derive(Deserialize)]
+pub struct TotpCodeForm {
+    code: Secret<String>,
+}
+
+fn totp_settings_page() -> HttpResponse {
+    see_other("/admin/totp")
+}
+
+#[tracing::instrument(name = "start totp enrolment", skip(pool))]
+pub async fn enroll_totp(
+    pool: web::Data<PgPool>,
+    user_id: web::ReqData<UserId>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let user_id = *user_id.into_inner();
+    start_totp_enrolment(&pool, user_id, &generate_totp_secret())
+        .await
+        .map_err(e500)?;
+    Ok(totp_settings_page())
+}
+
+/// the recovery codes are rendered straight away rather than redirecting,
+/// they aren't stored anywhere they could be shown from again
+#[tracing::instrument(name = "confirm totp enrolment", skip(form, pool, password_hashing))]
+pub async fn confirm_totp(
+    form: web::Form<TotpCodeForm>,
+    pool: web::Data<PgPool>,
+    user_id: web::ReqData<UserId>,
+    password_hashing: web::Data<PasswordHashing>,
+    audit: AuditContext,
+) -> Result<HttpResponse, actix_web::Error> {
+    let user_id = *user_id.into_inner();
+    let totp = match get_user_totp(&pool, user_id).await.map_err(e500)? {
+        Some(totp) if !totp.confirmed => totp,
+        _ => {
+            FlashMessage::error("There is no pending two-factor setup to confirm").send();
+            return Ok(totp_settings_page());
+        }
+    };
+
+    let code = form.0.code.expose_secret().trim().replace(' ', "");
+    let Some(step) = verify_totp_code(&totp.secret, &code, totp.last_used_step).map_err(e500)?
+    else {
+        FlashMessage::error("That code didn't match, check your device's clock and try again")
+            .send();
+        return Ok(totp_settings_page());
+    };
+    let recovery_codes = confirm_totp_enrolment(&pool, user_id, step, &password_hashing)
+        .await
+        .map_err(e500)?;
+    record_audit_event(
+        pool.as_ref(),
+        &audit,
+        AuditAction::TotpEnable,
+        Some(&user_id.to_string()),
+        serde_json::json!({}),
+    )
+    .await
+    .map_err(e500)?;
+
+    let mut codes_html = String::new();
+    for code in recovery_codes {
+        writeln!(codes_html, "<li><code>{code}</code></li>").unwrap();
+    }
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"
+<!DOCTYPE html>
+<html lang="en">
+  <head>
+    <meta charset="UTF-8">
+    <meta name="viewport" content="width=device-width, initial-scale=1">
+    <title>Recovery codes</title>
+  </head>
+  <body>
+    <p>Two-factor authentication is on.</p>
+    <p>Keep these recovery codes somewhere safe, each one can be used once to log in without your device. They won't be shown again.</p>
+    <ol id="recovery-codes">
+{codes_html}    </ol>
+    <p><a href="/admin/dashboard">&lt;- Back</a></p>
+  </body>
+</html>
+            "#
+        )))
+}
+
+#[tracing::instrument(name = "disable totp", skip(form, pool))]
+pub async fn turn_off_totp(
+    form: web::Form<TotpCodeForm>,
+    pool: web::Data<PgPool>,
+    user_id: web::ReqData<UserId>,
+    audit: AuditContext,
+) -> Result<HttpResponse, actix_web::Error> {
+    let user_id = *user_id.into_inner();
+    let code = form.0.code.expose_secret().trim().replace(' ', "");
+    // someone walking up to a logged in browser shouldn't be able to drop the second factor
+    if !verify_second_factor(&pool, user_id, &code)
+        .await
+        .map_err(e500)?
+    {
+        FlashMessage::error("That code didn't match").send();
+        return Ok(totp_settings_page());
+    }
+
+    disable_totp(&pool, user_id).await.map_err(e500)?;
+    record_audit_event(
+        pool.as_ref(),
+        &audit,
+        AuditAction::TotpDisable,
+        Some(&user_id.to_string()),
+        serde_json::json!({}),
+    )
+    .await
+    .map_err(e500)?;
+    FlashMessage::info("Two-factor authentication is off").send();
+    Ok(totp_settings_page())
+}
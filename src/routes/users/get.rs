@@ -0,0 +1,179 @@
+use actix_web::{http::header::ContentType, web, HttpResponse};
+use actix_web_flash_messages::IncomingFlashMessages;
+use anyhow::Context;
+use serde::Deserialize;
+use sqlx::PgPool;
+use std::fmt::Write;
+
+use crate::{
+    authentication::{csrf::CsrfToken, middleware::UserId},
+    domain::UserRole,
+    users::list_users,
+    utils::e500,
+};
+
+use super::persistence::{is_invitation_valid, list_pending_invitations};
+
+#[derive(Deserialize)]
+pub struct InvitationParameters {
+    token: String,
+}
+
+fn role_options(selected: UserRole) -> String {
+    let mut options_html = String::new();
+    for role in UserRole::ALL {
+        let selected = if role == selected { " selected" } else { "" };
+        write!(
+            options_html,
+            r#"<option value="{0}"{selected}>{0}</option>"#,
+            role.as_str()
+        )
+        .unwrap();
+    }
+    options_html
+}
+
+pub async fn list_admin_users(
+    pool: web::Data<PgPool>,
+    flash_messages: IncomingFlashMessages,
+    user_id: web::ReqData<UserId>,
+    csrf_token: web::ReqData<CsrfToken>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let mut msg_html = String::new();
+    for m in flash_messages.iter() {
+        writeln!(msg_html, "<p><i>{}</i></p>", m.content()).unwrap();
+    }
+    let current_user_id = *user_id.into_inner();
+    let csrf_input = csrf_token.hidden_input();
+
+    let users = list_users(pool.as_ref())
+        .await
+        .context("failed to fetch users")
+        .map_err(e500)?;
+    let mut users_html = String::new();
+    for user in users {
+        let you = if user.user_id == current_user_id { " (you)" } else { "" };
+        writeln!(
+            users_html,
+            r#"<tr><td>{}{you}</td><td>{}</td><td><form action="/admin/users/{}/role" method="post">{csrf_input}<select name="role">{}</select><input type="submit" value="Change role"></form></td></tr>"#,
+            htmlescape::encode_minimal(&user.name),
+            htmlescape::encode_minimal(user.email.as_deref().unwrap_or_default()),
+            user.user_id,
+            role_options(user.role),
+        )
+        .unwrap();
+    }
+
+    let invitations = list_pending_invitations(pool.as_ref())
+        .await
+        .context("failed to fetch invitations")
+        .map_err(e500)?;
+    let mut invitations_html = String::new();
+    for invitation in invitations {
+        writeln!(
+            invitations_html,
+            r#"<tr><td>{}</td><td>{}</td><td>{}</td><td><form action="/admin/users/invitations/{}/revoke" method="post">{csrf_input}<input type="submit" value="Revoke"></form></td></tr>"#,
+            htmlescape::encode_minimal(&invitation.email),
+            htmlescape::encode_minimal(&invitation.role),
+            invitation.created_at.format("%Y-%m-%d %H:%M"),
+            invitation.invitation_id,
+        )
+        .unwrap();
+    }
+
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"
+<!DOCTYPE html>
+<html lang="en">
+  <head>
+    <meta charset="UTF-8">
+    <meta name="viewport" content="width=device-width, initial-scale=1">
+    <title>Users</title>
+  </head>
+  <body>
+    {msg_html}
+    <p>Owners manage users, publishers send newsletters and manage subscribers,
+    editors write newsletters, viewers can look around.</p>
+    <table>
+      <tr><th>username</th><th>email</th><th>role</th></tr>
+{users_html}    </table>
+    <p>pending invitations:</p>
+    <table>
+      <tr><th>email</th><th>role</th><th>sent</th><th></th></tr>
+{invitations_html}    </table>
+    <form action="/admin/users/invitations" method="post">
+      {csrf_input}
+      <label>Email
+        <input type="email" name="email">
+      </label>
+      <label>Role
+        <select name="role">{}</select>
+      </label>
+      <button type="submit">invite</button>
+    </form>
+    <p><a href="/admin/dashboard">&lt;- Back</a></p>
+  </body>
+</html>
+            "#,
+            role_options(UserRole::Viewer)
+        )))
+}
+
+#[tracing::instrument(name = "invitation link", skip(parameters, pool, flash_messages))]
+pub async fn accept_invitation_form(
+    parameters: web::Query<InvitationParameters>,
+    pool: web::Data<PgPool>,
+    flash_messages: IncomingFlashMessages,
+) -> Result<HttpResponse, actix_web::Error> {
+    let content_html = if is_invitation_valid(pool.as_ref(), &parameters.token)
+        .await
+        .context("failed to look up invitation")
+        .map_err(e500)?
+    {
+        let mut msg_html = String::new();
+        for m in flash_messages.iter() {
+            writeln!(msg_html, "<p><i>{}</i></p>", m.content()).unwrap();
+        }
+        format!(
+            r#"{msg_html}
+  <p>Pick a username and password to finish setting up your account.</p>
+  <form action="/invitations/accept" method="post">
+     <input hidden type="text" name="token" value="{}">
+     <label>
+        Username
+        <input type="text" name="username">
+      </label>
+     <label>
+        Password
+        <input type="password" name="password">
+      </label>
+     <label>
+        Confirm password
+        <input type="password" name="confirm_password">
+      </label>
+      <button type="submit">Create account</button>
+   </form>"#,
+            htmlescape::encode_attribute(&parameters.token)
+        )
+    } else {
+        "<p>This invitation has expired or was already used, ask for a new one.</p>".to_string()
+    };
+
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"<!DOCTYPE html>
+<html lang="en">
+  <head>
+    <meta charset="UTF-8">
+    <meta name="viewport" content="width=device-width, initial-scale=1">
+    <title>Accept invitation</title>
+  </head>
+  <body>
+  {content_html}
+  </body>
+</html>"#
+        )))
+}
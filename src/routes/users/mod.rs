@@ -0,0 +1,6 @@
+mod get;
+mod persistence;
+mod post;
+
+pub use get::*;
+pub use post::*;
@@ -0,0 +1,135 @@
+use chrono::{DateTime, Utc};
+use sqlx::{PgExecutor, Postgres, Transaction};
+use uuid::Uuid;
+
+use crate::{domain::UserRole, utils::hash_token};
+
+/// invitees have a week to pick a username and password
+pub const INVITATION_TTL_DAYS: i32 = 7;
+
+pub struct PendingInvitation {
+    pub invitation_id: Uuid,
+    pub email: String,
+    pub role: String,
+    pub created_at: DateTime<Utc>,
+}
+
+pub struct AcceptedInvitation {
+    pub email: String,
+    pub role: UserRole,
+}
+
+pub async fn store_invitation<'e>(
+    executor: impl PgExecutor<'e>,
+    invitation_token: &str,
+    email: &str,
+    role: UserRole,
+    invited_by: Uuid,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+        INSERT INTO user_invitations(
+            invitation_id,
+            invitation_token_hash,
+            email,
+            role,
+            invited_by,
+            created_at,
+            accepted_at
+        )
+        VALUES ($1, $2, $3, $4, $5, now(), NULL)
+        "#,
+        Uuid::new_v4(),
+        hash_token(invitation_token),
+        email,
+        role.as_str(),
+        invited_by
+    )
+    .execute(executor)
+    .await?;
+    Ok(())
+}
+
+pub async fn list_pending_invitations<'e>(
+    executor: impl PgExecutor<'e>,
+) -> Result<Vec<PendingInvitation>, sqlx::Error> {
+    sqlx::query_as!(
+        PendingInvitation,
+        r#"
+        SELECT invitation_id, email, role, created_at
+        FROM user_invitations
+        WHERE
+            accepted_at IS NULL AND
+            created_at > now() - make_interval(days => $1)
+        ORDER BY created_at DESC
+        "#,
+        INVITATION_TTL_DAYS
+    )
+    .fetch_all(executor)
+    .await
+}
+
+pub async fn delete_invitation<'e>(
+    executor: impl PgExecutor<'e>,
+    invitation_id: Uuid,
+) -> Result<bool, sqlx::Error> {
+    let n_deleted_rows = sqlx::query!(
+        "DELETE FROM user_invitations WHERE invitation_id = $1 AND accepted_at IS NULL",
+        invitation_id
+    )
+    .execute(executor)
+    .await?
+    .rows_affected();
+    Ok(n_deleted_rows > 0)
+}
+
+/// false if the token doesn't exist, was used or has expired
+pub async fn is_invitation_valid<'e>(
+    executor: impl PgExecutor<'e>,
+    invitation_token: &str,
+) -> Result<bool, sqlx::Error> {
+    sqlx::query_scalar!(
+        r#"
+        SELECT EXISTS(
+            SELECT 1 FROM user_invitations
+            WHERE
+                invitation_token_hash = $1 AND
+                accepted_at IS NULL AND
+                created_at > now() - make_interval(days => $2)
+        ) as "exists!"
+        "#,
+        hash_token(invitation_token),
+        INVITATION_TTL_DAYS
+    )
+    .fetch_one(executor)
+    .await
+}
+
+/// marks the invitation accepted; `None` if it wasn't valid anymore
+pub async fn consume_invitation(
+    transaction: &mut Transaction<'_, Postgres>,
+    invitation_token: &str,
+) -> Result<Option<AcceptedInvitation>, anyhow::Error> {
+    let row = sqlx::query!(
+        r#"
+        UPDATE user_invitations SET accepted_at = now()
+        WHERE
+            invitation_token_hash = $1 AND
+            accepted_at IS NULL AND
+            created_at > now() - make_interval(days => $2)
+        RETURNING email, role
+        "#,
+        hash_token(invitation_token),
+        INVITATION_TTL_DAYS
+    )
+    .fetch_optional(&mut **transaction)
+    .await?;
+
+    row.map(|r| {
+        Ok(AcceptedInvitation {
+            email: r.email,
+            role: UserRole::try_from(r.role).map_err(|e| anyhow::anyhow!(e))?,
+        })
+    })
+    .transpose()
+}
@@ -0,0 +1,320 @@
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::FlashMessage;
+use anyhow::Context;
+use secrecy::{ExposeSecret, Secret};
+use serde::Deserialize;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::{
+    audit::{record_audit_event, AuditContext},
+    authentication::{check_password_policy, middleware::UserId, PasswordHashing},
+    configuration::PasswordPolicySettings,
+    domain::{AuditAction, SubscriberEmail, UserRole},
+    email_client::EmailClient,
+    routes::generate_random_token,
+    users::{get_user_role, insert_user, set_user_role},
+    utils::{e500, see_other},
+    ApplicationBaseUrl,
+};
+
+use super::persistence::{consume_invitation, delete_invitation, store_invitation, INVITATION_TTL_DAYS};
+
+#[derive(Deserialize)]
+pub struct InvitationForm {
+    email: String,
+    role: String,
+}
+
+#[derive(Deserialize)]
+pub struct RoleForm {
+    role: String,
+}
+
+#[derive(Deserialize)]
+pub struct AcceptInvitationForm {
+    token: String,
+    username: String,
+    password: Secret<String>,
+    confirm_password: Secret<String>,
+}
+
+fn users_page() -> HttpResponse {
+    see_other("/admin/users")
+}
+
+#[tracing::instrument(name = "invite user", skip(form, pool, email_client, base_url))]
+pub async fn invite_user(
+    form: web::Form<InvitationForm>,
+    pool: web::Data<PgPool>,
+    email_client: web::Data<EmailClient>,
+    base_url: web::Data<ApplicationBaseUrl>,
+    user_id: web::ReqData<UserId>,
+    audit: AuditContext,
+) -> Result<HttpResponse, actix_web::Error> {
+    let InvitationForm { email, role } = form.0;
+    let email = match SubscriberEmail::parse(email.trim().to_lowercase()) {
+        Ok(email) => email,
+        Err(e) => {
+            FlashMessage::error(htmlescape::encode_minimal(&e)).send();
+            return Ok(users_page());
+        }
+    };
+    let role = match UserRole::try_from(role) {
+        Ok(role) => role,
+        Err(e) => {
+            FlashMessage::error(htmlescape::encode_minimal(&e)).send();
+            return Ok(users_page());
+        }
+    };
+
+    let token = generate_random_token();
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("failed to establish connection to postgres")
+        .map_err(e500)?;
+    store_invitation(&mut *transaction, &token, email.as_ref(), role, *user_id.into_inner())
+        .await
+        .context("failed to store invitation")
+        .map_err(e500)?;
+    record_audit_event(
+        &mut *transaction,
+        &audit,
+        AuditAction::UserInvite,
+        Some(email.as_ref()),
+        serde_json::json!({ "role": role.as_str() }),
+    )
+    .await
+    .map_err(e500)?;
+    transaction
+        .commit()
+        .await
+        .context("failed to commit postgres transaction")
+        .map_err(e500)?;
+    send_invitation_email(&email_client, &email, role, &base_url.0, &token)
+        .await
+        .context("failed to send invitation email")
+        .map_err(e500)?;
+
+    FlashMessage::info(format!(
+        "Invited {} as {}",
+        htmlescape::encode_minimal(email.as_ref()),
+        role.as_str()
+    ))
+    .send();
+    Ok(users_page())
+}
+
+#[tracing::instrument(name = "revoke invitation", skip(pool))]
+pub async fn revoke_invitation(
+    path: web::Path<Uuid>,
+    pool: web::Data<PgPool>,
+    audit: AuditContext,
+) -> Result<HttpResponse, actix_web::Error> {
+    let invitation_id = path.into_inner();
+    if delete_invitation(pool.as_ref(), invitation_id)
+        .await
+        .context("failed to revoke invitation")
+        .map_err(e500)?
+    {
+        record_audit_event(
+            pool.as_ref(),
+            &audit,
+            AuditAction::InvitationRevoke,
+            Some(&invitation_id.to_string()),
+            serde_json::json!({}),
+        )
+        .await
+        .map_err(e500)?;
+        FlashMessage::info("Invitation revoked").send();
+    }
+    Ok(users_page())
+}
+
+#[tracing::instrument(name = "change user role", skip(form, pool))]
+pub async fn change_user_role(
+    path: web::Path<Uuid>,
+    form: web::Form<RoleForm>,
+    pool: web::Data<PgPool>,
+    audit: AuditContext,
+) -> Result<HttpResponse, actix_web::Error> {
+    let target_user_id = path.into_inner();
+    let role = match UserRole::try_from(form.0.role) {
+        Ok(role) => role,
+        Err(e) => {
+            FlashMessage::error(htmlescape::encode_minimal(&e)).send();
+            return Ok(users_page());
+        }
+    };
+
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("failed to establish connection to postgres")
+        .map_err(e500)?;
+    let previous_role = get_user_role(&mut *transaction, target_user_id)
+        .await
+        .map_err(e500)?;
+    if set_user_role(&mut transaction, target_user_id, role)
+        .await
+        .context("failed to change role")
+        .map_err(e500)?
+    {
+        record_audit_event(
+            &mut *transaction,
+            &audit,
+            AuditAction::UserRoleChange,
+            Some(&target_user_id.to_string()),
+            serde_json::json!({
+                "role": { "from": previous_role.map(|r| r.as_str()), "to": role.as_str() }
+            }),
+        )
+        .await
+        .map_err(e500)?;
+        transaction
+            .commit()
+            .await
+            .context("failed to commit postgres transaction")
+            .map_err(e500)?;
+        FlashMessage::info("Role changed").send();
+    } else {
+        FlashMessage::error("There has to be at least one owner").send();
+    }
+    Ok(users_page())
+}
+
+#[tracing::instrument(
+    name = "accept invitation",
+    skip(form, pool, password_policy, password_hashing),
+    fields(username = %form.username)
+)]
+pub async fn accept_invitation(
+    form: web::Form<AcceptInvitationForm>,
+    pool: web::Data<PgPool>,
+    password_policy: web::Data<PasswordPolicySettings>,
+    password_hashing: web::Data<PasswordHashing>,
+    audit: AuditContext,
+) -> Result<HttpResponse, actix_web::Error> {
+    let AcceptInvitationForm {
+        token,
+        username,
+        password,
+        confirm_password,
+    } = form.0;
+    let retry = |message: &str| {
+        FlashMessage::error(message).send();
+        see_other(&format!(
+            "/invitations/accept?token={}",
+            urlencoding::encode(&token)
+        ))
+    };
+
+    let username = username.trim().to_string();
+    if username.is_empty() {
+        return Ok(retry("Pick a username"));
+    }
+    if password.expose_secret() != confirm_password.expose_secret() {
+        return Ok(retry("You entered two different passwords"));
+    }
+    if let Err(e) = check_password_policy(&password_policy, &password, &username) {
+        return Ok(retry(&e.to_string()));
+    }
+
+    let password_hashing = password_hashing.get_ref().clone();
+    let password_hash = tokio::task::spawn_blocking(move || password_hashing.hash(password))
+        .await
+        .context("failed to spawn thread")
+        .map_err(e500)?
+        .map_err(e500)?;
+
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("failed to establish connection to postgres")
+        .map_err(e500)?;
+    let Some(invitation) = consume_invitation(&mut transaction, &token)
+        .await
+        .context("failed to accept invitation")
+        .map_err(e500)?
+    else {
+        FlashMessage::error("This invitation has expired or was already used").send();
+        return Ok(see_other("/login"));
+    };
+
+    let result = insert_user(
+        &mut *transaction,
+        &username,
+        password_hash.expose_secret(),
+        &invitation.email,
+        invitation.role,
+    )
+    .await;
+    let new_user_id = match result {
+        Ok(user_id) => user_id,
+        // the transaction is dropped, so the invitation can still be used
+        Err(sqlx::Error::Database(e)) if e.constraint() == Some("users_name_key") => {
+            return Ok(retry("That username is taken"));
+        }
+        Err(sqlx::Error::Database(e)) if e.constraint() == Some("users_email_key") => {
+            return Ok(retry("There already is an account with this email"));
+        }
+        Err(e) => {
+            return Err(e500(anyhow::Error::new(e).context("failed to create user")));
+        }
+    };
+    record_audit_event(
+        &mut *transaction,
+        &audit.as_user(new_user_id),
+        AuditAction::InvitationAccept,
+        Some(&new_user_id.to_string()),
+        serde_json::json!({
+            "username": username,
+            "email": invitation.email,
+            "role": invitation.role.as_str(),
+        }),
+    )
+    .await
+    .map_err(e500)?;
+    transaction
+        .commit()
+        .await
+        .context("failed to commit postgres transaction")
+        .map_err(e500)?;
+
+    FlashMessage::info("Your account is ready, you can log in now").send();
+    Ok(see_other("/login"))
+}
+
+#[tracing::instrument(name = "send invitation email", skip(email_client, email, base_url, token))]
+async fn send_invitation_email(
+    email_client: &EmailClient,
+    email: &SubscriberEmail,
+    role: UserRole,
+    base_url: &str,
+    token: &str,
+) -> Result<(), reqwest::Error> {
+    let accept_link = format!("{}/invitations/accept?token={}", base_url, token);
+
+    let plain_body = format!(
+        "You've been invited to help run the newsletter as {}.\n\
+        Set up your account here: {}\n\
+        This link expires in {} days.",
+        role.as_str(),
+        accept_link,
+        INVITATION_TTL_DAYS
+    );
+    let html_body = format!(
+        "You've been invited to help run the newsletter as {}.<br />\
+        <a href=\"{}\">Set up your account</a><br />\
+        This link expires in {} days.",
+        role.as_str(),
+        accept_link,
+        INVITATION_TTL_DAYS
+    );
+
+    email_client
+        .send_email(email, "you're invited", &html_body, &plain_body)
+        .await?;
+    Ok(())
+}
@@ -0,0 +1,194 @@
+use actix_web::{
+    http::{
+        header::{self, HeaderValue},
+        StatusCode,
+    },
+    web, HttpRequest, HttpResponse, ResponseError,
+};
+use anyhow::Context;
+use serde::Deserialize;
+use sqlx::{Executor, PgPool};
+use uuid::Uuid;
+
+use crate::{
+    authentication::{basic_authentication, credentials_match},
+    configuration::WebhookSettings,
+    domain::{SubscriptionStatus, SuppressionEntry},
+    suppressions::add_suppression,
+};
+
+/// the fields we care about from Postmark's bounce and spam complaint webhooks,
+/// the whole payload is kept in `email_events` as well
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "PascalCase")]
+struct PostmarkEvent {
+    record_type: String,
+    #[serde(rename = "Type")]
+    bounce_type: Option<String>,
+    email: String,
+}
+
+impl PostmarkEvent {
+    /// `None` for events that don't say anything about the address, e.g. soft bounces
+    fn subscription_status(&self) -> Option<SubscriptionStatus> {
+        match (self.record_type.as_str(), self.bounce_type.as_deref()) {
+            ("SpamComplaint", _) | ("Bounce", Some("SpamComplaint")) => {
+                Some(SubscriptionStatus::Complained)
+            }
+            ("Bounce", Some("HardBounce" | "BadEmailAddress" | "ManuallyDeactivated")) => {
+                Some(SubscriptionStatus::Bounced)
+            }
+            _ => None,
+        }
+    }
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum WebhookError {
+    #[error("Authentication failed")]
+    AuthError(#[source] anyhow::Error),
+    #[error("{0}")]
+    ValidationError(String),
+    #[error(transparent)]
+    UnexpectedError(#[from] anyhow::Error),
+}
+
+impl ResponseError for WebhookError {
+    fn error_response(&self) -> HttpResponse<actix_web::body::BoxBody> {
+        match self {
+            Self::AuthError(_) => {
+                let mut response = HttpResponse::new(StatusCode::UNAUTHORIZED);
+                response.headers_mut().insert(
+                    header::WWW_AUTHENTICATE,
+                    HeaderValue::from_str(r#"Basic realm="webhooks""#).unwrap(),
+                );
+                response
+            }
+            Self::ValidationError(_) => HttpResponse::new(StatusCode::BAD_REQUEST),
+            Self::UnexpectedError(_) => HttpResponse::new(StatusCode::INTERNAL_SERVER_ERROR),
+        }
+    }
+}
+
+#[tracing::instrument(name = "postmark webhook", skip(request, body, pool, webhooks))]
+pub async fn postmark_webhook(
+    request: HttpRequest,
+    body: web::Bytes,
+    pool: web::Data<PgPool>,
+    webhooks: web::Data<WebhookSettings>,
+) -> Result<HttpResponse, WebhookError> {
+    let credentials = basic_authentication(request.headers()).map_err(WebhookError::AuthError)?;
+    if !credentials_match(
+        &credentials,
+        &webhooks.postmark_username,
+        &webhooks.postmark_password,
+    ) {
+        return Err(WebhookError::AuthError(anyhow::anyhow!(
+            "invalid webhook credentials"
+        )));
+    }
+
+    let payload: serde_json::Value = serde_json::from_slice(&body)
+        .map_err(|e| WebhookError::ValidationError(e.to_string()))?;
+    let event: PostmarkEvent = serde_json::from_value(payload.clone())
+        .map_err(|e| WebhookError::ValidationError(e.to_string()))?;
+
+    // Postmark retries anything that isn't a 2xx, so events we don't act on are acknowledged
+    let Some(status) = event.subscription_status() else {
+        tracing::info!(
+            record_type = %event.record_type,
+            bounce_type = ?event.bounce_type,
+            "ignoring postmark event"
+        );
+        return Ok(HttpResponse::Ok().finish());
+    };
+
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("failed to establish connection to postgres")?;
+
+    transaction
+        .execute(sqlx::query!(
+            r#"
+            INSERT INTO email_events(event_id, email, record_type, bounce_type, payload, received_at)
+            VALUES ($1, $2, $3, $4, $5, now())
+            "#,
+            Uuid::new_v4(),
+            event.email,
+            event.record_type,
+            event.bounce_type,
+            payload,
+        ))
+        .await
+        .context("failed to record email event")?;
+
+    transaction
+        .execute(sqlx::query!(
+            "UPDATE subscriptions SET status = $2 WHERE lower(email) = lower($1)",
+            event.email,
+            status.as_str(),
+        ))
+        .await
+        .context("failed to update subscription status")?;
+
+    transaction
+        .execute(sqlx::query!(
+            "DELETE FROM issue_delivery_queue WHERE lower(email) = lower($1)",
+            event.email
+        ))
+        .await
+        .context("failed to clear pending deliveries")?;
+
+    // the status alone would be lost if they re-subscribe or get re-imported
+    if let Ok(entry) = SuppressionEntry::parse(event.email.clone()) {
+        let reason = match status {
+            SubscriptionStatus::Complained => "spam complaint",
+            _ => "hard bounce",
+        };
+        add_suppression(&mut *transaction, &entry, reason, None)
+            .await
+            .context("failed to suppress address")?;
+    }
+
+    transaction
+        .commit()
+        .await
+        .context("failed to commit postgres transaction")?;
+
+    tracing::warn!(status = status.as_str(), "subscriber stopped receiving emails");
+    Ok(HttpResponse::Ok().finish())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::PostmarkEvent;
+    use crate::domain::SubscriptionStatus;
+
+    fn event(record_type: &str, bounce_type: &str) -> PostmarkEvent {
+        PostmarkEvent {
+            record_type: record_type.into(),
+            bounce_type: Some(bounce_type.into()),
+            email: "ursula_le_guin@gmail.com".into(),
+        }
+    }
+
+    #[test]
+    fn hard_bounces_and_complaints_change_the_status() {
+        assert_eq!(
+            event("Bounce", "HardBounce").subscription_status(),
+            Some(SubscriptionStatus::Bounced)
+        );
+        assert_eq!(
+            event("SpamComplaint", "SpamComplaint").subscription_status(),
+            Some(SubscriptionStatus::Complained)
+        );
+    }
+
+    #[test]
+    fn soft_bounces_are_ignored() {
+        assert_eq!(event("Bounce", "SoftBounce").subscription_status(), None);
+        assert_eq!(event("Bounce", "Transient").subscription_status(), None);
+        assert_eq!(event("Delivery", "").subscription_status(), None);
+    }
+}
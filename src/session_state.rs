@@ -4,6 +4,8 @@ use actix_session::{Session, SessionExt, SessionGetError, SessionInsertError};
 use actix_web::{dev::Payload, FromRequest, HttpRequest};
 use uuid::Uuid;
 
+use crate::authentication::oidc::PendingOidcLogin;
+
 pub struct TypedSession(Session);
 
 //NOTE: this helps us avoid typos down the line & minimizes refactor pains
@@ -11,6 +13,14 @@ pub struct TypedSession(Session);
 
 impl TypedSession{
     const USER_ID_KEY: &'static str = "user_id";
+    // row in `user_sessions`, see `authentication::sessions`
+    const SESSION_ID_KEY: &'static str = "session_id";
+    // see `authentication::csrf`
+    const CSRF_TOKEN_KEY: &'static str = "csrf_token";
+    // password checked, second factor still outstanding
+    const PENDING_USER_ID_KEY: &'static str = "pending_user_id";
+    // sent to the identity provider, waiting for it to send the user back
+    const PENDING_OIDC_LOGIN_KEY: &'static str = "pending_oidc_login";
 
     pub fn renew(&self){
         self.0.renew();
@@ -18,12 +28,40 @@ impl TypedSession{
     pub fn purge(&self){
         self.0.purge();
     }
-    pub fn insert_user_id(&self, user_id: Uuid)->Result<(), SessionInsertError>{
+    pub fn insert_user_id(&self, user_id: Uuid, session_id: Uuid)->Result<(), SessionInsertError>{
+        self.0.insert(Self::SESSION_ID_KEY, session_id)?;
         self.0.insert(Self::USER_ID_KEY, user_id)
     }
     pub fn get_user_id(&self)->Result<Option<Uuid>, SessionGetError>{
         self.0.get(Self::USER_ID_KEY)
     }
+    pub fn get_session_id(&self)->Result<Option<Uuid>, SessionGetError>{
+        self.0.get(Self::SESSION_ID_KEY)
+    }
+    pub fn insert_pending_user_id(&self, user_id: Uuid)->Result<(), SessionInsertError>{
+        self.0.insert(Self::PENDING_USER_ID_KEY, user_id)
+    }
+    pub fn get_pending_user_id(&self)->Result<Option<Uuid>, SessionGetError>{
+        self.0.get(Self::PENDING_USER_ID_KEY)
+    }
+    pub fn remove_pending_user_id(&self){
+        self.0.remove(Self::PENDING_USER_ID_KEY);
+    }
+    pub fn insert_pending_oidc_login(&self, login: &PendingOidcLogin)->Result<(), SessionInsertError>{
+        self.0.insert(Self::PENDING_OIDC_LOGIN_KEY, login)
+    }
+    /// a login can only be completed once
+    pub fn take_pending_oidc_login(&self)->Result<Option<PendingOidcLogin>, SessionGetError>{
+        let login = self.0.get(Self::PENDING_OIDC_LOGIN_KEY)?;
+        self.0.remove(Self::PENDING_OIDC_LOGIN_KEY);
+        Ok(login)
+    }
+    pub fn insert_csrf_token(&self, token: &str)->Result<(), SessionInsertError>{
+        self.0.insert(Self::CSRF_TOKEN_KEY, token)
+    }
+    pub fn get_csrf_token(&self)->Result<Option<String>, SessionGetError>{
+        self.0.get(Self::CSRF_TOKEN_KEY)
+    }
 }
 
 // custom extractor implementation 
@@ -1,21 +1,34 @@
+use actix_multipart::form::MultipartFormConfig;
 use actix_session::storage::RedisSessionStore;
+use actix_session::config::BrowserSession;
 use actix_session::SessionMiddleware;
-use actix_web::cookie::Key;
+use actix_web::cookie::{time::Duration, Key};
 use actix_web::web::scope;
 use actix_web_flash_messages::storage::CookieMessageStore;
 use actix_web_flash_messages::FlashMessagesFramework;
 use secrecy::{ExposeSecret, Secret};
 use sqlx::postgres::PgPoolOptions;
 use sqlx::PgPool;
-use std::net::TcpListener;
+use std::net::{IpAddr, TcpListener};
 
 use actix_web::middleware::{from_fn, Logger};
 use actix_web::{dev::Server, web, App, HttpRequest, HttpResponse, HttpServer, Responder};
 
-use crate::authentication::middleware::reject_anonymous_users;
+use crate::authentication::middleware::{
+    reject_anonymous_users, reject_invalid_api_tokens, require_permission,
+};
+use crate::authentication::csrf::reject_invalid_csrf_tokens;
+use crate::authentication::oidc::OidcClient;
+use crate::authentication::sessions::SESSION_TTL_HOURS;
+use crate::authentication::PasswordHashing;
 use crate::configuration::{DatabaseSettings, Settings};
+use crate::domain::Permission;
 use crate::email_client::{EmailClient};
-use crate::routes::*;
+use crate::idempotency::idempotent;
+use crate::rate_limit::RateLimiter;
+use crate::routes::{api, *};
+
+const MAX_UPLOAD_SIZE: usize = 20 * 1024 * 1024;
 
 pub fn get_connection_pool(config: &DatabaseSettings) -> PgPool {
     // since its lazy fn doesn't need to be async
@@ -33,25 +46,13 @@ impl Application {
     pub async fn build(settings: Settings) -> Result<Self, std::io::Error> {
         let db_pool = get_connection_pool(&settings.database);
 
-        let email_client = settings.email_client.client().expect("failed");
+        let email_client = settings.email_client.clone().client().expect("failed");
 
-        let app_settings = settings.app;
-        let address = format!("{}:{}", app_settings.host, app_settings.port);
+        let address = format!("{}:{}", settings.app.host, settings.app.port);
         let listener = TcpListener::bind(address)?;
-
-        let hmac_secret = app_settings.hmac_secret;
         let port = listener.local_addr().unwrap().port();
-        let redis_uri = settings.redis_uri;
-
-        let server = run(
-            listener,
-            db_pool,
-            email_client,
-            app_settings.base_url,
-            hmac_secret,
-            redis_uri,
-        )
-        .await?;
+
+        let server = run(listener, db_pool, email_client, settings).await?;
 
         Ok(Self { port, server })
     }
@@ -67,56 +68,337 @@ impl Application {
 
 pub struct ApplicationBaseUrl(pub String);
 
+pub struct TrustedProxies(pub Vec<IpAddr>);
+
 pub async fn run(
     listener: TcpListener,
     db_pool: PgPool,
     email_client: EmailClient,
-    base_url: String, // set in env
-    hmac_secret: Secret<String>,
-    redis_uri: Secret<String>,
+    settings: Settings,
 ) -> Result<Server, std::io::Error> {
     println!("{:?}", listener.local_addr());
+    let Settings {
+        app,
+        redis_uri,
+        webhooks,
+        rate_limit,
+        signup,
+        login: login_settings,
+        password_policy,
+        password_hashing,
+        idempotency,
+        oidc,
+        ..
+    } = settings;
+    let base_url = app.base_url; // set in env
+    let hmac_secret = app.hmac_secret;
+    // handlers take an `Option<web::Data<OidcClient>>`, `None` when sso isn't configured
+    let oidc = oidc
+        .map(|settings| OidcClient::new(settings, &base_url).map(web::Data::new))
+        .transpose()
+        .map_err(std::io::Error::other)?;
 
     // an Arc<PgPool> we need to be Clone
     let connection = web::Data::new(db_pool);
     let email_client = web::Data::new(email_client);
     let base_url = web::Data::new(ApplicationBaseUrl(base_url));
+    let trusted_proxies = web::Data::new(TrustedProxies(app.trusted_proxies));
+    let webhooks = web::Data::new(webhooks);
     let secret_key = Key::from(hmac_secret.expose_secret().as_bytes());
     let message_store = CookieMessageStore::builder(secret_key.clone()).build();
     let message_framework = FlashMessagesFramework::builder(message_store).build();
     let session_store = RedisSessionStore::new(redis_uri.expose_secret())
         .await
         .unwrap();
+    let rate_limiter = RateLimiter::new(redis_uri.expose_secret(), rate_limit.key_prefix)
+        .await
+        .map_err(std::io::Error::other)?;
+    let rate_limiter = web::Data::new(rate_limiter);
+    let signup = web::Data::new(signup);
+    let login_settings = web::Data::new(login_settings);
+    let password_policy = web::Data::new(password_policy);
+    let password_hashing =
+        web::Data::new(PasswordHashing::new(&password_hashing).map_err(std::io::Error::other)?);
+    let idempotency = web::Data::new(idempotency);
 
     let server = HttpServer::new(move || {
+        let mut app = App::new();
+        if let Some(oidc) = &oidc {
+            app = app.app_data(oidc.clone());
+        }
         //builder pattern
-        App::new()
+        app
             .wrap(Logger::default())
             .wrap(message_framework.clone())
-            .wrap(SessionMiddleware::new(
-                session_store.clone(),
-                secret_key.clone(),
-            ))
+            .wrap(
+                SessionMiddleware::builder(session_store.clone(), secret_key.clone())
+                    // the sessions page only lists logins younger than this
+                    .session_lifecycle(
+                        BrowserSession::default()
+                            .state_ttl(Duration::hours(SESSION_TTL_HOURS)),
+                    )
+                    .build(),
+            )
             .app_data(connection.clone())
             .app_data(email_client.clone()) // wanna reuse same email client ?
             .app_data(base_url.clone())
+            .app_data(trusted_proxies.clone())
+            .app_data(webhooks.clone())
+            .app_data(rate_limiter.clone())
+            .app_data(signup.clone())
+            .app_data(login_settings.clone())
+            .app_data(password_policy.clone())
+            .app_data(password_hashing.clone())
+            .app_data(idempotency.clone())
             .app_data(secret_key.clone())
             .route("/health_check", web::get().to(check_health))
+            .route("/health/live", web::get().to(liveness))
+            .route("/health/ready", web::get().to(readiness))
             .route("/nate", web::get().to(nate))
-            .route("/subscribe", web::post().to(subscribe))
+            .route("/subscribe", web::post().to(subscribe).wrap(from_fn(idempotent)))
             .route("/subscribe/confirm", web::get().to(confirm))
+            .route("/subscriptions/data", web::get().to(download_subscriber_data))
+            .route("/subscriptions/data/request", web::get().to(data_request_form))
+            .route("/subscriptions/data/request", web::post().to(request_subscriber_data))
+            .route("/subscriptions/erase", web::get().to(erasure_form))
+            .route("/subscriptions/erase", web::post().to(erase_subscriber_data))
+            .route("/webhooks/postmark", web::post().to(postmark_webhook))
             .route("/", web::get().to(home))
             .route("/login", web::get().to(login_form))
             .route("/login", web::post().to(login))
+            .route("/login/two_factor", web::get().to(login_two_factor_form))
+            .route("/login/two_factor", web::post().to(login_two_factor))
+            .route("/login/oidc", web::get().to(login_oidc))
+            .route("/login/oidc/callback", web::get().to(login_oidc_callback))
+            .route("/password_reset", web::get().to(password_reset_form))
+            .route("/password_reset", web::post().to(request_password_reset))
+            .route("/password_reset/confirm", web::get().to(set_new_password_form))
+            .route("/password_reset/confirm", web::post().to(reset_password))
+            .route("/invitations/accept", web::get().to(accept_invitation_form))
+            .route("/invitations/accept", web::post().to(accept_invitation))
             .service(
                 scope("/admin")
+                    // registered first so that it runs after the login check
+                    .wrap(from_fn(reject_invalid_csrf_tokens))
                     .wrap(from_fn(reject_anonymous_users))
+                    // csv imports are held in memory while they're parsed
+                    .app_data(
+                        MultipartFormConfig::default()
+                            .total_limit(MAX_UPLOAD_SIZE)
+                            .memory_limit(MAX_UPLOAD_SIZE),
+                    )
                     .route("/dashboard", web::get().to(admin_dashboard))
                     .route("/password", web::get().to(change_password_form))
                     .route("/password", web::post().to(change_password))
+                    .route("/password/email", web::post().to(change_account_email))
                     .route("/logout", web::post().to(logout))
-                    .route("/newsletters", web::get().to(create_newsletter))
-                    .route("/newsletters", web::post().to(publish_newsletter)),
+                    .route("/totp", web::get().to(totp_settings))
+                    .route("/totp/enroll", web::post().to(enroll_totp))
+                    .route("/totp/confirm", web::post().to(confirm_totp))
+                    .route("/totp/disable", web::post().to(turn_off_totp))
+                    .route("/sessions", web::get().to(list_sessions))
+                    .route("/api_tokens", web::get().to(api_tokens_page))
+                    .route("/api_tokens", web::post().to(issue_api_token))
+                    .route("/api_tokens/{token_id}/revoke", web::post().to(withdraw_api_token))
+                    .route("/sessions/revoke_others", web::post().to(end_other_sessions))
+                    .route("/sessions/{session_id}/revoke", web::post().to(end_session))
+                    .route(
+                        "/newsletters",
+                        web::get().to(create_newsletter).wrap(from_fn(require_permission(
+                            Permission::DraftNewsletters,
+                        ))),
+                    )
+                    .route(
+                        "/newsletters",
+                        web::post()
+                            .to(publish_newsletter)
+                            .wrap(from_fn(idempotent))
+                            .wrap(from_fn(require_permission(Permission::PublishNewsletters))),
+                    )
+                    .route(
+                        "/newsletters/drafts",
+                        web::get().to(newsletter_drafts_page).wrap(from_fn(require_permission(
+                            Permission::DraftNewsletters,
+                        ))),
+                    )
+                    .route(
+                        "/newsletters/drafts",
+                        web::post().to(save_newsletter_draft).wrap(from_fn(require_permission(
+                            Permission::DraftNewsletters,
+                        ))),
+                    )
+                    .route(
+                        "/newsletters/drafts/{draft_id}",
+                        web::get().to(edit_newsletter_draft).wrap(from_fn(require_permission(
+                            Permission::DraftNewsletters,
+                        ))),
+                    )
+                    .route(
+                        "/newsletters/drafts/{draft_id}",
+                        web::post().to(update_newsletter_draft).wrap(from_fn(
+                            require_permission(Permission::DraftNewsletters),
+                        )),
+                    )
+                    .route(
+                        "/newsletters/drafts/{draft_id}/publish",
+                        web::post()
+                            .to(publish_newsletter_draft)
+                            .wrap(from_fn(idempotent))
+                            .wrap(from_fn(require_permission(Permission::PublishNewsletters))),
+                    )
+                    .route(
+                        "/login_attempts",
+                        web::get()
+                            .to(failed_login_attempts)
+                            .wrap(from_fn(require_permission(Permission::ManageUsers))),
+                    )
+                    .route(
+                        "/audit",
+                        web::get()
+                            .to(audit_log)
+                            .wrap(from_fn(require_permission(Permission::ManageUsers))),
+                    )
+                    .route(
+                        "/users",
+                        web::get()
+                            .to(list_admin_users)
+                            .wrap(from_fn(require_permission(Permission::ManageUsers))),
+                    )
+                    .route(
+                        "/users/invitations",
+                        web::post()
+                            .to(invite_user)
+                            .wrap(from_fn(require_permission(Permission::ManageUsers))),
+                    )
+                    .route(
+                        "/users/invitations/{invitation_id}/revoke",
+                        web::post()
+                            .to(revoke_invitation)
+                            .wrap(from_fn(require_permission(Permission::ManageUsers))),
+                    )
+                    .route(
+                        "/users/{user_id}/role",
+                        web::post()
+                            .to(change_user_role)
+                            .wrap(from_fn(require_permission(Permission::ManageUsers))),
+                    )
+                    .route(
+                        "/suppressions",
+                        web::get()
+                            .to(suppressions_page)
+                            .wrap(from_fn(require_permission(Permission::ViewSubscribers))),
+                    )
+                    .route(
+                        "/suppressions",
+                        web::post()
+                            .to(add_suppression_entry)
+                            .wrap(from_fn(require_permission(Permission::ManageSubscribers))),
+                    )
+                    .route(
+                        "/suppressions/import",
+                        web::post()
+                            .to(import_suppressions)
+                            .wrap(from_fn(require_permission(Permission::ManageSubscribers))),
+                    )
+                    .route(
+                        "/suppressions/remove",
+                        web::post()
+                            .to(remove_suppression_entry)
+                            .wrap(from_fn(require_permission(Permission::ManageSubscribers))),
+                    )
+                    .route(
+                        "/subscribers",
+                        web::get()
+                            .to(list_subscribers)
+                            .wrap(from_fn(require_permission(Permission::ViewSubscribers))),
+                    )
+                    // a full copy of everyone's personal data, not just a view of it
+                    .route(
+                        "/subscribers/export",
+                        web::get()
+                            .to(export_subscribers)
+                            .wrap(from_fn(require_permission(Permission::ManageSubscribers))),
+                    )
+                    .route(
+                        "/subscribers/imports",
+                        web::get()
+                            .to(import_subscribers_form)
+                            .wrap(from_fn(require_permission(Permission::ManageSubscribers))),
+                    )
+                    .route(
+                        "/subscribers/imports",
+                        web::post()
+                            .to(import_subscribers)
+                            .wrap(from_fn(require_permission(Permission::ManageSubscribers))),
+                    )
+                    .route(
+                        "/subscribers/imports/{import_id}/report",
+                        web::get()
+                            .to(import_report)
+                            .wrap(from_fn(require_permission(Permission::ManageSubscribers))),
+                    )
+                    .route(
+                        "/subscribers/{subscriber_id}",
+                        web::get()
+                            .to(subscriber_details)
+                            .wrap(from_fn(require_permission(Permission::ViewSubscribers))),
+                    )
+                    .route(
+                        "/subscribers/{subscriber_id}/confirm",
+                        web::post()
+                            .to(admin_confirm_subscriber)
+                            .wrap(from_fn(require_permission(Permission::ManageSubscribers))),
+                    )
+                    .route(
+                        "/subscribers/{subscriber_id}/unsubscribe",
+                        web::post()
+                            .to(unsubscribe_subscriber)
+                            .wrap(from_fn(require_permission(Permission::ManageSubscribers))),
+                    )
+                    .route(
+                        "/subscribers/{subscriber_id}/delete",
+                        web::post()
+                            .to(delete_subscriber)
+                            .wrap(from_fn(require_permission(Permission::ManageSubscribers))),
+                    )
+                    .route(
+                        "/subscribers/{subscriber_id}/resend_confirmation",
+                        web::post()
+                            .to(resend_confirmation)
+                            .wrap(from_fn(require_permission(Permission::ManageSubscribers))),
+                    ),
+            )
+            .route("/api/openapi.json", web::get().to(api::openapi_spec))
+            .service(
+                scope("/api/v1")
+                    .wrap(from_fn(reject_invalid_api_tokens))
+                    .app_data(api::json_config())
+                    .app_data(api::query_config())
+                    .route(
+                        "/issues",
+                        web::get()
+                            .to(api::api_list_issues)
+                            .wrap(from_fn(require_permission(Permission::DraftNewsletters))),
+                    )
+                    .route(
+                        "/issues",
+                        web::post()
+                            .to(api::api_publish_issue)
+                            .wrap(from_fn(idempotent))
+                            .wrap(from_fn(require_permission(Permission::PublishNewsletters))),
+                    )
+                    .route(
+                        "/subscribers",
+                        web::get()
+                            .to(api::api_list_subscribers)
+                            .wrap(from_fn(require_permission(Permission::ViewSubscribers))),
+                    )
+                    .route(
+                        "/subscribers/{subscriber_id}",
+                        web::get()
+                            .to(api::api_get_subscriber)
+                            .wrap(from_fn(require_permission(Permission::ViewSubscribers))),
+                    ),
             )
     })
     .listen(listener)?
@@ -0,0 +1,154 @@
+use std::time::Duration;
+
+use sqlx::{Executor, PgPool, PgTransaction};
+use uuid::Uuid;
+
+use crate::{
+    configuration::Settings,
+    domain::SubscriberEmail,
+    email_client::EmailClient,
+    get_connection_pool,
+    issue_delivery_workers::ExecutionOutcome,
+    routes::{generate_random_token, store_data_token},
+};
+
+struct DataRequestTask {
+    request_id: Uuid,
+    email: String,
+}
+
+pub async fn run_worker_until_stopped(config: Settings) {
+    let pool = get_connection_pool(&config.database);
+    let email_client = config.email_client.client().expect("failed to parse email");
+
+    let _ = worker_loop(&pool, &email_client, &config.app.base_url).await;
+}
+
+pub async fn worker_loop(
+    pool: &PgPool,
+    email_client: &EmailClient,
+    base_url: &str,
+) -> Result<(), anyhow::Error> {
+    loop {
+        match try_execute_task(pool, email_client, base_url).await {
+            Ok(ExecutionOutcome::EmptyQueue) => {
+                tokio::time::sleep(Duration::from_secs(10)).await;
+            }
+            Err(_) => {
+                tokio::time::sleep(Duration::from_secs(1)).await;
+            }
+            _ => {}
+        };
+    }
+}
+
+//NOTE: one data request per transaction, same as the issue delivery queue
+pub async fn try_execute_task(
+    pool: &PgPool,
+    email_client: &EmailClient,
+    base_url: &str,
+) -> Result<ExecutionOutcome, anyhow::Error> {
+    let Some((mut transaction, task)) = dequeue_task(pool).await? else {
+        return Ok(ExecutionOutcome::EmptyQueue);
+    };
+
+    let subscriber_id = sqlx::query_scalar!(
+        "SELECT id FROM subscriptions WHERE lower(email) = lower($1)",
+        task.email
+    )
+    .fetch_optional(&mut *transaction)
+    .await?;
+    // unknown addresses are dropped without a trace, nobody asked us to email them
+    let token = match subscriber_id {
+        Some(subscriber_id) => {
+            let token = generate_random_token();
+            store_data_token(&mut transaction, &token, subscriber_id).await?;
+            Some(token)
+        }
+        None => None,
+    };
+    delete_task(transaction, task.request_id).await?;
+
+    // sent once the token is committed, so no locks are held across the http call;
+    // if it fails the subscriber can ask again
+    let (Some(token), Ok(email)) = (token, SubscriberEmail::parse(task.email)) else {
+        return Ok(ExecutionOutcome::TaskCompleted);
+    };
+    if let Err(e) = send_data_links_email(email_client, &email, base_url, &token).await {
+        tracing::error!(
+            error.cause_chain = ?e,
+            error.message = %e,
+            "failed to send data links email"
+        );
+        return Ok(ExecutionOutcome::TaskFailed);
+    }
+    Ok(ExecutionOutcome::TaskCompleted)
+}
+
+async fn dequeue_task(
+    pool: &PgPool,
+) -> Result<Option<(PgTransaction<'static>, DataRequestTask)>, sqlx::Error> {
+    let mut transaction = pool.begin().await?;
+    let task = sqlx::query_as!(
+        DataRequestTask,
+        r#"
+        SELECT request_id, email
+        FROM subscriber_data_request_queue
+        ORDER BY requested_at
+        FOR UPDATE
+        SKIP LOCKED
+        LIMIT 1
+        "#,
+    )
+    .fetch_optional(&mut *transaction)
+    .await?;
+
+    Ok(task.map(|task| (transaction, task)))
+}
+
+async fn delete_task(
+    mut transaction: PgTransaction<'_>,
+    request_id: Uuid,
+) -> Result<(), sqlx::Error> {
+    let query = sqlx::query!(
+        "DELETE FROM subscriber_data_request_queue WHERE request_id = $1",
+        request_id
+    );
+    transaction.execute(query).await?;
+    transaction.commit().await?;
+    Ok(())
+}
+
+#[tracing::instrument(
+    name = "send data links email",
+    skip(email_client, email, base_url, token)
+)]
+async fn send_data_links_email(
+    email_client: &EmailClient,
+    email: &SubscriberEmail,
+    base_url: &str,
+    token: &str,
+) -> Result<(), reqwest::Error> {
+    let download_link = format!("{}/subscriptions/data?token={}", base_url, token);
+    let erase_link = format!("{}/subscriptions/erase?token={}", base_url, token);
+
+    let plain_body = format!(
+        "Here is the data we hold about you.\n\
+        Download it: {}\n\
+        Erase it: {}\n\
+        These links expire in 24 hours.",
+        download_link, erase_link
+    );
+    let html_body = format!(
+        "Here is the data we hold about you.<br />\
+        <a href=\"{}\">Download it</a><br />\
+        <a href=\"{}\">Erase it</a><br />\
+        These links expire in 24 hours.",
+        download_link, erase_link
+    );
+
+    email_client
+        .send_email(email, "your data", &html_body, &plain_body)
+        .await?;
+    Ok(())
+}
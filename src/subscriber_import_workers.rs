@@ -0,0 +1,352 @@
+use std::time::Duration;
+
+use sqlx::{Executor, PgPool, PgTransaction};
+use uuid::Uuid;
+
+use crate::{
+    configuration::Settings,
+    domain::{
+        ConsentContext, ConsentEventKind, NewSubscriber, SubscriberEmail, SubscriberName,
+        SubscriptionStatus,
+    },
+    email_client::EmailClient,
+    get_connection_pool,
+    issue_delivery_workers::ExecutionOutcome,
+    routes::{generate_random_token, record_consent_event, send_confirmation_email, store_token},
+};
+
+/// how imported subscribers end up in the `subscriptions` table
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ImportMode {
+    /// rows are trusted and go straight to `confirmed`
+    Confirmed,
+    /// rows are `pending_confirmation` and get a confirmation email
+    DoubleOptIn,
+}
+
+impl ImportMode {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ImportMode::Confirmed => "confirmed",
+            ImportMode::DoubleOptIn => "double_opt_in",
+        }
+    }
+}
+
+impl TryFrom<String> for ImportMode {
+    type Error = String;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        match s.as_str() {
+            "confirmed" => Ok(ImportMode::Confirmed),
+            "double_opt_in" => Ok(ImportMode::DoubleOptIn),
+            _ => Err(format!("unknown import mode `{}`", s)),
+        }
+    }
+}
+
+enum RowOutcome {
+    Imported,
+    Duplicate,
+    Rejected(String),
+}
+
+impl From<RowOutcome> for ExecutionOutcome {
+    fn from(outcome: RowOutcome) -> Self {
+        match outcome {
+            RowOutcome::Rejected(_) => ExecutionOutcome::TaskFailed,
+            _ => ExecutionOutcome::TaskCompleted,
+        }
+    }
+}
+
+struct ImportTask {
+    import_id: Uuid,
+    row_number: i32,
+    email: String,
+    name: String,
+    mode: String,
+}
+
+pub async fn run_worker_until_stopped(config: Settings) {
+    let pool = get_connection_pool(&config.database);
+    let email_client = config.email_client.client().expect("failed to parse email");
+
+    let _ = worker_loop(&pool, &email_client, &config.app.base_url).await;
+}
+
+pub async fn worker_loop(
+    pool: &PgPool,
+    email_client: &EmailClient,
+    base_url: &str,
+) -> Result<(), anyhow::Error> {
+    loop {
+        match try_execute_task(pool, email_client, base_url).await {
+            Ok(ExecutionOutcome::EmptyQueue) => {
+                tokio::time::sleep(Duration::from_secs(10)).await;
+            }
+            Err(_) => {
+                tokio::time::sleep(Duration::from_secs(1)).await;
+            }
+            _ => {}
+        };
+    }
+}
+
+//NOTE: one queued csv row per transaction, same as the issue delivery queue
+pub async fn try_execute_task(
+    pool: &PgPool,
+    email_client: &EmailClient,
+    base_url: &str,
+) -> Result<ExecutionOutcome, anyhow::Error> {
+    let Some((mut transaction, task)) = dequeue_task(pool).await? else {
+        return Ok(ExecutionOutcome::EmptyQueue);
+    };
+    let mode = ImportMode::try_from(task.mode.clone()).map_err(anyhow::Error::msg)?;
+
+    let subscriber = SubscriberEmail::parse(task.email.clone()).and_then(|email| {
+        let name = SubscriberName::parse(task.name.clone())?;
+        Ok(NewSubscriber { name, email })
+    });
+    let (outcome, imported) = match subscriber {
+        Ok(subscriber) => {
+            match import_subscriber(&mut transaction, &task, &subscriber, mode).await? {
+                Some(imported) => (RowOutcome::Imported, Some((subscriber, imported))),
+                None => (RowOutcome::Duplicate, None),
+            }
+        }
+        Err(reason) => (RowOutcome::Rejected(reason), None),
+    };
+
+    record_outcome(&mut transaction, &task, &outcome).await?;
+    delete_task(transaction, task.import_id, task.row_number).await?;
+
+    // sent once the row is committed, so no locks are held across the http call
+    let Some((subscriber, imported)) = imported else {
+        return Ok(outcome.into());
+    };
+    if let Some(token) = imported.confirmation_token {
+        if send_confirmation_email(email_client, pool, subscriber, base_url, &token)
+            .await
+            .is_err()
+        {
+            let reason = "failed to send confirmation email";
+            undo_import(pool, &task, imported.subscriber_id, reason).await?;
+            return Ok(ExecutionOutcome::TaskFailed);
+        }
+    }
+    Ok(outcome.into())
+}
+
+struct ImportedSubscriber {
+    subscriber_id: Uuid,
+    /// set for double opt-in imports, whose confirmation email is still to be sent
+    confirmation_token: Option<String>,
+}
+
+/// `None` if the address is already subscribed
+async fn import_subscriber(
+    transaction: &mut PgTransaction<'static>,
+    task: &ImportTask,
+    subscriber: &NewSubscriber,
+    mode: ImportMode,
+) -> Result<Option<ImportedSubscriber>, sqlx::Error> {
+    let status = match mode {
+        ImportMode::Confirmed => SubscriptionStatus::Confirmed,
+        ImportMode::DoubleOptIn => SubscriptionStatus::PendingConfirmation,
+    };
+    let subscriber_id = Uuid::new_v4();
+
+    // de-duplicates both against existing subscribers and within the uploaded
+    // file; addresses differing only in case are the same inbox
+    let n_inserted_rows = transaction
+        .execute(sqlx::query!(
+            r#"
+            INSERT INTO subscriptions(id, email, name, subscribed_at, status)
+            SELECT $1::uuid, $2::text, $3::text, now(), $4::text
+            WHERE NOT EXISTS (
+                SELECT 1 FROM subscriptions WHERE lower(email) = lower($2)
+            )
+            ON CONFLICT (email) DO NOTHING
+            "#,
+            subscriber_id,
+            subscriber.email.as_ref(),
+            subscriber.name.as_ref(),
+            status.as_str(),
+        ))
+        .await?
+        .rows_affected();
+
+    if n_inserted_rows == 0 {
+        return Ok(None);
+    }
+
+    // whatever they agreed to, they agreed to it with the previous provider
+    let consent = ConsentContext {
+        ip_address: None,
+        user_agent: None,
+        source: format!("subscriber_import:{}", task.import_id),
+    };
+    record_consent_event(
+        transaction,
+        subscriber_id,
+        ConsentEventKind::Import,
+        &consent,
+    )
+    .await?;
+
+    let confirmation_token = match mode {
+        ImportMode::Confirmed => None,
+        ImportMode::DoubleOptIn => {
+            let token = generate_random_token();
+            store_token(&token, subscriber_id, transaction).await?;
+            Some(token)
+        }
+    };
+
+    Ok(Some(ImportedSubscriber {
+        subscriber_id,
+        confirmation_token,
+    }))
+}
+
+/// takes back an imported row whose confirmation email couldn't be sent, and
+/// reports it as rejected instead
+async fn undo_import(
+    pool: &PgPool,
+    task: &ImportTask,
+    subscriber_id: Uuid,
+    reason: &str,
+) -> Result<(), sqlx::Error> {
+    let mut transaction = pool.begin().await?;
+    transaction
+        .execute(sqlx::query!(
+            "DELETE FROM subscription_tokens WHERE subscriber_id = $1",
+            subscriber_id
+        ))
+        .await?;
+    transaction
+        .execute(sqlx::query!(
+            "DELETE FROM consent_events WHERE subscriber_id = $1",
+            subscriber_id
+        ))
+        .await?;
+    transaction
+        .execute(sqlx::query!(
+            "DELETE FROM subscriptions WHERE id = $1",
+            subscriber_id
+        ))
+        .await?;
+    transaction
+        .execute(sqlx::query!(
+            r#"
+            UPDATE subscriber_imports
+            SET
+                imported_rows = imported_rows - 1,
+                rejected_rows = rejected_rows + 1
+            WHERE import_id = $1
+            "#,
+            task.import_id,
+        ))
+        .await?;
+    transaction
+        .execute(sqlx::query!(
+            r#"
+            INSERT INTO subscriber_import_rejections(import_id, row_number, email, name, reason)
+            VALUES ($1, $2, $3, $4, $5)
+            "#,
+            task.import_id,
+            task.row_number,
+            task.email,
+            task.name,
+            reason,
+        ))
+        .await?;
+    transaction.commit().await
+}
+
+async fn dequeue_task(
+    pool: &PgPool,
+) -> Result<Option<(PgTransaction<'static>, ImportTask)>, sqlx::Error> {
+    let mut transaction = pool.begin().await?;
+    let task = sqlx::query_as!(
+        ImportTask,
+        r#"
+        SELECT q.import_id, q.row_number, q.email, q.name, i.mode
+        FROM subscriber_import_queue q
+        JOIN subscriber_imports i USING (import_id)
+        FOR UPDATE OF q
+        SKIP LOCKED
+        LIMIT 1
+        "#,
+    )
+    .fetch_optional(&mut *transaction)
+    .await?;
+
+    Ok(task.map(|task| (transaction, task)))
+}
+
+async fn record_outcome(
+    transaction: &mut PgTransaction<'static>,
+    task: &ImportTask,
+    outcome: &RowOutcome,
+) -> Result<(), sqlx::Error> {
+    let (imported, duplicate, rejected) = match outcome {
+        RowOutcome::Imported => (1, 0, 0),
+        RowOutcome::Duplicate => (0, 1, 0),
+        RowOutcome::Rejected(_) => (0, 0, 1),
+    };
+    transaction
+        .execute(sqlx::query!(
+            r#"
+            UPDATE subscriber_imports
+            SET
+                imported_rows = imported_rows + $2,
+                duplicate_rows = duplicate_rows + $3,
+                rejected_rows = rejected_rows + $4
+            WHERE import_id = $1
+            "#,
+            task.import_id,
+            imported,
+            duplicate,
+            rejected,
+        ))
+        .await?;
+
+    if let RowOutcome::Rejected(reason) = outcome {
+        transaction
+            .execute(sqlx::query!(
+                r#"
+                INSERT INTO subscriber_import_rejections(import_id, row_number, email, name, reason)
+                VALUES ($1, $2, $3, $4, $5)
+                "#,
+                task.import_id,
+                task.row_number,
+                task.email,
+                task.name,
+                reason,
+            ))
+            .await?;
+    }
+    Ok(())
+}
+
+async fn delete_task(
+    mut transaction: PgTransaction<'_>,
+    import_id: Uuid,
+    row_number: i32,
+) -> Result<(), sqlx::Error> {
+    let query = sqlx::query!(
+        r#"
+        DELETE FROM subscriber_import_queue
+        WHERE
+            import_id = $1 AND
+            row_number = $2
+        "#,
+        import_id,
+        row_number
+    );
+    transaction.execute(query).await?;
+    transaction.commit().await?;
+    Ok(())
+}
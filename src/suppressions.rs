@@ -0,0 +1,104 @@
+use chrono::{DateTime, Utc};
+use sqlx::PgExecutor;
+use uuid::Uuid;
+
+use crate::domain::{SubscriberEmail, SuppressionEntry};
+
+pub struct Suppression {
+    pub entry: String,
+    pub reason: String,
+    pub created_at: DateTime<Utc>,
+}
+
+/// true if either the address itself, its domain or a parent domain is suppressed
+#[tracing::instrument(name = "check suppression list", skip(executor))]
+pub async fn is_suppressed<'e>(
+    executor: impl PgExecutor<'e>,
+    email: &SubscriberEmail,
+) -> Result<bool, sqlx::Error> {
+    let address = email.as_ref().to_lowercase();
+    let domains = domain_and_parents(&email.domain().to_lowercase());
+
+    sqlx::query_scalar!(
+        r#"SELECT EXISTS(SELECT 1 FROM suppressions WHERE entry = $1 OR entry = ANY($2)) as "exists!""#,
+        address,
+        &domains
+    )
+    .fetch_one(executor)
+    .await
+}
+
+/// `mail.example.com`, `example.com`; top-level domains can't be suppressed
+fn domain_and_parents(domain: &str) -> Vec<String> {
+    let mut domains = vec![domain.to_string()];
+    let mut candidate = domain;
+    while let Some((_, parent)) = candidate.split_once('.') {
+        if !parent.contains('.') {
+            break;
+        }
+        domains.push(parent.to_string());
+        candidate = parent;
+    }
+    domains
+}
+
+/// returns false if the entry was already on the list, the original reason is kept
+pub async fn add_suppression<'e>(
+    executor: impl PgExecutor<'e>,
+    entry: &SuppressionEntry,
+    reason: &str,
+    user_id: Option<Uuid>,
+) -> Result<bool, sqlx::Error> {
+    let n_inserted_rows = sqlx::query!(
+        r#"
+        INSERT INTO suppressions(entry, reason, user_id, created_at)
+        VALUES ($1, $2, $3, now())
+        ON CONFLICT (entry) DO NOTHING
+        "#,
+        entry.as_ref(),
+        reason,
+        user_id
+    )
+    .execute(executor)
+    .await?
+    .rows_affected();
+
+    Ok(n_inserted_rows > 0)
+}
+
+pub async fn remove_suppression<'e>(
+    executor: impl PgExecutor<'e>,
+    entry: &SuppressionEntry,
+) -> Result<bool, sqlx::Error> {
+    let n_deleted_rows = sqlx::query!("DELETE FROM suppressions WHERE entry = $1", entry.as_ref())
+        .execute(executor)
+        .await?
+        .rows_affected();
+
+    Ok(n_deleted_rows > 0)
+}
+
+pub async fn list_suppressions<'e>(
+    executor: impl PgExecutor<'e>,
+) -> Result<Vec<Suppression>, sqlx::Error> {
+    sqlx::query_as!(
+        Suppression,
+        "SELECT entry, reason, created_at FROM suppressions ORDER BY created_at DESC, entry"
+    )
+    .fetch_all(executor)
+    .await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::domain_and_parents;
+
+    #[test]
+    fn subdomains_are_checked_against_their_parents() {
+        assert_eq!(
+            domain_and_parents("eu.mail.example.com"),
+            vec!["eu.mail.example.com", "mail.example.com", "example.com"]
+        );
+        assert_eq!(domain_and_parents("example.com"), vec!["example.com"]);
+    }
+}
@@ -0,0 +1,94 @@
+use sqlx::{PgExecutor, Postgres, Transaction};
+use uuid::Uuid;
+
+use crate::domain::UserRole;
+
+pub struct User {
+    pub user_id: Uuid,
+    pub name: String,
+    pub email: Option<String>,
+    pub role: UserRole,
+}
+
+/// `None` if the user doesn't exist (anymore)
+pub async fn get_user_role<'e>(
+    executor: impl PgExecutor<'e>,
+    user_id: Uuid,
+) -> Result<Option<UserRole>, anyhow::Error> {
+    sqlx::query_scalar!("SELECT role FROM users WHERE user_id = $1", user_id)
+        .fetch_optional(executor)
+        .await?
+        .map(|role| UserRole::try_from(role).map_err(|e| anyhow::anyhow!(e)))
+        .transpose()
+}
+
+pub async fn list_users<'e>(executor: impl PgExecutor<'e>) -> Result<Vec<User>, anyhow::Error> {
+    sqlx::query!("SELECT user_id, name, email, role FROM users ORDER BY name")
+        .fetch_all(executor)
+        .await?
+        .into_iter()
+        .map(|r| {
+            Ok(User {
+                user_id: r.user_id,
+                name: r.name,
+                email: r.email,
+                role: UserRole::try_from(r.role).map_err(|e| anyhow::anyhow!(e))?,
+            })
+        })
+        .collect()
+}
+
+/// refuses to demote the last owner, someone has to be able to manage users;
+/// returns false if nothing was changed because of that
+pub async fn set_user_role(
+    transaction: &mut Transaction<'_, Postgres>,
+    user_id: Uuid,
+    role: UserRole,
+) -> Result<bool, sqlx::Error> {
+    // two owners demoting each other at once would both still see the other one
+    // as an owner, this makes the second demotion wait for the first
+    sqlx::query!("SELECT user_id FROM users WHERE role = 'owner' FOR UPDATE")
+        .fetch_all(&mut **transaction)
+        .await?;
+    let n_updated_rows = sqlx::query!(
+        r#"
+        UPDATE users SET role = $2
+        WHERE
+            user_id = $1 AND
+            ($2 = 'owner' OR EXISTS(
+                SELECT 1 FROM users WHERE role = 'owner' AND user_id != $1
+            ))
+        "#,
+        user_id,
+        role.as_str()
+    )
+    .execute(&mut **transaction)
+    .await?
+    .rows_affected();
+
+    Ok(n_updated_rows > 0)
+}
+
+pub async fn insert_user<'e>(
+    executor: impl PgExecutor<'e>,
+    name: &str,
+    password_hash: &str,
+    email: &str,
+    role: UserRole,
+) -> Result<Uuid, sqlx::Error> {
+    let user_id = Uuid::new_v4();
+    sqlx::query!(
+        r#"
+        INSERT INTO users(user_id, name, password_hash, email, role)
+        VALUES ($1, $2, $3, $4, $5)
+        "#,
+        user_id,
+        name,
+        password_hash,
+        email,
+        role.as_str()
+    )
+    .execute(executor)
+    .await?;
+    Ok(user_id)
+}
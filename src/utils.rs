@@ -1,5 +1,15 @@
-use actix_web::{error::{ErrorBadRequest, ErrorInternalServerError}, http::header::LOCATION, HttpResponse};
-use std::fmt::{Debug, Display};
+use actix_web::{
+    error::{ErrorBadRequest, ErrorInternalServerError, ErrorNotFound},
+    http::{header::LOCATION, StatusCode},
+    web, HttpRequest, HttpResponse,
+};
+use sha2::{Digest, Sha256};
+use std::{
+    fmt::{Debug, Display},
+    net::IpAddr,
+};
+
+use crate::TrustedProxies;
 
 pub fn e500<E>(e: E) -> actix_web::Error
 where
@@ -15,8 +25,65 @@ where
     ErrorBadRequest(e)
 }
 
+pub fn e404<E>(e: E) -> actix_web::Error
+where
+    E: Debug + Display + 'static,
+{
+    ErrorNotFound(e)
+}
+
 pub fn see_other(location: &str) -> HttpResponse {
     HttpResponse::SeeOther()
         .insert_header((LOCATION, location))
         .finish()
 }
+
+/// compares secrets without the time taken giving away how much of them matched
+pub fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |acc, (a, b)| acc | (a ^ b)) == 0
+}
+
+/// what we store in place of a token we hand out: sha-256 in hex, the tokens are
+/// random so a slow hash buys nothing
+pub fn hash_token(token: &str) -> String {
+    hex::encode(Sha256::digest(token.as_bytes()))
+}
+
+/// error body for the JSON api: `{"error": "..."}`
+pub fn json_error(status: StatusCode, message: &str) -> HttpResponse {
+    HttpResponse::build(status).json(serde_json::json!({ "error": message }))
+}
+
+/// the address a request is rate limited and audited under; forwarded-for headers
+/// are only believed when they were set by one of `app.trusted_proxies`, since anyone
+/// can send them
+pub fn client_ip(request: &HttpRequest) -> Option<String> {
+    let peer = request.peer_addr()?.ip();
+    let Some(proxies) = request.app_data::<web::Data<TrustedProxies>>() else {
+        return Some(peer.to_string());
+    };
+    if !proxies.0.contains(&peer) {
+        return Some(peer.to_string());
+    }
+
+    // each proxy appends the address it got the request from, so only the entries
+    // right of the last untrusted one are real; whatever is left of it the client
+    // wrote itself
+    let mut client = peer;
+    let hops = request
+        .headers()
+        .get_all("X-Forwarded-For")
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .collect::<Vec<_>>();
+    for hop in hops.into_iter().rev() {
+        let Ok(hop) = hop.trim().parse::<IpAddr>() else {
+            break;
+        };
+        client = hop;
+        if !proxies.0.contains(&hop) {
+            break;
+        }
+    }
+    Some(client.to_string())
+}
@@ -0,0 +1,141 @@
+use uuid::Uuid;
+use wiremock::{
+    matchers::{method, path},
+    Mock, ResponseTemplate,
+};
+
+use crate::helpers::{assert_is_redirect_to, spawn_app};
+
+#[tokio::test]
+async fn must_be_logged_in_to_manage_subscribers() {
+    let app = spawn_app().await;
+
+    let response = app.get_admin_subscribers("").await;
+    assert_is_redirect_to(&response, "/login");
+
+    let response = app
+        .post_subscriber_action(Uuid::new_v4(), "delete")
+        .await;
+    assert_is_redirect_to(&response, "/login");
+}
+
+#[tokio::test]
+async fn subscribers_can_be_searched_and_filtered() {
+    let app = spawn_app().await;
+    app.login_as(&app.user).await;
+    let ursula = app.create_subscriber("le guin", "ursula_le_guin@gmail.com").await;
+    app.create_subscriber("octavia", "octavia_butler@gmail.com").await;
+    app.post_subscriber_action(ursula, "confirm").await;
+
+    let html_page = app.get_admin_subscribers_html("search=butler").await;
+    assert!(html_page.contains("octavia_butler@gmail.com"));
+    assert!(!html_page.contains("ursula_le_guin@gmail.com"));
+
+    let html_page = app.get_admin_subscribers_html("status=confirmed").await;
+    assert!(html_page.contains("ursula_le_guin@gmail.com"));
+    assert!(!html_page.contains("octavia_butler@gmail.com"));
+
+    let response = app.get_admin_subscribers("status=not_a_status").await;
+    assert_eq!(response.status().as_u16(), 400);
+}
+
+#[tokio::test]
+async fn subscribers_are_paginated() {
+    let app = spawn_app().await;
+    app.login_as(&app.user).await;
+    for i in 0..21 {
+        app.create_subscriber(&format!("reader {}", i), &format!("reader{}@gmail.com", i))
+            .await;
+    }
+
+    let html_page = app.get_admin_subscribers_html("").await;
+    assert!(html_page.contains("page 1 of 2 (21 subscribers)"));
+
+    let html_page = app.get_admin_subscribers_html("page=2").await;
+    assert!(html_page.contains("page 2 of 2 (21 subscribers)"));
+    assert_eq!(html_page.matches("@gmail.com").count(), 1);
+}
+
+#[tokio::test]
+async fn admins_can_confirm_and_unsubscribe_subscribers() {
+    let app = spawn_app().await;
+    app.login_as(&app.user).await;
+    let subscriber_id = app.create_subscriber("le guin", "ursula_le_guin@gmail.com").await;
+
+    let response = app.post_subscriber_action(subscriber_id, "confirm").await;
+    assert_is_redirect_to(&response, &format!("/admin/subscribers/{}", subscriber_id));
+    let html_page = app.get_subscriber_details_html(subscriber_id).await;
+    assert!(html_page.contains("Subscriber confirmed"));
+    assert!(html_page.contains("status: confirmed"));
+
+    app.post_subscriber_action(subscriber_id, "unsubscribe").await;
+    let html_page = app.get_subscriber_details_html(subscriber_id).await;
+    assert!(html_page.contains("status: unsubscribed"));
+}
+
+#[tokio::test]
+async fn deleting_a_subscriber_removes_their_tokens() {
+    let app = spawn_app().await;
+    app.login_as(&app.user).await;
+    let subscriber_id = app.create_subscriber("le guin", "ursula_le_guin@gmail.com").await;
+
+    let response = app.post_subscriber_action(subscriber_id, "delete").await;
+    assert_is_redirect_to(&response, "/admin/subscribers");
+
+    let n_tokens = sqlx::query_scalar!(
+        r#"SELECT count(*) as "count!" FROM subscription_tokens WHERE subscriber_id = $1"#,
+        subscriber_id
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .unwrap();
+    assert_eq!(n_tokens, 0);
+
+    let response = app.post_subscriber_action(subscriber_id, "delete").await;
+    assert_eq!(response.status().as_u16(), 404);
+}
+
+#[tokio::test]
+async fn confirmation_email_can_be_resent_to_pending_subscribers() {
+    let app = spawn_app().await;
+    app.login_as(&app.user).await;
+    let subscriber_id = app.create_subscriber("le guin", "ursula_le_guin@gmail.com").await;
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    let response = app
+        .post_subscriber_action(subscriber_id, "resend_confirmation")
+        .await;
+    assert_is_redirect_to(&response, &format!("/admin/subscribers/{}", subscriber_id));
+    let html_page = app.get_subscriber_details_html(subscriber_id).await;
+    assert!(html_page.contains("Confirmation email sent"));
+
+    // the new link confirms the subscriber
+    let email_request = app.email_server.received_requests().await.unwrap().pop().unwrap();
+    let links = app.get_confirmation_links(&email_request);
+    reqwest::get(links.text)
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+    let html_page = app.get_subscriber_details_html(subscriber_id).await;
+    assert!(html_page.contains("status: confirmed"));
+}
+
+#[tokio::test]
+async fn subscriber_details_show_the_consent_history() {
+    let app = spawn_app().await;
+    app.login_as(&app.user).await;
+    let subscriber_id = app.create_subscriber("le guin", "ursula_le_guin@gmail.com").await;
+
+    let html_page = app.get_subscriber_details_html(subscriber_id).await;
+
+    assert!(html_page.contains("consent history"));
+    assert!(html_page.contains("<td>subscribe</td>"));
+    assert!(html_page.contains("subscribe_form"));
+}
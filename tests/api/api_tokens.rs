@@ -0,0 +1,204 @@
+use reqwest::Method;
+use serde_json::{json, Value};
+use uuid::Uuid;
+
+use crate::helpers::{spawn_app, TestApp, TestUser};
+
+fn new_issue() -> Value {
+    json!({
+        "title": "Newsletter title",
+        "text": "Newsletter body as plain text",
+        "html": "<p>Newsletter body as HTML</p>",
+    })
+}
+
+async fn publish(app: &TestApp, token: &str, key: &str) -> reqwest::Response {
+    app.api_request(Method::POST, "/issues", token)
+        .header("Idempotency-Key", key)
+        .json(&new_issue())
+        .send()
+        .await
+        .unwrap()
+}
+
+#[tokio::test]
+async fn requests_without_a_valid_token_are_rejected() {
+    let app = spawn_app().await;
+
+    let response = reqwest::Client::new()
+        .get(format!("{}/api/v1/issues", &app.address))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status().as_u16(), 401);
+    assert_eq!(response.headers()["WWW-Authenticate"], "Bearer");
+
+    let response = app
+        .api_request(Method::GET, "/issues", "z2p_not-a-real-token")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status().as_u16(), 401);
+    let body: Value = response.json().await.unwrap();
+    assert_eq!(body["error"], "missing or invalid api token");
+}
+
+#[tokio::test]
+async fn tokens_are_only_stored_hashed() {
+    let app = spawn_app().await;
+    app.login_as(&app.user).await;
+    let token = app.create_api_token(&["view_subscribers"]).await;
+    assert!(token.starts_with("z2p_"));
+
+    let n_plaintext = sqlx::query_scalar!(
+        r#"SELECT count(*) as "count!" FROM api_tokens WHERE token_hash = $1"#,
+        token
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .unwrap();
+    assert_eq!(n_plaintext, 0);
+
+    let html_page = app.get_html(&format!("{}/admin/api_tokens", &app.address)).await;
+    assert!(!html_page.contains(&token));
+    assert!(html_page.contains("view_subscribers"));
+}
+
+#[tokio::test]
+async fn issues_can_be_published_once_per_idempotency_key() {
+    let app = spawn_app().await;
+    app.login_as(&app.user).await;
+    let token = app
+        .create_api_token(&["draft_newsletters", "publish_newsletters"])
+        .await;
+
+    let key = Uuid::new_v4().to_string();
+    let response = publish(&app, &token, &key).await;
+    assert_eq!(response.status().as_u16(), 201);
+    let first: Value = response.json().await.unwrap();
+
+    let response = publish(&app, &token, &key).await;
+    assert_eq!(response.status().as_u16(), 201);
+    let retry: Value = response.json().await.unwrap();
+    assert_eq!(first["issue_id"], retry["issue_id"]);
+
+    let issues: Value = app
+        .api_request(Method::GET, "/issues", &token)
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(issues["issues"].as_array().unwrap().len(), 1);
+    assert_eq!(issues["issues"][0]["issue_id"], first["issue_id"]);
+}
+
+#[tokio::test]
+async fn publishing_requires_an_idempotency_key() {
+    let app = spawn_app().await;
+    app.login_as(&app.user).await;
+    let token = app.create_api_token(&["publish_newsletters"]).await;
+
+    let response = app
+        .api_request(Method::POST, "/issues", &token)
+        .json(&new_issue())
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status().as_u16(), 400);
+
+    let response = app
+        .api_request(Method::POST, "/issues", &token)
+        .header("Idempotency-Key", "key")
+        .json(&json!({ "title": "missing the content" }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status().as_u16(), 400);
+    let body: Value = response.json().await.unwrap();
+    assert!(body["error"].is_string());
+}
+
+#[tokio::test]
+async fn tokens_are_limited_to_their_scopes() {
+    let app = spawn_app().await;
+    app.login_as(&app.user).await;
+    let token = app.create_api_token(&["view_subscribers"]).await;
+
+    let response = app
+        .api_request(Method::GET, "/subscribers?status=confirmed", &token)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status().as_u16(), 200);
+    let body: Value = response.json().await.unwrap();
+    assert_eq!(body["total"], 0);
+
+    let response = publish(&app, &token, "key").await;
+    assert_eq!(response.status().as_u16(), 403);
+    let body: Value = response.json().await.unwrap();
+    assert!(body["error"]
+        .as_str()
+        .unwrap()
+        .contains("publish_newsletters"));
+
+    let response = app
+        .api_request(Method::GET, &format!("/subscribers/{}", Uuid::new_v4()), &token)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status().as_u16(), 404);
+}
+
+#[tokio::test]
+async fn tokens_follow_their_owners_role() {
+    let app = spawn_app().await;
+    let publisher = TestUser::generate();
+    publisher.store_with_role(&app.db_pool, "publisher").await;
+    app.login_as(&publisher).await;
+    let token = app.create_api_token(&["publish_newsletters"]).await;
+
+    // scopes the role doesn't have can't be requested
+    let response = app
+        .post_admin_form(
+            "/api_tokens",
+            &[("name", "too much"), ("scope.manage_users", "on")],
+        )
+        .await;
+    assert_eq!(response.status().as_u16(), 303);
+
+    sqlx::query!(
+        "UPDATE users SET role = 'viewer' WHERE user_id = $1",
+        publisher.user_id
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+    let response = publish(&app, &token, "key").await;
+    assert_eq!(response.status().as_u16(), 403);
+}
+
+#[tokio::test]
+async fn revoked_tokens_stop_working() {
+    let app = spawn_app().await;
+    app.login_as(&app.user).await;
+    let token = app.create_api_token(&["draft_newsletters"]).await;
+    let token_id = sqlx::query_scalar!(
+        "SELECT token_id FROM api_tokens WHERE user_id = $1",
+        app.user.user_id
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .unwrap();
+
+    app.post_admin_form(&format!("/api_tokens/{}/revoke", token_id), &json!({}))
+        .await;
+
+    let response = app
+        .api_request(Method::GET, "/issues", &token)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status().as_u16(), 401);
+}
@@ -0,0 +1,209 @@
+use serde_json::json;
+
+use crate::helpers::{assert_is_redirect_to, spawn_app, spawn_app_with, TestApp, TestUser};
+
+struct Event {
+    user_id: Option<uuid::Uuid>,
+    ip_address: Option<String>,
+    target: Option<String>,
+    changes: serde_json::Value,
+}
+
+async fn events(app: &TestApp, action: &str) -> Vec<Event> {
+    sqlx::query_as!(
+        Event,
+        "SELECT user_id, ip_address, target, changes FROM audit_log WHERE action = $1 ORDER BY occurred_at",
+        action
+    )
+    .fetch_all(&app.db_pool)
+    .await
+    .unwrap()
+}
+
+async fn get_audit_log_html(app: &TestApp, query: &str) -> String {
+    app.app_client
+        .get(format!("{}/admin/audit?{}", &app.address, query))
+        .send()
+        .await
+        .unwrap()
+        .text()
+        .await
+        .unwrap()
+}
+
+#[tokio::test]
+async fn logins_and_logouts_are_recorded() {
+    let app = spawn_app().await;
+
+    app.login_as(&app.user).await;
+    app.post_logout().await;
+
+    let logins = events(&app, "login").await;
+    assert_eq!(logins.len(), 1);
+    assert_eq!(logins[0].user_id, Some(app.user.user_id));
+    assert!(logins[0].ip_address.is_some());
+    assert_eq!(logins[0].changes["method"], "password");
+    let logouts = events(&app, "logout").await;
+    assert_eq!(logouts.len(), 1);
+    assert_eq!(logouts[0].user_id, Some(app.user.user_id));
+    assert_eq!(logouts[0].changes["session_id"], logins[0].changes["session_id"]);
+}
+
+#[tokio::test]
+async fn failed_logins_are_not_recorded_as_logins() {
+    let app = spawn_app().await;
+
+    app.post_login(&json!({ "username": app.user.username, "password": "wrong" }))
+        .await;
+
+    assert!(events(&app, "login").await.is_empty());
+}
+
+#[tokio::test]
+async fn role_changes_record_what_changed() {
+    let app = spawn_app().await;
+    let viewer = TestUser::generate();
+    viewer.store_with_role(&app.db_pool, "viewer").await;
+    app.login_as(&app.user).await;
+
+    let response = app
+        .post_admin_form(
+            &format!("/users/{}/role", viewer.user_id),
+            &json!({ "role": "publisher" }),
+        )
+        .await;
+    assert_is_redirect_to(&response, "/admin/users");
+
+    let changes = events(&app, "user_role_change").await;
+    assert_eq!(changes.len(), 1);
+    assert_eq!(changes[0].user_id, Some(app.user.user_id));
+    assert_eq!(changes[0].target, Some(viewer.user_id.to_string()));
+    assert_eq!(
+        changes[0].changes,
+        json!({ "role": { "from": "viewer", "to": "publisher" } })
+    );
+}
+
+#[tokio::test]
+async fn the_audit_log_can_be_filtered() {
+    let app = spawn_app().await;
+    let viewer = TestUser::generate();
+    viewer.store_with_role(&app.db_pool, "viewer").await;
+    app.login_as(&viewer).await;
+    app.post_logout().await;
+    app.login_as(&app.user).await;
+
+    let html_page = get_audit_log_html(&app, "").await;
+    assert!(html_page.contains("(3 events)"));
+
+    let html_page = get_audit_log_html(&app, "action=login&user=").await;
+    assert!(html_page.contains("(2 events)"));
+    let html_page =
+        get_audit_log_html(&app, &format!("action=login&user={}", viewer.username)).await;
+    assert!(html_page.contains("(1 events)"));
+    assert!(html_page.contains(&viewer.username));
+    assert!(!html_page.contains(&format!("<td>{}</td>", app.user.username)));
+
+    let response = app
+        .app_client
+        .get(format!("{}/admin/audit?action=no_such_action", &app.address))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status().as_u16(), 400);
+}
+
+#[tokio::test]
+async fn only_owners_see_the_audit_log() {
+    let app = spawn_app().await;
+    let publisher = TestUser::generate();
+    publisher.store_with_role(&app.db_pool, "publisher").await;
+    app.login_as(&publisher).await;
+
+    let response = app
+        .app_client
+        .get(format!("{}/admin/audit", &app.address))
+        .send()
+        .await
+        .unwrap();
+    assert_is_redirect_to(&response, "/admin/dashboard");
+    assert!(!app.get_admin_dashboard_html().await.contains("/admin/audit"));
+}
+
+#[tokio::test]
+async fn audit_events_cannot_be_changed_or_removed() {
+    let app = spawn_app().await;
+    app.login_as(&app.user).await;
+
+    let update = sqlx::query!("UPDATE audit_log SET action = 'logout'")
+        .execute(&app.db_pool)
+        .await;
+    assert!(update.is_err());
+    let delete = sqlx::query!("DELETE FROM audit_log").execute(&app.db_pool).await;
+    assert!(delete.is_err());
+    let truncate = sqlx::query!("TRUNCATE audit_log").execute(&app.db_pool).await;
+    assert!(truncate.is_err());
+    assert_eq!(events(&app, "login").await.len(), 1);
+}
+
+#[tokio::test]
+async fn entries_outlive_their_user() {
+    let app = spawn_app().await;
+    let viewer = TestUser::generate();
+    viewer.store_with_role(&app.db_pool, "viewer").await;
+    app.login_as(&viewer).await;
+    app.post_logout().await;
+
+    sqlx::query!("DELETE FROM user_sessions WHERE user_id = $1", viewer.user_id)
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+    sqlx::query!("DELETE FROM users WHERE user_id = $1", viewer.user_id)
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+
+    app.login_as(&app.user).await;
+    let html_page = get_audit_log_html(&app, "action=login").await;
+    assert!(html_page.contains(&viewer.user_id.to_string()));
+}
+
+#[tokio::test]
+async fn forwarded_addresses_are_only_recorded_from_trusted_proxies() {
+    let login = |app: &TestApp| {
+        app.app_client
+            .post(format!("{}/login", &app.address))
+            .header("X-Forwarded-For", "203.0.113.7")
+            .form(&json!({ "username": app.user.username, "password": app.user.password }))
+            .send()
+    };
+
+    let app = spawn_app().await;
+    login(&app).await.unwrap();
+    let logins = events(&app, "login").await;
+    assert_eq!(logins[0].ip_address.as_deref(), Some("127.0.0.1"));
+
+    let app = spawn_app_with(|c| c.app.trusted_proxies = vec!["127.0.0.1".parse().unwrap()]).await;
+    login(&app).await.unwrap();
+    let logins = events(&app, "login").await;
+    assert_eq!(logins[0].ip_address.as_deref(), Some("203.0.113.7"));
+}
+
+#[tokio::test]
+async fn addresses_the_client_forwarded_itself_are_ignored() {
+    let app = spawn_app_with(|c| {
+        c.app.trusted_proxies = vec!["127.0.0.1".parse().unwrap(), "10.0.0.2".parse().unwrap()]
+    })
+    .await;
+
+    // the client sent the first entry, the proxies added the real address after it
+    app.app_client
+        .post(format!("{}/login", &app.address))
+        .header("X-Forwarded-For", "198.51.100.1, 203.0.113.7, 10.0.0.2")
+        .form(&json!({ "username": app.user.username, "password": app.user.password }))
+        .send()
+        .await
+        .unwrap();
+    let logins = events(&app, "login").await;
+    assert_eq!(logins[0].ip_address.as_deref(), Some("203.0.113.7"));
+}
@@ -92,6 +92,7 @@ async fn logout_clears_session_state() {
         "confirm_new_password": new_password,
     });
     let response = app.post_change_password(&body).await;
+    assert_is_redirect_to(&response, "/admin/password");
     let html_page = app.get_change_password_html().await;
     assert!(html_page.contains("Password changed successfully"));
 
@@ -111,3 +112,36 @@ async fn logout_clears_session_state() {
     .await;
     assert_is_redirect_to(&response, "/admin/dashboard");
 }
+
+#[tokio::test]
+async fn new_password_must_follow_the_policy() {
+    let app = spawn_app().await;
+    app.post_login(&json!({
+        "username": app.user.username,
+        "password": app.user.password
+    }))
+    .await;
+
+    let test_cases = [
+        ("", "Passwords must be at least 12 characters long"),
+        ("qwertyuiop123", "That password is on a list of commonly used passwords"),
+        (
+            &format!("{}-{}", app.user.username, "lathe of heaven"),
+            "Passwords can't contain your username",
+        ),
+        ("aaaaaaaaaaaaaaaa", "That password is too easy to guess"),
+    ];
+    for (new_password, message) in test_cases {
+        let response = app
+            .post_change_password(&json!({
+                "old_password": app.user.password,
+                "new_password": new_password,
+                "confirm_new_password": new_password,
+            }))
+            .await;
+        assert_is_redirect_to(&response, "/admin/password");
+
+        let html_page = app.get_change_password_html().await;
+        assert!(html_page.contains(message), "{}", message);
+    }
+}
@@ -0,0 +1,178 @@
+use crate::helpers::{assert_is_redirect_to, spawn_app, TestApp};
+use reqwest::redirect::Policy;
+use serde_json::json;
+use uuid::Uuid;
+
+fn newsletter_body() -> serde_json::Value {
+    json!({
+        "title": "Newsletter title",
+        "text": "Newsletter body as plain text",
+        "html": "<p>Newsletter body as HTML</p>",
+        "idempotency_key": Uuid::new_v4().to_string(),
+    })
+}
+
+async fn n_issues(app: &TestApp) -> i64 {
+    sqlx::query_scalar!(r#"SELECT count(*) as "count!" FROM newsletter_issues"#)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+}
+
+#[tokio::test]
+async fn admin_forms_carry_a_csrf_token() {
+    let app = spawn_app().await;
+    app.login_as(&app.user).await;
+
+    let token = app.csrf_token().await;
+    assert_eq!(token.len(), 32);
+    for page in ["dashboard", "newsletters", "password", "suppressions", "users", "totp"] {
+        let html_page = app
+            .get_html(&format!("{}/admin/{}", &app.address, page))
+            .await;
+        let n_forms = html_page.matches(r#"method="post""#).count();
+        assert!(n_forms > 0, "no forms on {}", page);
+        assert_eq!(
+            html_page
+                .matches(&format!(r#"name="csrf_token" value="{}""#, token))
+                .count(),
+            n_forms,
+            "a form on {} is missing the token",
+            page
+        );
+    }
+}
+
+#[tokio::test]
+async fn forged_newsletters_are_rejected() {
+    let app = spawn_app().await;
+    app.login_as(&app.user).await;
+
+    // what a malicious page gets the browser to send: the cookie, but no token
+    let response = app
+        .app_client
+        .post(format!("{}/admin/newsletters", &app.address))
+        .form(&newsletter_body())
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status().as_u16(), 403);
+
+    let mut body = newsletter_body();
+    body["csrf_token"] = json!("not-the-token");
+    let response = app
+        .app_client
+        .post(format!("{}/admin/newsletters", &app.address))
+        .form(&body)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status().as_u16(), 403);
+
+    assert_eq!(n_issues(&app).await, 0);
+}
+
+#[tokio::test]
+async fn the_token_can_be_sent_as_a_form_field() {
+    let app = spawn_app().await;
+    app.login_as(&app.user).await;
+
+    let mut body = newsletter_body();
+    body["csrf_token"] = json!(app.csrf_token().await);
+    let response = app
+        .app_client
+        .post(format!("{}/admin/newsletters", &app.address))
+        .form(&body)
+        .send()
+        .await
+        .unwrap();
+    assert_is_redirect_to(&response, "/admin/dashboard");
+    assert_eq!(n_issues(&app).await, 1);
+}
+
+#[tokio::test]
+async fn tokens_are_tied_to_their_session() {
+    let app = spawn_app().await;
+    app.login_as(&app.user).await;
+    let token = app.csrf_token().await;
+
+    // the same user in another browser
+    let other = reqwest::Client::builder()
+        .redirect(Policy::none())
+        .cookie_store(true)
+        .build()
+        .unwrap();
+    other
+        .post(format!("{}/login", &app.address))
+        .form(&json!({
+            "username": &app.user.username,
+            "password": &app.user.password,
+        }))
+        .send()
+        .await
+        .unwrap();
+    let response = other
+        .post(format!("{}/admin/logout", &app.address))
+        .header("X-CSRF-Token", token)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status().as_u16(), 403);
+}
+
+#[tokio::test]
+async fn forged_logouts_are_rejected() {
+    let app = spawn_app().await;
+    app.login_as(&app.user).await;
+
+    let response = app
+        .app_client
+        .post(format!("{}/admin/logout", &app.address))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status().as_u16(), 403);
+    assert_eq!(app.get_admin_dashboard().await.status().as_u16(), 200);
+}
+
+#[tokio::test]
+async fn multipart_forms_send_the_token_in_the_query() {
+    let app = spawn_app().await;
+    app.login_as(&app.user).await;
+    let form = || {
+        let file = reqwest::multipart::Part::text("email,name\n".to_string())
+            .file_name("subscribers.csv")
+            .mime_str("text/csv")
+            .unwrap();
+        reqwest::multipart::Form::new()
+            .part("file", file)
+            .text("mode", "confirmed")
+    };
+
+    let response = app
+        .app_client
+        .post(format!("{}/admin/subscribers/imports", &app.address))
+        .multipart(form())
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status().as_u16(), 403);
+
+    let html_page = app
+        .get_html(&format!("{}/admin/subscribers/imports", &app.address))
+        .await;
+    let action = html_page
+        .split(r#"<form action=""#)
+        .nth(1)
+        .and_then(|rest| rest.split('"').next())
+        .unwrap()
+        .replace("&amp;", "&");
+    let response = app
+        .app_client
+        .post(format!("{}{}", &app.address, action))
+        .multipart(form())
+        .send()
+        .await
+        .unwrap();
+    assert_ne!(response.status().as_u16(), 403);
+}
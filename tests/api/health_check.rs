@@ -1,5 +1,8 @@
 use reqwest::Client;
-use crate::helpers::spawn_app;
+use serde_json::Value;
+use zero2prod::issue_delivery_workers::record_heartbeat;
+
+use crate::helpers::{spawn_app, TestApp};
 
 
 #[tokio::test]
@@ -22,3 +25,79 @@ async fn test_health() {
     assert!(connection.is_ok());
 }
 
+
+async fn get_readiness(app: &TestApp) -> (u16, Value) {
+    let response = Client::new()
+        .get(format!("{}/health/ready", app.address))
+        .send()
+        .await
+        .unwrap();
+    (response.status().as_u16(), response.json().await.unwrap())
+}
+
+#[tokio::test]
+async fn liveness_doesnt_depend_on_anything() {
+    let app = spawn_app().await;
+
+    let response = Client::new()
+        .get(format!("{}/health/live", app.address))
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(response.status().as_u16(), 200);
+}
+
+#[tokio::test]
+async fn ready_once_every_dependency_is_up() {
+    let app = spawn_app().await;
+    record_heartbeat(&app.db_pool).await.unwrap();
+
+    let (status, body) = get_readiness(&app).await;
+
+    assert_eq!(status, 200);
+    assert_eq!(body["status"], "ready");
+    for check in ["postgres", "redis", "migrations", "delivery_worker"] {
+        assert_eq!(body["checks"][check], "up", "{check}");
+    }
+}
+
+#[tokio::test]
+async fn not_ready_without_a_recent_delivery_worker_heartbeat() {
+    let app = spawn_app().await;
+
+    let (status, body) = get_readiness(&app).await;
+    assert_eq!(status, 503);
+    assert_eq!(body["status"], "unavailable");
+    assert_eq!(body["checks"]["delivery_worker"], "down");
+    assert_eq!(body["checks"]["postgres"], "up");
+
+    record_heartbeat(&app.db_pool).await.unwrap();
+    sqlx::query!("UPDATE worker_heartbeats SET last_seen_at = now() - interval '1 hour'")
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+    let (status, body) = get_readiness(&app).await;
+    assert_eq!(status, 503);
+    // the reason is logged, not handed to whoever asks
+    assert_eq!(body["checks"]["delivery_worker"], "down");
+    assert!(!body.to_string().contains("last seen"));
+}
+
+#[tokio::test]
+async fn not_ready_when_migrations_are_missing() {
+    let app = spawn_app().await;
+    record_heartbeat(&app.db_pool).await.unwrap();
+    sqlx::query(
+        "DELETE FROM _sqlx_migrations WHERE version = (SELECT max(version) FROM _sqlx_migrations)",
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+
+    let (status, body) = get_readiness(&app).await;
+
+    assert_eq!(status, 503);
+    assert_eq!(body["checks"]["migrations"], "down");
+    assert!(!body.to_string().contains("migration "));
+}
@@ -0,0 +1,368 @@
+use std::time::Duration;
+
+use reqwest::Method;
+use serde_json::{json, Value};
+use uuid::Uuid;
+use wiremock::{
+    matchers::{method, path},
+    Mock, ResponseTemplate,
+};
+use zero2prod::{
+    idempotency::delete_expired_idempotency_keys,
+    routes::{BodyData, Content},
+};
+
+use crate::helpers::{assert_is_redirect_to, spawn_app, spawn_app_with, TestApp, TestUser};
+
+const SIGN_UP: &str = "name=le%20guin&email=ursula_le_guin%40gmail.com";
+
+async fn subscribe_with_key(app: &TestApp, key: &str) -> reqwest::Response {
+    app.app_client
+        .post(format!("{}/subscribe", &app.address))
+        .header("Content-Type", "application/x-www-form-urlencoded")
+        .header("Idempotency-Key", key)
+        .body(SIGN_UP)
+        .send()
+        .await
+        .unwrap()
+}
+
+async fn n_issues(app: &TestApp) -> i64 {
+    sqlx::query_scalar!(r#"SELECT count(*) as "count!" FROM newsletter_issues"#)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+}
+
+#[tokio::test]
+async fn anonymous_retries_with_the_same_key_are_replayed() {
+    let app = spawn_app().await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    let key = Uuid::new_v4().to_string();
+    let response = subscribe_with_key(&app, &key).await;
+    assert_eq!(response.status().as_u16(), 200);
+    let response = subscribe_with_key(&app, &key).await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    let n_saved = sqlx::query_scalar!(
+        r#"SELECT count(*) as "count!" FROM idempotency WHERE idempotency_key = $1 AND client_ip IS NOT NULL"#,
+        key
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .unwrap();
+    assert_eq!(n_saved, 1);
+}
+
+#[tokio::test]
+async fn anonymous_keys_belong_to_the_peer_address_not_a_forwarded_one() {
+    let app = spawn_app().await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    let key = Uuid::new_v4().to_string();
+    for forwarded_for in ["203.0.113.1", "203.0.113.2"] {
+        let response = app
+            .app_client
+            .post(format!("{}/subscribe", &app.address))
+            .header("Content-Type", "application/x-www-form-urlencoded")
+            .header("Idempotency-Key", &key)
+            .header("X-Forwarded-For", forwarded_for)
+            .body(SIGN_UP)
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status().as_u16(), 200);
+    }
+
+    let client_ip = sqlx::query_scalar!(
+        "SELECT client_ip FROM idempotency WHERE idempotency_key = $1",
+        key
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .unwrap();
+    assert_eq!(client_ip.as_deref(), Some("127.0.0.1"));
+}
+
+#[tokio::test]
+async fn requests_without_a_key_are_not_deduplicated() {
+    let app = spawn_app().await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(2)
+        .mount(&app.email_server)
+        .await;
+
+    app.post_subscriptions(SIGN_UP).await;
+    app.post_subscriptions(SIGN_UP).await;
+}
+
+#[tokio::test]
+async fn form_submissions_are_deduplicated_by_their_key_field() {
+    let app = spawn_app().await;
+    app.login_as(&app.user).await;
+    let body = serde_urlencoded::to_string(BodyData::new(
+        "Newsletter title".into(),
+        Content {
+            text: "Newsletter body as plain text".into(),
+            html: "<p>Newsletter body as HTML</p>".into(),
+        },
+    ))
+    .unwrap();
+
+    let response = app.post_newsletters(body.clone()).await;
+    assert_is_redirect_to(&response, "/admin/dashboard");
+    let response = app.post_newsletters(body).await;
+    assert_is_redirect_to(&response, "/admin/dashboard");
+
+    assert_eq!(n_issues(&app).await, 1);
+}
+
+#[tokio::test]
+async fn keys_belong_to_the_user_who_sent_them() {
+    let app = spawn_app().await;
+    let publisher = TestUser::generate();
+    publisher.store_with_role(&app.db_pool, "publisher").await;
+    let body = serde_urlencoded::to_string(BodyData::new(
+        "Newsletter title".into(),
+        Content {
+            text: "Newsletter body as plain text".into(),
+            html: "<p>Newsletter body as HTML</p>".into(),
+        },
+    ))
+    .unwrap();
+
+    app.login_as(&app.user).await;
+    app.post_newsletters(body.clone()).await;
+    app.post_logout().await;
+    app.login_as(&publisher).await;
+    app.post_newsletters(body).await;
+
+    assert_eq!(n_issues(&app).await, 2);
+}
+
+#[tokio::test]
+async fn invalid_keys_are_rejected() {
+    let app = spawn_app().await;
+
+    let response = subscribe_with_key(&app, "").await;
+    assert_eq!(response.status().as_u16(), 400);
+    let response = subscribe_with_key(&app, &"k".repeat(51)).await;
+    assert_eq!(response.status().as_u16(), 400);
+}
+
+#[tokio::test]
+async fn reusing_a_key_for_a_different_request_is_rejected() {
+    let app = spawn_app().await;
+    app.login_as(&app.user).await;
+    let token = app.create_api_token(&["publish_newsletters"]).await;
+    let publish = |title: &str| {
+        app.api_request(Method::POST, "/issues", &token)
+            .header("Idempotency-Key", "reused-key")
+            .json(&json!({ "title": title, "text": "text", "html": "<p>html</p>" }))
+            .send()
+    };
+
+    let response = publish("First issue").await.unwrap();
+    assert_eq!(response.status().as_u16(), 201);
+    let response = publish("Second issue").await.unwrap();
+    assert_eq!(response.status().as_u16(), 422);
+    let body: Value = response.json().await.unwrap();
+    assert!(body["error"]
+        .as_str()
+        .unwrap()
+        .contains("already used for a different request"));
+    let response = publish("First issue").await.unwrap();
+    assert_eq!(response.status().as_u16(), 201);
+
+    assert_eq!(n_issues(&app).await, 1);
+}
+
+#[tokio::test]
+async fn reusing_a_form_key_with_different_fields_is_rejected() {
+    let app = spawn_app().await;
+    app.login_as(&app.user).await;
+    let key = Uuid::new_v4().to_string();
+    let form = |title: &str| {
+        format!(
+            "title={}&text=text&html=html&idempotency_key={}",
+            urlencoding::encode(title),
+            key
+        )
+    };
+
+    let response = app.post_newsletters(form("First issue")).await;
+    assert_is_redirect_to(&response, "/admin/dashboard");
+    let response = app.post_newsletters(form("Second issue")).await;
+    assert_eq!(response.status().as_u16(), 422);
+
+    assert_eq!(n_issues(&app).await, 1);
+}
+
+async fn publish_with_key(app: &TestApp, token: &str, key: &str) -> reqwest::Response {
+    app.api_request(Method::POST, "/issues", token)
+        .header("Idempotency-Key", key)
+        .json(&json!({ "title": "Newsletter title", "text": "text", "html": "<p>html</p>" }))
+        .send()
+        .await
+        .unwrap()
+}
+
+async fn n_keys(app: &TestApp, key: &str) -> i64 {
+    sqlx::query_scalar!(
+        r#"SELECT count(*) as "count!" FROM idempotency WHERE idempotency_key = $1"#,
+        key
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .unwrap()
+}
+
+#[tokio::test]
+async fn expired_keys_can_be_used_again() {
+    let app = spawn_app().await;
+    app.login_as(&app.user).await;
+    let token = app.create_api_token(&["publish_newsletters"]).await;
+
+    let response = publish_with_key(&app, &token, "a-key").await;
+    assert_eq!(response.status().as_u16(), 201);
+    sqlx::query!("UPDATE idempotency SET created_at = now() - interval '2 days'")
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+    let response = publish_with_key(&app, &token, "a-key").await;
+    assert_eq!(response.status().as_u16(), 201);
+
+    assert_eq!(n_issues(&app).await, 2);
+}
+
+#[tokio::test]
+async fn a_request_that_never_finished_is_retried_after_the_timeout() {
+    let app = spawn_app().await;
+    app.login_as(&app.user).await;
+    let token = app.create_api_token(&["publish_newsletters"]).await;
+    // left behind by a process that died while handling the request
+    sqlx::query!(
+        r#"
+        INSERT INTO idempotency(user_id, idempotency_key, created_at)
+        VALUES ($1, 'a-key', now() - interval '1 hour')
+        "#,
+        app.user.user_id
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+
+    let response = publish_with_key(&app, &token, "a-key").await;
+    assert_eq!(response.status().as_u16(), 201);
+    let first: Value = response.json().await.unwrap();
+    let response = publish_with_key(&app, &token, "a-key").await;
+    assert_eq!(response.status().as_u16(), 201);
+    let retry: Value = response.json().await.unwrap();
+
+    assert_eq!(first["issue_id"], retry["issue_id"]);
+    assert_eq!(n_issues(&app).await, 1);
+}
+
+#[tokio::test]
+async fn server_errors_free_the_key() {
+    let app = spawn_app().await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(500))
+        .mount(&app.email_server)
+        .await;
+
+    let key = Uuid::new_v4().to_string();
+    let response = subscribe_with_key(&app, &key).await;
+    assert_eq!(response.status().as_u16(), 500);
+
+    assert_eq!(n_keys(&app, &key).await, 0);
+}
+
+#[tokio::test]
+async fn the_cleanup_deletes_expired_keys_only() {
+    let app = spawn_app().await;
+    app.login_as(&app.user).await;
+    let token = app.create_api_token(&["publish_newsletters"]).await;
+    publish_with_key(&app, &token, "old-key").await;
+    publish_with_key(&app, &token, "new-key").await;
+    sqlx::query!(
+        "UPDATE idempotency SET created_at = now() - interval '2 days' WHERE idempotency_key = 'old-key'"
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+
+    let n_deleted = delete_expired_idempotency_keys(&app.db_pool, Duration::from_secs(86400))
+        .await
+        .unwrap();
+
+    assert_eq!(n_deleted, 1);
+    assert_eq!(n_keys(&app, "old-key").await, 0);
+    assert_eq!(n_keys(&app, "new-key").await, 1);
+}
+
+/// the first request is held up sending its confirmation email for `delay`,
+/// a duplicate arrives while it is
+async fn race_duplicate_sign_ups(
+    in_progress_wait_milliseconds: u64,
+    delay: Duration,
+) -> (TestApp, reqwest::Response, reqwest::Response) {
+    let app = spawn_app_with(|c| {
+        c.idempotency.in_progress_wait_milliseconds = in_progress_wait_milliseconds
+    })
+    .await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200).set_delay(delay))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    let key = Uuid::new_v4().to_string();
+    let (first, duplicate) = tokio::join!(subscribe_with_key(&app, &key), async {
+        tokio::time::sleep(Duration::from_millis(300)).await;
+        subscribe_with_key(&app, &key).await
+    });
+    (app, first, duplicate)
+}
+
+#[tokio::test]
+async fn a_concurrent_duplicate_waits_for_the_original_response() {
+    let (_app, first, duplicate) =
+        race_duplicate_sign_ups(5000, Duration::from_millis(1500)).await;
+
+    assert_eq!(first.status().as_u16(), 200);
+    assert_eq!(duplicate.status().as_u16(), 200);
+}
+
+#[tokio::test]
+async fn a_concurrent_duplicate_is_told_to_retry_when_the_original_is_slow() {
+    let (app, first, duplicate) =
+        race_duplicate_sign_ups(500, Duration::from_millis(2000)).await;
+
+    assert_eq!(first.status().as_u16(), 200);
+    assert_eq!(duplicate.status().as_u16(), 409);
+    assert_eq!(duplicate.headers()["Retry-After"], "1");
+
+    // once the original is done, retries get its response
+    let key = sqlx::query_scalar!("SELECT idempotency_key FROM idempotency")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    let retry = subscribe_with_key(&app, &key).await;
+    assert_eq!(retry.status().as_u16(), 200);
+}
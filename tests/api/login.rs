@@ -1,4 +1,4 @@
-use crate::helpers::{assert_is_redirect_to, spawn_app};
+use crate::helpers::{assert_is_redirect_to, spawn_app, spawn_app_with};
 use serde_json::json;
 
 #[tokio::test]
@@ -37,3 +37,150 @@ async fn redirect_to_admin_dashboard_on_login_success() {
     let html_page = app.get_admin_dashboard_html().await;
     assert!(html_page.contains(&format!("Welcome {} !", app.user.username)));
 }
+
+#[tokio::test]
+async fn repeated_failures_lock_the_username_out() {
+    let app = spawn_app_with(|c| c.login.max_failures_per_username = 3).await;
+    let wrong_password = json!({
+        "username": &app.user.username,
+        "password": "not-the-password",
+    });
+    for _ in 0..3 {
+        app.post_login(&wrong_password).await;
+    }
+
+    // even the right password is refused while locked out
+    let response = app
+        .post_login(&json!({
+            "username": &app.user.username,
+            "password": &app.user.password,
+        }))
+        .await;
+    assert_is_redirect_to(&response, "/login");
+    let login_html = app.get_login_html().await;
+    assert!(login_html.contains("Too many failed login attempts, please try again later"));
+
+    // usernames that don't exist are locked out the same way
+    let unknown_user = json!({"username": "nobody", "password": "password"});
+    for _ in 0..3 {
+        app.post_login(&unknown_user).await;
+    }
+    app.post_login(&unknown_user).await;
+    let login_html = app.get_login_html().await;
+    assert!(login_html.contains("Too many failed login attempts, please try again later"));
+}
+
+#[tokio::test]
+async fn repeated_failures_from_one_ip_lock_it_out() {
+    let app = spawn_app_with(|c| c.login.max_failures_per_ip = 2).await;
+    for username in ["alice", "bob"] {
+        app.post_login(&json!({"username": username, "password": "password"}))
+            .await;
+    }
+
+    app.post_login(&json!({
+        "username": &app.user.username,
+        "password": &app.user.password,
+    }))
+    .await;
+
+    let login_html = app.get_login_html().await;
+    assert!(login_html.contains("Too many failed login attempts, please try again later"));
+}
+
+#[tokio::test]
+async fn parallel_guesses_cannot_exceed_the_limit() {
+    let app = spawn_app_with(|c| c.login.max_failures_per_username = 3).await;
+    let wrong_password = json!({
+        "username": &app.user.username,
+        "password": "not-the-password",
+    });
+
+    futures_util::future::join_all((0..8).map(|_| app.post_login(&wrong_password))).await;
+
+    let n_checked = sqlx::query_scalar!(
+        r#"SELECT count(*) as "count!" FROM failed_login_attempts WHERE NOT locked_out"#
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .unwrap();
+    assert_eq!(n_checked, 3);
+}
+
+#[tokio::test]
+async fn forwarded_for_headers_do_not_reset_the_ip_allowance() {
+    let app = spawn_app_with(|c| c.login.max_failures_per_ip = 2).await;
+    for (i, username) in ["alice", "bob", "carol"].into_iter().enumerate() {
+        app.app_client
+            .post(format!("{}/login", &app.address))
+            .header("X-Forwarded-For", format!("203.0.113.{}", i))
+            .form(&json!({"username": username, "password": "password"}))
+            .send()
+            .await
+            .unwrap();
+    }
+
+    let login_html = app.get_login_html().await;
+    assert!(login_html.contains("Too many failed login attempts, please try again later"));
+}
+
+#[tokio::test]
+async fn failed_attempts_are_logged_for_admins() {
+    let app = spawn_app().await;
+    app.post_login(&json!({"username": "Mallory", "password": "guess"}))
+        .await;
+
+    app.post_login(&json!({
+        "username": &app.user.username,
+        "password": &app.user.password,
+    }))
+    .await;
+    let html_page = app
+        .app_client
+        .get(format!("{}/admin/login_attempts", &app.address))
+        .send()
+        .await
+        .unwrap()
+        .text()
+        .await
+        .unwrap();
+
+    assert!(html_page.contains("<td>mallory</td>"));
+    assert!(html_page.contains("<td>127.0.0.1</td>"));
+}
+
+#[tokio::test]
+async fn stale_password_hashes_are_upgraded_on_login() {
+    let app = spawn_app_with(|c| {
+        c.password_hashing.memory_kib = 19456;
+        c.password_hashing.iterations = 3;
+    })
+    .await;
+
+    let response = app
+        .post_login(&json!({
+            "username": &app.user.username,
+            "password": &app.user.password,
+        }))
+        .await;
+    assert_is_redirect_to(&response, "/admin/dashboard");
+
+    let password_hash = sqlx::query_scalar!(
+        "SELECT password_hash FROM users WHERE user_id = $1",
+        app.user.user_id
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .unwrap();
+    assert!(password_hash.starts_with("$argon2id$v=19$m=19456,t=3,p=1$"));
+
+    // the upgraded hash still lets the user in
+    app.post_logout().await;
+    let response = app
+        .post_login(&json!({
+            "username": &app.user.username,
+            "password": &app.user.password,
+        }))
+        .await;
+    assert_is_redirect_to(&response, "/admin/dashboard");
+}
@@ -6,3 +6,19 @@ mod newsletter;
 mod login;
 mod admin_dashboard;
 mod change_password;
+mod admin_subscribers;
+mod subscriber_import;
+mod subscriber_export;
+mod subscriber_data;
+mod postmark_webhook;
+mod suppressions;
+mod two_factor;
+mod password_reset;
+mod users;
+mod sessions;
+mod csrf;
+mod api_tokens;
+mod openapi;
+mod oidc;
+mod audit_log;
+mod idempotency;
@@ -0,0 +1,327 @@
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use jsonwebtoken::{EncodingKey, Header};
+use reqwest::Url;
+use secrecy::Secret;
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
+use uuid::Uuid;
+use wiremock::{
+    matchers::{body_string_contains, method, path},
+    Mock, MockServer, ResponseTemplate,
+};
+use zero2prod::configuration::OidcSettings;
+
+use crate::helpers::{assert_is_redirect_to, spawn_app, spawn_app_with, TestApp};
+
+const SIGNING_KEY: &[u8] = include_bytes!("fixtures/oidc/signing_key.pem");
+const JWKS: &str = include_str!("fixtures/oidc/jwks.json");
+const CLIENT_ID: &str = "zero2prod-test";
+
+/// a stand-in identity provider: discovery, keys, and a token endpoint
+/// that hands out whatever id token the test asks for
+struct MockIdp {
+    server: MockServer,
+}
+
+impl MockIdp {
+    async fn start() -> Self {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/.well-known/openid-configuration"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "issuer": server.uri(),
+                "authorization_endpoint": format!("{}/authorize", server.uri()),
+                "token_endpoint": format!("{}/token", server.uri()),
+                "jwks_uri": format!("{}/jwks", server.uri()),
+            })))
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/jwks"))
+            .respond_with(ResponseTemplate::new(200).set_body_raw(JWKS, "application/json"))
+            .mount(&server)
+            .await;
+        Self { server }
+    }
+
+    fn settings(&self, provisioning_domains: &[&str]) -> OidcSettings {
+        OidcSettings {
+            provider_name: "Test SSO".into(),
+            issuer_url: self.server.uri(),
+            client_id: CLIENT_ID.into(),
+            client_secret: Secret::new("client-secret".into()),
+            provisioning_domains: provisioning_domains.iter().map(|d| d.to_string()).collect(),
+            provisioning_role: "viewer".into(),
+        }
+    }
+
+    /// valid for us and the given login, `claims` fill in or override the rest
+    fn id_token(&self, nonce: &str, claims: Value) -> String {
+        let mut token = json!({
+            "iss": self.server.uri(),
+            "aud": CLIENT_ID,
+            "exp": chrono::Utc::now().timestamp() + 300,
+            "iat": chrono::Utc::now().timestamp(),
+            "nonce": nonce,
+        });
+        token
+            .as_object_mut()
+            .unwrap()
+            .extend(claims.as_object().unwrap().clone());
+        let mut header = Header::new(jsonwebtoken::Algorithm::RS256);
+        header.kid = Some("test-key".into());
+        jsonwebtoken::encode(&header, &token, &EncodingKey::from_rsa_pem(SIGNING_KEY).unwrap())
+            .unwrap()
+    }
+}
+
+/// the query of the authorization request the app redirected to
+async fn start_login(app: &TestApp) -> Url {
+    let response = app
+        .app_client
+        .get(format!("{}/login/oidc", &app.address))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status().as_u16(), 303);
+    Url::parse(response.headers()["Location"].to_str().unwrap()).unwrap()
+}
+
+fn param(url: &Url, name: &str) -> String {
+    url.query_pairs()
+        .find(|(k, _)| k == name)
+        .map(|(_, v)| v.into_owned())
+        .unwrap()
+}
+
+async fn callback(app: &TestApp, query: &[(&str, &str)]) -> reqwest::Response {
+    app.app_client
+        .get(format!("{}/login/oidc/callback", &app.address))
+        .query(query)
+        .send()
+        .await
+        .unwrap()
+}
+
+/// the whole round trip, with the provider vouching for `claims`
+async fn sign_in(app: &TestApp, idp: &MockIdp, claims: Value) -> reqwest::Response {
+    let authorization = start_login(app).await;
+    let code = Uuid::new_v4().to_string();
+    Mock::given(method("POST"))
+        .and(path("/token"))
+        .and(body_string_contains(format!("code={}", code)))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "access_token": "access-token",
+            "token_type": "Bearer",
+            "id_token": idp.id_token(&param(&authorization, "nonce"), claims),
+        })))
+        .mount(&idp.server)
+        .await;
+    callback(app, &[("code", &code), ("state", &param(&authorization, "state"))]).await
+}
+
+async fn set_email(app: &TestApp, email: &str) {
+    sqlx::query!("UPDATE users SET email = $1 WHERE user_id = $2", email, app.user.user_id)
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+}
+
+#[tokio::test]
+async fn the_login_page_offers_single_sign_on_only_when_configured() {
+    let app = spawn_app().await;
+    assert!(!app.get_login_html().await.contains("/login/oidc"));
+    let response = app
+        .app_client
+        .get(format!("{}/login/oidc", &app.address))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status().as_u16(), 404);
+
+    let idp = MockIdp::start().await;
+    let app = spawn_app_with(|c| c.oidc = Some(idp.settings(&[]))).await;
+    assert!(app
+        .get_login_html()
+        .await
+        .contains(r#"<a href="/login/oidc">Sign in with Test SSO</a>"#));
+}
+
+#[tokio::test]
+async fn the_code_exchange_is_bound_to_the_authorization_request_with_pkce() {
+    let idp = MockIdp::start().await;
+    let app = spawn_app_with(|c| c.oidc = Some(idp.settings(&[]))).await;
+    set_email(&app, "admin@example.com").await;
+
+    let authorization = start_login(&app).await;
+    assert_eq!(authorization.path(), "/authorize");
+    assert_eq!(param(&authorization, "client_id"), CLIENT_ID);
+    assert_eq!(param(&authorization, "code_challenge_method"), "S256");
+    let claims = json!({ "sub": "admin", "email": "admin@example.com", "email_verified": true });
+    Mock::given(method("POST"))
+        .and(path("/token"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "access_token": "access-token",
+            "token_type": "Bearer",
+            "id_token": idp.id_token(&param(&authorization, "nonce"), claims),
+        })))
+        .mount(&idp.server)
+        .await;
+    let response = callback(
+        &app,
+        &[("code", "the-code"), ("state", &param(&authorization, "state"))],
+    )
+    .await;
+    assert_is_redirect_to(&response, "/admin/dashboard");
+
+    let token_request = idp
+        .server
+        .received_requests()
+        .await
+        .unwrap()
+        .into_iter()
+        .find(|r| r.url.path() == "/token")
+        .unwrap();
+    let form: Vec<(String, String)> = serde_urlencoded::from_bytes(&token_request.body).unwrap();
+    let field = |name: &str| form.iter().find(|(k, _)| k == name).unwrap().1.clone();
+    assert_eq!(field("code"), "the-code");
+    assert_eq!(
+        URL_SAFE_NO_PAD.encode(Sha256::digest(field("code_verifier").as_bytes())),
+        param(&authorization, "code_challenge")
+    );
+}
+
+#[tokio::test]
+async fn users_are_linked_by_verified_email_then_recognised_by_subject() {
+    let idp = MockIdp::start().await;
+    let app = spawn_app_with(|c| c.oidc = Some(idp.settings(&[]))).await;
+    set_email(&app, "admin@example.com").await;
+
+    let response = sign_in(
+        &app,
+        &idp,
+        json!({ "sub": "admin-at-idp", "email": "Admin@Example.com", "email_verified": true }),
+    )
+    .await;
+    assert_is_redirect_to(&response, "/admin/dashboard");
+    let html_page = app.get_admin_dashboard_html().await;
+    assert!(html_page.contains(&format!("Welcome {} !", app.user.username)));
+
+    // once linked the email doesn't matter anymore
+    app.post_logout().await;
+    let response = sign_in(&app, &idp, json!({ "sub": "admin-at-idp" })).await;
+    assert_is_redirect_to(&response, "/admin/dashboard");
+}
+
+#[tokio::test]
+async fn unverified_emails_are_not_linked() {
+    let idp = MockIdp::start().await;
+    let app = spawn_app_with(|c| c.oidc = Some(idp.settings(&["example.com"]))).await;
+    set_email(&app, "admin@example.com").await;
+
+    let response = sign_in(
+        &app,
+        &idp,
+        json!({ "sub": "impostor", "email": "admin@example.com", "email_verified": false }),
+    )
+    .await;
+    assert_is_redirect_to(&response, "/login");
+    assert!(app
+        .get_login_html()
+        .await
+        .contains("isn't linked to a user"));
+    let response = app.get_admin_dashboard().await;
+    assert_is_redirect_to(&response, "/login");
+}
+
+#[tokio::test]
+async fn accounts_are_provisioned_for_configured_domains_only() {
+    let idp = MockIdp::start().await;
+    let app = spawn_app_with(|c| c.oidc = Some(idp.settings(&["example.com"]))).await;
+
+    let response = sign_in(
+        &app,
+        &idp,
+        json!({ "sub": "outsider", "email": "someone@elsewhere.com", "email_verified": true }),
+    )
+    .await;
+    assert_is_redirect_to(&response, "/login");
+
+    let response = sign_in(
+        &app,
+        &idp,
+        json!({ "sub": "new-hire", "email": "new.hire@example.com", "email_verified": true }),
+    )
+    .await;
+    assert_is_redirect_to(&response, "/admin/dashboard");
+    let user = sqlx::query!("SELECT name, role FROM users WHERE email = 'new.hire@example.com'")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(user.name, "new.hire@example.com");
+    assert_eq!(user.role, "viewer");
+    let n_outsiders = sqlx::query_scalar!(
+        r#"SELECT count(*) as "count!" FROM users WHERE email = 'someone@elsewhere.com'"#
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .unwrap();
+    assert_eq!(n_outsiders, 0);
+}
+
+#[tokio::test]
+async fn callbacks_that_dont_match_the_pending_login_are_rejected() {
+    let idp = MockIdp::start().await;
+    let app = spawn_app_with(|c| c.oidc = Some(idp.settings(&[]))).await;
+
+    // never started from this browser
+    let response = callback(&app, &[("code", "some-code"), ("state", "some-state")]).await;
+    assert_is_redirect_to(&response, "/login");
+    assert!(app.get_login_html().await.contains("Authentication failed"));
+
+    // the provider declined
+    let authorization = start_login(&app).await;
+    let response = callback(
+        &app,
+        &[("error", "access_denied"), ("state", &param(&authorization, "state"))],
+    )
+    .await;
+    assert_is_redirect_to(&response, "/login");
+
+    // a state from an earlier login can't be reused
+    let authorization = start_login(&app).await;
+    start_login(&app).await;
+    let response = callback(
+        &app,
+        &[("code", "some-code"), ("state", &param(&authorization, "state"))],
+    )
+    .await;
+    assert_is_redirect_to(&response, "/login");
+}
+
+#[tokio::test]
+async fn id_tokens_are_checked() {
+    let idp = MockIdp::start().await;
+    let app = spawn_app_with(|c| c.oidc = Some(idp.settings(&[]))).await;
+    set_email(&app, "admin@example.com").await;
+    let user = json!({ "sub": "admin", "email": "admin@example.com", "email_verified": true });
+
+    for overrides in [
+        json!({ "aud": "another-client" }),
+        json!({ "iss": "https://another-issuer.example" }),
+        json!({ "nonce": "another-login" }),
+        json!({ "exp": chrono::Utc::now().timestamp() - 3600 }),
+    ] {
+        let mut claims = user.clone();
+        claims
+            .as_object_mut()
+            .unwrap()
+            .extend(overrides.as_object().unwrap().clone());
+        let response = sign_in(&app, &idp, claims).await;
+        assert_is_redirect_to(&response, "/login");
+        assert!(app.get_login_html().await.contains("Authentication failed"));
+    }
+
+    let response = sign_in(&app, &idp, user).await;
+    assert_is_redirect_to(&response, "/admin/dashboard");
+}
@@ -0,0 +1,39 @@
+use utoipa::OpenApi;
+use zero2prod::routes::api::ApiDoc;
+
+use crate::helpers::spawn_app;
+
+const COMMITTED_SPEC: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/openapi.json");
+
+/// run with `UPDATE_OPENAPI=1` to rewrite the committed spec after changing the api
+#[test]
+fn committed_spec_matches_the_generated_one() {
+    let generated = ApiDoc::openapi().to_pretty_json().unwrap() + "\n";
+    if std::env::var("UPDATE_OPENAPI").is_ok_and(|v| v == "1") {
+        std::fs::write(COMMITTED_SPEC, &generated).unwrap();
+        return;
+    }
+
+    let committed = std::fs::read_to_string(COMMITTED_SPEC).unwrap_or_default();
+    assert!(
+        committed == generated,
+        "openapi.json is out of date, regenerate it with \
+         `UPDATE_OPENAPI=1 cargo test committed_spec_matches_the_generated_one`"
+    );
+}
+
+#[tokio::test]
+async fn the_spec_is_served_without_a_token() {
+    let app = spawn_app().await;
+
+    let response = reqwest::Client::new()
+        .get(format!("{}/api/openapi.json", &app.address))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status().as_u16(), 200);
+    let spec: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(spec["openapi"], "3.1.0");
+    assert!(spec["paths"]["/issues"]["post"].is_object());
+    assert!(spec["components"]["securitySchemes"]["api_token"].is_object());
+}
@@ -0,0 +1,337 @@
+use serde_json::json;
+use uuid::Uuid;
+use wiremock::{
+    matchers::{any, method, path},
+    Mock, ResponseTemplate,
+};
+
+use crate::helpers::{assert_is_redirect_to, spawn_app, TestApp};
+
+const EMAIL: &str = "admin@example.com";
+
+async fn set_user_email(app: &TestApp) {
+    sqlx::query!(
+        "UPDATE users SET email = $1 WHERE name = $2",
+        EMAIL,
+        app.user.username
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+}
+
+/// returns the token from the emailed link
+async fn request_reset_token(app: &TestApp, identifier: &str) -> String {
+    let _mock_guard = Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount_as_scoped(&app.email_server)
+        .await;
+
+    let response = app.post_password_reset_request(identifier).await;
+    assert_is_redirect_to(&response, "/password_reset");
+    app.dispatch_all_pending_reset_requests().await;
+
+    let email_request = app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+    let link = app.get_confirmation_links(&email_request).html;
+    assert_eq!(link.path(), "/password_reset/confirm");
+    link.query_pairs()
+        .find(|(k, _)| k == "token")
+        .map(|(_, v)| v.to_string())
+        .unwrap()
+}
+
+fn new_password_form(token: &str, password: &str) -> serde_json::Value {
+    json!({
+        "token": token,
+        "new_password": password,
+        "confirm_new_password": password,
+    })
+}
+
+#[tokio::test]
+async fn reset_link_sets_a_new_password_and_ends_existing_sessions() {
+    let app = spawn_app().await;
+    set_user_email(&app).await;
+    app.post_login(&json!({
+        "username": &app.user.username,
+        "password": &app.user.password,
+    }))
+    .await;
+
+    let token = request_reset_token(&app, EMAIL).await;
+    let html_page = app
+        .get_html(&format!("{}/password_reset/confirm?token={}", app.address, token))
+        .await;
+    assert!(html_page.contains(r#"name="new_password""#));
+
+    let new_password = Uuid::new_v4().to_string();
+    let response = app
+        .post_password_reset(&new_password_form(&token, &new_password))
+        .await;
+    assert_is_redirect_to(&response, "/login");
+
+    // the session from before the reset is gone
+    let response = app.get_admin_dashboard().await;
+    assert_is_redirect_to(&response, "/login");
+
+    let response = app
+        .post_login(&json!({
+            "username": &app.user.username,
+            "password": &app.user.password,
+        }))
+        .await;
+    assert_is_redirect_to(&response, "/login");
+    let response = app
+        .post_login(&json!({
+            "username": &app.user.username,
+            "password": &new_password,
+        }))
+        .await;
+    assert_is_redirect_to(&response, "/admin/dashboard");
+}
+
+#[tokio::test]
+async fn reset_links_only_work_once() {
+    let app = spawn_app().await;
+    set_user_email(&app).await;
+    let token = request_reset_token(&app, &app.user.username).await;
+
+    let response = app
+        .post_password_reset(&new_password_form(&token, &Uuid::new_v4().to_string()))
+        .await;
+    assert_is_redirect_to(&response, "/login");
+    let response = app
+        .post_password_reset(&new_password_form(&token, &Uuid::new_v4().to_string()))
+        .await;
+    assert_is_redirect_to(&response, "/password_reset");
+
+    let html_page = app
+        .get_html(&format!("{}/password_reset/confirm?token={}", app.address, token))
+        .await;
+    assert!(html_page.contains("expired or was already used"));
+}
+
+#[tokio::test]
+async fn only_a_hash_of_the_reset_token_is_stored() {
+    let app = spawn_app().await;
+    set_user_email(&app).await;
+    let token = request_reset_token(&app, EMAIL).await;
+
+    let stored = sqlx::query_scalar!("SELECT reset_token_hash FROM password_reset_tokens")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_ne!(stored, token);
+    assert_eq!(stored.len(), 64);
+}
+
+#[tokio::test]
+async fn a_failed_reset_leaves_the_link_and_the_password_alone() {
+    let app = spawn_app().await;
+    set_user_email(&app).await;
+    let token = request_reset_token(&app, EMAIL).await;
+    let password_hash = || {
+        sqlx::query_scalar!(
+            "SELECT password_hash FROM users WHERE user_id = $1",
+            app.user.user_id
+        )
+        .fetch_one(&app.db_pool)
+    };
+    let old_hash = password_hash().await.unwrap();
+    // the audit entry is the last write of the reset
+    sqlx::query!("ALTER TABLE audit_log DROP COLUMN changes")
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+
+    let response = app
+        .post_password_reset(&new_password_form(&token, &Uuid::new_v4().to_string()))
+        .await;
+    assert_eq!(response.status().as_u16(), 500);
+
+    let html_page = app
+        .get_html(&format!(
+            "{}/password_reset/confirm?token={}",
+            app.address, token
+        ))
+        .await;
+    assert!(html_page.contains(r#"name="new_password""#));
+    assert_eq!(password_hash().await.unwrap(), old_hash);
+}
+
+#[tokio::test]
+async fn expired_reset_links_are_rejected() {
+    let app = spawn_app().await;
+    set_user_email(&app).await;
+    let token = request_reset_token(&app, EMAIL).await;
+    sqlx::query!(
+        "UPDATE password_reset_tokens SET created_at = now() - interval '1 hour'"
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+
+    let response = app
+        .post_password_reset(&new_password_form(&token, &Uuid::new_v4().to_string()))
+        .await;
+    assert_is_redirect_to(&response, "/password_reset");
+}
+
+#[tokio::test]
+async fn mismatched_passwords_leave_the_link_usable() {
+    let app = spawn_app().await;
+    set_user_email(&app).await;
+    let token = request_reset_token(&app, EMAIL).await;
+
+    let password = Uuid::new_v4().to_string();
+    let response = app
+        .post_password_reset(&json!({
+            "token": &token,
+            "new_password": &password,
+            "confirm_new_password": Uuid::new_v4().to_string(),
+        }))
+        .await;
+    assert_is_redirect_to(
+        &response,
+        &format!("/password_reset/confirm?token={}", token),
+    );
+
+    let response = app
+        .post_password_reset(&new_password_form(&token, &password))
+        .await;
+    assert_is_redirect_to(&response, "/login");
+}
+
+#[tokio::test]
+async fn new_passwords_must_follow_the_policy() {
+    let app = spawn_app().await;
+    set_user_email(&app).await;
+    let token = request_reset_token(&app, EMAIL).await;
+
+    let response = app
+        .post_password_reset(&new_password_form(&token, "password123"))
+        .await;
+    let confirm_path = format!("/password_reset/confirm?token={}", token);
+    assert_is_redirect_to(&response, &confirm_path);
+    let html_page = app
+        .get_html(&format!("{}{}", app.address, confirm_path))
+        .await;
+    assert!(html_page.contains("Passwords must be at least 12 characters long"));
+
+    // the link wasn't used up by the rejected attempt
+    let response = app
+        .post_password_reset(&new_password_form(&token, &Uuid::new_v4().to_string()))
+        .await;
+    assert_is_redirect_to(&response, "/login");
+}
+
+#[tokio::test]
+async fn unknown_accounts_get_the_same_answer_and_no_email() {
+    let app = spawn_app().await;
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    let response = app.post_password_reset_request("nobody@example.com").await;
+    assert_is_redirect_to(&response, "/password_reset");
+    let html_page = app
+        .get_html(&format!("{}/password_reset", app.address))
+        .await;
+    assert!(html_page.contains("If that account exists"));
+
+    // users without an email can't be reset either
+    let response = app.post_password_reset_request(&app.user.username).await;
+    assert_is_redirect_to(&response, "/password_reset");
+    app.dispatch_all_pending_reset_requests().await;
+}
+
+#[tokio::test]
+async fn reset_emails_are_sent_by_the_worker_not_the_request() {
+    let app = spawn_app().await;
+    set_user_email(&app).await;
+    // a failing email provider can't tell existing accounts apart either
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(500))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    let response = app.post_password_reset_request(EMAIL).await;
+    assert_is_redirect_to(&response, "/password_reset");
+    let n_sent = app.email_server.received_requests().await.unwrap().len();
+    assert_eq!(n_sent, 0);
+
+    app.dispatch_all_pending_reset_requests().await;
+}
+
+#[tokio::test]
+async fn reset_emails_are_limited_per_user() {
+    let app = spawn_app().await;
+    set_user_email(&app).await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(3)
+        .mount(&app.email_server)
+        .await;
+
+    // by username and by email alike
+    for identifier in [EMAIL, &app.user.username, EMAIL, &app.user.username] {
+        let response = app.post_password_reset_request(identifier).await;
+        assert_is_redirect_to(&response, "/password_reset");
+    }
+    app.dispatch_all_pending_reset_requests().await;
+}
+
+#[tokio::test]
+async fn reset_requests_are_limited_per_ip() {
+    let app = spawn_app().await;
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    for i in 0..10 {
+        app.post_password_reset_request(&format!("nobody{i}@example.com"))
+            .await;
+    }
+    let response = app.post_password_reset_request("nobody@example.com").await;
+    assert_is_redirect_to(&response, "/password_reset");
+    let n_queued =
+        sqlx::query_scalar!(r#"SELECT count(*) as "count!" FROM password_reset_request_queue"#)
+            .fetch_one(&app.db_pool)
+            .await
+            .unwrap();
+    assert_eq!(n_queued, 10);
+}
+
+#[tokio::test]
+async fn admins_can_set_the_email_reset_links_go_to() {
+    let app = spawn_app().await;
+    app.post_login(&json!({
+        "username": &app.user.username,
+        "password": &app.user.password,
+    }))
+    .await;
+
+    let response = app
+        .post_admin_form("/password/email", &json!({ "email": "Admin@Example.com" }))
+        .await;
+    assert_is_redirect_to(&response, "/admin/password");
+
+    let html_page = app.get_change_password_html().await;
+    assert!(html_page.contains("<p><i>Email updated</i></p>"));
+    assert!(html_page.contains(EMAIL));
+}
@@ -0,0 +1,144 @@
+use crate::helpers::{spawn_app, TestApp};
+
+const HARD_BOUNCE: &str = include_str!("fixtures/postmark/hard_bounce.json");
+const SPAM_COMPLAINT: &str = include_str!("fixtures/postmark/spam_complaint.json");
+const SOFT_BOUNCE: &str = include_str!("fixtures/postmark/soft_bounce.json");
+
+/// the fixtures are all about this address
+async fn subscription_status(app: &TestApp) -> String {
+    sqlx::query_scalar!("SELECT status FROM subscriptions WHERE email = 'ursula_le_guin@gmail.com'")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+}
+
+async fn enqueue_delivery(app: &TestApp) {
+    let issue_id = uuid::Uuid::new_v4();
+    sqlx::query!(
+        r#"
+        INSERT INTO newsletter_issues(issue_id, title, text_content, html_content, published_at)
+        VALUES ($1, 'an issue', 'text', '<p>html</p>', now()::text)
+        "#,
+        issue_id
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+    sqlx::query!(
+        "INSERT INTO issue_delivery_queue(issue_id, email) VALUES ($1, 'ursula_le_guin@gmail.com')",
+        issue_id
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+}
+
+#[tokio::test]
+async fn webhook_requires_credentials() {
+    let app = spawn_app().await;
+
+    let response = app
+        .app_client
+        .post(format!("{}/webhooks/postmark", &app.address))
+        .basic_auth(&app.webhooks.postmark_username, Some("wrong-password"))
+        .body(HARD_BOUNCE)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status().as_u16(), 401);
+
+    let response = app
+        .app_client
+        .post(format!("{}/webhooks/postmark", &app.address))
+        .body(HARD_BOUNCE)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status().as_u16(), 401);
+    assert_eq!(
+        response.headers()["WWW-Authenticate"],
+        r#"Basic realm="webhooks""#
+    );
+}
+
+#[tokio::test]
+async fn hard_bounces_mark_the_subscriber_bounced_and_drop_pending_deliveries() {
+    let app = spawn_app().await;
+    app.create_subscriber("le guin", "ursula_le_guin@gmail.com").await;
+    enqueue_delivery(&app).await;
+
+    let response = app.post_postmark_webhook(HARD_BOUNCE).await;
+
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(subscription_status(&app).await, "bounced");
+    let n_pending = sqlx::query_scalar!(r#"SELECT count(*) as "count!" FROM issue_delivery_queue"#)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(n_pending, 0);
+    let event = sqlx::query!("SELECT record_type, bounce_type, payload FROM email_events")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(event.record_type, "Bounce");
+    assert_eq!(event.bounce_type.as_deref(), Some("HardBounce"));
+    assert_eq!(event.payload["MessageID"], "883953f4-6105-42a2-a16a-77a8eac79483");
+    let reason = sqlx::query_scalar!(
+        "SELECT reason FROM suppressions WHERE entry = 'ursula_le_guin@gmail.com'"
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .unwrap();
+    assert_eq!(reason, "hard bounce");
+}
+
+#[tokio::test]
+async fn events_match_the_subscriber_regardless_of_case() {
+    let app = spawn_app().await;
+    app.create_subscriber("le guin", "Ursula_Le_Guin@gmail.com").await;
+
+    app.post_postmark_webhook(HARD_BOUNCE).await;
+
+    let status = sqlx::query_scalar!("SELECT status FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(status, "bounced");
+}
+
+#[tokio::test]
+async fn spam_complaints_mark_the_subscriber_complained() {
+    let app = spawn_app().await;
+    app.create_subscriber("le guin", "ursula_le_guin@gmail.com").await;
+
+    let response = app.post_postmark_webhook(SPAM_COMPLAINT).await;
+
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(subscription_status(&app).await, "complained");
+}
+
+#[tokio::test]
+async fn soft_bounces_are_acknowledged_but_ignored() {
+    let app = spawn_app().await;
+    app.create_subscriber("le guin", "ursula_le_guin@gmail.com").await;
+    enqueue_delivery(&app).await;
+
+    let response = app.post_postmark_webhook(SOFT_BOUNCE).await;
+
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(subscription_status(&app).await, "pending_confirmation");
+    let n_pending = sqlx::query_scalar!(r#"SELECT count(*) as "count!" FROM issue_delivery_queue"#)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(n_pending, 1);
+}
+
+#[tokio::test]
+async fn malformed_payloads_are_rejected() {
+    let app = spawn_app().await;
+
+    let response = app.post_postmark_webhook(r#"{"RecordType": "Bounce"}"#).await;
+
+    assert_eq!(response.status().as_u16(), 400);
+}
@@ -0,0 +1,136 @@
+use crate::helpers::{assert_is_redirect_to, spawn_app, TestApp};
+use reqwest::redirect::Policy;
+use serde_json::json;
+use uuid::Uuid;
+
+/// a second browser logged in as the same user
+async fn log_in_elsewhere(app: &TestApp) -> reqwest::Client {
+    let client = reqwest::Client::builder()
+        .redirect(Policy::none())
+        .cookie_store(true)
+        .user_agent("OtherBrowser/1.0")
+        .build()
+        .unwrap();
+    let response = client
+        .post(format!("{}/login", &app.address))
+        .form(&json!({
+            "username": &app.user.username,
+            "password": &app.user.password,
+        }))
+        .send()
+        .await
+        .unwrap();
+    assert_is_redirect_to(&response, "/admin/dashboard");
+    client
+}
+
+async fn dashboard_status(app: &TestApp, client: &reqwest::Client) -> reqwest::Response {
+    client
+        .get(format!("{}/admin/dashboard", &app.address))
+        .send()
+        .await
+        .unwrap()
+}
+
+async fn session_ids(app: &TestApp) -> Vec<Uuid> {
+    sqlx::query_scalar!(
+        "SELECT session_id FROM user_sessions WHERE user_id = $1 AND revoked_at IS NULL ORDER BY created_at",
+        app.user.user_id
+    )
+    .fetch_all(&app.db_pool)
+    .await
+    .unwrap()
+}
+
+#[tokio::test]
+async fn sessions_page_lists_every_login() {
+    let app = spawn_app().await;
+    app.login_as(&app.user).await;
+    log_in_elsewhere(&app).await;
+
+    let html_page = app.get_html(&format!("{}/admin/sessions", &app.address)).await;
+    assert!(html_page.contains("<td>127.0.0.1</td>"));
+    assert!(html_page.contains("OtherBrowser/1.0"));
+    assert!(html_page.contains("this session"));
+    assert_eq!(html_page.matches("/revoke\" method=\"post\"").count(), 1);
+}
+
+#[tokio::test]
+async fn a_single_session_can_be_logged_out_remotely() {
+    let app = spawn_app().await;
+    app.login_as(&app.user).await;
+    let other = log_in_elsewhere(&app).await;
+    let other_session_id = session_ids(&app).await[1];
+
+    let response = app
+        .post_admin_form(&format!("/sessions/{}/revoke", other_session_id), &json!({}))
+        .await;
+    assert_is_redirect_to(&response, "/admin/sessions");
+
+    assert_is_redirect_to(&dashboard_status(&app, &other).await, "/login");
+    assert_eq!(app.get_admin_dashboard().await.status().as_u16(), 200);
+}
+
+#[tokio::test]
+async fn sessions_of_other_users_cannot_be_ended() {
+    let app = spawn_app().await;
+    let other = log_in_elsewhere(&app).await;
+    let session_id = session_ids(&app).await[0];
+
+    let intruder = crate::helpers::TestUser::generate();
+    intruder.store(&app.db_pool).await;
+    app.login_as(&intruder).await;
+    app.post_admin_form(&format!("/sessions/{}/revoke", session_id), &json!({}))
+        .await;
+
+    assert!(app
+        .get_html(&format!("{}/admin/sessions", &app.address))
+        .await
+        .contains("That session had already ended"));
+    assert_eq!(dashboard_status(&app, &other).await.status().as_u16(), 200);
+}
+
+#[tokio::test]
+async fn logging_out_other_sessions_keeps_the_current_one() {
+    let app = spawn_app().await;
+    app.login_as(&app.user).await;
+    let first = log_in_elsewhere(&app).await;
+    let second = log_in_elsewhere(&app).await;
+
+    let response = app.post_admin_form("/sessions/revoke_others", &json!({})).await;
+    assert_is_redirect_to(&response, "/admin/sessions");
+
+    assert_is_redirect_to(&dashboard_status(&app, &first).await, "/login");
+    assert_is_redirect_to(&dashboard_status(&app, &second).await, "/login");
+    assert_eq!(app.get_admin_dashboard().await.status().as_u16(), 200);
+}
+
+#[tokio::test]
+async fn logging_out_ends_the_session_record() {
+    let app = spawn_app().await;
+    app.login_as(&app.user).await;
+    assert_eq!(session_ids(&app).await.len(), 1);
+
+    app.post_logout().await;
+    assert!(session_ids(&app).await.is_empty());
+}
+
+#[tokio::test]
+async fn changing_the_password_logs_out_other_sessions() {
+    let app = spawn_app().await;
+    app.login_as(&app.user).await;
+    let other = log_in_elsewhere(&app).await;
+
+    let new_password = Uuid::new_v4().to_string();
+    let response = app
+        .post_change_password(&json!({
+            "old_password": &app.user.password,
+            "new_password": &new_password,
+            "confirm_new_password": &new_password,
+        }))
+        .await;
+    assert_is_redirect_to(&response, "/admin/password");
+
+    assert_is_redirect_to(&dashboard_status(&app, &other).await, "/login");
+    assert_eq!(app.get_admin_dashboard().await.status().as_u16(), 200);
+}
@@ -0,0 +1,219 @@
+use wiremock::{
+    matchers::{any, method, path},
+    Mock, ResponseTemplate,
+};
+
+use crate::helpers::{spawn_app, spawn_app_with, TestApp};
+
+async fn request_data_links(app: &TestApp) -> (reqwest::Url, reqwest::Url) {
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    let response = app.post_data_request("ursula_le_guin@gmail.com").await;
+    assert_eq!(response.status().as_u16(), 200);
+    app.dispatch_all_pending_data_requests().await;
+
+    let email_request = app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+    app.get_data_links(&email_request)
+}
+
+#[tokio::test]
+async fn unknown_addresses_get_the_same_answer_but_no_email() {
+    let app = spawn_app().await;
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    let response = app.post_data_request("nobody@gmail.com").await;
+
+    assert_eq!(response.status().as_u16(), 200);
+    assert!(response
+        .text()
+        .await
+        .unwrap()
+        .contains("If this address is subscribed"));
+    app.dispatch_all_pending_data_requests().await;
+}
+
+#[tokio::test]
+async fn data_links_are_emailed_by_the_worker_not_the_request() {
+    let app = spawn_app().await;
+    app.create_subscriber("le guin", "ursula_le_guin@gmail.com").await;
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    let response = app.post_data_request("Ursula_Le_Guin@gmail.com").await;
+
+    assert_eq!(response.status().as_u16(), 200);
+    let n_queued =
+        sqlx::query_scalar!(r#"SELECT count(*) as "count!" FROM subscriber_data_request_queue"#)
+            .fetch_one(&app.db_pool)
+            .await
+            .unwrap();
+    assert_eq!(n_queued, 1);
+}
+
+#[tokio::test]
+async fn only_a_hash_of_the_data_token_is_stored() {
+    let app = spawn_app().await;
+    app.create_subscriber("le guin", "ursula_le_guin@gmail.com").await;
+    let (download_link, _) = request_data_links(&app).await;
+    let token = download_link
+        .query_pairs()
+        .find(|(k, _)| k == "token")
+        .unwrap()
+        .1
+        .into_owned();
+
+    let stored = sqlx::query_scalar!("SELECT data_token_hash FROM subscriber_data_tokens")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_ne!(stored, token);
+    assert_eq!(stored.len(), 64);
+}
+
+#[tokio::test]
+async fn data_requests_are_rate_limited_per_address_and_per_ip() {
+    let app = spawn_app_with(|c| {
+        c.signup.max_attempts_per_email = 1;
+        c.signup.max_attempts_per_ip = 3;
+    })
+    .await;
+
+    let response = app.post_data_request("ursula_le_guin@gmail.com").await;
+    assert_eq!(response.status().as_u16(), 200);
+    let response = app.post_data_request("ursula_le_guin@gmail.com").await;
+    assert_eq!(response.status().as_u16(), 429);
+
+    let response = app.post_data_request("octavia_butler@gmail.com").await;
+    assert_eq!(response.status().as_u16(), 200);
+    let response = app.post_data_request("n_k_jemisin@gmail.com").await;
+    assert_eq!(response.status().as_u16(), 429);
+}
+
+#[tokio::test]
+async fn subscribers_can_download_their_data() {
+    let app = spawn_app().await;
+    app.create_subscriber("le guin", "ursula_le_guin@gmail.com").await;
+    let (download_link, _) = request_data_links(&app).await;
+
+    let response = reqwest::get(download_link).await.unwrap();
+
+    assert_eq!(response.status().as_u16(), 200);
+    let data: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(data["subscription"]["email"], "ursula_le_guin@gmail.com");
+    assert_eq!(data["subscription"]["status"], "pending_confirmation");
+    assert_eq!(data["subscription_tokens"].as_array().unwrap().len(), 1);
+    assert_eq!(data["consent_events"][0]["kind"], "subscribe");
+}
+
+#[tokio::test]
+async fn invalid_data_tokens_are_rejected() {
+    let app = spawn_app().await;
+
+    let response = reqwest::get(format!(
+        "{}/subscriptions/data?token=not-a-token",
+        app.address
+    ))
+    .await
+    .unwrap();
+    assert_eq!(response.status().as_u16(), 401);
+
+    let response = app.post_erasure("not-a-token").await;
+    assert_eq!(response.status().as_u16(), 401);
+}
+
+#[tokio::test]
+async fn erasing_removes_the_subscriber_and_records_the_erasure() {
+    let app = spawn_app().await;
+    app.create_subscriber("le guin", "ursula_le_guin@gmail.com").await;
+    let (_, erase_link) = request_data_links(&app).await;
+
+    // following the link only shows a confirmation page
+    let response = reqwest::get(erase_link.clone()).await.unwrap();
+    assert_eq!(response.status().as_u16(), 200);
+    let n_subscribers = sqlx::query_scalar!(r#"SELECT count(*) as "count!" FROM subscriptions"#)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(n_subscribers, 1);
+
+    let token = erase_link
+        .query_pairs()
+        .find(|(k, _)| k == "token")
+        .unwrap()
+        .1
+        .into_owned();
+    let response = app.post_erasure(&token).await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    let n_subscribers = sqlx::query_scalar!(r#"SELECT count(*) as "count!" FROM subscriptions"#)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(n_subscribers, 0);
+
+    let erasure = sqlx::query!("SELECT requested_by, user_id, deleted_rows FROM subscriber_erasures")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(erasure.requested_by, "subscriber");
+    assert_eq!(erasure.user_id, None);
+    assert_eq!(erasure.deleted_rows["subscriptions"], 1);
+    assert_eq!(erasure.deleted_rows["subscription_tokens"], 1);
+    assert_eq!(erasure.deleted_rows["consent_events"], 1);
+
+    // the token was erased along with everything else
+    let response = app.post_erasure(&token).await;
+    assert_eq!(response.status().as_u16(), 401);
+}
+
+#[tokio::test]
+async fn email_events_are_found_regardless_of_case() {
+    let app = spawn_app().await;
+    app.create_subscriber("le guin", "ursula_le_guin@gmail.com").await;
+    // postmark reports the address however the receiving server spelled it
+    let hard_bounce = include_str!("fixtures/postmark/hard_bounce.json")
+        .replace("ursula_le_guin@gmail.com", "Ursula_Le_Guin@Gmail.com");
+    let response = app.post_postmark_webhook(&hard_bounce).await;
+    assert_eq!(response.status().as_u16(), 200);
+    let (download_link, erase_link) = request_data_links(&app).await;
+
+    let data: serde_json::Value = reqwest::get(download_link)
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(data["email_events"].as_array().unwrap().len(), 1);
+
+    let token = erase_link
+        .query_pairs()
+        .find(|(k, _)| k == "token")
+        .unwrap()
+        .1
+        .into_owned();
+    let response = app.post_erasure(&token).await;
+    assert_eq!(response.status().as_u16(), 200);
+    let n_events = sqlx::query_scalar!(r#"SELECT count(*) as "count!" FROM email_events"#)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(n_events, 0);
+}
@@ -0,0 +1,143 @@
+use serde_json::Value;
+use uuid::Uuid;
+
+use crate::helpers::{assert_is_redirect_to, spawn_app, TestApp};
+
+async fn insert_subscriber(app: &TestApp, email: &str, status: &str, subscribed_at: &str) {
+    sqlx::query!(
+        r#"
+        INSERT INTO subscriptions(id, email, name, subscribed_at, status)
+        VALUES ($1, $2, 'reader', $3::text::timestamptz, $4)
+        "#,
+        Uuid::new_v4(),
+        email,
+        subscribed_at,
+        status,
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+}
+
+async fn seed(app: &TestApp) {
+    insert_subscriber(app, "january@gmail.com", "confirmed", "2025-01-15T10:00:00Z").await;
+    insert_subscriber(app, "february@gmail.com", "pending_confirmation", "2025-02-15T10:00:00Z").await;
+    insert_subscriber(app, "march@gmail.com", "confirmed", "2025-03-15T10:00:00Z").await;
+}
+
+#[tokio::test]
+async fn must_be_logged_in_to_export_subscribers() {
+    let app = spawn_app().await;
+
+    let response = app.get_subscriber_export("").await;
+    assert_is_redirect_to(&response, "/login");
+}
+
+#[tokio::test]
+async fn subscribers_are_exported_as_csv_by_default() {
+    let app = spawn_app().await;
+    app.login_as(&app.user).await;
+    seed(&app).await;
+
+    let response = app.get_subscriber_export("").await;
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(response.headers()["Content-Type"], "text/csv");
+    assert_eq!(
+        response.text().await.unwrap(),
+        "email,name,status,subscribed_at,bounces,complaints,last_email_event_at,pending_deliveries\n\
+        january@gmail.com,reader,confirmed,2025-01-15T10:00:00+00:00,0,0,,0\n\
+        february@gmail.com,reader,pending_confirmation,2025-02-15T10:00:00+00:00,0,0,,0\n\
+        march@gmail.com,reader,confirmed,2025-03-15T10:00:00+00:00,0,0,,0\n"
+    );
+}
+
+#[tokio::test]
+async fn ndjson_export_can_be_filtered_by_status_and_date() {
+    let app = spawn_app().await;
+    app.login_as(&app.user).await;
+    seed(&app).await;
+
+    let response = app
+        .get_subscriber_export("format=ndjson&status=confirmed&from=2025-02-01&to=2025-03-15")
+        .await;
+    assert_eq!(response.headers()["Content-Type"], "application/x-ndjson");
+
+    let body = response.text().await.unwrap();
+    let rows: Vec<Value> = body
+        .lines()
+        .map(|l| serde_json::from_str(l).unwrap())
+        .collect();
+    assert_eq!(rows.len(), 1);
+    assert_eq!(rows[0]["email"], "march@gmail.com");
+    assert_eq!(rows[0]["status"], "confirmed");
+}
+
+#[tokio::test]
+async fn engagement_is_exported_with_each_subscriber() {
+    let app = spawn_app().await;
+    app.login_as(&app.user).await;
+    seed(&app).await;
+    sqlx::query!(
+        r#"
+        INSERT INTO email_events(event_id, email, record_type, bounce_type, payload, received_at)
+        VALUES
+            (gen_random_uuid(), 'January@gmail.com', 'Bounce', 'SoftBounce', '{}', '2025-04-01T10:00:00Z'),
+            (gen_random_uuid(), 'january@gmail.com', 'SpamComplaint', NULL, '{}', '2025-04-02T10:00:00Z')
+        "#
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+    let issue_id = Uuid::new_v4();
+    sqlx::query!(
+        r#"
+        INSERT INTO newsletter_issues(issue_id, title, text_content, html_content, published_at)
+        VALUES ($1, 'title', 'text', 'html', now())
+        "#,
+        issue_id
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+    sqlx::query!(
+        "INSERT INTO issue_delivery_queue(issue_id, email) VALUES ($1, 'march@gmail.com')",
+        issue_id
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+
+    let body = app
+        .get_subscriber_export("format=ndjson")
+        .await
+        .text()
+        .await
+        .unwrap();
+    let rows: Vec<Value> = body
+        .lines()
+        .map(|l| serde_json::from_str(l).unwrap())
+        .collect();
+    assert_eq!(rows[0]["email"], "january@gmail.com");
+    assert_eq!(rows[0]["bounces"], 1);
+    assert_eq!(rows[0]["complaints"], 1);
+    assert_eq!(rows[0]["last_email_event_at"], "2025-04-02T10:00:00+00:00");
+    assert_eq!(rows[0]["pending_deliveries"], 0);
+    assert_eq!(rows[1]["last_email_event_at"], Value::Null);
+    assert_eq!(rows[2]["pending_deliveries"], 1);
+}
+
+#[tokio::test]
+async fn invalid_export_parameters_are_rejected() {
+    let app = spawn_app().await;
+    app.login_as(&app.user).await;
+
+    let test_cases = vec![
+        ("format=xml", "unknown format"),
+        ("status=sleeping", "unknown status"),
+        ("from=15/01/2025", "badly formatted date"),
+    ];
+    for (query, error_message) in test_cases {
+        let response = app.get_subscriber_export(query).await;
+        assert_eq!(response.status().as_u16(), 400, "{}", error_message);
+    }
+}
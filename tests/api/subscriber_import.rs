@@ -0,0 +1,240 @@
+use wiremock::{
+    matchers::{method, path},
+    Mock, ResponseTemplate,
+};
+
+use crate::helpers::{assert_is_redirect_to, spawn_app, TestApp};
+
+async fn import_id(app: &TestApp) -> uuid::Uuid {
+    sqlx::query_scalar!("SELECT import_id FROM subscriber_imports")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+}
+
+#[tokio::test]
+async fn must_be_logged_in_to_import_subscribers() {
+    let app = spawn_app().await;
+
+    let response = app
+        .post_subscriber_import("email,name\nursula@gmail.com,le guin\n", "confirmed")
+        .await;
+    assert_is_redirect_to(&response, "/login");
+}
+
+#[tokio::test]
+async fn csv_without_required_columns_is_rejected() {
+    let app = spawn_app().await;
+    app.login_as(&app.user).await;
+
+    let response = app
+        .post_subscriber_import("address\nursula@gmail.com\n", "confirmed")
+        .await;
+    assert_is_redirect_to(&response, "/admin/subscribers/imports");
+
+    let html_page = app
+        .app_client
+        .get(format!("{}/admin/subscribers/imports", app.address))
+        .send()
+        .await
+        .unwrap()
+        .text()
+        .await
+        .unwrap();
+    assert!(html_page.contains("the CSV file must have `email` and `name` columns"));
+}
+
+#[tokio::test]
+async fn error_messages_are_escaped() {
+    let app = spawn_app().await;
+    app.login_as(&app.user).await;
+
+    let response = app
+        .post_subscriber_import("email,name\nursula@gmail.com,le guin\n", "<b>confirmed</b>")
+        .await;
+    assert_is_redirect_to(&response, "/admin/subscribers/imports");
+
+    let html_page = app
+        .get_html(&format!("{}/admin/subscribers/imports", app.address))
+        .await;
+    assert!(html_page.contains("unknown import mode `&lt;b&gt;confirmed&lt;/b&gt;`"));
+}
+
+#[tokio::test]
+async fn import_as_confirmed_validates_and_deduplicates_rows() {
+    let app = spawn_app().await;
+    app.login_as(&app.user).await;
+
+    // nobody gets a confirmation email in this mode
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    let csv = "email,name\n\
+        ursula@gmail.com,le guin\n\
+        not-an-email,bad email\n\
+        octavia@gmail.com,\n\
+        ursula@gmail.com,le guin again\n\
+        ned@gmail.com,ned\n";
+    let response = app.post_subscriber_import(csv, "confirmed").await;
+    assert_is_redirect_to(&response, "/admin/subscribers/imports");
+
+    app.dispatch_all_pending_imports().await;
+
+    let statuses = sqlx::query!("SELECT email, status FROM subscriptions ORDER BY email")
+        .fetch_all(&app.db_pool)
+        .await
+        .unwrap();
+    let statuses: Vec<_> = statuses
+        .iter()
+        .map(|r| (r.email.as_str(), r.status.as_str()))
+        .collect();
+    assert_eq!(
+        statuses,
+        vec![("ned@gmail.com", "confirmed"), ("ursula@gmail.com", "confirmed")]
+    );
+
+    let summary = sqlx::query!(
+        "SELECT total_rows, imported_rows, duplicate_rows, rejected_rows FROM subscriber_imports"
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .unwrap();
+    assert_eq!(summary.total_rows, 5);
+    assert_eq!(summary.imported_rows, 2);
+    assert_eq!(summary.duplicate_rows, 1);
+    assert_eq!(summary.rejected_rows, 2);
+
+    // rejected rows come back as a csv report with their reasons
+    let response = app
+        .app_client
+        .get(format!(
+            "{}/admin/subscribers/imports/{}/report",
+            app.address,
+            import_id(&app).await
+        ))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.headers()["Content-Type"], "text/csv");
+    let report = response.text().await.unwrap();
+    assert_eq!(
+        report,
+        "row,email,name,reason\n\
+        3,not-an-email,bad email,invalid email provided\n\
+        4,octavia@gmail.com,,invalid subscriber name !\n"
+    );
+}
+
+#[tokio::test]
+async fn double_opt_in_import_sends_confirmation_emails() {
+    let app = spawn_app().await;
+    app.login_as(&app.user).await;
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(2)
+        .mount(&app.email_server)
+        .await;
+
+    let csv = "email,name\nursula@gmail.com,le guin\nned@gmail.com,ned\n";
+    app.post_subscriber_import(csv, "double_opt_in").await;
+    app.dispatch_all_pending_imports().await;
+
+    let n_pending = sqlx::query_scalar!(
+        r#"SELECT count(*) as "count!" FROM subscriptions WHERE status = 'pending_confirmation'"#
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .unwrap();
+    assert_eq!(n_pending, 2);
+
+    // the emailed link confirms the imported subscriber
+    let email_request = &app.email_server.received_requests().await.unwrap()[0];
+    let links = app.get_confirmation_links(email_request);
+    reqwest::get(links.text)
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+    let n_confirmed = sqlx::query_scalar!(
+        r#"SELECT count(*) as "count!" FROM subscriptions WHERE status = 'confirmed'"#
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .unwrap();
+    assert_eq!(n_confirmed, 1);
+}
+
+#[tokio::test]
+async fn rows_whose_confirmation_email_fails_are_rejected() {
+    let app = spawn_app().await;
+    app.login_as(&app.user).await;
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(500))
+        .mount(&app.email_server)
+        .await;
+
+    app.post_subscriber_import("email,name\nursula@gmail.com,le guin\n", "double_opt_in")
+        .await;
+    app.dispatch_all_pending_imports().await;
+
+    let n_subscribers = sqlx::query_scalar!(r#"SELECT count(*) as "count!" FROM subscriptions"#)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(n_subscribers, 0);
+
+    let reason = sqlx::query_scalar!("SELECT reason FROM subscriber_import_rejections")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(reason, "failed to send confirmation email");
+}
+
+#[tokio::test]
+async fn addresses_differing_only_in_case_are_duplicates() {
+    let app = spawn_app().await;
+    app.login_as(&app.user).await;
+
+    let csv = "email,name\nUrsula@gmail.com,le guin\nursula@gmail.com,le guin again\n";
+    app.post_subscriber_import(csv, "confirmed").await;
+    app.dispatch_all_pending_imports().await;
+
+    let emails = sqlx::query_scalar!("SELECT email FROM subscriptions")
+        .fetch_all(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(emails, vec!["Ursula@gmail.com"]);
+    let duplicate_rows = sqlx::query_scalar!("SELECT duplicate_rows FROM subscriber_imports")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(duplicate_rows, 1);
+}
+
+#[tokio::test]
+async fn imported_subscribers_get_a_consent_record() {
+    let app = spawn_app().await;
+    app.login_as(&app.user).await;
+
+    app.post_subscriber_import("email,name\nursula@gmail.com,le guin\n", "confirmed")
+        .await;
+    app.dispatch_all_pending_imports().await;
+
+    let event = sqlx::query!("SELECT kind, source FROM consent_events")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(event.kind, "import");
+    assert_eq!(
+        event.source,
+        format!("subscriber_import:{}", import_id(&app).await)
+    );
+}
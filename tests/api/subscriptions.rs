@@ -1,6 +1,5 @@
-use crate::helpers::spawn_app;
-use tokio;
-use wiremock::matchers::{method, path};
+use crate::helpers::{spawn_app, spawn_app_with};
+use wiremock::matchers::{any, method, path};
 use wiremock::{Mock, ResponseTemplate};
 
 // check user subscribe
@@ -56,7 +55,7 @@ async fn subscribe_sends_confirmation_email_with_a_link() {
     let _ = app.post_subscriptions(body).await;
     let email_request = &app.email_server.received_requests().await.unwrap()[0];
 
-    let links = app.get_confirmation_links(&email_request);
+    let links = app.get_confirmation_links(email_request);
 
     assert_eq!(links.html, links.text);
 }
@@ -110,3 +109,114 @@ async fn drop_token_column_throws_storetokenerror() {
     assert_eq!(response.status().as_u16(), 500);
 }
 
+
+#[tokio::test]
+async fn filled_in_honeypot_is_silently_ignored() {
+    let app = spawn_app().await;
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    let response = app
+        .post_subscriptions("name=le%20guin&email=ursula_le_guin%40gmail.com&website=spam.example")
+        .await;
+
+    assert_eq!(response.status().as_u16(), 200);
+    let n_subscribers = sqlx::query_scalar!(r#"SELECT count(*) as "count!" FROM subscriptions"#)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(n_subscribers, 0);
+}
+
+#[tokio::test]
+async fn disposable_email_domains_are_rejected() {
+    let app = spawn_app().await;
+
+    let response = app
+        .post_subscriptions("name=le%20guin&email=ursula%40mailinator.com")
+        .await;
+
+    assert_eq!(response.status().as_u16(), 400);
+}
+
+#[tokio::test]
+async fn repeated_sign_ups_for_one_address_are_rate_limited() {
+    let app = spawn_app_with(|c| c.signup.max_attempts_per_email = 2).await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(3)
+        .mount(&app.email_server)
+        .await;
+    let body = "name=le%20guin&email=ursula_le_guin%40gmail.com";
+
+    assert_eq!(app.post_subscriptions(body).await.status().as_u16(), 200);
+    assert_eq!(app.post_subscriptions(body).await.status().as_u16(), 200);
+    assert_eq!(app.post_subscriptions(body).await.status().as_u16(), 429);
+
+    // a different address from the same client is still fine
+    let response = app
+        .post_subscriptions("name=octavia&email=octavia_butler%40gmail.com")
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+}
+
+#[tokio::test]
+async fn sign_ups_from_one_ip_are_rate_limited() {
+    let app = spawn_app_with(|c| c.signup.max_attempts_per_ip = 2).await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+
+    for (i, expected_status) in [200, 200, 429].into_iter().enumerate() {
+        let body = format!("name=reader&email=reader{}%40gmail.com", i);
+        let response = app.post_subscriptions(body).await;
+        assert_eq!(response.status().as_u16(), expected_status);
+    }
+}
+
+#[tokio::test]
+async fn forwarded_for_headers_do_not_get_around_the_ip_limit() {
+    let app = spawn_app_with(|c| c.signup.max_attempts_per_ip = 2).await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+
+    for (i, expected_status) in [200, 200, 429].into_iter().enumerate() {
+        let response = app
+            .app_client
+            .post(format!("{}/subscribe", &app.address))
+            .header("Content-Type", "application/x-www-form-urlencoded")
+            .header("X-Forwarded-For", format!("203.0.113.{}", i))
+            .body(format!("name=reader&email=reader{}%40gmail.com", i))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status().as_u16(), expected_status);
+    }
+}
+
+#[tokio::test]
+async fn honeypot_submissions_do_not_count_against_the_ip_limit() {
+    let app = spawn_app_with(|c| c.signup.max_attempts_per_ip = 1).await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+
+    app.post_subscriptions("name=bot&email=bot%40gmail.com&website=spam.example")
+        .await;
+    let response = app
+        .post_subscriptions("name=le%20guin&email=ursula_le_guin%40gmail.com")
+        .await;
+
+    assert_eq!(response.status().as_u16(), 200);
+}
@@ -1,6 +1,7 @@
 use crate::helpers::spawn_app;
 use reqwest::{self};
-use tokio;
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, ResponseTemplate};
 
 #[tokio::test]
 async fn confirmations_without_token_throws_400() {
@@ -22,7 +23,7 @@ async fn link_returned_by_subscribe_returns_200_if_called() {
     let _ = app.post_subscriptions(body).await;
     let email_request = &app.email_server.received_requests().await.unwrap()[0];
 
-    let links = app.get_confirmation_links(&email_request);
+    let links = app.get_confirmation_links(email_request);
     let confirmation_link = links.text;
 
     assert_eq!(confirmation_link.host_str().unwrap(), "127.0.0.1");
@@ -62,3 +63,54 @@ async fn clicking_link_confirms_new_user() {
     assert_eq!(saved.email, "ursula_le_guin@gmail.com");
     assert_eq!(saved.status, "confirmed");
 }
+
+#[tokio::test]
+async fn subscribing_and_confirming_record_consent_events() {
+    let app = spawn_app().await;
+    let body = "name=le%20guin&email=ursula_le_guin%40gmail.com&source=spring_campaign";
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+
+    app.app_client
+        .post(format!("{}/subscribe", &app.address))
+        .header("Content-Type", "application/x-www-form-urlencoded")
+        .header("User-Agent", "test-browser/1.0")
+        .body(body)
+        .send()
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+    let email_request = &app.email_server.received_requests().await.unwrap()[0];
+    let links = app.get_confirmation_links(email_request);
+    reqwest::get(links.text)
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+
+    let events = sqlx::query!(
+        r#"
+        SELECT kind, ip_address, user_agent, source, consent_text_version
+        FROM consent_events
+        ORDER BY occurred_at
+        "#
+    )
+    .fetch_all(&app.db_pool)
+    .await
+    .unwrap();
+
+    assert_eq!(events.len(), 2);
+    assert_eq!(events[0].kind, "subscribe");
+    assert_eq!(events[0].source, "spring_campaign");
+    assert_eq!(events[0].user_agent.as_deref(), Some("test-browser/1.0"));
+    assert_eq!(events[0].ip_address.as_deref(), Some("127.0.0.1"));
+    assert_eq!(events[1].kind, "confirm");
+    assert_eq!(events[1].source, "confirmation_link");
+    assert!(events
+        .iter()
+        .all(|e| e.consent_text_version == events[0].consent_text_version));
+}
@@ -0,0 +1,130 @@
+use serde_json::json;
+use wiremock::{
+    matchers::{any, method, path},
+    Mock, ResponseTemplate,
+};
+
+use crate::helpers::{assert_is_redirect_to, spawn_app};
+
+#[tokio::test]
+async fn must_be_logged_in_to_manage_suppressions() {
+    let app = spawn_app().await;
+
+    let response = app
+        .post_suppressions("", &json!({"entry": "example.com", "reason": ""}))
+        .await;
+
+    assert_is_redirect_to(&response, "/login");
+}
+
+#[tokio::test]
+async fn suppressions_can_be_added_imported_and_removed() {
+    let app = spawn_app().await;
+    app.login_as(&app.user).await;
+
+    let response = app
+        .post_suppressions("", &json!({"entry": "Spammer@Example.com", "reason": "legal request"}))
+        .await;
+    assert_is_redirect_to(&response, "/admin/suppressions");
+    let html_page = app.get_admin_suppressions_html().await;
+    assert!(html_page.contains("spammer@example.com is now suppressed"));
+    assert!(html_page.contains("legal request"));
+
+    app.post_suppressions(
+        "/import",
+        &json!({
+            "entries": "mailinator.com\nspammer@example.com\nnot an entry\n",
+            "reason": ""
+        }),
+    )
+    .await;
+    let html_page = app.get_admin_suppressions_html().await;
+    assert!(html_page.contains("1 suppressions added, 1 already present"));
+    assert!(html_page.contains("Skipped invalid lines: not an entry"));
+    assert!(html_page.contains("<td>mailinator.com</td>"));
+
+    app.post_suppressions("/remove", &json!({"entry": "mailinator.com"}))
+        .await;
+    let html_page = app.get_admin_suppressions_html().await;
+    assert!(html_page.contains("Suppression removed"));
+    assert!(!html_page.contains("<td>mailinator.com</td>"));
+}
+
+#[tokio::test]
+async fn suppressed_domains_get_no_confirmation_email() {
+    let app = spawn_app().await;
+    app.login_as(&app.user).await;
+    app.post_suppressions("", &json!({"entry": "gmail.com", "reason": ""}))
+        .await;
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    let response = app
+        .post_subscriptions("name=le%20guin&email=ursula_le_guin%40gmail.com")
+        .await;
+
+    // the response doesn't tell anyone the address is suppressed
+    assert_eq!(response.status().as_u16(), 200);
+}
+
+#[tokio::test]
+async fn suppressed_domains_cover_their_subdomains() {
+    let app = spawn_app().await;
+    app.login_as(&app.user).await;
+    app.post_suppressions("", &json!({"entry": "example.com", "reason": ""}))
+        .await;
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    let response = app
+        .post_subscriptions("name=le%20guin&email=ursula%40Mail.Example.com")
+        .await;
+
+    assert_eq!(response.status().as_u16(), 200);
+}
+
+#[tokio::test]
+async fn newsletters_are_not_delivered_to_suppressed_addresses() {
+    let app = spawn_app().await;
+    app.login_as(&app.user).await;
+    app.post_suppressions("", &json!({"entry": "ursula_le_guin@gmail.com", "reason": ""}))
+        .await;
+    let issue_id = uuid::Uuid::new_v4();
+    sqlx::query!(
+        r#"
+        INSERT INTO newsletter_issues(issue_id, title, text_content, html_content, published_at)
+        VALUES ($1, 'an issue', 'text', '<p>html</p>', now()::text)
+        "#,
+        issue_id
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+    sqlx::query!(
+        "INSERT INTO issue_delivery_queue(issue_id, email) VALUES ($1, 'ursula_le_guin@gmail.com')",
+        issue_id
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    app.dispatch_all_pending_emails().await;
+
+    let n_pending = sqlx::query_scalar!(r#"SELECT count(*) as "count!" FROM issue_delivery_queue"#)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(n_pending, 0);
+}
@@ -0,0 +1,201 @@
+use crate::helpers::{assert_is_redirect_to, spawn_app, spawn_app_with, TestApp};
+use serde_json::json;
+use totp_rs::{Algorithm, Secret, TOTP};
+
+fn current_code(secret: &str) -> String {
+    TOTP::new(
+        Algorithm::SHA1,
+        6,
+        0,
+        30,
+        Secret::Encoded(secret.to_string()).to_bytes().unwrap(),
+        None,
+        "test".to_string(),
+    )
+    .unwrap()
+    .generate_current()
+    .unwrap()
+}
+
+/// skips enrolment, with `last_used_step` unset so the current code is accepted
+async fn enable_totp(app: &TestApp) -> String {
+    let secret = match Secret::generate_secret().to_encoded() {
+        Secret::Encoded(secret) => secret,
+        Secret::Raw(_) => unreachable!(),
+    };
+    sqlx::query!(
+        r#"
+        INSERT INTO user_totp(user_id, secret, confirmed_at, last_used_step, created_at)
+        VALUES ($1, $2, now(), NULL, now())
+        "#,
+        app.user.user_id,
+        secret
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+    secret
+}
+
+async fn login_with_password(app: &TestApp) -> reqwest::Response {
+    app.post_login(&json!({
+        "username": &app.user.username,
+        "password": &app.user.password,
+    }))
+    .await
+}
+
+#[tokio::test]
+async fn password_alone_does_not_log_in_users_with_totp() {
+    let app = spawn_app().await;
+    enable_totp(&app).await;
+
+    let response = login_with_password(&app).await;
+    assert_is_redirect_to(&response, "/login/two_factor");
+
+    let response = app.get_admin_dashboard().await;
+    assert_is_redirect_to(&response, "/login");
+}
+
+#[tokio::test]
+async fn a_valid_totp_code_completes_the_login_only_once() {
+    let app = spawn_app().await;
+    let secret = enable_totp(&app).await;
+    let code = current_code(&secret);
+
+    login_with_password(&app).await;
+    let response = app.post_login_two_factor(&code).await;
+    assert_is_redirect_to(&response, "/admin/dashboard");
+    let html_page = app.get_admin_dashboard_html().await;
+    assert!(html_page.contains(&format!("Welcome {} !", app.user.username)));
+
+    // the same code can't be replayed for another login
+    app.post_logout().await;
+    login_with_password(&app).await;
+    let response = app.post_login_two_factor(&code).await;
+    assert_is_redirect_to(&response, "/login/two_factor");
+    assert!(app
+        .get_login_two_factor_html()
+        .await
+        .contains("<p><i>Authentication failed</i></p>"));
+}
+
+#[tokio::test]
+async fn second_step_requires_a_password_first() {
+    let app = spawn_app().await;
+    let secret = enable_totp(&app).await;
+
+    let response = app.post_login_two_factor(&current_code(&secret)).await;
+    assert_is_redirect_to(&response, "/login");
+    let response = app.get_admin_dashboard().await;
+    assert_is_redirect_to(&response, "/login");
+}
+
+#[tokio::test]
+async fn repeated_wrong_codes_send_the_user_back_to_the_password_step() {
+    let app = spawn_app_with(|c| c.login.max_failures_per_username = 2).await;
+    let secret = enable_totp(&app).await;
+
+    login_with_password(&app).await;
+    for _ in 0..2 {
+        let response = app.post_login_two_factor("000000").await;
+        assert_is_redirect_to(&response, "/login/two_factor");
+    }
+
+    // a correct code is refused once locked out
+    let response = app.post_login_two_factor(&current_code(&secret)).await;
+    assert_is_redirect_to(&response, "/login");
+    assert!(app
+        .get_login_html()
+        .await
+        .contains("Too many failed login attempts"));
+    let response = app.get_admin_dashboard().await;
+    assert_is_redirect_to(&response, "/login");
+}
+
+#[tokio::test]
+async fn enrolment_hands_out_single_use_recovery_codes() {
+    let app = spawn_app().await;
+    login_with_password(&app).await;
+
+    let response = app.post_totp("/enroll", &json!({})).await;
+    assert_is_redirect_to(&response, "/admin/totp");
+    let html_page = app.get_admin_totp_html().await;
+    assert!(html_page.contains("<svg"));
+    assert!(html_page.contains("otpauth://totp/"));
+    let secret = html_page
+        .split(r#"<code id="totp-secret">"#)
+        .nth(1)
+        .and_then(|rest| rest.split("</code>").next())
+        .unwrap()
+        .to_string();
+
+    let response = app
+        .post_totp("/confirm", &json!({ "code": current_code(&secret) }))
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+    let html_page = response.text().await.unwrap();
+    let recovery_codes: Vec<&str> = html_page
+        .split("<li><code>")
+        .skip(1)
+        .map(|rest| rest.split("</code>").next().unwrap())
+        .collect();
+    assert_eq!(recovery_codes.len(), 10);
+
+    // the codes are stored hashed
+    let n_plaintext = sqlx::query_scalar!(
+        r#"SELECT count(*) as "count!" FROM totp_recovery_codes WHERE code_hash = $1"#,
+        recovery_codes[0]
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .unwrap();
+    assert_eq!(n_plaintext, 0);
+
+    app.post_logout().await;
+    let response = login_with_password(&app).await;
+    assert_is_redirect_to(&response, "/login/two_factor");
+    let response = app.post_login_two_factor(recovery_codes[0]).await;
+    assert_is_redirect_to(&response, "/admin/dashboard");
+
+    app.post_logout().await;
+    login_with_password(&app).await;
+    let response = app.post_login_two_factor(recovery_codes[0]).await;
+    assert_is_redirect_to(&response, "/login/two_factor");
+}
+
+#[tokio::test]
+async fn disabling_totp_requires_a_code() {
+    let app = spawn_app().await;
+    let secret = enable_totp(&app).await;
+    login_with_password(&app).await;
+    app.post_login_two_factor(&current_code(&secret)).await;
+
+    let response = app.post_totp("/disable", &json!({ "code": "000000" })).await;
+    assert_is_redirect_to(&response, "/admin/totp");
+    assert!(app
+        .get_admin_totp_html()
+        .await
+        .contains("Two-factor authentication is on."));
+
+    // the login spent the current code, pretend a new 30 second step has started
+    sqlx::query!(
+        "UPDATE user_totp SET last_used_step = NULL WHERE user_id = $1",
+        app.user.user_id
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+    let response = app
+        .post_totp("/disable", &json!({ "code": current_code(&secret) }))
+        .await;
+    assert_is_redirect_to(&response, "/admin/totp");
+    assert!(app
+        .get_admin_totp_html()
+        .await
+        .contains("Two-factor authentication is off"));
+
+    app.post_logout().await;
+    let response = login_with_password(&app).await;
+    assert_is_redirect_to(&response, "/admin/dashboard");
+}
@@ -0,0 +1,269 @@
+use serde_json::json;
+use uuid::Uuid;
+use wiremock::{
+    matchers::{method, path},
+    Mock, ResponseTemplate,
+};
+use zero2prod::routes::{BodyData, Content};
+
+use crate::helpers::{assert_is_redirect_to, spawn_app, TestApp, TestUser};
+
+async fn spawn_user(app: &TestApp, role: &str) -> TestUser {
+    let user = TestUser::generate();
+    user.store_with_role(&app.db_pool, role).await;
+    user
+}
+
+fn newsletter_body() -> String {
+    serde_urlencoded::to_string(BodyData::new(
+        "Newsletter title".into(),
+        Content {
+            text: "Newsletter body as plain text".into(),
+            html: "<p>Newsletter body as HTML</p>".into(),
+        },
+    ))
+    .unwrap()
+}
+
+async fn get_status(app: &TestApp, path: &str) -> reqwest::Response {
+    app.app_client
+        .get(format!("{}/admin{}", app.address, path))
+        .send()
+        .await
+        .unwrap()
+}
+
+#[tokio::test]
+async fn viewers_can_look_but_not_change_anything() {
+    let app = spawn_app().await;
+    let viewer = spawn_user(&app, "viewer").await;
+    app.login_as(&viewer).await;
+
+    assert_eq!(get_status(&app, "/subscribers").await.status().as_u16(), 200);
+    assert_eq!(get_status(&app, "/suppressions").await.status().as_u16(), 200);
+
+    for path in ["/newsletters", "/users", "/subscribers/imports", "/subscribers/export"] {
+        let response = get_status(&app, path).await;
+        assert_is_redirect_to(&response, "/admin/dashboard");
+    }
+    let response = app
+        .post_admin_form("/suppressions", &json!({ "entry": "example.com", "reason": "" }))
+        .await;
+    assert_is_redirect_to(&response, "/admin/dashboard");
+
+    let html_page = app.get_admin_dashboard_html().await;
+    assert!(html_page.contains("<p><i>You don't have permission to do that</i></p>"));
+    assert!(html_page.contains("Your role: viewer"));
+    assert!(!html_page.contains(r#"href="/admin/users""#));
+}
+
+#[tokio::test]
+async fn editors_can_draft_but_only_publishers_can_send() {
+    let app = spawn_app().await;
+    let editor = spawn_user(&app, "editor").await;
+    let publisher = spawn_user(&app, "publisher").await;
+
+    app.login_as(&editor).await;
+    assert_eq!(get_status(&app, "/newsletters").await.status().as_u16(), 200);
+    let response = app.post_newsletters(newsletter_body()).await;
+    assert_is_redirect_to(&response, "/admin/dashboard");
+    let n_issues = sqlx::query_scalar!(r#"SELECT count(*) as "count!" FROM newsletter_issues"#)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(n_issues, 0);
+
+    app.post_logout().await;
+    app.login_as(&publisher).await;
+    let response = app.post_newsletters(newsletter_body()).await;
+    assert_is_redirect_to(&response, "/admin/dashboard");
+    let n_issues = sqlx::query_scalar!(r#"SELECT count(*) as "count!" FROM newsletter_issues"#)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(n_issues, 1);
+
+    // publishers still can't manage users
+    let response = get_status(&app, "/users").await;
+    assert_is_redirect_to(&response, "/admin/dashboard");
+}
+
+#[tokio::test]
+async fn editors_save_drafts_that_publishers_send() {
+    let app = spawn_app().await;
+    let editor = spawn_user(&app, "editor").await;
+    let publisher = spawn_user(&app, "publisher").await;
+    let draft =
+        |title: &str| json!({ "title": title, "text": "plain text", "html": "<p>html</p>" });
+
+    app.login_as(&editor).await;
+    let response = app
+        .post_admin_form("/newsletters/drafts", &draft("draft"))
+        .await;
+    let location = response.headers()["Location"].to_str().unwrap().to_string();
+    assert!(location.starts_with("/admin/newsletters/drafts/"));
+    let draft_path = location.trim_start_matches("/admin");
+    let response = app.post_admin_form(draft_path, &draft("revised")).await;
+    assert_is_redirect_to(&response, &location);
+    let html_page = app.get_html(&format!("{}{}", app.address, location)).await;
+    assert!(html_page.contains(r#"value="revised""#));
+    // editors get no send button, and sending anyway is refused
+    assert!(!html_page.contains("/publish"));
+    let publish_path = format!("{}/publish", draft_path);
+    let publish_form = json!({ "idempotency_key": Uuid::new_v4().to_string() });
+    let response = app.post_admin_form(&publish_path, &publish_form).await;
+    assert_is_redirect_to(&response, "/admin/dashboard");
+
+    app.post_logout().await;
+    app.login_as(&publisher).await;
+    let response = app.post_admin_form(&publish_path, &publish_form).await;
+    assert_is_redirect_to(&response, "/admin/dashboard");
+
+    let title = sqlx::query_scalar!("SELECT title FROM newsletter_issues")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(title, "revised");
+    let n_drafts = sqlx::query_scalar!(r#"SELECT count(*) as "count!" FROM newsletter_drafts"#)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(n_drafts, 0);
+}
+
+#[tokio::test]
+async fn viewers_cannot_save_drafts() {
+    let app = spawn_app().await;
+    let viewer = spawn_user(&app, "viewer").await;
+    app.login_as(&viewer).await;
+
+    let response = app
+        .post_admin_form(
+            "/newsletters/drafts",
+            &json!({ "title": "t", "text": "plain text", "html": "<p>html</p>" }),
+        )
+        .await;
+
+    assert_is_redirect_to(&response, "/admin/dashboard");
+    let response = get_status(&app, "/newsletters/drafts").await;
+    assert_is_redirect_to(&response, "/admin/dashboard");
+}
+
+#[tokio::test]
+async fn invited_users_set_up_their_own_account() {
+    let app = spawn_app().await;
+    app.login_as(&app.user).await;
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+    let response = app
+        .post_admin_form(
+            "/users/invitations",
+            &json!({ "email": "editor@example.com", "role": "editor" }),
+        )
+        .await;
+    assert_is_redirect_to(&response, "/admin/users");
+    let html_page = app.get_html(&format!("{}/admin/users", app.address)).await;
+    assert!(html_page.contains("Invited editor@example.com as editor"));
+
+    let email_request = &app.email_server.received_requests().await.unwrap()[0];
+    let link = app.get_confirmation_links(email_request).html;
+    assert_eq!(link.path(), "/invitations/accept");
+    let token = link
+        .query_pairs()
+        .find(|(k, _)| k == "token")
+        .map(|(_, v)| v.to_string())
+        .unwrap();
+    // only a hash of the token is stored, the email has the only copy
+    let stored = sqlx::query_scalar!("SELECT invitation_token_hash FROM user_invitations")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_ne!(stored, token);
+    assert_eq!(stored.len(), 64);
+    app.post_logout().await;
+
+    let html_page = app.get_html(link.as_str()).await;
+    assert!(html_page.contains(r#"name="username""#));
+
+    let password = Uuid::new_v4().to_string();
+    let accept = |username: &str| {
+        json!({
+            "token": &token,
+            "username": username,
+            "password": &password,
+            "confirm_password": &password,
+        })
+    };
+    let response = app
+        .app_client
+        .post(format!("{}/invitations/accept", app.address))
+        .form(&accept("new-editor"))
+        .send()
+        .await
+        .unwrap();
+    assert_is_redirect_to(&response, "/login");
+
+    let response = app
+        .post_login(&json!({ "username": "new-editor", "password": &password }))
+        .await;
+    assert_is_redirect_to(&response, "/admin/dashboard");
+    assert!(app
+        .get_admin_dashboard_html()
+        .await
+        .contains("Your role: editor"));
+
+    // the invitation can't be used a second time
+    let response = app
+        .app_client
+        .post(format!("{}/invitations/accept", app.address))
+        .form(&accept("another-editor"))
+        .send()
+        .await
+        .unwrap();
+    assert_is_redirect_to(&response, "/login");
+    let n_users = sqlx::query_scalar!(r#"SELECT count(*) as "count!" FROM users WHERE name = 'another-editor'"#)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(n_users, 0);
+}
+
+#[tokio::test]
+async fn owners_change_roles_but_never_remove_the_last_owner() {
+    let app = spawn_app().await;
+    let viewer = spawn_user(&app, "viewer").await;
+    // the migrations seed an owner of their own
+    sqlx::query!("UPDATE users SET role = 'viewer' WHERE name = 'admin'")
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+    app.login_as(&app.user).await;
+
+    let response = app
+        .post_admin_form(
+            &format!("/users/{}/role", viewer.user_id),
+            &json!({ "role": "publisher" }),
+        )
+        .await;
+    assert_is_redirect_to(&response, "/admin/users");
+    let role = sqlx::query_scalar!("SELECT role FROM users WHERE user_id = $1", viewer.user_id)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(role, "publisher");
+
+    let response = app
+        .post_admin_form(
+            &format!("/users/{}/role", app.user.user_id),
+            &json!({ "role": "viewer" }),
+        )
+        .await;
+    assert_is_redirect_to(&response, "/admin/users");
+    let html_page = app.get_html(&format!("{}/admin/users", app.address)).await;
+    assert!(html_page.contains("There has to be at least one owner"));
+}